@@ -18,10 +18,31 @@ use ahash::AHashMap;
 // UpdateIdDedup — monotonic sequence-based
 // ---------------------------------------------------------------------------
 
+/// Outcome of [`UpdateIdDedup::check`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IdCheck {
+    /// Not greater than the last seen ID: drop it.
+    Duplicate,
+    /// New, within the allowed step of the last seen ID.
+    New,
+    /// New, but IDs `from..to` were skipped (the step exceeded the allowed
+    /// maximum).
+    Gap { from: u64, to: u64 },
+}
+
+impl IdCheck {
+    /// Whether the message should be forwarded.
+    #[inline]
+    pub fn is_new(self) -> bool {
+        !matches!(self, Self::Duplicate)
+    }
+}
+
 /// Deduplicator based on a per-symbol monotonically increasing update ID.
 ///
 /// For each symbol, the last seen update ID is stored. A new message is
 /// accepted only if its update ID is strictly greater than the stored value.
+/// [`check`](Self::check) additionally reports jumps in the ID sequence.
 ///
 /// # Thread safety
 ///
@@ -43,12 +64,26 @@ impl UpdateIdDedup {
     /// If `true`, the internal state is updated to record this ID.
     #[inline]
     pub fn check_and_update(&mut self, symbol: &str, update_id: u64) -> bool {
-        let entry = self.last_ids.entry(symbol.to_string()).or_insert(0);
-        if update_id > *entry {
-            *entry = update_id;
-            true
-        } else {
-            false
+        self.check(symbol, update_id, None).is_new()
+    }
+
+    /// Like [`check_and_update`](Self::check_and_update), but also reports a
+    /// [`Gap`](IdCheck::Gap) when `update_id` is more than `max_step` past
+    /// the last seen ID (`max_step = 1` for strictly contiguous IDs, `None`
+    /// to skip the check). The first ID of a symbol never counts as a gap.
+    #[inline]
+    pub fn check(&mut self, symbol: &str, update_id: u64, max_step: Option<u64>) -> IdCheck {
+        let Some(last) = self.last_ids.get_mut(symbol) else {
+            self.last_ids.insert(symbol.to_string(), update_id);
+            return if update_id > 0 { IdCheck::New } else { IdCheck::Duplicate };
+        };
+        if update_id <= *last {
+            return IdCheck::Duplicate;
+        }
+        let prev = std::mem::replace(last, update_id);
+        match max_step {
+            Some(step) if prev > 0 && update_id - prev > step => IdCheck::Gap { from: prev + 1, to: update_id },
+            _ => IdCheck::New,
         }
     }
 
@@ -57,6 +92,11 @@ impl UpdateIdDedup {
         self.last_ids.get(symbol).copied()
     }
 
+    /// Forget `symbol`'s last ID, so its next ID is accepted as a first one.
+    pub fn remove(&mut self, symbol: &str) {
+        self.last_ids.remove(symbol);
+    }
+
     /// Clear all state.
     pub fn clear(&mut self) {
         self.last_ids.clear();
@@ -152,6 +192,22 @@ mod tests {
         assert!(!d.check_and_update("BTCUSDT", 1));
     }
 
+    #[test]
+    fn update_id_dedup_reports_gaps() {
+        let mut d = UpdateIdDedup::new();
+        assert_eq!(d.check("BTCUSDT", 100, Some(1)), IdCheck::New); // first id is never a gap
+        assert_eq!(d.check("BTCUSDT", 101, Some(1)), IdCheck::New);
+        assert_eq!(d.check("BTCUSDT", 105, Some(1)), IdCheck::Gap { from: 102, to: 105 });
+        assert_eq!(d.check("BTCUSDT", 103, Some(1)), IdCheck::Duplicate);
+        assert_eq!(d.check("BTCUSDT", 108, Some(3)), IdCheck::New); // within tolerance
+        assert_eq!(d.check("BTCUSDT", 200, None), IdCheck::New); // unchecked
+        assert_eq!(d.last_id("BTCUSDT"), Some(200));
+
+        d.remove("BTCUSDT");
+        assert_eq!(d.last_id("BTCUSDT"), None);
+        assert_eq!(d.check("BTCUSDT", 500, Some(1)), IdCheck::New); // starts over
+    }
+
     #[test]
     fn uuid_dedup_basic() {
         let mut d = UuidDedup::new();
@@ -0,0 +1,90 @@
+//! Binance `exchangeInfo` parsing.
+//!
+//! Spot (`/api/v3`), USDⓈ-M (`/fapi/v1`) and COIN-M (`/dapi/v1`) responses
+//! share a shape: a `symbols` array whose entries carry `PRICE_FILTER` and
+//! `LOT_SIZE` filters. Futures add `contractType` and `deliveryDate`; COIN-M
+//! adds `contractSize` (USD per contract) and names its status field
+//! `contractStatus`.
+
+use serde_json::Value;
+
+use super::{Instrument, InstrumentKind, num, str_field};
+use crate::types::{Exchange, ProductType};
+
+/// Parse an `exchangeInfo` response for `product_type` (`Spot`, `Futures`
+/// for USDⓈ-M or `CoinMargin` for COIN-M).
+pub fn parse_exchange_info(info: &Value, product_type: ProductType) -> Vec<Instrument> {
+    info.get("symbols")
+        .and_then(Value::as_array)
+        .map(|list| list.iter().filter_map(|v| parse_symbol(v, product_type)).collect())
+        .unwrap_or_default()
+}
+
+fn parse_symbol(v: &Value, product_type: ProductType) -> Option<Instrument> {
+    let filter = |kind: &str| {
+        v.get("filters")?.as_array()?.iter().find(|f| f.get("filterType").and_then(Value::as_str) == Some(kind))
+    };
+    let (kind, expiry_ms) = match product_type {
+        ProductType::Spot => (InstrumentKind::Spot, None),
+        _ => match str_field(v, "contractType")? {
+            "" => return None,
+            t if t.contains("PERPETUAL") => (InstrumentKind::Perpetual, None),
+            _ => (InstrumentKind::Future, v.get("deliveryDate")?.as_u64()),
+        },
+    };
+    let status = str_field(v, "status").or_else(|| str_field(v, "contractStatus"))?;
+    let lot = filter("LOT_SIZE")?;
+    Some(Instrument {
+        exchange: Exchange::Binance,
+        product_type,
+        kind,
+        symbol: str_field(v, "symbol")?.to_string(),
+        base: str_field(v, "baseAsset")?.to_string(),
+        quote: str_field(v, "quoteAsset")?.to_string(),
+        settle: str_field(v, "marginAsset").or_else(|| str_field(v, "quoteAsset"))?.to_string(),
+        tick_size: num(filter("PRICE_FILTER")?, "tickSize")?,
+        lot_size: num(lot, "stepSize")?,
+        min_qty: num(lot, "minQty")?,
+        contract_multiplier: num(v, "contractSize").unwrap_or(1.0),
+        expiry_ms,
+        trading: status == "TRADING",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_coin_margined_contracts() {
+        let info = serde_json::json!({"symbols": [
+            {
+                "symbol": "BTCUSD_PERP", "pair": "BTCUSD", "contractType": "PERPETUAL",
+                "deliveryDate": 4133404800000u64, "contractStatus": "TRADING", "contractSize": 100,
+                "baseAsset": "BTC", "quoteAsset": "USD", "marginAsset": "BTC",
+                "filters": [
+                    {"filterType": "PRICE_FILTER", "tickSize": "0.1"},
+                    {"filterType": "LOT_SIZE", "stepSize": "1", "minQty": "1"}
+                ]
+            },
+            {
+                "symbol": "BTCUSD_250627", "pair": "BTCUSD", "contractType": "CURRENT_QUARTER",
+                "deliveryDate": 1751011200000u64, "contractStatus": "TRADING", "contractSize": 100,
+                "baseAsset": "BTC", "quoteAsset": "USD", "marginAsset": "BTC",
+                "filters": [
+                    {"filterType": "PRICE_FILTER", "tickSize": "0.1"},
+                    {"filterType": "LOT_SIZE", "stepSize": "1", "minQty": "1"}
+                ]
+            },
+            {"symbol": "BROKEN", "contractType": "PERPETUAL", "contractStatus": "TRADING"}
+        ]});
+
+        let instruments = parse_exchange_info(&info, ProductType::CoinMargin);
+
+        assert_eq!(instruments.len(), 2);
+        assert_eq!(instruments[0].canonical_id(), "BINANCE:BTC-USD:PERP");
+        assert_eq!(instruments[0].settle, "BTC");
+        assert_eq!((instruments[0].tick_size, instruments[0].contract_multiplier), (0.1, 100.0));
+        assert_eq!(instruments[1].canonical_id(), "BINANCE:BTC-USD:20250627");
+    }
+}
@@ -0,0 +1,86 @@
+//! Bitget `spot/public/symbols` and `mix/market/contracts` parsing.
+//!
+//! Spot gives price and quantity precisions as decimal places; contracts
+//! give `pricePlace` with a `priceEndStep` multiple, `sizeMultiplier` as the
+//! quantity step, and `symbolType` (`perpetual`/`delivery`).
+
+use serde_json::Value;
+
+use super::{Instrument, InstrumentKind, num, step_from_places, str_field};
+use crate::types::{Exchange, ProductType};
+
+/// Parse the `data` array of a spot symbols response.
+pub fn parse_spot_symbols(data: &[Value]) -> Vec<Instrument> {
+    data.iter().filter_map(parse_spot_symbol).collect()
+}
+
+/// Parse the `data` array of a contracts response for a USDT- or
+/// USDC-margined product type.
+pub fn parse_contracts(data: &[Value]) -> Vec<Instrument> {
+    data.iter().filter_map(parse_contract).collect()
+}
+
+fn parse_spot_symbol(v: &Value) -> Option<Instrument> {
+    let quote = str_field(v, "quoteCoin")?;
+    let lot_size = step_from_places(num(v, "quantityPrecision")?);
+    Some(Instrument {
+        exchange: Exchange::Bitget,
+        product_type: ProductType::Spot,
+        kind: InstrumentKind::Spot,
+        symbol: str_field(v, "symbol")?.to_string(),
+        base: str_field(v, "baseCoin")?.to_string(),
+        quote: quote.to_string(),
+        settle: quote.to_string(),
+        tick_size: step_from_places(num(v, "pricePrecision")?),
+        lot_size,
+        min_qty: num(v, "minTradeAmount").filter(|&q| q > 0.0).unwrap_or(lot_size),
+        contract_multiplier: 1.0,
+        expiry_ms: None,
+        trading: str_field(v, "status")? == "online",
+    })
+}
+
+fn parse_contract(v: &Value) -> Option<Instrument> {
+    let quote = str_field(v, "quoteCoin")?;
+    let kind = match str_field(v, "symbolType")? {
+        "perpetual" => InstrumentKind::Perpetual,
+        _ => InstrumentKind::Future,
+    };
+    Some(Instrument {
+        exchange: Exchange::Bitget,
+        product_type: ProductType::Futures,
+        kind,
+        symbol: str_field(v, "symbol")?.to_string(),
+        base: str_field(v, "baseCoin")?.to_string(),
+        quote: quote.to_string(),
+        settle: quote.to_string(),
+        tick_size: step_from_places(num(v, "pricePlace")?) * num(v, "priceEndStep").unwrap_or(1.0),
+        lot_size: num(v, "sizeMultiplier")?,
+        min_qty: num(v, "minTradeNum")?,
+        contract_multiplier: 1.0,
+        expiry_ms: num(v, "deliveryTime").map(|ms| ms as u64).filter(|&ms| ms > 0),
+        trading: str_field(v, "symbolStatus")? == "normal",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_precisions_into_increments() {
+        let spot = serde_json::json!([{"symbol": "BTCUSDT", "baseCoin": "BTC", "quoteCoin": "USDT",
+            "pricePrecision": "2", "quantityPrecision": "6", "minTradeAmount": "0", "status": "online"}]);
+        let contracts = serde_json::json!([{"symbol": "BTCUSDT", "baseCoin": "BTC", "quoteCoin": "USDT",
+            "symbolType": "perpetual", "symbolStatus": "normal", "pricePlace": "1", "priceEndStep": "5",
+            "sizeMultiplier": "0.001", "minTradeNum": "0.001", "deliveryTime": ""}]);
+
+        let spot = parse_spot_symbols(spot.as_array().unwrap());
+        let perp = parse_contracts(contracts.as_array().unwrap());
+
+        assert_eq!(spot[0].canonical_id(), "BITGET:BTC-USDT:SPOT");
+        assert_eq!((spot[0].tick_size, spot[0].lot_size, spot[0].min_qty), (0.01, 0.000001, 0.000001));
+        assert_eq!(perp[0].canonical_id(), "BITGET:BTC-USDT:PERP");
+        assert_eq!((perp[0].tick_size, perp[0].lot_size), (0.5, 0.001));
+    }
+}
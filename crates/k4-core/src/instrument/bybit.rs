@@ -0,0 +1,70 @@
+//! Bybit `instruments-info` parsing.
+//!
+//! Spot entries give the quantity step as `lotSizeFilter.basePrecision`;
+//! linear and inverse ones as `lotSizeFilter.qtyStep`, plus `contractType`,
+//! `settleCoin` and `deliveryTime` (`"0"` for perpetuals).
+
+use serde_json::Value;
+
+use super::{Instrument, InstrumentKind, num, str_field};
+use crate::types::{Exchange, ProductType};
+
+/// Parse the `result.list` array of an instruments-info response for
+/// `category` (`"spot"`, `"linear"` or `"inverse"`).
+pub fn parse_instruments(list: &[Value], category: &str) -> Vec<Instrument> {
+    list.iter().filter_map(|v| parse_instrument(v, category)).collect()
+}
+
+fn parse_instrument(v: &Value, category: &str) -> Option<Instrument> {
+    let lot = v.get("lotSizeFilter")?;
+    let quote = str_field(v, "quoteCoin")?;
+    let (product_type, kind, settle, lot_size) = match category {
+        "spot" => (ProductType::Spot, InstrumentKind::Spot, quote, num(lot, "basePrecision")?),
+        _ => {
+            let product_type = if category == "linear" { ProductType::Futures } else { ProductType::CoinMargin };
+            let kind = match str_field(v, "contractType")? {
+                "LinearPerpetual" | "InversePerpetual" => InstrumentKind::Perpetual,
+                _ => InstrumentKind::Future,
+            };
+            (product_type, kind, str_field(v, "settleCoin")?, num(lot, "qtyStep")?)
+        }
+    };
+    Some(Instrument {
+        exchange: Exchange::Bybit,
+        product_type,
+        kind,
+        symbol: str_field(v, "symbol")?.to_string(),
+        base: str_field(v, "baseCoin")?.to_string(),
+        quote: quote.to_string(),
+        settle: settle.to_string(),
+        tick_size: num(v.get("priceFilter")?, "tickSize")?,
+        lot_size,
+        min_qty: num(lot, "minOrderQty")?,
+        contract_multiplier: 1.0,
+        expiry_ms: num(v, "deliveryTime").map(|ms| ms as u64).filter(|&ms| ms > 0),
+        trading: str_field(v, "status")? == "Trading",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_linear_perpetuals_and_futures() {
+        let list = serde_json::json!([
+            {"symbol": "BTCUSDT", "contractType": "LinearPerpetual", "status": "Trading", "baseCoin": "BTC",
+             "quoteCoin": "USDT", "settleCoin": "USDT", "deliveryTime": "0", "priceFilter": {"tickSize": "0.10"},
+             "lotSizeFilter": {"qtyStep": "0.001", "minOrderQty": "0.001"}},
+            {"symbol": "BTC-27JUN25", "contractType": "LinearFutures", "status": "Trading", "baseCoin": "BTC",
+             "quoteCoin": "USDC", "settleCoin": "USDC", "deliveryTime": "1751011200000",
+             "priceFilter": {"tickSize": "0.50"}, "lotSizeFilter": {"qtyStep": "0.001", "minOrderQty": "0.001"}},
+        ]);
+
+        let instruments = parse_instruments(list.as_array().unwrap(), "linear");
+
+        assert_eq!(instruments[0].canonical_id(), "BYBIT:BTC-USDT:PERP");
+        assert_eq!((instruments[0].tick_size, instruments[0].expiry_ms), (0.1, None));
+        assert_eq!(instruments[1].canonical_id(), "BYBIT:BTC-USDC:20250627");
+    }
+}
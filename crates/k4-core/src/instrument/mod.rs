@@ -0,0 +1,428 @@
+//! Instrument registry: contract specs and canonical ids.
+//!
+//! Each venue names instruments its own way (Binance `BTCUSDT`, OKX
+//! `BTC-USDT-SWAP`, Binance coin-margined `BTCUSD_PERP`). An [`Instrument`]
+//! records the contract spec of one venue instrument (tick size, lot size,
+//! contract multiplier, base/quote/settlement assets, expiry) and derives a
+//! venue-independent canonical id from it:
+//!
+//! ```text
+//! BINANCE:BTC-USDT:PERP     OKX:ETH-USDT:SPOT     BYBIT:BTC-USDT:20250627
+//! ```
+//!
+//! i.e. `EXCHANGE:BASE-QUOTE:KIND`, where `KIND` is `SPOT`, `PERP` or the
+//! expiry date of a dated future. The [`InstrumentRegistry`] maps between
+//! venue symbols and canonical ids and is shared by MD and TD modules; it is
+//! filled from exchange metadata with the per-venue parsers below and can be
+//! published to SHM (see [`InstrumentRegistry::publish`]) for strategies and
+//! TD modules to look contract specs up by canonical id (see
+//! [`InstrumentRegistry::open`]).
+
+pub mod binance;
+pub mod bitget;
+pub mod bybit;
+pub mod okx;
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use tracing::warn;
+
+use crate::{
+    shm::ShmMdStore,
+    types::{
+        Exchange, ProductType,
+        symbol::{SYMBOL_LEN, symbol_from_bytes, symbol_to_bytes},
+    },
+};
+
+/// How an instrument expires.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[repr(u8)]
+pub enum InstrumentKind {
+    Spot = 0,
+    /// Perpetual swap.
+    Perpetual = 1,
+    /// Dated future, expiring at [`Instrument::expiry_ms`].
+    Future = 2,
+}
+
+/// Contract spec of one venue instrument.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Instrument {
+    pub exchange: Exchange,
+    /// `Spot`, `Futures` (linear) or `CoinMargin` (inverse).
+    pub product_type: ProductType,
+    pub kind: InstrumentKind,
+    /// Venue symbol, as used on the wire (e.g. `"BTC-USDT-SWAP"`).
+    pub symbol: String,
+    pub base: String,
+    /// Currency prices are quoted in.
+    pub quote: String,
+    /// Currency margin and PnL settle in (the quote currency for spot).
+    pub settle: String,
+    /// Smallest price increment.
+    pub tick_size: f64,
+    /// Smallest quantity increment, in the venue's quantity unit.
+    pub lot_size: f64,
+    /// Smallest order quantity, in the venue's quantity unit.
+    pub min_qty: f64,
+    /// Size of one contract: base units for linear contracts, quote units
+    /// for inverse ones. `1` where quantities are not in contracts.
+    pub contract_multiplier: f64,
+    /// Expiry of a dated future, in ms since the epoch.
+    pub expiry_ms: Option<u64>,
+    /// Whether the instrument is currently trading.
+    pub trading: bool,
+}
+
+impl Instrument {
+    /// Canonical id, e.g. `"BINANCE:BTC-USDT:PERP"`.
+    pub fn canonical_id(&self) -> String {
+        let kind = match (self.kind, self.expiry_ms) {
+            (InstrumentKind::Spot, _) => "SPOT".to_string(),
+            (InstrumentKind::Perpetual, _) => "PERP".to_string(),
+            (InstrumentKind::Future, Some(ms)) => expiry_date(ms),
+            (InstrumentKind::Future, None) => "FUT".to_string(),
+        };
+        format!("{}:{}-{}:{kind}", self.exchange.to_string().to_uppercase(), self.base, self.quote)
+    }
+}
+
+/// `YYYYMMDD` (UTC) of a timestamp in ms.
+fn expiry_date(ms: u64) -> String {
+    // Civil-from-days (H. Hinnant), for days since 1970-01-01.
+    let z = (ms / 86_400_000) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}{month:02}{day:02}")
+}
+
+/// A JSON field holding a number or a numeric string.
+fn num(v: &Value, key: &str) -> Option<f64> {
+    match v.get(key)? {
+        Value::String(s) => s.parse().ok(),
+        n => n.as_f64(),
+    }
+}
+
+/// A JSON string field.
+fn str_field<'a>(v: &'a Value, key: &str) -> Option<&'a str> {
+    v.get(key)?.as_str()
+}
+
+/// `10^-places`, for venues that give precisions instead of increments.
+fn step_from_places(places: f64) -> f64 {
+    10f64.powi(-(places as i32))
+}
+
+/// Instruments by canonical id and by venue symbol.
+#[derive(Debug, Clone, Default)]
+pub struct InstrumentRegistry {
+    /// `(canonical id, instrument)`, in insertion order.
+    entries: Vec<(String, Instrument)>,
+    by_id: HashMap<String, usize>,
+    by_venue: HashMap<(Exchange, ProductType, String), usize>,
+}
+
+impl InstrumentRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `instrument`, replacing any with the same canonical id.
+    pub fn insert(&mut self, instrument: Instrument) {
+        let id = instrument.canonical_id();
+        let venue_key = (instrument.exchange, instrument.product_type, instrument.symbol.clone());
+        match self.by_id.get(&id) {
+            Some(&i) => {
+                let old = &self.entries[i].1;
+                self.by_venue.remove(&(old.exchange, old.product_type, old.symbol.clone()));
+                self.entries[i].1 = instrument;
+                self.by_venue.insert(venue_key, i);
+            }
+            None => {
+                let i = self.entries.len();
+                self.by_id.insert(id.clone(), i);
+                self.by_venue.insert(venue_key, i);
+                self.entries.push((id, instrument));
+            }
+        }
+    }
+
+    /// The instrument with canonical id `id`.
+    pub fn get(&self, id: &str) -> Option<&Instrument> {
+        self.by_id.get(id).map(|&i| &self.entries[i].1)
+    }
+
+    /// The instrument `exchange` lists as `symbol` among its `product_type`
+    /// instruments.
+    pub fn by_venue_symbol(&self, exchange: Exchange, product_type: ProductType, symbol: &str) -> Option<&Instrument> {
+        self.venue_index(exchange, product_type, symbol).map(|i| &self.entries[i].1)
+    }
+
+    /// Canonical id of a venue symbol.
+    pub fn canonical_id(&self, exchange: Exchange, product_type: ProductType, symbol: &str) -> Option<&str> {
+        self.venue_index(exchange, product_type, symbol).map(|i| self.entries[i].0.as_str())
+    }
+
+    /// Venue symbol of a canonical id.
+    pub fn venue_symbol(&self, id: &str) -> Option<&str> {
+        self.get(id).map(|i| i.symbol.as_str())
+    }
+
+    fn venue_index(&self, exchange: Exchange, product_type: ProductType, symbol: &str) -> Option<usize> {
+        self.by_venue.get(&(exchange, product_type, symbol.to_string())).copied()
+    }
+
+    /// `(canonical id, instrument)` pairs, in insertion order.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Instrument)> {
+        self.entries.iter().map(|(id, i)| (id.as_str(), i))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Write every instrument to a new SHM region named `shm_name`: one
+    /// single-entry slot per instrument, keyed by canonical id and holding
+    /// its [`InstrumentRecord`]. Ids longer than the slot name are skipped.
+    ///
+    /// The region lives as long as the returned store.
+    pub fn publish(&self, shm_name: &str) -> anyhow::Result<ShmMdStore<InstrumentRecord>> {
+        let (ids, skipped): (Vec<String>, Vec<String>) =
+            self.entries.iter().map(|(id, _)| id.clone()).partition(|id| id.len() <= SYMBOL_LEN);
+        for id in &skipped {
+            warn!("{shm_name}: instrument id {id} is longer than {SYMBOL_LEN} bytes, not published");
+        }
+        let store = ShmMdStore::create(shm_name, &ids, 1)?;
+        for id in &ids {
+            if let Some(instrument) = self.get(id) {
+                store.write(id, &InstrumentRecord::from(instrument));
+            }
+        }
+        Ok(store)
+    }
+
+    /// Read the instruments another process published to `shm_name` with
+    /// [`publish`](Self::publish). Records that do not decode are skipped.
+    pub fn open(shm_name: &str) -> anyhow::Result<Self> {
+        let store = ShmMdStore::<InstrumentRecord>::open(shm_name)?;
+        let mut registry = Self::new();
+        for id in store.symbols() {
+            match store.read_latest(&id).as_ref().and_then(InstrumentRecord::instrument) {
+                Some(instrument) => registry.insert(instrument),
+                None => warn!("{shm_name}: instrument {id} has no readable record"),
+            }
+        }
+        Ok(registry)
+    }
+}
+
+impl Extend<Instrument> for InstrumentRegistry {
+    fn extend<I: IntoIterator<Item = Instrument>>(&mut self, iter: I) {
+        for instrument in iter {
+            self.insert(instrument);
+        }
+    }
+}
+
+impl FromIterator<Instrument> for InstrumentRegistry {
+    fn from_iter<I: IntoIterator<Item = Instrument>>(iter: I) -> Self {
+        let mut registry = Self::new();
+        registry.extend(iter);
+        registry
+    }
+}
+
+/// SHM form of an [`Instrument`] (see [`InstrumentRegistry::publish`]).
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InstrumentRecord {
+    /// Venue symbol, null-padded.
+    pub symbol: [u8; SYMBOL_LEN],
+    pub base: [u8; SYMBOL_LEN],
+    pub quote: [u8; SYMBOL_LEN],
+    pub settle: [u8; SYMBOL_LEN],
+    pub tick_size: f64,
+    pub lot_size: f64,
+    pub min_qty: f64,
+    pub contract_multiplier: f64,
+    /// `0` if the instrument does not expire.
+    pub expiry_ms: u64,
+    /// [`Exchange`] discriminant.
+    pub exchange: u8,
+    /// [`ProductType`] discriminant.
+    pub product_type: u8,
+    /// [`InstrumentKind`] discriminant.
+    pub kind: u8,
+    /// `1` if trading.
+    pub trading: u8,
+    pub _pad: [u8; 4],
+}
+
+impl InstrumentRecord {
+    /// The instrument this record was written from, or `None` if a
+    /// discriminant is unknown.
+    pub fn instrument(&self) -> Option<Instrument> {
+        let kind = match self.kind {
+            0 => InstrumentKind::Spot,
+            1 => InstrumentKind::Perpetual,
+            2 => InstrumentKind::Future,
+            _ => return None,
+        };
+        Some(Instrument {
+            exchange: Exchange::from_u8(self.exchange)?,
+            product_type: ProductType::from_u8(self.product_type)?,
+            kind,
+            symbol: symbol_from_bytes(&self.symbol).to_string(),
+            base: symbol_from_bytes(&self.base).to_string(),
+            quote: symbol_from_bytes(&self.quote).to_string(),
+            settle: symbol_from_bytes(&self.settle).to_string(),
+            tick_size: self.tick_size,
+            lot_size: self.lot_size,
+            min_qty: self.min_qty,
+            contract_multiplier: self.contract_multiplier,
+            expiry_ms: (self.expiry_ms != 0).then_some(self.expiry_ms),
+            trading: self.trading != 0,
+        })
+    }
+}
+
+impl From<&Instrument> for InstrumentRecord {
+    fn from(i: &Instrument) -> Self {
+        Self {
+            symbol: symbol_to_bytes(&i.symbol),
+            base: symbol_to_bytes(&i.base),
+            quote: symbol_to_bytes(&i.quote),
+            settle: symbol_to_bytes(&i.settle),
+            tick_size: i.tick_size,
+            lot_size: i.lot_size,
+            min_qty: i.min_qty,
+            contract_multiplier: i.contract_multiplier,
+            expiry_ms: i.expiry_ms.unwrap_or(0),
+            exchange: i.exchange as u8,
+            product_type: i.product_type as u8,
+            kind: i.kind as u8,
+            trading: u8::from(i.trading),
+            _pad: [0; 4],
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::symbol::symbol_from_bytes;
+
+    fn instrument(exchange: Exchange, product_type: ProductType, kind: InstrumentKind, symbol: &str) -> Instrument {
+        Instrument {
+            exchange,
+            product_type,
+            kind,
+            symbol: symbol.into(),
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            settle: "USDT".into(),
+            tick_size: 0.1,
+            lot_size: 0.001,
+            min_qty: 0.001,
+            contract_multiplier: 1.0,
+            expiry_ms: None,
+            trading: true,
+        }
+    }
+
+    #[test]
+    fn canonical_ids() {
+        let perp = instrument(Exchange::Binance, ProductType::Futures, InstrumentKind::Perpetual, "BTCUSDT");
+        assert_eq!(perp.canonical_id(), "BINANCE:BTC-USDT:PERP");
+        let spot = instrument(Exchange::Okx, ProductType::Spot, InstrumentKind::Spot, "BTC-USDT");
+        assert_eq!(spot.canonical_id(), "OKX:BTC-USDT:SPOT");
+        // 2025-06-27T08:00:00Z
+        let dated = Instrument {
+            expiry_ms: Some(1_751_011_200_000),
+            ..instrument(Exchange::Bybit, ProductType::Futures, InstrumentKind::Future, "BTC-27JUN25")
+        };
+        assert_eq!(dated.canonical_id(), "BYBIT:BTC-USDT:20250627");
+        assert_eq!(expiry_date(0), "19700101");
+        assert_eq!(expiry_date(951_782_400_000), "20000229");
+    }
+
+    #[test]
+    fn maps_between_venue_symbols_and_ids() {
+        let registry: InstrumentRegistry = [
+            instrument(Exchange::Binance, ProductType::Spot, InstrumentKind::Spot, "BTCUSDT"),
+            instrument(Exchange::Binance, ProductType::Futures, InstrumentKind::Perpetual, "BTCUSDT"),
+            instrument(Exchange::Okx, ProductType::Futures, InstrumentKind::Perpetual, "BTC-USDT-SWAP"),
+        ]
+        .into_iter()
+        .collect();
+
+        assert_eq!(registry.len(), 3);
+        assert_eq!(
+            registry.canonical_id(Exchange::Binance, ProductType::Spot, "BTCUSDT"),
+            Some("BINANCE:BTC-USDT:SPOT")
+        );
+        assert_eq!(
+            registry.canonical_id(Exchange::Binance, ProductType::Futures, "BTCUSDT"),
+            Some("BINANCE:BTC-USDT:PERP")
+        );
+        assert_eq!(registry.venue_symbol("OKX:BTC-USDT:PERP"), Some("BTC-USDT-SWAP"));
+        assert!(registry.canonical_id(Exchange::Okx, ProductType::Spot, "BTC-USDT").is_none());
+
+        // Re-inserting an id replaces the instrument.
+        let mut registry = registry;
+        registry.insert(Instrument {
+            tick_size: 0.5,
+            ..instrument(Exchange::Okx, ProductType::Futures, InstrumentKind::Perpetual, "BTC-USDT-SWAP")
+        });
+        assert_eq!(registry.len(), 3);
+        assert_eq!(registry.get("OKX:BTC-USDT:PERP").unwrap().tick_size, 0.5);
+    }
+
+    #[test]
+    fn publishes_records_by_canonical_id() {
+        let registry: InstrumentRegistry =
+            [instrument(Exchange::Bitget, ProductType::Futures, InstrumentKind::Perpetual, "BTCUSDT")]
+                .into_iter()
+                .collect();
+        let store = registry.publish("test_shm_instruments").unwrap();
+
+        let record = store.read_latest("BITGET:BTC-USDT:PERP").unwrap();
+        assert_eq!(symbol_from_bytes(&record.symbol), "BTCUSDT");
+        assert_eq!((record.tick_size, record.lot_size, record.expiry_ms), (0.1, 0.001, 0));
+        assert_eq!((record.exchange, record.product_type, record.kind), (3, 1, 1));
+        assert_eq!(record.instrument().as_ref(), registry.get("BITGET:BTC-USDT:PERP"));
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn opens_a_published_registry() {
+        let registry: InstrumentRegistry = [
+            instrument(Exchange::Binance, ProductType::Spot, InstrumentKind::Spot, "BTCUSDT"),
+            instrument(Exchange::Binance, ProductType::Futures, InstrumentKind::Perpetual, "BTCUSDT"),
+        ]
+        .into_iter()
+        .collect();
+        let _store = registry.publish("test_shm_instruments_open").unwrap();
+
+        let opened = InstrumentRegistry::open("test_shm_instruments_open").unwrap();
+        assert_eq!(opened.len(), 2);
+        assert_eq!(
+            opened.by_venue_symbol(Exchange::Binance, ProductType::Futures, "BTCUSDT"),
+            registry.get("BINANCE:BTC-USDT:PERP")
+        );
+    }
+}
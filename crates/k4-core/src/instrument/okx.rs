@@ -0,0 +1,73 @@
+//! OKX `public/instruments` parsing.
+//!
+//! Spot entries carry `baseCcy`/`quoteCcy`; swaps and futures carry the
+//! underlying (`uly`, e.g. `BTC-USDT`), `ctType` (`linear`/`inverse`),
+//! `ctVal` and `settleCcy`. Futures also have `expTime`.
+
+use serde_json::Value;
+
+use super::{Instrument, InstrumentKind, num, str_field};
+use crate::types::{Exchange, ProductType};
+
+/// Parse the `data` array of an instruments response.
+pub fn parse_instruments(data: &[Value]) -> Vec<Instrument> {
+    data.iter().filter_map(parse_instrument).collect()
+}
+
+fn parse_instrument(v: &Value) -> Option<Instrument> {
+    let (product_type, kind, base, quote, settle, multiplier) = match str_field(v, "instType")? {
+        "SPOT" => {
+            let quote = str_field(v, "quoteCcy")?;
+            (ProductType::Spot, InstrumentKind::Spot, str_field(v, "baseCcy")?, quote, quote, 1.0)
+        }
+        inst_type @ ("SWAP" | "FUTURES") => {
+            let (base, quote) = str_field(v, "uly")?.split_once('-')?;
+            let product_type = match str_field(v, "ctType")? {
+                "linear" => ProductType::Futures,
+                _ => ProductType::CoinMargin,
+            };
+            let kind = if inst_type == "SWAP" { InstrumentKind::Perpetual } else { InstrumentKind::Future };
+            (product_type, kind, base, quote, str_field(v, "settleCcy")?, num(v, "ctVal")?)
+        }
+        _ => return None,
+    };
+    Some(Instrument {
+        exchange: Exchange::Okx,
+        product_type,
+        kind,
+        symbol: str_field(v, "instId")?.to_string(),
+        base: base.to_string(),
+        quote: quote.to_string(),
+        settle: settle.to_string(),
+        tick_size: num(v, "tickSz")?,
+        lot_size: num(v, "lotSz")?,
+        min_qty: num(v, "minSz")?,
+        contract_multiplier: multiplier,
+        expiry_ms: num(v, "expTime").map(|ms| ms as u64),
+        trading: str_field(v, "state")? == "live",
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_spot_and_swaps() {
+        let data = serde_json::json!([
+            {"instType": "SPOT", "instId": "BTC-USDT", "baseCcy": "BTC", "quoteCcy": "USDT",
+             "tickSz": "0.1", "lotSz": "0.00000001", "minSz": "0.00001", "state": "live", "expTime": ""},
+            {"instType": "SWAP", "instId": "ETH-USD-SWAP", "uly": "ETH-USD", "ctType": "inverse", "ctVal": "10",
+             "settleCcy": "ETH", "tickSz": "0.01", "lotSz": "1", "minSz": "1", "state": "suspend", "expTime": ""},
+        ]);
+
+        let instruments = parse_instruments(data.as_array().unwrap());
+
+        assert_eq!(instruments[0].canonical_id(), "OKX:BTC-USDT:SPOT");
+        assert_eq!(instruments[0].min_qty, 0.00001);
+        assert_eq!(instruments[1].canonical_id(), "OKX:ETH-USD:PERP");
+        assert_eq!(instruments[1].product_type, ProductType::CoinMargin);
+        assert_eq!((instruments[1].contract_multiplier, instruments[1].trading), (10.0, false));
+        assert_eq!(instruments[1].expiry_ms, None);
+    }
+}
@@ -5,6 +5,7 @@
 //! - **Types** (`types`) — enums, market data structs, trading structs, symbol utils
 //! - **Configuration** (`config`) — JSON config deserialization
 //! - **Error types** (`error`) — domain-specific `K4Error` via thiserror
+//! - **Instruments** (`instrument`) — contract specs, canonical ids, venue symbol mapping
 //! - **Shared memory** (`shm`) — ring-buffer market data store over mmap
 //! - **UDP channel** (`udp`) — async UDP sender/receiver with rkyv serialization
 //! - **WebSocket** (`ws`) — WS client with auto-reconnect + redundancy
@@ -18,6 +19,7 @@ pub mod config;
 pub mod cpu_affinity;
 pub mod dedup;
 pub mod error;
+pub mod instrument;
 pub mod latency;
 pub mod logging;
 pub mod shm;
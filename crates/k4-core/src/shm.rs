@@ -9,7 +9,8 @@
 //!
 //! ```text
 //! ┌─────────────────────────────────────────────────────────────┐
-//! │ ShmHeader (update_num, instrument_count, buffer_size)       │
+//! │ ShmHeader (update_num, instrument_count, buffer_size,       │
+//! │            version, record_size)                            │
 //! ├─────────────────────────────────────────────────────────────┤
 //! │ InstrumentSlot[0]: InstrumentHeader + T[buffer_size]        │
 //! │ InstrumentSlot[1]: InstrumentHeader + T[buffer_size]        │
@@ -20,27 +21,72 @@
 //!
 //! The `current_index` in each `InstrumentHeader` is atomically incremented on
 //! each write, and readers use it to locate the latest sample.
+//!
+//! A region may be created with spare slots beyond its initial symbols (see
+//! [`ShmMdStore::create_with_spare`]). A free slot has an all-zero symbol;
+//! [`add_symbol`](ShmMdStore::add_symbol) claims one for a symbol subscribed
+//! at runtime and [`remove_symbol`](ShmMdStore::remove_symbol) frees it again.
+//! Readers should therefore look symbols up by name rather than by position.
+//!
+//! Reassigning a slot is guarded by its [`generation`](InstrumentHeader::generation)
+//! counter, a seqlock: it is odd while the symbol is being rewritten and
+//! advances on every claim and release. Readers resolve a slot with
+//! [`InstrumentHeader::read_symbol`] and, after copying a record, check that
+//! the generation is unchanged; otherwise the slot changed hands and the
+//! record may belong to another symbol.
+//!
+//! The header carries the writer's [`SHM_LAYOUT_VERSION`] and the size of
+//! its records, so a reader built against other market data structs can
+//! detect the mismatch (see [`ShmHeader::check`]) instead of misreading them.
 
 use std::{
     collections::HashMap,
-    sync::atomic::{AtomicI64, Ordering},
+    sync::atomic::{AtomicI64, AtomicU32, Ordering, fence},
 };
 
-use crate::types::symbol::{SYMBOL_LEN, symbol_to_bytes};
+use crate::types::symbol::{SYMBOL_LEN, symbol_from_bytes, symbol_to_bytes};
 
 // ---------------------------------------------------------------------------
 // On-disk (mmap) structures
 // ---------------------------------------------------------------------------
 
+/// Layout version of the region and its market data records. Bumped
+/// whenever a header or record layout changes.
+///
+/// Version 2 added `version` and `record_size` to [`ShmHeader`],
+/// [`InstrumentHeader::flags`], and `Trade::cross_seq` and `kernel_rx_us` to
+/// every market data record;
+/// regions written by older builds have no version (they read as 0).
+/// Version 3 added [`InstrumentHeader::generation`].
+pub const SHM_LAYOUT_VERSION: u32 = 3;
+
 /// Global header at the start of the shared memory region.
 #[repr(C)]
 pub struct ShmHeader {
     /// Total number of updates written across all instruments.
     pub update_num: u64,
-    /// Number of instruments in this SHM region.
+    /// Number of instrument slots in this SHM region, free ones included.
     pub instrument_count: u32,
     /// Ring buffer size per instrument (number of `T` slots).
     pub buffer_size: u32,
+    /// [`SHM_LAYOUT_VERSION`] of the writer.
+    pub version: u32,
+    /// Size in bytes of one record (`T`).
+    pub record_size: u32,
+}
+
+impl ShmHeader {
+    /// Check that the region was written with this build's layout for
+    /// records of type `T`.
+    pub fn check<T>(&self) -> anyhow::Result<()> {
+        if self.version != SHM_LAYOUT_VERSION {
+            anyhow::bail!("SHM layout version {} (expected {SHM_LAYOUT_VERSION})", self.version);
+        }
+        if self.record_size as usize != std::mem::size_of::<T>() {
+            anyhow::bail!("SHM record size {} (expected {})", self.record_size, std::mem::size_of::<T>());
+        }
+        Ok(())
+    }
 }
 
 /// Per-instrument header preceding its ring buffer.
@@ -53,10 +99,46 @@ pub struct InstrumentHeader {
     pub current_index: AtomicI64,
     /// Number of `T` slots in this instrument's buffer.
     pub buffer_len: u32,
-    /// Padding for alignment.
-    _pad: u32,
+    /// Status bits (see [`INSTRUMENT_FLAG_STALE`]).
+    pub flags: AtomicU32,
+    /// Seqlock over `symbol`: odd while the slot is being claimed or freed,
+    /// advanced by two on every completed claim or release.
+    pub generation: AtomicU32,
+}
+
+impl InstrumentHeader {
+    /// The slot's symbol and the generation it was read at, or `None` while
+    /// the slot is being reassigned (retry shortly).
+    pub fn read_symbol(&self) -> Option<([u8; SYMBOL_LEN], u32)> {
+        let before = self.generation.load(Ordering::Acquire);
+        if before % 2 == 1 {
+            return None;
+        }
+        // SAFETY: the field is plain bytes; a torn read is detected below.
+        let symbol = unsafe { std::ptr::read_volatile(&self.symbol) };
+        fence(Ordering::Acquire);
+        (self.generation.load(Ordering::Relaxed) == before).then_some((symbol, before))
+    }
+
+    /// Rewrite the symbol of a slot no writer is using, behind the
+    /// generation seqlock.
+    fn reassign(&mut self, symbol: [u8; SYMBOL_LEN]) {
+        let generation = self.generation.load(Ordering::Relaxed);
+        self.generation.store(generation.wrapping_add(1), Ordering::Relaxed);
+        fence(Ordering::Release);
+        // SAFETY: `self` is a valid header; readers detect the concurrent
+        // write through the odd generation.
+        unsafe { std::ptr::write_volatile(&mut self.symbol, symbol) };
+        self.current_index.store(-1, Ordering::Relaxed);
+        self.flags.store(0, Ordering::Relaxed);
+        self.generation.store(generation.wrapping_add(2), Ordering::Release);
+    }
 }
 
+/// [`InstrumentHeader::flags`] bit: the upstream feed for this instrument is
+/// down and the latest sample may be arbitrarily old.
+pub const INSTRUMENT_FLAG_STALE: u32 = 1;
+
 // ---------------------------------------------------------------------------
 // ShmMdStore
 // ---------------------------------------------------------------------------
@@ -76,6 +158,8 @@ pub struct ShmMdStore<T: Copy> {
     buffer_size: u32,
     /// Map from symbol string to (InstrumentHeader ptr, data slice base ptr).
     index: HashMap<String, (*mut InstrumentHeader, *mut T)>,
+    /// Unclaimed slots, claimed from the back.
+    free: Vec<(*mut InstrumentHeader, *mut T)>,
     /// SHM name (for cleanup).
     #[allow(dead_code)]
     shm_name: String,
@@ -100,11 +184,22 @@ impl<T: Copy> ShmMdStore<T> {
     /// - `shm_name`: POSIX shared memory name (e.g. `"spot_bbo"`)
     /// - `symbols`: list of instrument symbols to allocate slots for
     /// - `buffer_size`: number of `T` entries per symbol ring buffer
-    #[cfg(target_os = "linux")]
     pub fn create(shm_name: &str, symbols: &[String], buffer_size: u32) -> anyhow::Result<Self> {
+        Self::create_with_spare(shm_name, symbols, 0, buffer_size)
+    }
+
+    /// Like [`create`](Self::create), with `spare` extra free slots for
+    /// symbols added later with [`add_symbol`](Self::add_symbol).
+    #[cfg(target_os = "linux")]
+    pub fn create_with_spare(
+        shm_name: &str,
+        symbols: &[String],
+        spare: usize,
+        buffer_size: u32,
+    ) -> anyhow::Result<Self> {
         use std::ffi::CString;
 
-        let instrument_count = symbols.len();
+        let instrument_count = symbols.len() + spare;
         let total_size = Self::calc_size(instrument_count, buffer_size);
 
         // Remove stale SHM if it exists
@@ -144,38 +239,20 @@ impl<T: Copy> ShmMdStore<T> {
 
             let base = base as *mut u8;
 
-            // Write global header
-            let header = &mut *(base as *mut ShmHeader);
-            header.update_num = 0;
-            header.instrument_count = instrument_count as u32;
-            header.buffer_size = buffer_size;
-
-            // Initialize instrument slots and build index
-            let mut index = HashMap::new();
-            let mut offset = std::mem::size_of::<ShmHeader>();
-
-            for sym in symbols {
-                let inst_hdr = &mut *(base.add(offset) as *mut InstrumentHeader);
-                inst_hdr.symbol = symbol_to_bytes(sym);
-                inst_hdr.current_index = AtomicI64::new(-1);
-                inst_hdr.buffer_len = buffer_size;
-
-                let data_ptr = base.add(offset + std::mem::size_of::<InstrumentHeader>()) as *mut T;
-
-                index.insert(sym.clone(), (inst_hdr as *mut InstrumentHeader, data_ptr));
-
-                offset += std::mem::size_of::<InstrumentHeader>() + std::mem::size_of::<T>() * buffer_size as usize;
-            }
-
-            Ok(Self { base, total_size, buffer_size, index, shm_name: shm_name.to_string() })
+            Ok(Self::init(base, total_size, symbols, instrument_count, buffer_size, shm_name))
         }
     }
 
     /// Stub for non-Linux platforms (shared memory is Linux-only in production).
     #[cfg(not(target_os = "linux"))]
-    pub fn create(shm_name: &str, symbols: &[String], buffer_size: u32) -> anyhow::Result<Self> {
+    pub fn create_with_spare(
+        shm_name: &str,
+        symbols: &[String],
+        spare: usize,
+        buffer_size: u32,
+    ) -> anyhow::Result<Self> {
         // On macOS/Windows, allocate a heap buffer to allow development/testing.
-        let instrument_count = symbols.len();
+        let instrument_count = symbols.len() + spare;
         let total_size = Self::calc_size(instrument_count, buffer_size);
 
         let layout =
@@ -190,28 +267,137 @@ impl<T: Copy> ShmMdStore<T> {
             ptr
         };
 
+        // SAFETY: `base` is a zeroed allocation of `total_size` bytes.
+        Ok(unsafe { Self::init(base, total_size, symbols, instrument_count, buffer_size, shm_name) })
+    }
+
+    /// Map an existing region created by another process, for reading.
+    ///
+    /// Fails if the region was written with another layout (see
+    /// [`ShmHeader::check`]). Symbols are resolved once, when opened; slots
+    /// claimed or freed later are not seen.
+    #[cfg(target_os = "linux")]
+    pub fn open(shm_name: &str) -> anyhow::Result<Self> {
+        use std::ffi::CString;
+
+        let c_name = CString::new(shm_name)?;
+        // SAFETY: POSIX shm_open + fstat + mmap of an existing region; the
+        // header is validated before any slot is touched.
+        unsafe {
+            let fd = libc::shm_open(c_name.as_ptr(), libc::O_RDWR, 0);
+            if fd < 0 {
+                anyhow::bail!("shm_open {shm_name} failed: {}", std::io::Error::last_os_error());
+            }
+            let mut stat: libc::stat = std::mem::zeroed();
+            if libc::fstat(fd, &mut stat) != 0 {
+                libc::close(fd);
+                anyhow::bail!("fstat {shm_name} failed: {}", std::io::Error::last_os_error());
+            }
+            let total_size = stat.st_size as usize;
+            if total_size < std::mem::size_of::<ShmHeader>() {
+                libc::close(fd);
+                anyhow::bail!("{shm_name}: region of {total_size} bytes has no header");
+            }
+            let base = libc::mmap(
+                std::ptr::null_mut(),
+                total_size,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_SHARED,
+                fd,
+                0,
+            );
+            libc::close(fd);
+            if base == libc::MAP_FAILED {
+                anyhow::bail!("mmap {shm_name} failed");
+            }
+            let base = base as *mut u8;
+            let mut store = Self {
+                base,
+                total_size,
+                buffer_size: 0,
+                index: HashMap::new(),
+                free: Vec::new(),
+                shm_name: shm_name.to_string(),
+            };
+
+            let header = &*(base as *const ShmHeader);
+            header.check::<T>().map_err(|e| anyhow::anyhow!("{shm_name}: {e}"))?;
+            let (slot_count, buffer_size) = (header.instrument_count as usize, header.buffer_size);
+            if buffer_size == 0 || Self::calc_size(slot_count, buffer_size) > total_size {
+                anyhow::bail!("{shm_name}: {slot_count} slots of {buffer_size} do not fit {total_size} bytes");
+            }
+            store.buffer_size = buffer_size;
+
+            let slot_size = std::mem::size_of::<InstrumentHeader>() + std::mem::size_of::<T>() * buffer_size as usize;
+            for i in 0..slot_count {
+                let offset = std::mem::size_of::<ShmHeader>() + slot_size * i;
+                let inst_hdr = base.add(offset) as *mut InstrumentHeader;
+                let data_ptr = base.add(offset + std::mem::size_of::<InstrumentHeader>()) as *mut T;
+                let Some((symbol, _)) = (*inst_hdr).read_symbol() else { continue };
+                let symbol = symbol_from_bytes(&symbol);
+                if !symbol.is_empty() {
+                    store.index.insert(symbol.to_string(), (inst_hdr, data_ptr));
+                }
+            }
+            Ok(store)
+        }
+    }
+
+    /// Stub for non-Linux platforms: regions are process-local heap buffers
+    /// there, so there is nothing to open.
+    #[cfg(not(target_os = "linux"))]
+    pub fn open(shm_name: &str) -> anyhow::Result<Self> {
+        anyhow::bail!("{shm_name}: opening shared memory needs Linux")
+    }
+
+    /// Write the global header and `slot_count` instrument headers into the
+    /// zeroed region at `base`, assigning `symbols` to the first slots and
+    /// leaving the rest free.
+    ///
+    /// # Safety
+    /// `base` must point to `total_size` zeroed, writable bytes laid out for
+    /// `slot_count` slots of `buffer_size` entries.
+    unsafe fn init(
+        base: *mut u8,
+        total_size: usize,
+        symbols: &[String],
+        slot_count: usize,
+        buffer_size: u32,
+        shm_name: &str,
+    ) -> Self {
         unsafe {
             let header = &mut *(base as *mut ShmHeader);
             header.update_num = 0;
-            header.instrument_count = instrument_count as u32;
+            header.instrument_count = slot_count as u32;
             header.buffer_size = buffer_size;
+            header.version = SHM_LAYOUT_VERSION;
+            header.record_size = std::mem::size_of::<T>() as u32;
 
             let mut index = HashMap::new();
+            let mut free = Vec::new();
             let mut offset = std::mem::size_of::<ShmHeader>();
 
-            for sym in symbols {
+            for i in 0..slot_count {
                 let inst_hdr = &mut *(base.add(offset) as *mut InstrumentHeader);
-                inst_hdr.symbol = symbol_to_bytes(sym);
                 inst_hdr.current_index = AtomicI64::new(-1);
                 inst_hdr.buffer_len = buffer_size;
 
                 let data_ptr = base.add(offset + std::mem::size_of::<InstrumentHeader>()) as *mut T;
-                index.insert(sym.clone(), (inst_hdr as *mut InstrumentHeader, data_ptr));
+                let slot = (inst_hdr as *mut InstrumentHeader, data_ptr);
+                match symbols.get(i) {
+                    Some(sym) => {
+                        inst_hdr.symbol = symbol_to_bytes(sym);
+                        index.insert(sym.clone(), slot);
+                    }
+                    None => free.push(slot),
+                }
 
                 offset += std::mem::size_of::<InstrumentHeader>() + std::mem::size_of::<T>() * buffer_size as usize;
             }
+            // Claim the lowest free slot first.
+            free.reverse();
 
-            Ok(Self { base, total_size, buffer_size, index, shm_name: shm_name.to_string() })
+            Self { base, total_size, buffer_size, index, free, shm_name: shm_name.to_string() }
         }
     }
 
@@ -256,6 +442,59 @@ impl<T: Copy> ShmMdStore<T> {
         }
     }
 
+    /// Mark every instrument in this store as stale (or clear the flag).
+    ///
+    /// Used when the upstream feed is known to be down, so readers can
+    /// distinguish "no recent update" from "no updates are arriving".
+    pub fn set_stale(&self, stale: bool) {
+        for &(hdr, _) in self.index.values() {
+            // SAFETY: header pointers stay valid for the lifetime of the mapping.
+            let flags = unsafe { &(*hdr).flags };
+            if stale {
+                flags.fetch_or(INSTRUMENT_FLAG_STALE, Ordering::Release);
+            } else {
+                flags.fetch_and(!INSTRUMENT_FLAG_STALE, Ordering::Release);
+            }
+        }
+    }
+
+    /// Returns `true` if the symbol is currently flagged stale.
+    pub fn is_stale(&self, symbol: &str) -> bool {
+        self.index.get(symbol).is_some_and(|&(hdr, _)| {
+            // SAFETY: header pointers stay valid for the lifetime of the mapping.
+            unsafe { (*hdr).flags.load(Ordering::Acquire) & INSTRUMENT_FLAG_STALE != 0 }
+        })
+    }
+
+    /// Claim a free slot for `symbol`. Returns `Ok(false)` if the symbol
+    /// already has one, and an error if no slot is free.
+    pub fn add_symbol(&mut self, symbol: &str) -> anyhow::Result<bool> {
+        if self.index.contains_key(symbol) {
+            return Ok(false);
+        }
+        let slot = self.free.pop().ok_or_else(|| anyhow::anyhow!("{}: no free slot for {symbol}", self.shm_name))?;
+        // SAFETY: header pointers stay valid for the lifetime of the mapping,
+        // and a free slot has no writer.
+        unsafe { (*slot.0).reassign(symbol_to_bytes(symbol)) };
+        self.index.insert(symbol.to_string(), slot);
+        Ok(true)
+    }
+
+    /// Free the slot of `symbol` for reuse. Returns `false` if the symbol
+    /// has no slot.
+    pub fn remove_symbol(&mut self, symbol: &str) -> bool {
+        let Some(slot) = self.index.remove(symbol) else { return false };
+        // SAFETY: header pointers stay valid for the lifetime of the mapping.
+        unsafe { (*slot.0).reassign([0; SYMBOL_LEN]) };
+        self.free.push(slot);
+        true
+    }
+
+    /// Number of free slots left for [`add_symbol`](Self::add_symbol).
+    pub fn free_slots(&self) -> usize {
+        self.free.len()
+    }
+
     /// Returns the list of symbols in this store.
     pub fn symbols(&self) -> Vec<String> {
         self.index.keys().cloned().collect()
@@ -329,4 +568,91 @@ mod tests {
         // Latest should be the last written value
         assert_eq!(store.read_latest("BTCUSDT"), Some(9));
     }
+
+    #[test]
+    fn header_records_layout() {
+        let store = ShmMdStore::<[u64; 3]>::create("test_shm_layout", &["BTCUSDT".to_string()], 4).unwrap();
+        // SAFETY: the region starts with the header.
+        let header = unsafe { &*(store.base as *const ShmHeader) };
+        assert_eq!((header.version, header.record_size), (SHM_LAYOUT_VERSION, 24));
+        header.check::<[u64; 3]>().unwrap();
+        assert!(header.check::<u64>().is_err());
+    }
+
+    #[test]
+    fn spare_slots_are_claimed_and_freed() {
+        let symbols = vec!["BTCUSDT".to_string()];
+        let mut store = ShmMdStore::<u64>::create_with_spare("test_shm_spare", &symbols, 1, 4).unwrap();
+        assert_eq!(store.free_slots(), 1);
+        assert!(!store.write("ETHUSDT", &1));
+
+        assert!(store.add_symbol("ETHUSDT").unwrap());
+        assert!(!store.add_symbol("ETHUSDT").unwrap());
+        assert!(store.add_symbol("SOLUSDT").is_err());
+        assert!(store.write("ETHUSDT", &7));
+        assert_eq!(store.read_latest("ETHUSDT"), Some(7));
+
+        // A freed slot is reused from scratch.
+        assert!(store.remove_symbol("ETHUSDT"));
+        assert!(!store.remove_symbol("ETHUSDT"));
+        assert!(!store.write("ETHUSDT", &8));
+        assert!(store.add_symbol("SOLUSDT").unwrap());
+        assert!(store.read_latest("SOLUSDT").is_none());
+        assert_eq!(store.read_latest("BTCUSDT"), None);
+    }
+
+    #[test]
+    fn slot_reassignment_advances_generation() {
+        let mut store = ShmMdStore::<u64>::create_with_spare("test_shm_generation", &[], 1, 4).unwrap();
+        let (hdr, _) = *store.free.last().unwrap();
+        // SAFETY: the header lives as long as the store.
+        let hdr = unsafe { &*hdr };
+        assert_eq!(hdr.read_symbol(), Some(([0; SYMBOL_LEN], 0)));
+
+        store.add_symbol("ETHUSDT").unwrap();
+        assert_eq!(hdr.read_symbol(), Some((symbol_to_bytes("ETHUSDT"), 2)));
+        store.write("ETHUSDT", &7);
+        store.remove_symbol("ETHUSDT");
+        store.add_symbol("SOLUSDT").unwrap();
+        // A reader that resolved ETHUSDT at generation 2 sees the change.
+        assert_eq!(hdr.read_symbol(), Some((symbol_to_bytes("SOLUSDT"), 6)));
+        assert_eq!(hdr.current_index.load(Ordering::Acquire), -1);
+
+        // Mid-rewrite the symbol is not readable.
+        hdr.generation.fetch_add(1, Ordering::Relaxed);
+        assert_eq!(hdr.read_symbol(), None);
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn open_maps_a_written_region() {
+        let symbols = vec!["BTCUSDT".to_string()];
+        let mut writer = ShmMdStore::<u64>::create_with_spare("test_shm_open", &symbols, 1, 4).unwrap();
+        writer.add_symbol("ETHUSDT").unwrap();
+        writer.write("ETHUSDT", &7);
+
+        let reader = ShmMdStore::<u64>::open("test_shm_open").unwrap();
+        let mut seen = reader.symbols();
+        seen.sort();
+        assert_eq!(seen, ["BTCUSDT", "ETHUSDT"]);
+        assert_eq!(reader.read_latest("ETHUSDT"), Some(7));
+        assert!(reader.read_latest("BTCUSDT").is_none());
+
+        // Records of another size are refused.
+        assert!(ShmMdStore::<u32>::open("test_shm_open").is_err());
+        assert!(ShmMdStore::<u64>::open("test_shm_missing").is_err());
+    }
+
+    #[test]
+    fn stale_flag() {
+        let symbols = vec!["BTCUSDT".to_string(), "ETHUSDT".to_string()];
+        let store = ShmMdStore::<u64>::create("test_shm_stale", &symbols, 4).unwrap();
+        assert!(!store.is_stale("BTCUSDT"));
+        store.set_stale(true);
+        assert!(store.is_stale("BTCUSDT"));
+        assert!(store.is_stale("ETHUSDT"));
+        store.set_stale(false);
+        assert!(!store.is_stale("BTCUSDT"));
+        assert!(!store.is_stale("UNKNOWN"));
+    }
 }
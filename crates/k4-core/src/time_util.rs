@@ -3,8 +3,6 @@
 //! Provides microsecond- and nanosecond-resolution timestamps using
 //! `clock_gettime(CLOCK_REALTIME)` on Unix and `SystemTime` as fallback.
 
-use std::time::{SystemTime, UNIX_EPOCH};
-
 // ---------------------------------------------------------------------------
 // Linux: use clock_gettime for maximum precision
 // ---------------------------------------------------------------------------
@@ -38,6 +36,7 @@ fn clock_monotonic() -> (u64, u64) {
 #[cfg(not(target_os = "linux"))]
 #[inline]
 fn clock_realtime() -> (u64, u64) {
+    use std::time::{SystemTime, UNIX_EPOCH};
     let d = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
     (d.as_secs(), d.subsec_nanos() as u64)
 }
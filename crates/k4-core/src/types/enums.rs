@@ -14,13 +14,45 @@ use serde::{Deserialize, Serialize};
 // ---------------------------------------------------------------------------
 
 /// Supported cryptocurrency exchanges.
+///
+/// The discriminant is carried in the UDP packet header; `0` is reserved for
+/// "unknown".
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "lowercase")]
+#[repr(u8)]
 pub enum Exchange {
-    Binance,
-    Okx,
-    Bitget,
-    Bybit,
+    Binance = 1,
+    Okx = 2,
+    Bitget = 3,
+    Bybit = 4,
+}
+
+impl Exchange {
+    /// Decode a wire discriminant, `None` for `0` or unknown values.
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            1 => Some(Self::Binance),
+            2 => Some(Self::Okx),
+            3 => Some(Self::Bitget),
+            4 => Some(Self::Bybit),
+            _ => None,
+        }
+    }
+}
+
+impl std::str::FromStr for Exchange {
+    type Err = anyhow::Error;
+
+    /// Parse a config-style exchange name (case-insensitive).
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "binance" => Ok(Self::Binance),
+            "okx" => Ok(Self::Okx),
+            "bitget" => Ok(Self::Bitget),
+            "bybit" => Ok(Self::Bybit),
+            other => Err(anyhow::anyhow!("unknown exchange: {other}")),
+        }
+    }
 }
 
 impl std::fmt::Display for Exchange {
@@ -57,6 +89,23 @@ pub enum ProductType {
     BtcMargin = 7,
 }
 
+impl ProductType {
+    /// Decode a discriminant, `None` for unknown values.
+    pub fn from_u8(v: u8) -> Option<Self> {
+        match v {
+            0 => Some(Self::Spot),
+            1 => Some(Self::Futures),
+            2 => Some(Self::UMargin),
+            3 => Some(Self::CoinMargin),
+            4 => Some(Self::Options),
+            5 => Some(Self::UsdtFutures),
+            6 => Some(Self::UsdcFutures),
+            7 => Some(Self::BtcMargin),
+            _ => None,
+        }
+    }
+}
+
 // ---------------------------------------------------------------------------
 // Message types
 // ---------------------------------------------------------------------------
@@ -78,6 +127,7 @@ pub enum MessageType {
     TradeUpdate = 5,
     QueryOrderResponse = 6,
     QueryInternalResponse = 7,
+    DepthN = 8,
     DataUnknown = 100,
     Heartbeat = 101,
 }
@@ -9,9 +9,20 @@
 //! All timestamps are in **microseconds since Unix epoch** (us), matching the
 //! C++ convention of `E * 1000` (exchange sends milliseconds, we multiply by 1000).
 
+use std::{
+    fmt,
+    mem::ManuallyDrop,
+    ops::{Deref, DerefMut},
+    sync::LazyLock,
+};
+
+use crossbeam_queue::ArrayQueue;
 use rkyv::{Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
 
-use super::{enums::ProductType, symbol::SYMBOL_LEN};
+use super::{
+    enums::{MessageType, ProductType},
+    symbol::SYMBOL_LEN,
+};
 
 // ---------------------------------------------------------------------------
 // Bookticker (Best Bid / Offer)
@@ -33,6 +44,8 @@ pub struct Bookticker {
     pub bid_order_count: i32,
     pub ask_order_count: i32,
     pub local_time_us: u64,
+    /// Kernel receive time (µs); see [`MarketDataMsg::set_kernel_rx_us`].
+    pub kernel_rx_us: u64,
 }
 
 // ---------------------------------------------------------------------------
@@ -51,7 +64,14 @@ pub struct Trade {
     pub price: f64,
     pub vol: f64,
     pub is_buyer_maker: bool,
+    /// Recovered over REST after a sequence gap rather than received live.
+    pub backfilled: bool,
+    /// Matching-engine cross sequence, comparable with the `update_id` of
+    /// the venue's book updates (Bybit `seq`); 0 where the venue has none.
+    pub cross_seq: u64,
     pub local_time_us: u64,
+    /// Kernel receive time (µs); see [`MarketDataMsg::set_kernel_rx_us`].
+    pub kernel_rx_us: u64,
 }
 
 // ---------------------------------------------------------------------------
@@ -76,7 +96,11 @@ pub struct AggTrade {
     pub vol: f64,
     pub trade_count: i32,
     pub is_buyer_maker: bool,
+    /// Recovered over REST after a sequence gap rather than received live.
+    pub backfilled: bool,
     pub local_time_us: u64,
+    /// Kernel receive time (µs); see [`MarketDataMsg::set_kernel_rx_us`].
+    pub kernel_rx_us: u64,
 }
 
 // ---------------------------------------------------------------------------
@@ -105,6 +129,108 @@ pub struct Depth5 {
     pub bid_order_counts: [i32; 5],
     pub ask_order_counts: [i32; 5],
     pub local_time_us: u64,
+    /// Kernel receive time (µs); see [`MarketDataMsg::set_kernel_rx_us`].
+    pub kernel_rx_us: u64,
+}
+
+// ---------------------------------------------------------------------------
+// DepthN (deeper order book snapshot)
+// ---------------------------------------------------------------------------
+
+/// Most levels per side a [`DepthN`] holds.
+pub const MAX_DEPTH_LEVELS: usize = 50;
+
+/// Order book snapshot of up to [`MAX_DEPTH_LEVELS`] levels per side.
+///
+/// Laid out like [`Depth5`]; only the first `bid_level` / `ask_level`
+/// entries are set, the rest are zero. How many levels a stream fills is
+/// configured per product (`depth_levels`).
+#[derive(Debug, Clone, Copy, PartialEq, Archive, RkyvSerialize, RkyvDeserialize)]
+#[repr(C)]
+pub struct DepthN {
+    pub symbol: [u8; SYMBOL_LEN],
+    pub product_type: ProductType,
+    pub event_timestamp_us: u64,
+    pub trade_timestamp_us: u64,
+    pub update_id: u64,
+    pub bid_level: u32,
+    pub ask_level: u32,
+    pub last_price: f64,
+    pub bid_prices: [f64; MAX_DEPTH_LEVELS],
+    pub bid_vols: [f64; MAX_DEPTH_LEVELS],
+    pub ask_prices: [f64; MAX_DEPTH_LEVELS],
+    pub ask_vols: [f64; MAX_DEPTH_LEVELS],
+    pub bid_order_counts: [i32; MAX_DEPTH_LEVELS],
+    pub ask_order_counts: [i32; MAX_DEPTH_LEVELS],
+    pub local_time_us: u64,
+    /// Kernel receive time (µs); see [`MarketDataMsg::set_kernel_rx_us`].
+    pub kernel_rx_us: u64,
+}
+
+/// Most boxes [`DepthNBox`] keeps for reuse (about 2.5 KB each).
+const DEPTH_N_POOL_SIZE: usize = 1024;
+
+static DEPTH_N_POOL: LazyLock<ArrayQueue<Box<DepthN>>> = LazyLock::new(|| ArrayQueue::new(DEPTH_N_POOL_SIZE));
+
+/// A heap-allocated [`DepthN`] whose allocation is recycled.
+///
+/// Dropping it returns the box to a process-wide pool, which
+/// [`new`](Self::new) takes from, so book updates passed from the parsers to
+/// the dedup worker and UDP sender do not allocate once the pool is warm.
+pub struct DepthNBox(ManuallyDrop<Box<DepthN>>);
+
+impl DepthNBox {
+    /// Box `depth`, reusing a pooled allocation if there is one.
+    pub fn new(depth: DepthN) -> Self {
+        match DEPTH_N_POOL.pop() {
+            Some(mut b) => {
+                *b = depth;
+                Self(ManuallyDrop::new(b))
+            }
+            None => Self(ManuallyDrop::new(Box::new(depth))),
+        }
+    }
+}
+
+impl Drop for DepthNBox {
+    fn drop(&mut self) {
+        // SAFETY: the box is not used after this.
+        let b = unsafe { ManuallyDrop::take(&mut self.0) };
+        // A full pool frees the box.
+        let _ = DEPTH_N_POOL.push(b);
+    }
+}
+
+impl Deref for DepthNBox {
+    type Target = DepthN;
+
+    fn deref(&self) -> &DepthN {
+        &self.0
+    }
+}
+
+impl DerefMut for DepthNBox {
+    fn deref_mut(&mut self) -> &mut DepthN {
+        &mut self.0
+    }
+}
+
+impl Default for DepthNBox {
+    fn default() -> Self {
+        Self::new(DepthN::default())
+    }
+}
+
+impl Clone for DepthNBox {
+    fn clone(&self) -> Self {
+        Self::new(**self)
+    }
+}
+
+impl fmt::Debug for DepthNBox {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        (**self).fmt(f)
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -118,6 +244,73 @@ pub enum MarketDataMsg {
     Trade(Trade),
     AggTrade(AggTrade),
     Depth5(Depth5),
+    /// Boxed, as it is about ten times the size of the other variants.
+    DepthN(DepthNBox),
+}
+
+impl MarketDataMsg {
+    /// Local receive timestamp (µs) of the wrapped message.
+    #[inline]
+    pub fn local_time_us(&self) -> u64 {
+        match self {
+            Self::Bbo(d) => d.local_time_us,
+            Self::Trade(d) => d.local_time_us,
+            Self::AggTrade(d) => d.local_time_us,
+            Self::Depth5(d) => d.local_time_us,
+            Self::DepthN(d) => d.local_time_us,
+        }
+    }
+
+    /// Record the kernel receive time (µs) of the UDP packet that carried the
+    /// wrapped message (`SO_TIMESTAMPNS`). Only the UDP receiver sets it;
+    /// it stays 0 for records taken from the exchange directly or when the
+    /// kernel gave no timestamp.
+    #[inline]
+    pub fn set_kernel_rx_us(&mut self, kernel_rx_us: u64) {
+        match self {
+            Self::Bbo(d) => d.kernel_rx_us = kernel_rx_us,
+            Self::Trade(d) => d.kernel_rx_us = kernel_rx_us,
+            Self::AggTrade(d) => d.kernel_rx_us = kernel_rx_us,
+            Self::Depth5(d) => d.kernel_rx_us = kernel_rx_us,
+            Self::DepthN(d) => d.kernel_rx_us = kernel_rx_us,
+        }
+    }
+
+    /// Product type of the wrapped message.
+    #[inline]
+    pub fn product_type(&self) -> ProductType {
+        match self {
+            Self::Bbo(d) => d.product_type,
+            Self::Trade(d) => d.product_type,
+            Self::AggTrade(d) => d.product_type,
+            Self::Depth5(d) => d.product_type,
+            Self::DepthN(d) => d.product_type,
+        }
+    }
+
+    /// Raw symbol bytes of the wrapped message.
+    #[inline]
+    pub fn symbol(&self) -> &[u8; SYMBOL_LEN] {
+        match self {
+            Self::Bbo(d) => &d.symbol,
+            Self::Trade(d) => &d.symbol,
+            Self::AggTrade(d) => &d.symbol,
+            Self::Depth5(d) => &d.symbol,
+            Self::DepthN(d) => &d.symbol,
+        }
+    }
+
+    /// The [`MessageType`] corresponding to this variant.
+    #[inline]
+    pub fn msg_type(&self) -> MessageType {
+        match self {
+            Self::Bbo(_) => MessageType::BookTicker,
+            Self::Trade(_) => MessageType::Trade,
+            Self::AggTrade(_) => MessageType::AggTrade,
+            Self::Depth5(_) => MessageType::Depth5,
+            Self::DepthN(_) => MessageType::DepthN,
+        }
+    }
 }
 
 // ---------------------------------------------------------------------------
@@ -139,6 +332,7 @@ impl Default for Bookticker {
             bid_order_count: 0,
             ask_order_count: 0,
             local_time_us: 0,
+            kernel_rx_us: 0,
         }
     }
 }
@@ -154,7 +348,10 @@ impl Default for Trade {
             price: 0.0,
             vol: 0.0,
             is_buyer_maker: false,
+            backfilled: false,
+            cross_seq: 0,
             local_time_us: 0,
+            kernel_rx_us: 0,
         }
     }
 }
@@ -173,7 +370,9 @@ impl Default for AggTrade {
             vol: 0.0,
             trade_count: 0,
             is_buyer_maker: false,
+            backfilled: false,
             local_time_us: 0,
+            kernel_rx_us: 0,
         }
     }
 }
@@ -196,6 +395,30 @@ impl Default for Depth5 {
             bid_order_counts: [0; 5],
             ask_order_counts: [0; 5],
             local_time_us: 0,
+            kernel_rx_us: 0,
+        }
+    }
+}
+
+impl Default for DepthN {
+    fn default() -> Self {
+        Self {
+            symbol: [0; SYMBOL_LEN],
+            product_type: ProductType::default(),
+            event_timestamp_us: 0,
+            trade_timestamp_us: 0,
+            update_id: 0,
+            bid_level: 0,
+            ask_level: 0,
+            last_price: 0.0,
+            bid_prices: [0.0; MAX_DEPTH_LEVELS],
+            bid_vols: [0.0; MAX_DEPTH_LEVELS],
+            ask_prices: [0.0; MAX_DEPTH_LEVELS],
+            ask_vols: [0.0; MAX_DEPTH_LEVELS],
+            bid_order_counts: [0; MAX_DEPTH_LEVELS],
+            ask_order_counts: [0; MAX_DEPTH_LEVELS],
+            local_time_us: 0,
+            kernel_rx_us: 0,
         }
     }
 }
@@ -241,3 +464,14 @@ impl std::fmt::Display for Depth5 {
         )
     }
 }
+
+impl std::fmt::Display for DepthN {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let sym = super::symbol::symbol_from_bytes(&self.symbol);
+        write!(
+            f,
+            "DepthN({sym} {}x{} bid[0]={:.8} ask[0]={:.8} uid={})",
+            self.bid_level, self.ask_level, self.bid_prices[0], self.ask_prices[0], self.update_id
+        )
+    }
+}
@@ -0,0 +1,286 @@
+//! Receiver-side sequence tracking and gap filling.
+//!
+//! Each `(peer, channel)` stream carries its own sequence numbers. While the
+//! stream is contiguous, packets are delivered immediately. When a gap shows
+//! up, later packets are parked and a [`ReplayRequest`] is issued for the
+//! missing range; the parked packets are released in order once the replay
+//! arrives or the give-up deadline passes, whichever is first. Further gaps
+//! that open among the parked packets meanwhile are requested one after the
+//! other as each recovery finishes.
+
+use std::{
+    collections::BTreeMap,
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use ahash::AHashMap;
+
+/// Upper bound on parked packets per stream before a recovery is abandoned.
+const MAX_PENDING: usize = 65_536;
+
+/// Outcome of [`GapFiller::on_packet`].
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum Verdict {
+    /// In order — deliver the packet now.
+    Deliver,
+    /// Parked behind a gap; will be released by a later flush.
+    Parked,
+    /// Already delivered (or given up on) — discard.
+    Stale,
+}
+
+/// A range of sequence numbers to fetch from the sender's replay service.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct ReplayRequest {
+    pub peer: SocketAddr,
+    pub channel: u8,
+    pub from: u64,
+    pub to: u64,
+}
+
+/// Summary of a completed (or abandoned) recovery, for logging.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct GapReport {
+    pub peer: SocketAddr,
+    pub channel: u8,
+    pub from: u64,
+    pub to: u64,
+    /// Sequence numbers in `from..=to` that were never received.
+    pub lost: u64,
+    /// The next gap among the still-parked packets, now outstanding.
+    pub next: Option<ReplayRequest>,
+}
+
+#[derive(Default)]
+struct StreamState {
+    /// Next sequence number to deliver (0 = stream not seen yet).
+    expected: u64,
+    /// Packets received ahead of `expected`, keyed by sequence number.
+    pending: BTreeMap<u64, Vec<u8>>,
+    /// Outstanding recovery: requested range and give-up deadline.
+    recovery: Option<(u64, u64, Instant)>,
+}
+
+/// Per-stream sequence tracker. Owned by the receive loop.
+pub(crate) struct GapFiller {
+    timeout: Duration,
+    streams: AHashMap<(SocketAddr, u8), StreamState>,
+}
+
+impl GapFiller {
+    /// Create a tracker that abandons a recovery after `timeout`.
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout, streams: AHashMap::new() }
+    }
+
+    /// Classify an incoming packet. If it opens a new gap, returns the
+    /// replay request to issue.
+    pub fn on_packet(
+        &mut self,
+        peer: SocketAddr,
+        channel: u8,
+        seq: u64,
+        packet: &[u8],
+        now: Instant,
+    ) -> (Verdict, Option<ReplayRequest>) {
+        let st = self.streams.entry((peer, channel)).or_default();
+
+        // Sequences restart at 1 when the sender restarts.
+        if seq == 1 && st.expected > 1 {
+            *st = StreamState::default();
+        }
+        if st.expected == 0 || (seq == st.expected && st.recovery.is_none()) {
+            st.expected = seq + 1;
+            return (Verdict::Deliver, None);
+        }
+        if seq < st.expected {
+            return (Verdict::Stale, None);
+        }
+
+        st.pending.entry(seq).or_insert_with(|| packet.to_vec());
+        if st.recovery.is_some() {
+            return (Verdict::Parked, None);
+        }
+
+        let (from, to) = (st.expected, seq - 1);
+        st.recovery = Some((from, to, now + self.timeout));
+        (Verdict::Parked, Some(ReplayRequest { peer, channel, from, to }))
+    }
+
+    /// Merge the packets replayed for `req` and release everything that can
+    /// now be delivered, in order. Anything still missing in the range is
+    /// given up on.
+    ///
+    /// Ignored if `req` is no longer the stream's outstanding recovery (e.g.
+    /// it already timed out).
+    pub fn on_replay(
+        &mut self,
+        req: ReplayRequest,
+        packets: Vec<(u64, Vec<u8>)>,
+        now: Instant,
+        out: &mut Vec<Vec<u8>>,
+    ) -> Option<GapReport> {
+        let ReplayRequest { peer, channel, from, to } = req;
+        let st = self.streams.get_mut(&(peer, channel))?;
+        if !matches!(st.recovery, Some((f, t, _)) if (f, t) == (from, to)) {
+            return None;
+        }
+        for (seq, p) in packets {
+            if seq >= st.expected {
+                st.pending.entry(seq).or_insert(p);
+            }
+        }
+        Some(finish(peer, channel, st, now + self.timeout, out))
+    }
+
+    /// Abandon recoveries whose deadline has passed (or whose parked queue
+    /// grew too large), releasing their parked packets.
+    pub fn check_timeouts(&mut self, now: Instant, out: &mut Vec<Vec<u8>>) -> Vec<GapReport> {
+        let mut reports = Vec::new();
+        for (&(peer, channel), st) in &mut self.streams {
+            if let Some((_, _, deadline)) = st.recovery
+                && (now >= deadline || st.pending.len() >= MAX_PENDING)
+            {
+                reports.push(finish(peer, channel, st, now + self.timeout, out));
+            }
+        }
+        reports
+    }
+
+    /// Whether `(peer, channel)` is currently waiting on a replay.
+    #[cfg(test)]
+    fn recovering(&self, peer: SocketAddr, channel: u8) -> bool {
+        self.streams.get(&(peer, channel)).is_some_and(|s| s.recovery.is_some())
+    }
+}
+
+/// End the stream's recovery: deliver parked packets in sequence order,
+/// skipping and counting the holes left in the requested range. Delivery
+/// stops at the first hole past the range, which becomes the next recovery
+/// (with give-up deadline `deadline`).
+fn finish(peer: SocketAddr, channel: u8, st: &mut StreamState, deadline: Instant, out: &mut Vec<Vec<u8>>) -> GapReport {
+    let (from, to, _) = st.recovery.take().expect("finish called without recovery");
+    let mut lost = 0;
+    while let Some(entry) = st.pending.first_entry() {
+        let seq = *entry.key();
+        if seq < st.expected {
+            entry.remove();
+        } else if seq == st.expected {
+            st.expected = seq + 1;
+            out.push(entry.remove());
+        } else if st.expected <= to {
+            let resume = seq.min(to + 1);
+            lost += resume - st.expected;
+            st.expected = resume;
+        } else {
+            break;
+        }
+    }
+    let next = st.pending.first_key_value().map(|(&seq, _)| {
+        st.recovery = Some((st.expected, seq - 1, deadline));
+        ReplayRequest { peer, channel, from: st.expected, to: seq - 1 }
+    });
+    GapReport { peer, channel, from, to, lost, next }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn peer() -> SocketAddr {
+        "127.0.0.1:9000".parse().unwrap()
+    }
+
+    #[test]
+    fn in_order_and_duplicates() {
+        let mut g = GapFiller::new(Duration::from_millis(100));
+        let now = Instant::now();
+        assert_eq!(g.on_packet(peer(), 0, 5, b"5", now), (Verdict::Deliver, None));
+        assert_eq!(g.on_packet(peer(), 0, 6, b"6", now), (Verdict::Deliver, None));
+        assert_eq!(g.on_packet(peer(), 0, 6, b"6", now), (Verdict::Stale, None));
+        // Channels are independent.
+        assert_eq!(g.on_packet(peer(), 1, 1, b"1", now), (Verdict::Deliver, None));
+        // Sender restart.
+        assert_eq!(g.on_packet(peer(), 0, 1, b"1", now), (Verdict::Deliver, None));
+        assert_eq!(g.on_packet(peer(), 0, 2, b"2", now), (Verdict::Deliver, None));
+    }
+
+    #[test]
+    fn gap_filled_by_replay() {
+        let mut g = GapFiller::new(Duration::from_millis(100));
+        let now = Instant::now();
+        g.on_packet(peer(), 0, 1, b"1", now);
+
+        let (v, req) = g.on_packet(peer(), 0, 4, b"4", now);
+        assert_eq!(v, Verdict::Parked);
+        assert_eq!(req, Some(ReplayRequest { peer: peer(), channel: 0, from: 2, to: 3 }));
+        // Further packets are parked without a second request.
+        assert_eq!(g.on_packet(peer(), 0, 5, b"5", now), (Verdict::Parked, None));
+        assert!(g.recovering(peer(), 0));
+
+        let mut out = Vec::new();
+        let report = g.on_replay(req.unwrap(), vec![(2, b"2".to_vec()), (3, b"3".to_vec())], now, &mut out).unwrap();
+        assert_eq!(report.lost, 0);
+        assert_eq!(out, vec![b"2".to_vec(), b"3".to_vec(), b"4".to_vec(), b"5".to_vec()]);
+        assert!(!g.recovering(peer(), 0));
+        assert_eq!(g.on_packet(peer(), 0, 6, b"6", now), (Verdict::Deliver, None));
+    }
+
+    #[test]
+    fn partial_replay_counts_lost() {
+        let mut g = GapFiller::new(Duration::from_millis(100));
+        let now = Instant::now();
+        g.on_packet(peer(), 2, 1, b"1", now);
+        let (_, req) = g.on_packet(peer(), 2, 5, b"5", now);
+
+        let mut out = Vec::new();
+        let report = g.on_replay(req.unwrap(), vec![(3, b"3".to_vec())], now, &mut out).unwrap();
+        assert_eq!(report.lost, 2);
+        assert_eq!(out, vec![b"3".to_vec(), b"5".to_vec()]);
+    }
+
+    #[test]
+    fn give_up_after_timeout() {
+        let mut g = GapFiller::new(Duration::from_millis(100));
+        let t0 = Instant::now();
+        g.on_packet(peer(), 0, 1, b"1", t0);
+        let (_, req) = g.on_packet(peer(), 0, 3, b"3", t0);
+
+        let mut out = Vec::new();
+        assert!(g.check_timeouts(t0 + Duration::from_millis(50), &mut out).is_empty());
+        let reports = g.check_timeouts(t0 + Duration::from_millis(100), &mut out);
+        assert_eq!(reports.len(), 1);
+        assert_eq!(reports[0].lost, 1);
+        assert_eq!(out, vec![b"3".to_vec()]);
+
+        // A late replay after giving up is ignored.
+        assert!(g.on_replay(req.unwrap(), vec![(2, b"2".to_vec())], t0, &mut out).is_none());
+        assert_eq!(g.on_packet(peer(), 0, 2, b"2", t0), (Verdict::Stale, None));
+    }
+
+    #[test]
+    fn gap_opened_during_recovery_is_requested_next() {
+        let mut g = GapFiller::new(Duration::from_millis(100));
+        let now = Instant::now();
+        g.on_packet(peer(), 0, 1, b"1", now);
+        let (_, first) = g.on_packet(peer(), 0, 4, b"4", now);
+        // 5 and 6 are missed while 2..=3 is being recovered.
+        assert_eq!(g.on_packet(peer(), 0, 7, b"7", now), (Verdict::Parked, None));
+
+        let mut out = Vec::new();
+        let report = g.on_replay(first.unwrap(), vec![(2, b"2".to_vec())], now, &mut out).unwrap();
+        // Only the hole in the requested range counts as lost.
+        assert_eq!((report.from, report.to, report.lost), (2, 3, 1));
+        assert_eq!(out, vec![b"2".to_vec(), b"4".to_vec()]);
+        let second = report.next.unwrap();
+        assert_eq!((second.from, second.to), (5, 6));
+        assert!(g.recovering(peer(), 0));
+
+        out.clear();
+        let report = g.on_replay(second, vec![(5, b"5".to_vec()), (6, b"6".to_vec())], now, &mut out).unwrap();
+        assert_eq!((report.lost, report.next), (0, None));
+        assert_eq!(out, vec![b"5".to_vec(), b"6".to_vec(), b"7".to_vec()]);
+        assert_eq!(g.on_packet(peer(), 0, 8, b"8", now), (Verdict::Deliver, None));
+    }
+}
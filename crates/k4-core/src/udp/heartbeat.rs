@@ -0,0 +1,242 @@
+//! UDP heartbeat messages and per-source liveness tracking.
+//!
+//! A [`UdpSender`](super::UdpSender) periodically emits a [`UdpHeartbeat`]
+//! (`MessageType::Heartbeat`) carrying its source id, uptime, and per-channel
+//! message counters. Receivers feed every heartbeat into a [`SourceTracker`],
+//! which reports a [`FeedState::Down`] transition once a source has been silent
+//! for longer than the configured timeout — letting consumers distinguish a
+//! quiet market from a dead gateway.
+
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use ahash::AHashMap;
+use rkyv::{Archive, Deserialize as RkyvDeserialize, Serialize as RkyvSerialize};
+
+use crate::types::{Exchange, MarketDataMsg, MessageType};
+
+/// Number of per-channel counters carried in a heartbeat.
+///
+/// One channel per market data [`MessageType`]: `BookTicker`, `Trade`,
+/// `AggTrade`, `Depth5`, `DepthN` (see [`channel_of`]).
+pub const HEARTBEAT_CHANNELS: usize = 5;
+
+/// Periodic liveness message emitted by a UDP sender.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Archive, RkyvSerialize, RkyvDeserialize)]
+pub struct UdpHeartbeat {
+    /// Sender-assigned source identifier (unique per receiver).
+    pub source_id: u32,
+    /// Heartbeat interval the sender is configured with (ms).
+    pub interval_ms: u32,
+    /// Time since the sender was started (µs).
+    pub uptime_us: u64,
+    /// Wall-clock time the heartbeat was sent (µs since epoch).
+    pub send_time_us: u64,
+    /// Messages sent so far, per channel. Equal to the last sequence number
+    /// assigned on that channel.
+    pub msg_counts: [u64; HEARTBEAT_CHANNELS],
+    /// `local_time_us` of the last message sent, per channel (0 = never).
+    pub last_update_us: [u64; HEARTBEAT_CHANNELS],
+    /// TCP port of the sender's replay service (0 = no replay).
+    pub replay_port: u16,
+}
+
+impl UdpHeartbeat {
+    /// Returns the message types this source has sent at least once.
+    pub fn active_channels(&self) -> impl Iterator<Item = MessageType> + '_ {
+        CHANNEL_TYPES.iter().zip(self.msg_counts.iter()).filter(|(_, n)| **n > 0).map(|(t, _)| *t)
+    }
+}
+
+/// Channel index → message type.
+const CHANNEL_TYPES: [MessageType; HEARTBEAT_CHANNELS] =
+    [MessageType::BookTicker, MessageType::Trade, MessageType::AggTrade, MessageType::Depth5, MessageType::DepthN];
+
+/// Heartbeat channel index for a market data message.
+#[inline]
+pub fn channel_index(msg: &MarketDataMsg) -> usize {
+    match msg {
+        MarketDataMsg::Bbo(_) => 0,
+        MarketDataMsg::Trade(_) => 1,
+        MarketDataMsg::AggTrade(_) => 2,
+        MarketDataMsg::Depth5(_) => 3,
+        MarketDataMsg::DepthN(_) => 4,
+    }
+}
+
+/// Channel index of a packet's `msg_type` byte (`None` for non market data).
+///
+/// Equal to the discriminant for the first four types; `DepthN` came later.
+#[inline]
+pub fn channel_of(msg_type: u8) -> Option<u8> {
+    CHANNEL_TYPES.iter().position(|t| *t as u8 == msg_type).map(|i| i as u8)
+}
+
+// ---------------------------------------------------------------------------
+// Liveness tracking
+// ---------------------------------------------------------------------------
+
+/// Liveness state of a UDP source.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeedState {
+    /// Heartbeats are arriving within the timeout.
+    Up,
+    /// No heartbeat for longer than the timeout.
+    Down,
+}
+
+/// A liveness transition for one source.
+#[derive(Debug, Clone, Copy)]
+pub struct FeedEvent {
+    /// Source id from the heartbeat.
+    pub source_id: u32,
+    /// Address the heartbeats come from.
+    pub peer: SocketAddr,
+    /// Exchange from the heartbeat's packet header.
+    pub exchange: Option<Exchange>,
+    /// New state.
+    pub state: FeedState,
+    /// Last heartbeat received from this source.
+    pub heartbeat: UdpHeartbeat,
+}
+
+struct SourceEntry {
+    peer: SocketAddr,
+    exchange: Option<Exchange>,
+    last_seen: Instant,
+    state: FeedState,
+    heartbeat: UdpHeartbeat,
+}
+
+/// Tracks heartbeats per source and detects sources that went silent.
+///
+/// Not thread-safe — owned by the receive loop.
+pub struct SourceTracker {
+    timeout: Duration,
+    sources: AHashMap<u32, SourceEntry>,
+}
+
+impl SourceTracker {
+    /// Create a tracker that declares a source down after `timeout` without
+    /// a heartbeat.
+    pub fn new(timeout: Duration) -> Self {
+        Self { timeout, sources: AHashMap::new() }
+    }
+
+    /// Record a heartbeat sent for `exchange`. Returns an `Up` event if the
+    /// source is new or was previously down.
+    pub fn on_heartbeat(
+        &mut self,
+        hb: UdpHeartbeat,
+        peer: SocketAddr,
+        exchange: Option<Exchange>,
+        now: Instant,
+    ) -> Option<FeedEvent> {
+        let entry = self.sources.entry(hb.source_id).or_insert(SourceEntry {
+            peer,
+            exchange,
+            last_seen: now,
+            state: FeedState::Down,
+            heartbeat: hb,
+        });
+        entry.peer = peer;
+        entry.exchange = exchange;
+        entry.last_seen = now;
+        entry.heartbeat = hb;
+
+        if entry.state == FeedState::Down {
+            entry.state = FeedState::Up;
+            Some(FeedEvent { source_id: hb.source_id, peer, exchange, state: FeedState::Up, heartbeat: hb })
+        } else {
+            None
+        }
+    }
+
+    /// Check all sources for heartbeat timeouts, returning a `Down` event for
+    /// each source that just went silent.
+    pub fn check_timeouts(&mut self, now: Instant) -> Vec<FeedEvent> {
+        let mut events = Vec::new();
+        for (&source_id, entry) in &mut self.sources {
+            if entry.state == FeedState::Up && now.duration_since(entry.last_seen) > self.timeout {
+                entry.state = FeedState::Down;
+                events.push(FeedEvent {
+                    source_id,
+                    peer: entry.peer,
+                    exchange: entry.exchange,
+                    state: FeedState::Down,
+                    heartbeat: entry.heartbeat,
+                });
+            }
+        }
+        events
+    }
+
+    /// Current state of a source, or `None` if it has never sent a heartbeat.
+    pub fn state(&self, source_id: u32) -> Option<FeedState> {
+        self.sources.get(&source_id).map(|e| e.state)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::DepthNBox;
+
+    fn hb(source_id: u32) -> UdpHeartbeat {
+        UdpHeartbeat { source_id, interval_ms: 1000, msg_counts: [5, 0, 0, 2, 0], ..Default::default() }
+    }
+
+    #[test]
+    fn up_then_down_then_up() {
+        let peer: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let mut t = SourceTracker::new(Duration::from_millis(100));
+        let t0 = Instant::now();
+
+        let ev = t.on_heartbeat(hb(1), peer, None, t0).unwrap();
+        assert_eq!(ev.state, FeedState::Up);
+        // Repeated heartbeats produce no event.
+        assert!(t.on_heartbeat(hb(1), peer, None, t0 + Duration::from_millis(50)).is_none());
+        assert!(t.check_timeouts(t0 + Duration::from_millis(100)).is_empty());
+
+        let down = t.check_timeouts(t0 + Duration::from_millis(200));
+        assert_eq!(down.len(), 1);
+        assert_eq!(down[0].state, FeedState::Down);
+        assert_eq!(t.state(1), Some(FeedState::Down));
+        // Down is reported only once.
+        assert!(t.check_timeouts(t0 + Duration::from_millis(300)).is_empty());
+
+        let up = t.on_heartbeat(hb(1), peer, None, t0 + Duration::from_millis(400)).unwrap();
+        assert_eq!(up.state, FeedState::Up);
+    }
+
+    #[test]
+    fn sources_are_independent() {
+        let peer: SocketAddr = "127.0.0.1:9000".parse().unwrap();
+        let mut t = SourceTracker::new(Duration::from_millis(100));
+        let t0 = Instant::now();
+        t.on_heartbeat(hb(1), peer, None, t0);
+        t.on_heartbeat(hb(2), peer, None, t0 + Duration::from_millis(150));
+
+        let down = t.check_timeouts(t0 + Duration::from_millis(200));
+        assert_eq!(down.len(), 1);
+        assert_eq!(down[0].source_id, 1);
+        assert_eq!(t.state(2), Some(FeedState::Up));
+        assert_eq!(t.state(3), None);
+    }
+
+    #[test]
+    fn active_channels() {
+        let types: Vec<_> = hb(1).active_channels().collect();
+        assert_eq!(types, vec![MessageType::BookTicker, MessageType::Depth5]);
+    }
+
+    #[test]
+    fn depth_n_has_its_own_channel() {
+        assert_eq!(channel_of(MessageType::Depth5 as u8), Some(3));
+        assert_eq!(channel_of(MessageType::DepthN as u8), Some(4));
+        assert_eq!(channel_of(MessageType::Heartbeat as u8), None);
+        assert_eq!(channel_index(&MarketDataMsg::DepthN(DepthNBox::default())), 4);
+    }
+}
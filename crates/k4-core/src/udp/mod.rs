@@ -0,0 +1,420 @@
+//! Asynchronous UDP sender and receiver for market data distribution.
+//!
+//! Uses `rkyv` for safe zero-copy serialization — no `unsafe` pointer
+//! operations needed. The wire format is:
+//!
+//! ```text
+//! ┌─────────┬────────────┬────────────┬──────────────┬─────────────────────────┐
+//! │ version │ msg_type   │ exchange   │ seq          │ rkyv-serialized payload │
+//! │ u8      │ i8 (1 byte)│ u8         │ u64 LE       │ variable length         │
+//! └─────────┴────────────┴────────────┴──────────────┴─────────────────────────┘
+//! ```
+//!
+//! `version` is [`WIRE_VERSION`]; receivers drop packets of any other
+//! version, since the rkyv payload layout follows the market data structs.
+//!
+//! `exchange` is the sender's [`Exchange`] discriminant (`0` = unknown), so a
+//! receiver fed by several gateways can route data per exchange.
+//!
+//! `seq` counts up from 1 per message type (channel), so a receiver can detect
+//! lost packets and fetch them from the sender's TCP replay service — see
+//! [`replay`]. Heartbeats are unsequenced (`seq = 0`).
+//!
+//! Besides market data, senders periodically emit a [`UdpHeartbeat`]
+//! (`msg_type = Heartbeat`) so receivers can tell a quiet market from a dead
+//! gateway — see [`heartbeat`].
+//!
+//! Both ends accept [`UdpSocketOptions`] (buffer sizes, `SO_BUSY_POLL`,
+//! TOS/DSCP, interface or source IP). On Linux the receiver enables
+//! `SO_TIMESTAMPNS` and reports each packet's kernel receive time in
+//! [`RecvMeta`].
+
+mod gap;
+pub mod heartbeat;
+mod queue;
+mod receiver;
+pub mod replay;
+mod sender;
+mod socket;
+
+pub use heartbeat::{FeedEvent, FeedState, UdpHeartbeat};
+pub use queue::DropPolicy;
+pub use receiver::{MarketDataCallback, RecvMeta, UdpCallbackHandler, UdpReceiver, UdpReceiverOptions};
+pub use sender::{UdpSender, UdpSenderOptions, UdpSenderStats};
+pub use socket::UdpSocketOptions;
+
+use crate::types::{Exchange, MarketDataMsg, MessageType};
+
+/// Maximum UDP payload size.
+const MAX_UDP_PAYLOAD: usize = 65507;
+
+/// Wire format version, the first byte of every packet. Bumped whenever the
+/// header or a payload layout changes.
+///
+/// Packets from senders predating it start with their message type instead,
+/// so versions start above every [`MessageType`] sent over UDP. Version 200
+/// added the version byte itself, and `Trade::cross_seq` and `kernel_rx_us`
+/// to every market data payload.
+pub const WIRE_VERSION: u8 = 200;
+
+/// Size of the packet header: `version` (1) + `msg_type` (1) + `exchange`
+/// (1) + `seq` (8).
+const HEADER_LEN: usize = 11;
+
+/// Decoded packet header.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Header {
+    msg_type: u8,
+    exchange: Option<Exchange>,
+    seq: u64,
+}
+
+/// Copy payload into an aligned buffer and decode with rkyv.
+macro_rules! decode_rkyv {
+    ($T:ty, $payload:expr) => {{
+        let mut a = rkyv::util::AlignedVec::<8>::with_capacity($payload.len());
+        a.extend_from_slice($payload);
+        rkyv::from_bytes::<$T, rkyv::rancor::Error>(&a).ok()
+    }};
+}
+pub(crate) use decode_rkyv;
+
+/// Prepend the packet header to an rkyv-serialized payload.
+fn with_header(
+    msg_type: MessageType,
+    exchange: Option<Exchange>,
+    seq: u64,
+    payload: rkyv::util::AlignedVec,
+) -> Vec<u8> {
+    let mut buf = Vec::with_capacity(HEADER_LEN + payload.len());
+    buf.push(WIRE_VERSION);
+    buf.push(msg_type as u8);
+    buf.push(exchange.map_or(0, |e| e as u8));
+    buf.extend_from_slice(&seq.to_le_bytes());
+    buf.extend_from_slice(&payload);
+    buf
+}
+
+/// Split a packet into its header and payload. `None` for packets that are
+/// too short or of another [`WIRE_VERSION`].
+fn parse_header(packet: &[u8]) -> Option<(Header, &[u8])> {
+    if packet.len() <= HEADER_LEN || packet[0] != WIRE_VERSION {
+        return None; // Need the full header + at least 1 byte of payload
+    }
+    let header = Header {
+        msg_type: packet[1],
+        exchange: Exchange::from_u8(packet[2]),
+        seq: u64::from_le_bytes(packet[3..HEADER_LEN].try_into().ok()?),
+    };
+    Some((header, &packet[HEADER_LEN..]))
+}
+
+/// Encode a `MarketDataMsg` into bytes: `[header] ++ [rkyv payload]`.
+fn encode_msg(msg: &MarketDataMsg, exchange: Option<Exchange>, seq: u64) -> Option<Vec<u8>> {
+    type E = rkyv::rancor::Error;
+    let (msg_type, payload) = match msg {
+        MarketDataMsg::Bbo(d) => (MessageType::BookTicker, rkyv::to_bytes::<E>(d).ok()?),
+        MarketDataMsg::Trade(d) => (MessageType::Trade, rkyv::to_bytes::<E>(d).ok()?),
+        MarketDataMsg::AggTrade(d) => (MessageType::AggTrade, rkyv::to_bytes::<E>(d).ok()?),
+        MarketDataMsg::Depth5(d) => (MessageType::Depth5, rkyv::to_bytes::<E>(d).ok()?),
+        MarketDataMsg::DepthN(d) => (MessageType::DepthN, rkyv::to_bytes::<E>(&**d).ok()?),
+    };
+    Some(with_header(msg_type, exchange, seq, payload))
+}
+
+/// Encode a heartbeat into bytes (unsequenced).
+fn encode_heartbeat(hb: &UdpHeartbeat, exchange: Option<Exchange>) -> Option<Vec<u8>> {
+    Some(with_header(MessageType::Heartbeat, exchange, 0, rkyv::to_bytes::<rkyv::rancor::Error>(hb).ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::types::*;
+
+    #[test]
+    fn encode_decode_bookticker() {
+        let bbo = Bookticker {
+            symbol: symbol_to_bytes("BTCUSDT"),
+            product_type: ProductType::Spot,
+            event_timestamp_us: 1672515782136000,
+            trade_timestamp_us: 1672515782136000,
+            update_id: 123456,
+            bid_price: 50000.0,
+            bid_vol: 1.5,
+            ask_price: 50001.0,
+            ask_vol: 2.0,
+            bid_order_count: 10,
+            ask_order_count: 20,
+            local_time_us: 1672515782137000,
+            kernel_rx_us: 0,
+        };
+
+        // Encode via encode_msg
+        let bytes = encode_msg(&MarketDataMsg::Bbo(bbo), Some(Exchange::Okx), 17).unwrap();
+        let (header, payload) = parse_header(&bytes).unwrap();
+        assert_eq!(header, Header { msg_type: MessageType::BookTicker as u8, exchange: Some(Exchange::Okx), seq: 17 });
+
+        // Decode
+        let decoded = decode_rkyv!(Bookticker, payload).expect("rkyv decode failed");
+        assert_eq!(decoded.bid_price, bbo.bid_price);
+        assert_eq!(decoded.ask_price, bbo.ask_price);
+        assert_eq!(decoded.update_id, bbo.update_id);
+        assert_eq!(decoded.product_type, ProductType::Spot);
+        assert_eq!(symbol_from_bytes(&decoded.symbol), "BTCUSDT");
+    }
+
+    #[test]
+    fn encode_decode_trade() {
+        let trade = Trade {
+            symbol: symbol_to_bytes("ETHUSDT"),
+            product_type: ProductType::Futures,
+            event_timestamp_us: 100000,
+            trade_timestamp_us: 100000,
+            trade_id: 999,
+            price: 3000.5,
+            vol: 10.0,
+            is_buyer_maker: true,
+            backfilled: false,
+            cross_seq: 42,
+            local_time_us: 100001,
+            kernel_rx_us: 0,
+        };
+
+        let bytes = encode_msg(&MarketDataMsg::Trade(trade), None, 1).unwrap();
+        assert_eq!(parse_header(&bytes).unwrap().0.exchange, None);
+        let decoded = decode_rkyv!(Trade, &bytes[HEADER_LEN..]).unwrap();
+        assert_eq!(decoded.price, trade.price);
+        assert!(decoded.is_buyer_maker);
+        assert_eq!(decoded.product_type, ProductType::Futures);
+        assert_eq!(decoded.cross_seq, 42);
+    }
+
+    #[test]
+    fn encode_decode_depth_n() {
+        let mut depth = DepthN {
+            symbol: symbol_to_bytes("BTCUSDT"),
+            product_type: ProductType::Futures,
+            update_id: 42,
+            bid_level: MAX_DEPTH_LEVELS as u32,
+            ask_level: 1,
+            ..Default::default()
+        };
+        depth.bid_prices[MAX_DEPTH_LEVELS - 1] = 49_950.5;
+        depth.ask_prices[0] = 50_001.0;
+
+        let bytes = encode_msg(&MarketDataMsg::DepthN(DepthNBox::new(depth)), None, 3).unwrap();
+        let (header, payload) = parse_header(&bytes).unwrap();
+        assert_eq!(header.msg_type, MessageType::DepthN as u8);
+        assert_eq!(decode_rkyv!(DepthN, payload), Some(depth));
+    }
+
+    #[test]
+    fn encode_decode_heartbeat() {
+        let hb = UdpHeartbeat { source_id: 7, interval_ms: 500, msg_counts: [1, 2, 3, 4, 5], ..Default::default() };
+        let bytes = encode_heartbeat(&hb, Some(Exchange::Binance)).unwrap();
+        let (header, payload) = parse_header(&bytes).unwrap();
+        assert_eq!(
+            header,
+            Header { msg_type: MessageType::Heartbeat as u8, exchange: Some(Exchange::Binance), seq: 0 }
+        );
+        assert_eq!(decode_rkyv!(UdpHeartbeat, payload), Some(hb));
+    }
+
+    #[test]
+    fn other_wire_versions_are_rejected() {
+        let mut bytes = encode_msg(&MarketDataMsg::Trade(Trade::default()), None, 1).unwrap();
+        assert_eq!(bytes[0], WIRE_VERSION);
+        // An unversioned packet from an older sender starts with its message type.
+        bytes[0] = MessageType::Trade as u8;
+        assert!(parse_header(&bytes).is_none());
+    }
+
+    #[tokio::test]
+    async fn heartbeat_loopback_up_and_down() {
+        use std::sync::mpsc;
+
+        let receiver = UdpReceiver::bind_with_options(
+            "127.0.0.1:0".parse().unwrap(),
+            UdpReceiverOptions { heartbeat_timeout: Duration::from_millis(150), ..Default::default() },
+        )
+        .await
+        .unwrap();
+        let addr = receiver.local_addr().unwrap();
+
+        let (tx, rx) = mpsc::channel();
+        let handler = UdpCallbackHandler {
+            on_bbo: None,
+            on_trade: None,
+            on_agg_trade: None,
+            on_depth5: None,
+            on_depth_n: None,
+            on_market_data: None,
+            on_heartbeat: None,
+            on_feed_state: Some(Box::new(move |ev: FeedEvent| {
+                let _ = tx.send((ev.source_id, ev.state));
+            })),
+        };
+        let task = tokio::spawn(receiver.run(handler));
+
+        let sender = UdpSender::with_options(
+            addr,
+            UdpSenderOptions {
+                source_id: 42,
+                heartbeat_interval: Some(Duration::from_millis(20)),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert_eq!(rx.try_recv().ok(), Some((42, FeedState::Up)));
+
+        // Dropping the sender stops its heartbeats.
+        drop(sender);
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert_eq!(rx.try_recv().ok(), Some((42, FeedState::Down)));
+
+        task.abort();
+    }
+
+    fn bbo(update_id: u64) -> MarketDataMsg {
+        MarketDataMsg::Bbo(Bookticker {
+            symbol: symbol_to_bytes("BTCUSDT"),
+            product_type: ProductType::Spot,
+            update_id,
+            ..Default::default()
+        })
+    }
+
+    /// Receiver whose BBO callback forwards `update_id`s to a channel.
+    async fn bbo_receiver(
+        gap_fill_timeout: Duration,
+    ) -> (std::net::SocketAddr, tokio::sync::mpsc::UnboundedReceiver<u64>, tokio::task::JoinHandle<anyhow::Result<()>>)
+    {
+        let receiver = UdpReceiver::bind_with_options(
+            "127.0.0.1:0".parse().unwrap(),
+            UdpReceiverOptions { gap_fill_timeout: Some(gap_fill_timeout), ..Default::default() },
+        )
+        .await
+        .unwrap();
+        let addr = receiver.local_addr().unwrap();
+        let (tx, rx) = tokio::sync::mpsc::unbounded_channel();
+        let handler = UdpCallbackHandler {
+            on_bbo: Some(Box::new(move |b: Bookticker| {
+                let _ = tx.send(b.update_id);
+            })),
+            on_trade: None,
+            on_agg_trade: None,
+            on_depth5: None,
+            on_depth_n: None,
+            on_market_data: None,
+            on_heartbeat: None,
+            on_feed_state: None,
+        };
+        (addr, rx, tokio::spawn(receiver.run(handler)))
+    }
+
+    /// Collect `n` update ids, failing if they take longer than `within`.
+    async fn collect(rx: &mut tokio::sync::mpsc::UnboundedReceiver<u64>, n: usize, within: Duration) -> Vec<u64> {
+        let mut got = Vec::new();
+        tokio::time::timeout(within, async {
+            while got.len() < n {
+                got.push(rx.recv().await.unwrap());
+            }
+        })
+        .await
+        .expect("timed out waiting for messages");
+        got
+    }
+
+    #[tokio::test]
+    async fn gap_recovered_via_replay() {
+        let (recv_addr, mut rx, recv_task) = bbo_receiver(Duration::from_secs(2)).await;
+
+        // Lossy relay between sender and receiver: drops BBO seq 2 and 3.
+        let relay = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let relay_addr = relay.local_addr().unwrap();
+        let relay_task = tokio::spawn(async move {
+            let mut buf = vec![0u8; MAX_UDP_PAYLOAD];
+            loop {
+                let (n, _) = relay.recv_from(&mut buf).await.unwrap();
+                let (h, _) = parse_header(&buf[..n]).unwrap();
+                if h.msg_type == MessageType::BookTicker as u8 && (h.seq == 2 || h.seq == 3) {
+                    continue;
+                }
+                relay.send_to(&buf[..n], recv_addr).await.unwrap();
+            }
+        });
+
+        let sender = UdpSender::with_options(
+            relay_addr,
+            UdpSenderOptions {
+                heartbeat_interval: Some(Duration::from_millis(20)),
+                replay_addr: Some("127.0.0.1:0".parse().unwrap()),
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+        // Let the first heartbeat advertise the replay port.
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        for id in 1..=5 {
+            sender.send(bbo(id));
+        }
+
+        assert_eq!(collect(&mut rx, 5, Duration::from_secs(1)).await, vec![1, 2, 3, 4, 5]);
+
+        relay_task.abort();
+        recv_task.abort();
+    }
+
+    #[tokio::test]
+    async fn gap_skipped_without_replay_service() {
+        let (recv_addr, mut rx, recv_task) = bbo_receiver(Duration::from_secs(2)).await;
+        let socket = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        for seq in [1, 3, 4] {
+            let packet = encode_msg(&bbo(seq), None, seq).unwrap();
+            socket.send_to(&packet, recv_addr).await.unwrap();
+        }
+
+        // Released immediately rather than after the give-up timeout.
+        assert_eq!(collect(&mut rx, 3, Duration::from_millis(500)).await, vec![1, 3, 4]);
+
+        recv_task.abort();
+    }
+
+    #[tokio::test]
+    async fn sender_counts_drops() {
+        let sink = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let sender = UdpSender::with_options(
+            sink.local_addr().unwrap(),
+            UdpSenderOptions {
+                heartbeat_interval: None,
+                queue_capacity: 4,
+                drop_policy: DropPolicy::DropNewest,
+                ..Default::default()
+            },
+        )
+        .await
+        .unwrap();
+
+        // The send loop cannot run while this task pushes synchronously.
+        for id in 1..=10 {
+            sender.send(bbo(id));
+        }
+        let stats = sender.stats();
+        assert_eq!((stats.enqueued, stats.dropped, stats.queued), (4, 6, 4));
+
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while sender.stats().sent < 4 {
+                tokio::time::sleep(Duration::from_millis(5)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(sender.stats().queued, 0);
+    }
+}
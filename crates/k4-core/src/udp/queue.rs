@@ -0,0 +1,282 @@
+//! Bounded send queue with a configurable overflow policy.
+//!
+//! Sits between [`UdpSender::send`](super::UdpSender::send) (called from the
+//! dedup threads) and the async send loop. Unlike a plain channel it can
+//! conflate book updates per symbol and evict old entries, so a slow network
+//! path degrades to "latest book, every trade" rather than arbitrary loss.
+
+use std::{
+    collections::VecDeque,
+    sync::{
+        Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
+
+use ahash::AHashMap;
+use serde::Deserialize;
+use tokio::sync::Notify;
+
+use super::heartbeat::channel_index;
+use crate::types::{MarketDataMsg, SYMBOL_LEN};
+
+/// What to do when the send queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DropPolicy {
+    /// Discard the incoming message.
+    #[default]
+    DropNewest,
+    /// Discard the oldest queued message to make room.
+    DropOldest,
+    /// Replace a still-queued BBO/Depth5/DepthN for the same symbol with the newer
+    /// one. When full, evict the oldest queued BBO/Depth5/DepthN; trades are never
+    /// dropped (the queue may exceed its capacity to hold them).
+    Conflate,
+}
+
+/// Result of [`SendQueue::push`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum PushOutcome {
+    /// Appended to the queue.
+    Enqueued,
+    /// Overwrote a queued message for the same symbol.
+    Conflated,
+    /// Appended after evicting an older message.
+    Evicted,
+    /// Discarded.
+    Dropped,
+}
+
+/// Conflation key: channel + symbol.
+type BookKey = (usize, [u8; SYMBOL_LEN]);
+
+struct Inner {
+    /// Queued messages; `None` marks an evicted slot.
+    slots: VecDeque<Option<MarketDataMsg>>,
+    /// Absolute index of `slots[0]`.
+    base: u64,
+    /// Number of `Some` slots.
+    live: usize,
+    /// Absolute index of the queued book update per symbol (conflate only).
+    book: AHashMap<BookKey, u64>,
+}
+
+/// MPSC queue feeding the UDP send loop.
+pub(crate) struct SendQueue {
+    capacity: usize,
+    policy: DropPolicy,
+    inner: Mutex<Inner>,
+    notify: Notify,
+    closed: AtomicBool,
+    /// Current number of queued messages (for stats, without locking).
+    len: AtomicU64,
+}
+
+fn is_book(msg: &MarketDataMsg) -> bool {
+    matches!(msg, MarketDataMsg::Bbo(_) | MarketDataMsg::Depth5(_) | MarketDataMsg::DepthN(_))
+}
+
+fn book_key(msg: &MarketDataMsg) -> BookKey {
+    (channel_index(msg), *msg.symbol())
+}
+
+impl SendQueue {
+    pub fn new(capacity: usize, policy: DropPolicy) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            policy,
+            inner: Mutex::new(Inner { slots: VecDeque::new(), base: 0, live: 0, book: AHashMap::new() }),
+            notify: Notify::new(),
+            closed: AtomicBool::new(false),
+            len: AtomicU64::new(0),
+        }
+    }
+
+    /// Enqueue a message according to the drop policy and wake the consumer.
+    pub fn push(&self, msg: MarketDataMsg) -> PushOutcome {
+        let outcome = {
+            let Ok(mut q) = self.inner.lock() else { return PushOutcome::Dropped };
+            let outcome = self.push_locked(&mut q, msg);
+            self.len.store(q.live as u64, Ordering::Relaxed);
+            outcome
+        };
+        if outcome != PushOutcome::Dropped {
+            self.notify.notify_one();
+        }
+        outcome
+    }
+
+    fn push_locked(&self, q: &mut Inner, msg: MarketDataMsg) -> PushOutcome {
+        let conflate = self.policy == DropPolicy::Conflate && is_book(&msg);
+        if conflate && let Some(&pos) = q.book.get(&book_key(&msg)) {
+            let idx = (pos - q.base) as usize;
+            q.slots[idx] = Some(msg);
+            return PushOutcome::Conflated;
+        }
+
+        let mut outcome = PushOutcome::Enqueued;
+        if q.live >= self.capacity {
+            match self.policy {
+                DropPolicy::DropNewest => return PushOutcome::Dropped,
+                DropPolicy::DropOldest => {
+                    q.pop_live();
+                    outcome = PushOutcome::Evicted;
+                }
+                DropPolicy::Conflate => {
+                    if q.evict_oldest_book() {
+                        outcome = PushOutcome::Evicted;
+                    } else if conflate {
+                        // Full of trades: the book update loses.
+                        return PushOutcome::Dropped;
+                    }
+                    // Trades are kept even over capacity.
+                }
+            }
+        }
+
+        let pos = q.base + q.slots.len() as u64;
+        if conflate {
+            q.book.insert(book_key(&msg), pos);
+        }
+        q.slots.push_back(Some(msg));
+        q.live += 1;
+        outcome
+    }
+
+    /// Dequeue the oldest message, if any.
+    pub fn pop(&self) -> Option<MarketDataMsg> {
+        let mut q = self.inner.lock().ok()?;
+        let msg = q.pop_live();
+        self.len.store(q.live as u64, Ordering::Relaxed);
+        msg
+    }
+
+    /// Wait until a message may be available.
+    pub async fn notified(&self) {
+        self.notify.notified().await;
+    }
+
+    /// Wake the consumer again (e.g. after a partial drain).
+    pub fn wake(&self) {
+        self.notify.notify_one();
+    }
+
+    /// Mark the queue closed and wake the consumer so it can exit.
+    pub fn close(&self) {
+        self.closed.store(true, Ordering::Release);
+        self.notify.notify_one();
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Number of queued messages.
+    pub fn len(&self) -> u64 {
+        self.len.load(Ordering::Relaxed)
+    }
+}
+
+impl Inner {
+    /// Remove and return the first live message.
+    fn pop_live(&mut self) -> Option<MarketDataMsg> {
+        while let Some(slot) = self.slots.pop_front() {
+            let pos = self.base;
+            self.base += 1;
+            if let Some(msg) = slot {
+                self.live -= 1;
+                if is_book(&msg) {
+                    let key = book_key(&msg);
+                    if self.book.get(&key) == Some(&pos) {
+                        self.book.remove(&key);
+                    }
+                }
+                return Some(msg);
+            }
+        }
+        None
+    }
+
+    /// Evict the oldest queued BBO/Depth5/DepthN. Returns `false` if there is none.
+    fn evict_oldest_book(&mut self) -> bool {
+        let Some(idx) = self.slots.iter().position(|s| s.as_ref().is_some_and(is_book)) else {
+            return false;
+        };
+        let msg = self.slots[idx].take().expect("position() found a live slot");
+        self.live -= 1;
+        let key = book_key(&msg);
+        if self.book.get(&key) == Some(&(self.base + idx as u64)) {
+            self.book.remove(&key);
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::*;
+
+    fn bbo(sym: &str, id: u64) -> MarketDataMsg {
+        MarketDataMsg::Bbo(Bookticker { symbol: symbol_to_bytes(sym), update_id: id, ..Default::default() })
+    }
+
+    fn trade(id: u64) -> MarketDataMsg {
+        MarketDataMsg::Trade(Trade { symbol: symbol_to_bytes("BTCUSDT"), trade_id: id, ..Default::default() })
+    }
+
+    fn id(msg: &MarketDataMsg) -> u64 {
+        match msg {
+            MarketDataMsg::Bbo(b) => b.update_id,
+            MarketDataMsg::Trade(t) => t.trade_id,
+            _ => unreachable!(),
+        }
+    }
+
+    fn drain(q: &SendQueue) -> Vec<u64> {
+        std::iter::from_fn(|| q.pop()).map(|m| id(&m)).collect()
+    }
+
+    #[test]
+    fn drop_newest() {
+        let q = SendQueue::new(2, DropPolicy::DropNewest);
+        assert_eq!(q.push(bbo("A", 1)), PushOutcome::Enqueued);
+        assert_eq!(q.push(bbo("A", 2)), PushOutcome::Enqueued);
+        assert_eq!(q.push(bbo("A", 3)), PushOutcome::Dropped);
+        assert_eq!(q.len(), 2);
+        assert_eq!(drain(&q), vec![1, 2]);
+        assert_eq!(q.len(), 0);
+    }
+
+    #[test]
+    fn drop_oldest() {
+        let q = SendQueue::new(2, DropPolicy::DropOldest);
+        q.push(bbo("A", 1));
+        q.push(bbo("A", 2));
+        assert_eq!(q.push(bbo("A", 3)), PushOutcome::Evicted);
+        assert_eq!(drain(&q), vec![2, 3]);
+    }
+
+    #[test]
+    fn conflate_keeps_latest_book_and_all_trades() {
+        let q = SendQueue::new(3, DropPolicy::Conflate);
+        assert_eq!(q.push(bbo("A", 1)), PushOutcome::Enqueued);
+        assert_eq!(q.push(trade(100)), PushOutcome::Enqueued);
+        assert_eq!(q.push(bbo("A", 2)), PushOutcome::Conflated);
+        assert_eq!(q.push(bbo("B", 10)), PushOutcome::Enqueued);
+        // Full: the oldest book entry (A) is evicted for the trade.
+        assert_eq!(q.push(trade(101)), PushOutcome::Evicted);
+        // Full: B is evicted, then only trades remain — still accepted.
+        assert_eq!(q.push(trade(102)), PushOutcome::Evicted);
+        assert_eq!(q.push(trade(103)), PushOutcome::Enqueued);
+        // A book update cannot displace trades.
+        assert_eq!(q.push(bbo("C", 20)), PushOutcome::Dropped);
+        assert_eq!(drain(&q), vec![100, 101, 102, 103]);
+
+        // After draining, conflation starts afresh.
+        assert_eq!(q.push(bbo("A", 3)), PushOutcome::Enqueued);
+        assert_eq!(q.push(bbo("A", 4)), PushOutcome::Conflated);
+        assert_eq!(drain(&q), vec![4]);
+    }
+}
@@ -0,0 +1,326 @@
+//! Asynchronous UDP market data receiver.
+
+use std::{
+    net::SocketAddr,
+    time::{Duration, Instant},
+};
+
+use ahash::{AHashMap, AHashSet};
+use tokio::{net::UdpSocket, sync::mpsc};
+use tracing::{debug, error, warn};
+
+use super::{
+    HEADER_LEN, Header, MAX_UDP_PAYLOAD, WIRE_VERSION, decode_rkyv,
+    gap::{GapFiller, GapReport, ReplayRequest, Verdict},
+    heartbeat::{FeedEvent, SourceTracker, UdpHeartbeat, channel_of},
+    parse_header, replay,
+    socket::{UdpSocketOptions, bind_udp, recv_from_ts},
+};
+use crate::{
+    time_util,
+    types::{AggTrade, Bookticker, Depth5, DepthN, DepthNBox, Exchange, MarketDataMsg, MessageType, Trade},
+};
+
+/// Per-packet receive metadata passed to [`UdpCallbackHandler::on_market_data`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RecvMeta {
+    /// Exchange from the packet header.
+    pub exchange: Option<Exchange>,
+    /// Kernel receive time (µs since epoch) from `SO_TIMESTAMPNS`. `None`
+    /// when timestamps are off or unsupported, and for packets released
+    /// from gap filling.
+    pub kernel_rx_us: Option<u64>,
+    /// Wall-clock time (µs since epoch) when the receive loop read the packet.
+    pub recv_us: u64,
+}
+
+/// Callback receiving every market data message with its receive metadata.
+pub type MarketDataCallback = Box<dyn Fn(RecvMeta, MarketDataMsg) + Send>;
+
+/// Callback handler for received UDP market data.
+pub struct UdpCallbackHandler {
+    pub on_bbo: Option<Box<dyn Fn(Bookticker) + Send>>,
+    pub on_trade: Option<Box<dyn Fn(Trade) + Send>>,
+    pub on_agg_trade: Option<Box<dyn Fn(AggTrade) + Send>>,
+    pub on_depth5: Option<Box<dyn Fn(Depth5) + Send>>,
+    pub on_depth_n: Option<Box<dyn Fn(DepthN) + Send>>,
+    /// Invoked for every market data message, with the sender's exchange and
+    /// receive timestamps (after the typed callbacks above).
+    pub on_market_data: Option<MarketDataCallback>,
+    /// Invoked for every heartbeat received.
+    pub on_heartbeat: Option<Box<dyn Fn(UdpHeartbeat) + Send>>,
+    /// Invoked when a source comes up or goes silent.
+    pub on_feed_state: Option<Box<dyn Fn(FeedEvent) + Send>>,
+}
+
+/// Options for a [`UdpReceiver`].
+#[derive(Debug, Clone)]
+pub struct UdpReceiverOptions {
+    /// A source is declared down after this long without a heartbeat.
+    pub heartbeat_timeout: Duration,
+    /// When set, sequence gaps are filled from the sender's replay service,
+    /// holding back later packets for at most this long. `None` delivers
+    /// packets as they arrive.
+    pub gap_fill_timeout: Option<Duration>,
+    /// Record the kernel receive time of each packet (`SO_TIMESTAMPNS`, Linux).
+    pub kernel_timestamps: bool,
+    /// Socket tuning applied before binding.
+    pub socket: UdpSocketOptions,
+}
+
+impl Default for UdpReceiverOptions {
+    fn default() -> Self {
+        Self {
+            heartbeat_timeout: Duration::from_secs(3),
+            gap_fill_timeout: None,
+            kernel_timestamps: true,
+            socket: UdpSocketOptions::default(),
+        }
+    }
+}
+
+/// Packets replayed for a request, as `(seq, packet)`.
+type ReplayResult = (ReplayRequest, Vec<(u64, Vec<u8>)>);
+
+/// Asynchronous UDP receiver.
+pub struct UdpReceiver {
+    socket: UdpSocket,
+    options: UdpReceiverOptions,
+}
+
+impl UdpReceiver {
+    /// Bind a UDP socket on the given address with default options.
+    pub async fn bind(addr: SocketAddr) -> anyhow::Result<Self> {
+        Self::bind_with_options(addr, UdpReceiverOptions::default()).await
+    }
+
+    /// Bind a UDP socket on the given address.
+    pub async fn bind_with_options(addr: SocketAddr, options: UdpReceiverOptions) -> anyhow::Result<Self> {
+        let socket = bind_udp(addr, &options.socket, options.kernel_timestamps)?;
+        Ok(Self { socket, options })
+    }
+
+    /// Returns the local address the socket is bound to.
+    pub fn local_addr(&self) -> anyhow::Result<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    /// Run the receive loop, dispatching messages to `handler`.
+    ///
+    /// Heartbeat timeouts are checked several times per timeout period, so a
+    /// dead source is reported at most ~25% later than the configured timeout.
+    /// With gap filling enabled, packets are delivered in sequence order per
+    /// source and message type.
+    pub async fn run(self, handler: UdpCallbackHandler) -> anyhow::Result<()> {
+        let mut buf = vec![0u8; MAX_UDP_PAYLOAD];
+        let mut tracker = SourceTracker::new(self.options.heartbeat_timeout);
+        let mut gaps = self.options.gap_fill_timeout.map(GapFiller::new);
+        let mut replay_addrs: AHashMap<SocketAddr, SocketAddr> = AHashMap::new();
+        // Senders already warned about for using another wire format.
+        let mut mismatched: AHashSet<SocketAddr> = AHashSet::new();
+        let (replay_tx, mut replay_rx) = mpsc::unbounded_channel::<ReplayResult>();
+        let mut released = Vec::new();
+
+        let period = match self.options.gap_fill_timeout {
+            Some(g) => self.options.heartbeat_timeout.min(g) / 4,
+            None => self.options.heartbeat_timeout / 4,
+        };
+        let mut check = tokio::time::interval(period.max(Duration::from_millis(10)));
+        check.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                res = recv_from_ts(&self.socket, &mut buf) => {
+                    let (n, peer, kernel_rx_us) = match res {
+                        Ok(r) => r,
+                        Err(e) => {
+                            error!("UDP recv error: {e}");
+                            continue;
+                        }
+                    };
+
+                    let Some((header, payload)) = parse_header(&buf[..n]) else {
+                        if n > HEADER_LEN && buf[0] != WIRE_VERSION && mismatched.insert(peer) {
+                            warn!(
+                                "dropping UDP packets from {peer}: wire version {} (expected {WIRE_VERSION}); \
+                                 sender and receiver must run the same build",
+                                buf[0]
+                            );
+                        }
+                        continue;
+                    };
+                    let meta = RecvMeta { exchange: header.exchange, kernel_rx_us, recv_us: time_util::now_us() };
+
+                    if header.msg_type == MessageType::Heartbeat as u8 {
+                        if let Some(hb) = handle_heartbeat(payload, peer, header.exchange, &mut tracker, &handler)
+                            && hb.replay_port != 0
+                        {
+                            replay_addrs.insert(peer, SocketAddr::new(peer.ip(), hb.replay_port));
+                        }
+                        continue;
+                    }
+
+                    let (Some(gaps), Some(channel)) = (&mut gaps, channel_of(header.msg_type)) else {
+                        dispatch_payload(header, payload, meta, &handler);
+                        continue;
+                    };
+                    match gaps.on_packet(peer, channel, header.seq, &buf[..n], Instant::now()) {
+                        (Verdict::Deliver, _) => dispatch_payload(header, payload, meta, &handler),
+                        (Verdict::Parked, Some(req)) => {
+                            request_replay(req, gaps, &replay_addrs, &replay_tx, &mut released);
+                        }
+                        (Verdict::Parked, None) | (Verdict::Stale, _) => {}
+                    }
+                }
+
+                Some((req, packets)) = replay_rx.recv() => {
+                    if let Some(gaps) = &mut gaps
+                        && let Some(report) = gaps.on_replay(req, packets, Instant::now(), &mut released)
+                    {
+                        end_recovery(report, gaps, &replay_addrs, &replay_tx, &mut released);
+                    }
+                }
+
+                _ = check.tick() => {
+                    for ev in tracker.check_timeouts(Instant::now()) {
+                        if let Some(cb) = &handler.on_feed_state {
+                            cb(ev);
+                        }
+                    }
+                    if let Some(gaps) = &mut gaps {
+                        for report in gaps.check_timeouts(Instant::now(), &mut released) {
+                            end_recovery(report, gaps, &replay_addrs, &replay_tx, &mut released);
+                        }
+                    }
+                }
+            }
+
+            if !released.is_empty() {
+                let recv_us = time_util::now_us();
+                for packet in released.drain(..) {
+                    if let Some((header, payload)) = parse_header(&packet) {
+                        let meta = RecvMeta { exchange: header.exchange, kernel_rx_us: None, recv_us };
+                        dispatch_payload(header, payload, meta, &handler);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Fetch a missing range in the background and hand the result back to the
+/// receive loop. Failures are reported as an empty replay, which ends the
+/// recovery immediately.
+fn spawn_fetch(addr: SocketAddr, req: ReplayRequest, tx: mpsc::UnboundedSender<ReplayResult>) {
+    tokio::spawn(async move {
+        let packets = match replay::fetch_range(addr, req.channel, req.from, req.to).await {
+            Ok(packets) => packets,
+            Err(e) => {
+                debug!("UDP replay fetch from {addr} failed: {e}");
+                Vec::new()
+            }
+        };
+        let packets = packets.into_iter().filter_map(|p| Some((parse_header(&p)?.0.seq, p))).collect();
+        let _ = tx.send((req, packets));
+    });
+}
+
+/// Fetch `req` from the peer's replay service. Without one, the recovery
+/// ends at once and the parked packets are released, gap after gap.
+fn request_replay(
+    mut req: ReplayRequest,
+    gaps: &mut GapFiller,
+    replay_addrs: &AHashMap<SocketAddr, SocketAddr>,
+    replay_tx: &mpsc::UnboundedSender<ReplayResult>,
+    released: &mut Vec<Vec<u8>>,
+) {
+    if let Some(&addr) = replay_addrs.get(&req.peer) {
+        return spawn_fetch(addr, req, replay_tx.clone());
+    }
+    while let Some(report) = gaps.on_replay(req, Vec::new(), Instant::now(), released) {
+        log_gap(&report);
+        match report.next {
+            Some(next) => req = next,
+            None => break,
+        }
+    }
+}
+
+/// Log a finished recovery and request the gap queued behind it, if any.
+fn end_recovery(
+    report: GapReport,
+    gaps: &mut GapFiller,
+    replay_addrs: &AHashMap<SocketAddr, SocketAddr>,
+    replay_tx: &mpsc::UnboundedSender<ReplayResult>,
+    released: &mut Vec<Vec<u8>>,
+) {
+    log_gap(&report);
+    if let Some(next) = report.next {
+        request_replay(next, gaps, replay_addrs, replay_tx, released);
+    }
+}
+
+fn log_gap(r: &GapReport) {
+    if r.lost > 0 {
+        warn!("UDP gap from {} on channel {}: seq {}..={}, {} lost", r.peer, r.channel, r.from, r.to, r.lost);
+    } else {
+        debug!("UDP gap from {} on channel {}: seq {}..={} recovered", r.peer, r.channel, r.from, r.to);
+    }
+}
+
+fn handle_heartbeat(
+    payload: &[u8],
+    peer: SocketAddr,
+    exchange: Option<Exchange>,
+    tracker: &mut SourceTracker,
+    handler: &UdpCallbackHandler,
+) -> Option<UdpHeartbeat> {
+    let Some(hb) = decode_rkyv!(UdpHeartbeat, payload) else {
+        debug!("UDP heartbeat decode failed from {peer}");
+        return None;
+    };
+    if let Some(cb) = &handler.on_heartbeat {
+        cb(hb);
+    }
+    if let Some(ev) = tracker.on_heartbeat(hb, peer, exchange, Instant::now())
+        && let Some(cb) = &handler.on_feed_state
+    {
+        cb(ev);
+    }
+    Some(hb)
+}
+
+/// Decode a received payload into a [`MarketDataMsg`].
+///
+/// Uses `rkyv::from_bytes` for safe, validated deserialization.
+fn decode_payload(msg_type: u8, payload: &[u8]) -> Option<MarketDataMsg> {
+    match msg_type {
+        t if t == MessageType::BookTicker as u8 => decode_rkyv!(Bookticker, payload).map(MarketDataMsg::Bbo),
+        t if t == MessageType::Trade as u8 => decode_rkyv!(Trade, payload).map(MarketDataMsg::Trade),
+        t if t == MessageType::AggTrade as u8 => decode_rkyv!(AggTrade, payload).map(MarketDataMsg::AggTrade),
+        t if t == MessageType::Depth5 as u8 => decode_rkyv!(Depth5, payload).map(MarketDataMsg::Depth5),
+        t if t == MessageType::DepthN as u8 => {
+            decode_rkyv!(DepthN, payload).map(|d| MarketDataMsg::DepthN(DepthNBox::new(d)))
+        }
+        _ => None,
+    }
+}
+
+/// Dispatch a received payload to the appropriate callbacks.
+fn dispatch_payload(header: Header, payload: &[u8], meta: RecvMeta, handler: &UdpCallbackHandler) {
+    let Some(msg) = decode_payload(header.msg_type, payload) else {
+        debug!("UDP decode failed for message type {}", header.msg_type);
+        return;
+    };
+    match (&msg, handler) {
+        (MarketDataMsg::Bbo(d), UdpCallbackHandler { on_bbo: Some(cb), .. }) => cb(*d),
+        (MarketDataMsg::Trade(d), UdpCallbackHandler { on_trade: Some(cb), .. }) => cb(*d),
+        (MarketDataMsg::AggTrade(d), UdpCallbackHandler { on_agg_trade: Some(cb), .. }) => cb(*d),
+        (MarketDataMsg::Depth5(d), UdpCallbackHandler { on_depth5: Some(cb), .. }) => cb(*d),
+        (MarketDataMsg::DepthN(d), UdpCallbackHandler { on_depth_n: Some(cb), .. }) => cb(**d),
+        _ => {}
+    }
+    if let Some(cb) = &handler.on_market_data {
+        cb(meta, msg);
+    }
+}
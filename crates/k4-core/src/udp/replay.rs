@@ -0,0 +1,208 @@
+//! TCP replay service for recovering lost UDP packets.
+//!
+//! The sender keeps the last N encoded packets per channel in a
+//! [`RetransmitBuffer`] and serves them over a small request/response
+//! protocol. A receiver that detects a sequence gap connects, asks for the
+//! missing range, and merges the replayed packets back into the stream.
+//!
+//! # Protocol
+//!
+//! ```text
+//! request  : ┌────────────┬──────────────┬──────────────┐
+//!            │ channel u8 │ from_seq u64 │ to_seq u64   │   (inclusive, LE)
+//!            └────────────┴──────────────┴──────────────┘
+//! response : { ┌──────────┬──────────────────────────┐ }*  ┌──────────┐
+//!              │ len u32  │ packet (as sent over UDP)│     │ len = 0  │
+//!              └──────────┴──────────────────────────┘     └──────────┘
+//! ```
+//!
+//! Packets that have already been evicted from the buffer are silently
+//! omitted — the receiver treats whatever is still missing as lost.
+
+use std::{
+    collections::VecDeque,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt, BufWriter},
+    net::{TcpListener, TcpStream},
+};
+use tracing::{debug, info, warn};
+
+use super::heartbeat::HEARTBEAT_CHANNELS;
+
+/// Size of a replay request on the wire.
+const REQUEST_LEN: usize = 17;
+
+/// Upper bound on the number of packets served for a single request.
+const MAX_REPLAY_RANGE: u64 = 65_536;
+
+// ---------------------------------------------------------------------------
+// RetransmitBuffer
+// ---------------------------------------------------------------------------
+
+/// Bounded per-channel history of sent packets, keyed by sequence number.
+pub struct RetransmitBuffer {
+    capacity: usize,
+    channels: [VecDeque<(u64, Vec<u8>)>; HEARTBEAT_CHANNELS],
+}
+
+/// Shared handle to a [`RetransmitBuffer`] (written by the send loop, read by
+/// replay connections).
+pub type SharedRetransmitBuffer = Arc<Mutex<RetransmitBuffer>>;
+
+impl RetransmitBuffer {
+    /// Create a buffer retaining up to `capacity` packets per channel.
+    pub fn new(capacity: usize) -> Self {
+        Self { capacity: capacity.max(1), channels: Default::default() }
+    }
+
+    /// Record a sent packet. Sequence numbers must be increasing per channel.
+    pub fn push(&mut self, channel: usize, seq: u64, packet: Vec<u8>) {
+        let Some(ring) = self.channels.get_mut(channel) else { return };
+        if ring.len() == self.capacity {
+            ring.pop_front();
+        }
+        ring.push_back((seq, packet));
+    }
+
+    /// Collect the packets with `from <= seq <= to` still held for `channel`.
+    pub fn range(&self, channel: usize, from: u64, to: u64) -> Vec<Vec<u8>> {
+        let Some(ring) = self.channels.get(channel) else { return vec![] };
+        let Some(&(first, _)) = ring.front() else { return vec![] };
+        // Sequence numbers are contiguous within a channel, so index directly.
+        let start = from.saturating_sub(first) as usize;
+        ring.iter()
+            .skip(start)
+            .take_while(|(s, _)| *s <= to)
+            .filter(|(s, _)| *s >= from)
+            .map(|(_, p)| p.clone())
+            .collect()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Server
+// ---------------------------------------------------------------------------
+
+/// Bind the replay service on `addr` and serve requests from `buffer` in a
+/// background task. Returns the bound address and the task handle.
+pub async fn spawn_replay_server(
+    addr: SocketAddr,
+    buffer: SharedRetransmitBuffer,
+) -> anyhow::Result<(SocketAddr, tokio::task::JoinHandle<()>)> {
+    let listener = TcpListener::bind(addr).await?;
+    let local = listener.local_addr()?;
+    info!("UDP replay service listening on {local}");
+
+    let task = tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, peer)) => {
+                    let buffer = buffer.clone();
+                    tokio::spawn(async move {
+                        if let Err(e) = serve_connection(stream, buffer).await {
+                            debug!("UDP replay connection {peer} closed: {e}");
+                        }
+                    });
+                }
+                Err(e) => warn!("UDP replay accept error: {e}"),
+            }
+        }
+    });
+
+    Ok((local, task))
+}
+
+/// Serve requests on one connection until the client disconnects.
+async fn serve_connection(stream: TcpStream, buffer: SharedRetransmitBuffer) -> anyhow::Result<()> {
+    stream.set_nodelay(true)?;
+    let (mut rd, wr) = stream.into_split();
+    let mut wr = BufWriter::new(wr);
+    let mut req = [0u8; REQUEST_LEN];
+
+    loop {
+        rd.read_exact(&mut req).await?;
+        let channel = req[0] as usize;
+        let from = u64::from_le_bytes(req[1..9].try_into()?);
+        let to = u64::from_le_bytes(req[9..17].try_into()?).min(from.saturating_add(MAX_REPLAY_RANGE - 1));
+
+        // Copy out under the lock, write without holding it.
+        let packets = buffer.lock().map(|b| b.range(channel, from, to)).unwrap_or_default();
+        debug!("UDP replay: channel {channel} seq {from}..={to} → {} packets", packets.len());
+
+        for p in &packets {
+            wr.write_u32_le(p.len() as u32).await?;
+            wr.write_all(p).await?;
+        }
+        wr.write_u32_le(0).await?;
+        wr.flush().await?;
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Client
+// ---------------------------------------------------------------------------
+
+/// Request packets `from..=to` on `channel` from the replay service at `addr`.
+pub async fn fetch_range(addr: SocketAddr, channel: u8, from: u64, to: u64) -> anyhow::Result<Vec<Vec<u8>>> {
+    let mut stream = TcpStream::connect(addr).await?;
+    stream.set_nodelay(true)?;
+
+    let mut req = [0u8; REQUEST_LEN];
+    req[0] = channel;
+    req[1..9].copy_from_slice(&from.to_le_bytes());
+    req[9..17].copy_from_slice(&to.to_le_bytes());
+    stream.write_all(&req).await?;
+
+    let mut packets = Vec::new();
+    loop {
+        let len = stream.read_u32_le().await? as usize;
+        if len == 0 {
+            break;
+        }
+        if len > super::MAX_UDP_PAYLOAD {
+            anyhow::bail!("replay frame too large: {len}");
+        }
+        let mut p = vec![0u8; len];
+        stream.read_exact(&mut p).await?;
+        packets.push(p);
+    }
+    Ok(packets)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn buffer_range_and_eviction() {
+        let mut b = RetransmitBuffer::new(3);
+        for seq in 1..=5u64 {
+            b.push(0, seq, vec![seq as u8]);
+        }
+        // 1 and 2 evicted.
+        assert_eq!(b.range(0, 1, 5), vec![vec![3], vec![4], vec![5]]);
+        assert_eq!(b.range(0, 4, 4), vec![vec![4]]);
+        assert!(b.range(0, 6, 9).is_empty());
+        assert!(b.range(1, 1, 5).is_empty());
+        assert!(b.range(99, 1, 5).is_empty());
+    }
+
+    #[tokio::test]
+    async fn server_round_trip() {
+        let buffer = Arc::new(Mutex::new(RetransmitBuffer::new(16)));
+        for seq in 1..=10u64 {
+            buffer.lock().unwrap().push(2, seq, vec![2, seq as u8]);
+        }
+        let (addr, task) = spawn_replay_server("127.0.0.1:0".parse().unwrap(), buffer).await.unwrap();
+
+        let got = fetch_range(addr, 2, 4, 6).await.unwrap();
+        assert_eq!(got, vec![vec![2, 4], vec![2, 5], vec![2, 6]]);
+        assert!(fetch_range(addr, 0, 1, 3).await.unwrap().is_empty());
+
+        task.abort();
+    }
+}
@@ -0,0 +1,339 @@
+//! Asynchronous UDP market data sender.
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use tokio::net::UdpSocket;
+use tracing::{debug, warn};
+
+use super::{
+    encode_heartbeat, encode_msg,
+    heartbeat::{HEARTBEAT_CHANNELS, UdpHeartbeat, channel_index},
+    queue::{DropPolicy, PushOutcome, SendQueue},
+    replay::{RetransmitBuffer, SharedRetransmitBuffer, spawn_replay_server},
+    socket::{UdpSocketOptions, bind_udp, sender_bind_addr},
+};
+use crate::{
+    time_util,
+    types::{Exchange, MarketDataMsg},
+};
+
+/// Options for a [`UdpSender`].
+#[derive(Debug, Clone)]
+pub struct UdpSenderOptions {
+    /// Source identifier carried in heartbeats. Senders feeding the same
+    /// receiver must use distinct ids.
+    pub source_id: u32,
+    /// Exchange stamped into every packet header, for routing on the
+    /// receiver side.
+    pub exchange: Option<Exchange>,
+    /// Heartbeat interval. `None` disables heartbeats.
+    pub heartbeat_interval: Option<Duration>,
+    /// Address to serve the TCP replay service on. `None` disables replay.
+    pub replay_addr: Option<SocketAddr>,
+    /// Packets retained per channel for replay.
+    pub retransmit_capacity: usize,
+    /// Maximum number of queued messages awaiting send.
+    pub queue_capacity: usize,
+    /// What to discard when the queue is full.
+    pub drop_policy: DropPolicy,
+    /// Socket tuning (buffers, TOS, interface, source IP).
+    pub socket: UdpSocketOptions,
+}
+
+impl Default for UdpSenderOptions {
+    fn default() -> Self {
+        Self {
+            source_id: 0,
+            exchange: None,
+            heartbeat_interval: Some(Duration::from_secs(1)),
+            replay_addr: None,
+            retransmit_capacity: 16_384,
+            queue_capacity: 4096,
+            drop_policy: DropPolicy::DropNewest,
+            socket: UdpSocketOptions::default(),
+        }
+    }
+}
+
+/// Asynchronous UDP market data sender.
+///
+/// Messages are submitted via a bounded queue and sent from a background tokio
+/// task. This decouples the hot path (market data parsing) from network I/O.
+/// The same task emits periodic heartbeats so receivers can detect a dead
+/// sender even when the market is quiet.
+///
+/// When the queue is full, [`UdpSenderOptions::drop_policy`] decides what is
+/// discarded; every outcome is counted in [`UdpSender::stats`].
+///
+/// Every packet carries a per-channel sequence number. With
+/// [`UdpSenderOptions::replay_addr`] set, the most recent packets are also
+/// kept in a [`RetransmitBuffer`] and served over TCP so receivers can fill
+/// gaps.
+pub struct UdpSender {
+    queue: Arc<SendQueue>,
+    counters: Arc<Counters>,
+    drop_log: RateLimit,
+    _task: tokio::task::JoinHandle<()>,
+    replay_task: Option<tokio::task::JoinHandle<()>>,
+}
+
+impl UdpSender {
+    /// Create and start a new UDP sender targeting `dest_addr` with default
+    /// options.
+    pub async fn new(dest_addr: SocketAddr) -> anyhow::Result<Self> {
+        Self::with_options(dest_addr, UdpSenderOptions::default()).await
+    }
+
+    /// Create and start a new UDP sender targeting `dest_addr`.
+    pub async fn with_options(dest_addr: SocketAddr, options: UdpSenderOptions) -> anyhow::Result<Self> {
+        let socket = bind_udp(sender_bind_addr(dest_addr, &options.socket), &options.socket, false)?;
+        socket.connect(dest_addr).await?;
+        let queue = Arc::new(SendQueue::new(options.queue_capacity, options.drop_policy));
+        let counters = Arc::new(Counters::default());
+
+        let mut replay = None;
+        let mut replay_task = None;
+        if let Some(addr) = options.replay_addr {
+            let buffer = Arc::new(Mutex::new(RetransmitBuffer::new(options.retransmit_capacity)));
+            let (local, task) = spawn_replay_server(addr, buffer.clone()).await?;
+            replay = Some((buffer, local.port()));
+            replay_task = Some(task);
+        }
+
+        let task = tokio::spawn(send_loop(socket, queue.clone(), counters.clone(), options, replay));
+
+        Ok(Self { queue, counters, drop_log: RateLimit::new(DROP_LOG_INTERVAL_US), _task: task, replay_task })
+    }
+
+    /// Enqueue a market data message for sending.
+    ///
+    /// Returns immediately. If the queue is full, the drop policy applies;
+    /// drops are logged at most once per second with running totals.
+    #[inline]
+    pub fn send(&self, msg: MarketDataMsg) {
+        let c = &self.counters;
+        let outcome = self.queue.push(msg);
+        match outcome {
+            PushOutcome::Enqueued => {
+                c.enqueued.fetch_add(1, Ordering::Relaxed);
+            }
+            PushOutcome::Conflated => {
+                c.conflated.fetch_add(1, Ordering::Relaxed);
+            }
+            PushOutcome::Evicted | PushOutcome::Dropped => {
+                if outcome == PushOutcome::Evicted {
+                    // The new message is queued; an older one made room.
+                    c.enqueued.fetch_add(1, Ordering::Relaxed);
+                }
+                let dropped = c.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+                if self.drop_log.allow() {
+                    warn!("UDP sender queue full — {dropped} messages dropped so far ({})", self.stats());
+                }
+            }
+        }
+    }
+
+    /// Snapshot of the sender's counters.
+    pub fn stats(&self) -> UdpSenderStats {
+        self.counters.snapshot(self.queue.len())
+    }
+}
+
+impl Drop for UdpSender {
+    fn drop(&mut self) {
+        self.queue.close();
+        // The replay listener does not exit on its own.
+        if let Some(task) = self.replay_task.take() {
+            task.abort();
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Stats
+// ---------------------------------------------------------------------------
+
+/// Point-in-time sender counters (totals since start).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct UdpSenderStats {
+    /// Messages accepted into the queue.
+    pub enqueued: u64,
+    /// Book updates that replaced a still-queued one for the same symbol.
+    pub conflated: u64,
+    /// Messages discarded because the queue was full.
+    pub dropped: u64,
+    /// Packets handed to the socket.
+    pub sent: u64,
+    /// Socket send failures.
+    pub send_errors: u64,
+    /// Messages that failed to serialize.
+    pub encode_failed: u64,
+    /// Messages currently queued.
+    pub queued: u64,
+}
+
+impl std::fmt::Display for UdpSenderStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "enqueued={} conflated={} dropped={} sent={} send_errors={} encode_failed={} queued={}",
+            self.enqueued, self.conflated, self.dropped, self.sent, self.send_errors, self.encode_failed, self.queued,
+        )
+    }
+}
+
+#[derive(Default)]
+struct Counters {
+    enqueued: AtomicU64,
+    conflated: AtomicU64,
+    dropped: AtomicU64,
+    sent: AtomicU64,
+    send_errors: AtomicU64,
+    encode_failed: AtomicU64,
+}
+
+impl Counters {
+    fn snapshot(&self, queued: u64) -> UdpSenderStats {
+        UdpSenderStats {
+            enqueued: self.enqueued.load(Ordering::Relaxed),
+            conflated: self.conflated.load(Ordering::Relaxed),
+            dropped: self.dropped.load(Ordering::Relaxed),
+            sent: self.sent.load(Ordering::Relaxed),
+            send_errors: self.send_errors.load(Ordering::Relaxed),
+            encode_failed: self.encode_failed.load(Ordering::Relaxed),
+            queued,
+        }
+    }
+}
+
+/// Minimum spacing between "queue full" / error log lines.
+const DROP_LOG_INTERVAL_US: u64 = 1_000_000;
+
+/// Lock-free "at most once per interval" gate for log lines.
+struct RateLimit {
+    interval_us: u64,
+    last_us: AtomicU64,
+}
+
+impl RateLimit {
+    fn new(interval_us: u64) -> Self {
+        Self { interval_us, last_us: AtomicU64::new(0) }
+    }
+
+    fn allow(&self) -> bool {
+        let now = time_util::monotonic_us();
+        let last = self.last_us.load(Ordering::Relaxed);
+        (last == 0 || now.saturating_sub(last) >= self.interval_us)
+            && self.last_us.compare_exchange(last, now, Ordering::Relaxed, Ordering::Relaxed).is_ok()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Send loop
+// ---------------------------------------------------------------------------
+
+/// Messages sent per wakeup before heartbeats get a chance to run.
+const SEND_BATCH: usize = 256;
+
+/// Background task: drains the queue and interleaves heartbeats.
+async fn send_loop(
+    socket: UdpSocket,
+    queue: Arc<SendQueue>,
+    counters: Arc<Counters>,
+    options: UdpSenderOptions,
+    replay: Option<(SharedRetransmitBuffer, u16)>,
+) {
+    let started_us = time_util::monotonic_us();
+    let mut msg_counts = [0u64; HEARTBEAT_CHANNELS];
+    let mut last_update_us = [0u64; HEARTBEAT_CHANNELS];
+    let error_log = RateLimit::new(DROP_LOG_INTERVAL_US);
+
+    let mut hb_interval = options.heartbeat_interval.map(|d| {
+        let mut interval = tokio::time::interval(d);
+        interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        interval
+    });
+    let interval_ms = options.heartbeat_interval.map(|d| d.as_millis() as u32).unwrap_or(0);
+
+    loop {
+        tokio::select! {
+            _ = queue.notified() => {
+                for _ in 0..SEND_BATCH {
+                    let Some(msg) = queue.pop() else { break };
+                    let ch = channel_index(&msg);
+                    let seq = msg_counts[ch] + 1;
+                    let Some(bytes) = encode_msg(&msg, options.exchange, seq) else {
+                        let n = counters.encode_failed.fetch_add(1, Ordering::Relaxed) + 1;
+                        if error_log.allow() {
+                            warn!("UDP encode failed ({n} total), dropping message");
+                        }
+                        continue;
+                    };
+                    // The sequence number is consumed even if the send
+                    // fails, so the receiver sees a gap it can replay.
+                    msg_counts[ch] = seq;
+                    last_update_us[ch] = msg.local_time_us();
+                    match socket.send(&bytes).await {
+                        Ok(_) => {
+                            counters.sent.fetch_add(1, Ordering::Relaxed);
+                        }
+                        Err(e) => {
+                            let n = counters.send_errors.fetch_add(1, Ordering::Relaxed) + 1;
+                            if error_log.allow() {
+                                warn!("UDP send error ({n} total): {e}");
+                            }
+                        }
+                    }
+                    if let Some((buffer, _)) = &replay
+                        && let Ok(mut b) = buffer.lock()
+                    {
+                        b.push(ch, seq, bytes);
+                    }
+                }
+                if queue.len() > 0 {
+                    queue.wake();
+                } else if queue.is_closed() {
+                    break;
+                }
+            }
+
+            _ = tick_or_pending(&mut hb_interval) => {
+                let hb = UdpHeartbeat {
+                    source_id: options.source_id,
+                    interval_ms,
+                    uptime_us: time_util::monotonic_us() - started_us,
+                    send_time_us: time_util::now_us(),
+                    msg_counts,
+                    last_update_us,
+                    replay_port: replay.as_ref().map(|(_, port)| *port).unwrap_or(0),
+                };
+                match encode_heartbeat(&hb, options.exchange) {
+                    Some(bytes) => {
+                        if let Err(e) = socket.send(&bytes).await {
+                            warn!("UDP heartbeat send error: {e}");
+                        }
+                    }
+                    None => warn!("UDP heartbeat encode failed"),
+                }
+            }
+        }
+    }
+    debug!("UDP sender task exited");
+}
+
+/// Wait for the next tick of `interval`, or forever if there is none.
+async fn tick_or_pending(interval: &mut Option<tokio::time::Interval>) {
+    match interval {
+        Some(i) => {
+            i.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
@@ -0,0 +1,224 @@
+//! UDP socket construction and kernel receive timestamps.
+//!
+//! Sockets are built with `socket2` so buffer sizes, busy polling, TOS/DSCP
+//! marking and interface binding can be applied before `bind`. On Linux the
+//! receiver can enable `SO_TIMESTAMPNS` and read the kernel receive time of
+//! each datagram from its control message.
+
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+};
+
+use serde::Deserialize;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::net::UdpSocket;
+use tracing::warn;
+
+/// Tuning applied to a UDP socket before it is bound.
+///
+/// Every field is optional; unset fields leave the kernel default in place.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct UdpSocketOptions {
+    /// `SO_RCVBUF` in bytes (capped by `net.core.rmem_max`).
+    pub recv_buffer_size: Option<usize>,
+    /// `SO_SNDBUF` in bytes (capped by `net.core.wmem_max`).
+    pub send_buffer_size: Option<usize>,
+    /// `SO_BUSY_POLL` in microseconds (Linux; raising it above the
+    /// `net.core.busy_read` default needs `CAP_NET_ADMIN`).
+    pub busy_poll_us: Option<u32>,
+    /// IP TOS byte (`IP_TOS` / `IPV6_TCLASS`). DSCP occupies the upper six
+    /// bits, e.g. DSCP 46 (EF) is `184`.
+    pub tos: Option<u32>,
+    /// Bind to this network interface (`SO_BINDTODEVICE`, Linux).
+    pub interface: Option<String>,
+    /// Source IP to bind to. For receivers the listen address already
+    /// decides this; for senders it selects the outgoing address.
+    pub source_ip: Option<IpAddr>,
+}
+
+/// Create a non-blocking UDP socket with `opts` applied and bind it to `addr`.
+///
+/// `timestamps` enables `SO_TIMESTAMPNS` (Linux only; ignored elsewhere).
+/// Options the kernel refuses for lack of privilege are logged and skipped;
+/// a failed interface binding is an error.
+pub(crate) fn bind_udp(addr: SocketAddr, opts: &UdpSocketOptions, timestamps: bool) -> io::Result<UdpSocket> {
+    let socket = Socket::new(Domain::for_address(addr), Type::DGRAM, Some(Protocol::UDP))?;
+
+    if let Some(size) = opts.recv_buffer_size {
+        socket.set_recv_buffer_size(size)?;
+        let actual = socket.recv_buffer_size()?;
+        if actual < size {
+            warn!("UDP SO_RCVBUF capped at {actual} bytes (requested {size}); raise net.core.rmem_max");
+        }
+    }
+    if let Some(size) = opts.send_buffer_size {
+        socket.set_send_buffer_size(size)?;
+        let actual = socket.send_buffer_size()?;
+        if actual < size {
+            warn!("UDP SO_SNDBUF capped at {actual} bytes (requested {size}); raise net.core.wmem_max");
+        }
+    }
+    if let Some(tos) = opts.tos {
+        if addr.is_ipv4() {
+            socket.set_tos_v4(tos)?;
+        } else {
+            socket.set_tclass_v6(tos)?;
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    {
+        if let Some(us) = opts.busy_poll_us
+            && let Err(e) = socket.set_busy_poll(us)
+        {
+            warn!("UDP SO_BUSY_POLL={us} not applied: {e}");
+        }
+        if let Some(iface) = &opts.interface {
+            socket.bind_device(Some(iface.as_bytes()))?;
+        }
+        if timestamps {
+            set_timestampns(&socket)?;
+        }
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let _ = timestamps;
+        if opts.busy_poll_us.is_some() || opts.interface.is_some() {
+            warn!("UDP busy_poll_us / interface are only supported on Linux; ignoring");
+        }
+    }
+
+    socket.set_nonblocking(true)?;
+    socket.bind(&addr.into())?;
+    UdpSocket::from_std(socket.into())
+}
+
+/// Local address a sender should bind to for reaching `dest`.
+pub(crate) fn sender_bind_addr(dest: SocketAddr, opts: &UdpSocketOptions) -> SocketAddr {
+    let ip = opts.source_ip.unwrap_or(match dest {
+        SocketAddr::V4(_) => IpAddr::from([0, 0, 0, 0]),
+        SocketAddr::V6(_) => IpAddr::from([0u16; 8]),
+    });
+    SocketAddr::new(ip, 0)
+}
+
+#[cfg(target_os = "linux")]
+fn set_timestampns(socket: &Socket) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+
+    let on: libc::c_int = 1;
+    // SAFETY: valid fd and a correctly sized c_int option value.
+    let rc = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_SOCKET,
+            libc::SO_TIMESTAMPNS,
+            &on as *const libc::c_int as *const libc::c_void,
+            std::mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    if rc == 0 { Ok(()) } else { Err(io::Error::last_os_error()) }
+}
+
+/// Receive one datagram, returning `(len, peer, kernel_rx_us)`.
+///
+/// `kernel_rx_us` is the `SO_TIMESTAMPNS` receive time in µs since the epoch,
+/// or `None` if timestamps are disabled or unsupported.
+pub(crate) async fn recv_from_ts(socket: &UdpSocket, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, Option<u64>)> {
+    #[cfg(target_os = "linux")]
+    {
+        socket.async_io(tokio::io::Interest::READABLE, || recvmsg_ts(socket, buf)).await
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        let (n, peer) = socket.recv_from(buf).await?;
+        Ok((n, peer, None))
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn recvmsg_ts(socket: &UdpSocket, buf: &mut [u8]) -> io::Result<(usize, SocketAddr, Option<u64>)> {
+    use std::os::fd::AsRawFd;
+
+    // Room for one `timespec` control message, 8-byte aligned.
+    let mut control = [0u64; 8];
+    let mut ts_us = None;
+
+    // SAFETY: `try_init` hands us zeroed address storage; every pointer placed
+    // in `msghdr` refers to a live local buffer of the stated length.
+    let (n, addr) = unsafe {
+        socket2::SockAddr::try_init(|storage, len| {
+            let mut iov = libc::iovec { iov_base: buf.as_mut_ptr() as *mut libc::c_void, iov_len: buf.len() };
+            let mut msg: libc::msghdr = std::mem::zeroed();
+            msg.msg_name = storage as *mut libc::c_void;
+            msg.msg_namelen = *len;
+            msg.msg_iov = &mut iov;
+            msg.msg_iovlen = 1;
+            msg.msg_control = control.as_mut_ptr() as *mut libc::c_void;
+            msg.msg_controllen = std::mem::size_of_val(&control);
+
+            let n = libc::recvmsg(socket.as_raw_fd(), &mut msg, 0);
+            if n < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            *len = msg.msg_namelen;
+
+            let mut cmsg = libc::CMSG_FIRSTHDR(&msg);
+            while !cmsg.is_null() {
+                if (*cmsg).cmsg_level == libc::SOL_SOCKET && (*cmsg).cmsg_type == libc::SCM_TIMESTAMPNS {
+                    let ts = std::ptr::read_unaligned(libc::CMSG_DATA(cmsg) as *const libc::timespec);
+                    ts_us = Some(ts.tv_sec as u64 * 1_000_000 + ts.tv_nsec as u64 / 1_000);
+                }
+                cmsg = libc::CMSG_NXTHDR(&msg, cmsg);
+            }
+            Ok(n as usize)
+        })?
+    };
+
+    let peer = addr.as_socket().ok_or_else(|| io::Error::other("recvmsg: non-IP peer address"))?;
+    Ok((n, peer, ts_us))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::time_util;
+
+    #[tokio::test]
+    async fn options_applied_and_kernel_timestamp_read() {
+        let opts = UdpSocketOptions {
+            recv_buffer_size: Some(1 << 20),
+            send_buffer_size: Some(1 << 20),
+            tos: Some(184),
+            ..Default::default()
+        };
+        let rx = bind_udp("127.0.0.1:0".parse().unwrap(), &opts, true).unwrap();
+        let dest = rx.local_addr().unwrap();
+        let tx = bind_udp(sender_bind_addr(dest, &opts), &opts, false).unwrap();
+
+        let before = time_util::now_us();
+        tx.send_to(b"hello", dest).await.unwrap();
+
+        let mut buf = [0u8; 64];
+        let (n, peer, ts) = recv_from_ts(&rx, &mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"hello");
+        assert_eq!(peer.port(), tx.local_addr().unwrap().port());
+        if cfg!(target_os = "linux") {
+            let ts = ts.expect("kernel timestamp");
+            assert!(ts + 1_000 >= before && ts <= time_util::now_us() + 1_000);
+        }
+    }
+
+    #[tokio::test]
+    async fn no_timestamp_when_disabled() {
+        let rx = bind_udp("127.0.0.1:0".parse().unwrap(), &UdpSocketOptions::default(), false).unwrap();
+        let dest = rx.local_addr().unwrap();
+        let tx = tokio::net::UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        tx.send_to(b"x", dest).await.unwrap();
+
+        let mut buf = [0u8; 8];
+        let (_, _, ts) = recv_from_ts(&rx, &mut buf).await.unwrap();
+        assert_eq!(ts, None);
+    }
+}
@@ -1,19 +1,40 @@
 //! Single WebSocket connection with auto-reconnect and ping keep-alive.
 //!
 //! Each `WsConnection` runs as a tokio task that:
-//! 1. Connects to the exchange WebSocket endpoint (TLS).
+//! 1. Connects to the exchange WebSocket endpoint (TLS), optionally from a specific source IP /
+//!    interface and through an HTTP CONNECT or SOCKS5 proxy.
 //! 2. Sends the subscription message.
 //! 3. Reads messages and forwards them to a callback.
-//! 4. Sends periodic ping messages (exchange-specific format).
-//! 5. Automatically reconnects on disconnection with exponential backoff.
+//! 4. Sends periodic ping messages (exchange-specific format), measures the round trip to the
+//!    matching pong and drops the connection when pongs stop.
+//! 5. Records per-connection stats (see [`WsStats`]).
+//! 6. Automatically reconnects on disconnection with jittered exponential backoff, up to an
+//!    optional failure cap (see [`ReconnectPolicy`]), publishing lifecycle events to an optional
+//!    [`WsEventSink`].
 
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{collections::HashMap, net::IpAddr, sync::Arc, time::Duration};
 
 use futures_util::{SinkExt, StreamExt};
-use tokio::sync::{mpsc, watch};
-use tokio_tungstenite::tungstenite::Message;
+use tokio::{
+    net::TcpStream,
+    sync::{broadcast, mpsc, watch},
+};
+use tokio_tungstenite::{
+    MaybeTlsStream,
+    tungstenite::{Message, protocol::WebSocketConfig},
+};
 use tracing::{debug, error, info, warn};
 
+use super::{
+    compression::{BinaryCompression, DEFAULT_MAX_MESSAGE_SIZE, EXTENSION_OFFER, InflateStream},
+    dial::{LocalBind, dial},
+    event::{DisconnectReason, EVENT_CAPACITY, WsEvent, WsEventKind, WsEventSink},
+    proxy::ProxyConfig,
+    reconnect::ReconnectPolicy,
+    stats::{WsConnStats, WsStats},
+};
+use crate::time_util;
+
 /// Callback invoked for each received text message.
 ///
 /// Parameters: `(connection_id, message_text)`
@@ -35,6 +56,25 @@ pub enum PingPayload {
     WebSocketPing,
 }
 
+/// Recognises an exchange's reply to a text [`PingPayload`].
+#[derive(Debug, Clone)]
+pub enum PongMatcher {
+    /// The whole text frame equals this string (e.g. OKX/Bitget `"pong"`).
+    Exact(String),
+    /// The text frame contains this string (e.g. Bybit `"\"pong\""`).
+    Contains(String),
+}
+
+impl PongMatcher {
+    /// Whether `text` is a pong.
+    pub fn matches(&self, text: &str) -> bool {
+        match self {
+            Self::Exact(s) => text == s,
+            Self::Contains(s) => text.contains(s.as_str()),
+        }
+    }
+}
+
 /// Configuration for a single WebSocket connection.
 #[derive(Debug, Clone)]
 pub struct WsConnConfig {
@@ -48,18 +88,107 @@ pub struct WsConnConfig {
     pub ping_interval: Option<Duration>,
     /// Ping message format.
     pub ping_payload: Option<PingPayload>,
+    /// Reply to a text ping. Matched replies yield a round-trip time and are
+    /// not forwarded to the callback. WebSocket ping frames need no matcher.
+    pub pong: Option<PongMatcher>,
+    /// Drop the connection when a ping goes unanswered this long (`None` =
+    /// never). Only applies to pings that can be answered: WebSocket pings
+    /// and text pings with a [`pong`](Self::pong) matcher.
+    pub pong_timeout: Option<Duration>,
+    /// Offer permessage-deflate; compressed frames are inflated before the
+    /// callbacks see them.
+    pub permessage_deflate: bool,
+    /// Inflate binary frame payloads compressed this way. Payloads that
+    /// decompress to UTF-8 go to the text callback, others to the binary one.
+    pub binary_compression: Option<BinaryCompression>,
+    /// Largest frame payload and message, after inflating, accepted. A
+    /// larger frame or permessage-deflate message drops the connection; a
+    /// larger binary payload is dropped.
+    pub max_message_size: usize,
+    /// Egress proxy to tunnel through (`None` = connect directly).
+    pub proxy: Option<ProxyConfig>,
+    /// Local source IPs / interfaces to connect from. The first is used
+    /// until a connect attempt fails, then the next, and so on (empty = OS
+    /// choice). [`RedundantWsClient`](super::RedundantWsClient) rotates this
+    /// list per connection so each one starts on a different path.
+    pub local_binds: Vec<LocalBind>,
+    /// Connect to this exchange IP instead of resolving the URL host. TLS
+    /// SNI and the `Host` header still use the URL host.
+    pub pinned_ip: Option<IpAddr>,
+    /// Backoff, jitter and failure cap for reconnects.
+    pub reconnect: ReconnectPolicy,
+    /// Where to publish lifecycle events. If `None`, the connection creates
+    /// its own channel, tagged with the URL (see
+    /// [`WsConnection::subscribe_events`]).
+    pub events: Option<WsEventSink>,
     /// Connection identifier (unique within a RedundantWsClient).
     pub id: usize,
 }
 
+impl WsConnConfig {
+    /// A connection to `url` with no subscription, pings, proxy or local
+    /// binds, the default reconnect policy and no event sink.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            subscribe_msg: None,
+            extra_headers: HashMap::new(),
+            ping_interval: None,
+            ping_payload: None,
+            pong: None,
+            pong_timeout: None,
+            permessage_deflate: false,
+            binary_compression: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            proxy: None,
+            local_binds: Vec::new(),
+            pinned_ip: None,
+            reconnect: ReconnectPolicy::default(),
+            events: None,
+            id: 0,
+        }
+    }
+
+    pub(crate) fn emit(&self, kind: WsEventKind) {
+        if let Some(sink) = &self.events {
+            sink.emit(self.id, kind);
+        }
+    }
+
+    /// The pong timeout, if pings on this connection can be answered:
+    /// WebSocket pings always can, text pings only with a pong matcher.
+    pub(crate) fn effective_pong_timeout(&self) -> Option<Duration> {
+        self.pong_timeout.filter(|_| match self.ping_payload {
+            Some(PingPayload::Text(_) | PingPayload::Json(_)) => self.pong.is_some(),
+            Some(PingPayload::WebSocketPing) | None => true,
+        })
+    }
+
+    /// The next ping frame. WebSocket pings carry their monotonic send time
+    /// so the pong yields a round-trip time.
+    pub(crate) fn ping_message(&self) -> Message {
+        match &self.ping_payload {
+            Some(PingPayload::Text(t)) => Message::Text(t.clone().into()),
+            Some(PingPayload::Json(j)) => Message::Text(j.to_string().into()),
+            Some(PingPayload::WebSocketPing) | None => {
+                Message::Ping(time_util::monotonic_us().to_le_bytes().to_vec().into())
+            }
+        }
+    }
+}
+
 /// A single WebSocket connection managed by a background tokio task.
 pub struct WsConnection {
     /// Connection configuration.
     pub config: WsConnConfig,
     /// Channel to send outbound messages.
     outbound_tx: Option<mpsc::Sender<String>>,
+    /// Subscription sent on each (re)connect; starts as `config.subscribe_msg`.
+    subscribe_tx: watch::Sender<Option<String>>,
     /// Shutdown signal sender.
     shutdown_tx: Option<watch::Sender<bool>>,
+    /// Counters updated by the connection task.
+    stats: WsStats,
     /// Task join handle.
     task: Option<tokio::task::JoinHandle<()>>,
 }
@@ -67,7 +196,32 @@ pub struct WsConnection {
 impl WsConnection {
     /// Create a new (not yet started) connection.
     pub fn new(config: WsConnConfig) -> Self {
-        Self { config, outbound_tx: None, shutdown_tx: None, task: None }
+        Self::with_stats(config, WsStats::default())
+    }
+
+    /// Create a new connection that records into `stats`, a handle the
+    /// caller keeps for monitoring.
+    pub fn with_stats(mut config: WsConnConfig, stats: WsStats) -> Self {
+        if config.events.is_none() {
+            config.events = Some(WsEventSink::new(config.url.as_str(), broadcast::channel(EVENT_CAPACITY).0));
+        }
+        let subscribe_tx = watch::channel(config.subscribe_msg.clone()).0;
+        Self { config, outbound_tx: None, subscribe_tx, shutdown_tx: None, stats, task: None }
+    }
+
+    /// Receive this connection's lifecycle events from now on.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<WsEvent> {
+        self.config.events.as_ref().map_or_else(|| broadcast::channel(1).1, WsEventSink::subscribe)
+    }
+
+    /// Current stats of this connection.
+    pub fn stats(&self) -> WsConnStats {
+        self.stats.snapshot()
+    }
+
+    /// Shared handle to this connection's stats.
+    pub fn stats_handle(&self) -> WsStats {
+        self.stats.clone()
     }
 
     /// Start the connection task.
@@ -78,9 +232,11 @@ impl WsConnection {
         let (shutdown_tx, shutdown_rx) = watch::channel(false);
         let (outbound_tx, outbound_rx) = mpsc::channel::<String>(64);
         let config = self.config.clone();
+        let stats = self.stats.clone();
+        let subscribe_rx = self.subscribe_tx.subscribe();
 
         let task = tokio::spawn(async move {
-            connection_loop(config, on_text, on_binary, outbound_rx, shutdown_rx).await;
+            connection_loop(config, stats, on_text, on_binary, outbound_rx, subscribe_rx, shutdown_rx).await;
         });
 
         self.shutdown_tx = Some(shutdown_tx);
@@ -96,6 +252,13 @@ impl WsConnection {
         Ok(())
     }
 
+    /// Replace the subscription sent on every later (re)connect (`None` =
+    /// send nothing). Takes effect from the next session; use
+    /// [`send`](Self::send) to change the live one.
+    pub fn set_subscribe_msg(&self, msg: Option<String>) {
+        self.subscribe_tx.send_replace(msg);
+    }
+
     /// Stop the connection and wait for the task to finish.
     pub async fn stop(&mut self) {
         if let Some(tx) = self.shutdown_tx.take() {
@@ -107,17 +270,23 @@ impl WsConnection {
     }
 }
 
-/// Main connection loop — connects, subscribes, reads, pings, reconnects.
+type WsStream = tokio_tungstenite::WebSocketStream<InflateStream<MaybeTlsStream<TcpStream>>>;
+
+/// Main connection loop — connects, subscribes, reads, pings, reconnects
+/// according to the [`ReconnectPolicy`].
 async fn connection_loop(
     config: WsConnConfig,
+    stats: WsStats,
     on_text: OnMessageCallback,
     on_binary: Option<OnBinaryCallback>,
     mut outbound_rx: mpsc::Receiver<String>,
+    subscribe_rx: watch::Receiver<Option<String>>,
     mut shutdown_rx: watch::Receiver<bool>,
 ) {
-    let mut backoff = Duration::from_millis(100);
-    let max_backoff = Duration::from_secs(30);
     let conn_id = config.id;
+    let policy = &config.reconnect;
+    let mut failures = 0u32;
+    let mut bind_idx = 0;
 
     loop {
         // Check shutdown before connecting
@@ -126,90 +295,182 @@ async fn connection_loop(
             return;
         }
 
-        info!("[ws-{conn_id}] connecting to {}", config.url);
+        let bind = config.local_binds.get(bind_idx);
+        match bind {
+            Some(b) => info!("[ws-{conn_id}] connecting to {} from {b}", config.url),
+            None => info!("[ws-{conn_id}] connecting to {}", config.url),
+        }
+        config.emit(WsEventKind::Connecting { attempt: failures + 1 });
 
-        let ws_stream = match connect_ws(&config).await {
-            Ok(s) => {
-                backoff = Duration::from_millis(100); // reset backoff on success
+        let (reason, got_data) = match connect_ws(&config, bind).await {
+            Ok(ws_stream) => {
                 info!("[ws-{conn_id}] connected");
-                s
+                stats.on_connected();
+                let session = Session { config: &config, stats: &stats, on_text: &on_text, on_binary: &on_binary };
+                let sub_msg = subscribe_rx.borrow().clone();
+                session.run(ws_stream, sub_msg, &mut outbound_rx, &mut shutdown_rx).await
             }
             Err(e) => {
-                error!("[ws-{conn_id}] connection failed: {e}, retrying in {backoff:?}");
-                tokio::select! {
-                    _ = tokio::time::sleep(backoff) => {},
-                    _ = shutdown_rx.changed() => return,
+                error!("[ws-{conn_id}] connection failed: {e:#}");
+                // Fail over to the next local path, if any.
+                if !config.local_binds.is_empty() {
+                    bind_idx = (bind_idx + 1) % config.local_binds.len();
                 }
-                backoff = (backoff * 2).min(max_backoff);
-                continue;
+                (DisconnectReason::ConnectFailed(format!("{e:#}")), false)
             }
         };
+        let shutdown = reason == DisconnectReason::Shutdown;
+        stats.on_disconnected(&reason);
+        config.emit(WsEventKind::Disconnected { reason });
+        if shutdown {
+            info!("[ws-{conn_id}] shutdown signal received");
+            return;
+        }
 
+        // A session that delivered data resets the failure count.
+        failures = if got_data { 0 } else { failures + 1 };
+        let delay = if policy.circuit_open(failures) {
+            let retry_in = policy.cool_down();
+            config.emit(WsEventKind::GivingUp { failures, retry_in });
+            let Some(cool_down) = retry_in else {
+                error!("[ws-{conn_id}] giving up after {failures} consecutive failures");
+                return;
+            };
+            warn!("[ws-{conn_id}] {failures} consecutive failures, cooling down for {cool_down:?}");
+            failures = 0;
+            cool_down
+        } else {
+            let delay = policy.delay(failures);
+            warn!("[ws-{conn_id}] disconnected, reconnecting in {delay:?}");
+            delay
+        };
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {},
+            _ = shutdown_rx.changed() => return,
+        }
+    }
+}
+
+/// What one connected session needs from its connection.
+struct Session<'a> {
+    config: &'a WsConnConfig,
+    stats: &'a WsStats,
+    on_text: &'a OnMessageCallback,
+    on_binary: &'a Option<OnBinaryCallback>,
+}
+
+impl Session<'_> {
+    /// Subscribe, then read, ping and forward outbound messages until the
+    /// connection ends. Returns why it ended and whether any data frame arrived.
+    async fn run(
+        &self,
+        ws_stream: WsStream,
+        sub_msg: Option<String>,
+        outbound_rx: &mut mpsc::Receiver<String>,
+        shutdown_rx: &mut watch::Receiver<bool>,
+    ) -> (DisconnectReason, bool) {
+        let Self { config, stats, on_text, on_binary } = *self;
+        let conn_id = config.id;
         let (mut ws_write, mut ws_read) = ws_stream.split();
 
         // Send subscription message
-        if let Some(ref sub_msg) = config.subscribe_msg {
+        if let Some(sub_msg) = sub_msg {
             debug!("[ws-{conn_id}] subscribing: {sub_msg}");
-            if let Err(e) = ws_write.send(Message::Text(sub_msg.clone().into())).await {
+            if let Err(e) = ws_write.send(Message::Text(sub_msg.into())).await {
                 error!("[ws-{conn_id}] subscribe send failed: {e}");
-                continue;
+                return (DisconnectReason::SendFailed(e.to_string()), false);
             }
         }
+        config.emit(WsEventKind::Connected);
 
-        // Set up ping timer
-        let ping_interval = config.ping_interval.map(|d| tokio::time::interval(d));
+        // Set up ping timer; the first ping goes out one interval in.
+        let mut ping_timer = config.ping_interval.map(|d| tokio::time::interval_at(tokio::time::Instant::now() + d, d));
 
-        // Pin the interval for use in select!
-        tokio::pin! {
-            let ping_tick = async {
-                if let Some(mut interval) = ping_interval {
-                    loop {
-                        interval.tick().await;
-                    }
-                } else {
-                    // No pinging — wait forever
-                    std::future::pending::<()>().await
-                }
-            };
-        }
+        // Outstanding ping: monotonic send time and the deadline for its pong.
+        let pong_timeout = config.effective_pong_timeout();
+        let mut ping_sent: Option<u64> = None;
+        let mut pong_deadline: Option<tokio::time::Instant> = None;
+
+        let mut got_data = false;
+        // Reused buffer for compressed binary payloads.
+        let mut inflated = Vec::new();
 
         // Main read/write loop
-        loop {
+        let reason = loop {
             tokio::select! {
                 // Shutdown signal
                 _ = shutdown_rx.changed() => {
-                    info!("[ws-{conn_id}] shutdown signal received");
                     let _ = ws_write.close().await;
-                    return;
+                    break DisconnectReason::Shutdown;
                 }
 
                 // Incoming message
                 msg = ws_read.next() => {
                     match msg {
                         Some(Ok(Message::Text(text))) => {
+                            if let (Some(sent), Some(pong)) = (ping_sent, &config.pong)
+                                && pong.matches(&text)
+                            {
+                                stats.on_ping_rtt(Duration::from_micros(time_util::monotonic_us().saturating_sub(sent)));
+                                ping_sent = None;
+                                pong_deadline = None;
+                                continue;
+                            }
+                            got_data = true;
+                            stats.on_message(text.len());
                             on_text(conn_id, &text);
                         }
                         Some(Ok(Message::Binary(data))) => {
-                            if let Some(ref cb) = on_binary {
-                                cb(conn_id, &data);
+                            got_data = true;
+                            stats.on_message(data.len());
+                            let Some(kind) = config.binary_compression else {
+                                if let Some(cb) = on_binary {
+                                    cb(conn_id, &data);
+                                }
+                                continue;
+                            };
+                            inflated.clear();
+                            if let Err(e) = kind.decompress(&data, &mut inflated, config.max_message_size) {
+                                warn!("[ws-{conn_id}] dropping undecodable {kind:?} payload: {e}");
+                                continue;
+                            }
+                            match std::str::from_utf8(&inflated) {
+                                Ok(text) => on_text(conn_id, text),
+                                Err(_) => {
+                                    if let Some(cb) = on_binary {
+                                        cb(conn_id, &inflated);
+                                    }
+                                }
                             }
                         }
                         Some(Ok(Message::Ping(data))) => {
                             let _ = ws_write.send(Message::Pong(data)).await;
                         }
-                        Some(Ok(Message::Close(_))) => {
+                        Some(Ok(Message::Pong(data))) => {
+                            // Our pings carry their send time.
+                            if let Ok(sent) = <[u8; 8]>::try_from(&data[..]) {
+                                let rtt = time_util::monotonic_us().saturating_sub(u64::from_le_bytes(sent));
+                                stats.on_ping_rtt(Duration::from_micros(rtt));
+                            }
+                            ping_sent = None;
+                            pong_deadline = None;
+                        }
+                        Some(Ok(Message::Close(frame))) => {
                             warn!("[ws-{conn_id}] received close frame");
-                            break;
+                            break match frame {
+                                Some(f) => DisconnectReason::Closed { code: Some(f.code.into()), reason: f.reason.to_string() },
+                                None => DisconnectReason::Closed { code: None, reason: String::new() },
+                            };
                         }
                         Some(Err(e)) => {
                             error!("[ws-{conn_id}] read error: {e}");
-                            break;
+                            break DisconnectReason::ReadError(e.to_string());
                         }
                         None => {
                             warn!("[ws-{conn_id}] stream ended");
-                            break;
+                            break DisconnectReason::StreamEnded;
                         }
-                        _ => {} // Pong, Frame — ignore
+                        _ => {} // Frame — ignore
                     }
                 }
 
@@ -217,45 +478,66 @@ async fn connection_loop(
                 Some(msg) = outbound_rx.recv() => {
                     if let Err(e) = ws_write.send(Message::Text(msg.into())).await {
                         error!("[ws-{conn_id}] send error: {e}");
-                        break;
+                        break DisconnectReason::SendFailed(e.to_string());
                     }
                 }
 
                 // Ping timer
-                _ = &mut ping_tick => {
-                    let ping_msg = match &config.ping_payload {
-                        Some(PingPayload::Text(t)) => Message::Text(t.clone().into()),
-                        Some(PingPayload::Json(j)) => Message::Text(j.to_string().into()),
-                        Some(PingPayload::WebSocketPing) | None => {
-                            Message::Ping(vec![].into())
-                        }
-                    };
-                    if let Err(e) = ws_write.send(ping_msg).await {
+                _ = next_tick(&mut ping_timer) => {
+                    if let Err(e) = ws_write.send(config.ping_message()).await {
                         error!("[ws-{conn_id}] ping send error: {e}");
-                        break;
+                        break DisconnectReason::SendFailed(e.to_string());
+                    }
+                    // Time from the oldest unanswered ping.
+                    if ping_sent.is_none() {
+                        ping_sent = Some(time_util::monotonic_us());
+                        pong_deadline = pong_timeout.map(|d| tokio::time::Instant::now() + d);
                     }
                 }
+
+                // Pong overdue
+                _ = until(pong_deadline) => {
+                    let timeout = pong_timeout.unwrap_or_default();
+                    warn!("[ws-{conn_id}] no pong within {timeout:?}");
+                    break DisconnectReason::PongTimeout(timeout);
+                }
             }
-        }
+        };
+        (reason, got_data)
+    }
+}
 
-        // Disconnected — will reconnect at the top of the outer loop
-        warn!("[ws-{conn_id}] disconnected, reconnecting in {backoff:?}");
-        tokio::select! {
-            _ = tokio::time::sleep(backoff) => {},
-            _ = shutdown_rx.changed() => return,
+/// Wait for the next tick of `timer`; never completes without one.
+async fn next_tick(timer: &mut Option<tokio::time::Interval>) {
+    match timer {
+        Some(t) => {
+            t.tick().await;
         }
-        backoff = (backoff * 2).min(max_backoff);
+        None => std::future::pending().await,
+    }
+}
+
+/// Sleep until `deadline`; never completes without one.
+async fn until(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(d) => tokio::time::sleep_until(d).await,
+        None => std::future::pending().await,
     }
 }
 
 /// Establish a TLS WebSocket connection.
 ///
+/// TLS is set up here rather than by tungstenite so that an
+/// [`InflateStream`] can sit between it and tungstenite to undo
+/// permessage-deflate.
+///
 /// Uses `tungstenite::IntoClientRequest` to properly generate the
 /// `Sec-WebSocket-Key` and upgrade headers. Extra HTTP headers (e.g. API
-/// key for Binance SBE) are injected after the request is built.
-async fn connect_ws(
-    config: &WsConnConfig,
-) -> anyhow::Result<tokio_tungstenite::WebSocketStream<tokio_tungstenite::MaybeTlsStream<tokio::net::TcpStream>>> {
+/// key for Binance SBE) are injected after the request is built. With a
+/// proxy configured, the TLS handshake (SNI = exchange host) runs over the
+/// proxy tunnel. The TCP connection (to the exchange or the proxy) is made
+/// from `bind` when given, and goes to the pinned IP if one is set.
+async fn connect_ws(config: &WsConnConfig, bind: Option<&LocalBind>) -> anyhow::Result<WsStream> {
     use tokio_tungstenite::tungstenite::client::IntoClientRequest;
 
     // Let tungstenite generate proper WS handshake headers from the URL.
@@ -268,7 +550,292 @@ async fn connect_ws(
             tokio_tungstenite::tungstenite::http::HeaderValue::from_str(value)?,
         );
     }
+    if config.permessage_deflate {
+        request.headers_mut().insert("Sec-WebSocket-Extensions", EXTENSION_OFFER.parse()?);
+    }
 
-    let (stream, _response) = tokio_tungstenite::connect_async(request).await?;
+    let uri = request.uri();
+    let url_host = uri.host().ok_or_else(|| anyhow::anyhow!("no host in {}", config.url))?;
+    let url_host = url_host.trim_start_matches('[').trim_end_matches(']').to_string();
+    let host = match config.pinned_ip {
+        Some(ip) => ip.to_string(),
+        None => url_host.clone(),
+    };
+    let tls = uri.scheme_str() == Some("wss");
+    let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+
+    let tcp = match &config.proxy {
+        Some(proxy) => {
+            debug!("[ws-{}] tunnelling to {host}:{port} via {proxy}", config.id);
+            proxy.connect(&host, port, bind).await?
+        }
+        None => dial(&host, port, bind).await?,
+    };
+    let transport = if tls {
+        let connector = tokio_native_tls::TlsConnector::from(native_tls::TlsConnector::new()?);
+        MaybeTlsStream::NativeTls(connector.connect(&url_host, tcp).await?)
+    } else {
+        MaybeTlsStream::Plain(tcp)
+    };
+    let limits = WebSocketConfig::default()
+        .max_message_size(Some(config.max_message_size))
+        .max_frame_size(Some(config.max_message_size));
+    let stream = InflateStream::new(transport, config.permessage_deflate, config.max_message_size);
+    let (stream, _response) = tokio_tungstenite::client_async_with_config(request, stream, Some(limits)).await?;
+    if config.permessage_deflate {
+        debug!("[ws-{}] permessage-deflate active: {}", config.id, stream.get_ref().deflate_active());
+    }
     Ok(stream)
 }
+
+#[cfg(test)]
+mod tests {
+    use tokio::{net::TcpListener, sync::broadcast};
+    use tokio_tungstenite::tungstenite::protocol::{CloseFrame, frame::coding::CloseCode};
+
+    use super::*;
+    use crate::ws::WsEvent;
+
+    fn config(url: String, reconnect: ReconnectPolicy, tx: broadcast::Sender<WsEvent>) -> WsConnConfig {
+        WsConnConfig {
+            subscribe_msg: Some("sub".into()),
+            reconnect,
+            events: Some(WsEventSink::new("test", tx)),
+            id: 7,
+            ..WsConnConfig::new(url)
+        }
+    }
+
+    async fn next_kind(rx: &mut broadcast::Receiver<WsEvent>) -> WsEventKind {
+        let event = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+        assert_eq!((&*event.source, event.conn_id), ("test", 7));
+        event.kind
+    }
+
+    #[tokio::test]
+    async fn gives_up_after_max_failures() {
+        // Nothing listens on this port once the listener is dropped.
+        let port = TcpListener::bind("127.0.0.1:0").await.unwrap().local_addr().unwrap().port();
+        let (tx, mut rx) = broadcast::channel(64);
+        let policy = ReconnectPolicy { initial_backoff_ms: 10, max_failures: Some(3), ..Default::default() };
+        let mut conn = WsConnection::new(config(format!("ws://127.0.0.1:{port}/ws"), policy, tx));
+        conn.start(Arc::new(|_, _| {}), None);
+
+        for attempt in 1..=3 {
+            assert_eq!(next_kind(&mut rx).await, WsEventKind::Connecting { attempt });
+            let kind = next_kind(&mut rx).await;
+            assert!(
+                matches!(kind, WsEventKind::Disconnected { reason: DisconnectReason::ConnectFailed(_) }),
+                "{kind:?}"
+            );
+        }
+        assert_eq!(next_kind(&mut rx).await, WsEventKind::GivingUp { failures: 3, retry_in: None });
+
+        // The task has ended on its own.
+        tokio::time::timeout(Duration::from_secs(1), conn.task.take().unwrap()).await.unwrap().unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejected_sessions_trip_the_circuit_then_cool_down() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            // Accept the handshake, then reject the subscription.
+            while let Ok((tcp, _)) = listener.accept().await {
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                let frame = CloseFrame { code: CloseCode::Policy, reason: "bad sub".into() };
+                let _ = ws.close(Some(frame)).await;
+                while let Some(Ok(_)) = ws.next().await {}
+            }
+        });
+
+        let (tx, mut rx) = broadcast::channel(64);
+        let policy = ReconnectPolicy {
+            initial_backoff_ms: 10,
+            max_failures: Some(2),
+            cool_down_ms: Some(50),
+            ..Default::default()
+        };
+        let mut conn = WsConnection::new(config(format!("ws://127.0.0.1:{port}/ws"), policy, tx));
+        conn.start(Arc::new(|_, _| {}), None);
+
+        let closed = WsEventKind::Disconnected {
+            reason: DisconnectReason::Closed { code: Some(1008), reason: "bad sub".into() },
+        };
+        for attempt in 1..=2 {
+            assert_eq!(next_kind(&mut rx).await, WsEventKind::Connecting { attempt });
+            assert_eq!(next_kind(&mut rx).await, WsEventKind::Connected);
+            assert_eq!(next_kind(&mut rx).await, closed);
+        }
+        assert_eq!(
+            next_kind(&mut rx).await,
+            WsEventKind::GivingUp { failures: 2, retry_in: Some(Duration::from_millis(50)) }
+        );
+        // After the cool-down the count starts over.
+        assert_eq!(next_kind(&mut rx).await, WsEventKind::Connecting { attempt: 1 });
+        conn.stop().await;
+    }
+
+    #[tokio::test]
+    async fn stats_track_sessions_and_ping_rtt() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            // Echo for a while (answering pings), then close normally.
+            while let Ok((tcp, _)) = listener.accept().await {
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                let _ = tokio::time::timeout(Duration::from_millis(200), async {
+                    while let Some(Ok(msg)) = ws.next().await {
+                        if msg.is_text() {
+                            let _ = ws.send(msg).await;
+                        }
+                    }
+                })
+                .await;
+                let frame = CloseFrame { code: CloseCode::Normal, reason: "".into() };
+                let _ = ws.close(Some(frame)).await;
+                while let Some(Ok(_)) = ws.next().await {}
+            }
+        });
+
+        let (tx, mut rx) = broadcast::channel(64);
+        let mut cfg = config(format!("ws://127.0.0.1:{port}/ws"), ReconnectPolicy::default(), tx);
+        cfg.ping_interval = Some(Duration::from_millis(20));
+        cfg.ping_payload = Some(PingPayload::WebSocketPing);
+        let mut conn = WsConnection::new(cfg);
+        let mut own_events = conn.subscribe_events();
+        conn.start(Arc::new(|_, _| {}), None);
+
+        // Two sessions: the first ends with a close frame.
+        let mut connected = 0;
+        while connected < 2 {
+            if next_kind(&mut rx).await == WsEventKind::Connected {
+                connected += 1;
+            }
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        let stats = conn.stats();
+        assert!(stats.connected);
+        assert_eq!(stats.reconnects, 1);
+        assert_eq!(stats.last_close_code, Some(1000));
+        assert_eq!((stats.msgs_received, stats.bytes_received), (2, 6));
+        assert!(stats.ping_rtt.is_some_and(|rtt| rtt < Duration::from_secs(1)));
+        assert!(stats.since_last_msg.is_some() && stats.uptime.is_some());
+        // Subscribers on the connection see the same events.
+        assert_eq!(own_events.recv().await.unwrap().kind, WsEventKind::Connecting { attempt: 1 });
+
+        conn.stop().await;
+        assert!(!conn.stats().connected);
+    }
+
+    #[tokio::test]
+    async fn updated_subscription_is_sent_on_reconnect() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            // Echo text; close the session after echoing "bye".
+            while let Ok((tcp, _)) = listener.accept().await {
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                while let Some(Ok(msg)) = ws.next().await {
+                    if msg.is_text() {
+                        let bye = msg.to_text().unwrap() == "bye";
+                        ws.send(msg).await.unwrap();
+                        if bye {
+                            let _ = ws.close(None).await;
+                        }
+                    }
+                }
+            }
+        });
+
+        let (tx, _rx) = broadcast::channel(64);
+        let policy = ReconnectPolicy { initial_backoff_ms: 10, ..Default::default() };
+        let mut conn = WsConnection::new(config(format!("ws://127.0.0.1:{port}/ws"), policy, tx));
+        let (msg_tx, mut msg_rx) = tokio::sync::mpsc::unbounded_channel();
+        conn.start(
+            Arc::new(move |_, text| {
+                let _ = msg_tx.send(text.to_string());
+            }),
+            None,
+        );
+        let mut next = async || tokio::time::timeout(Duration::from_secs(5), msg_rx.recv()).await.unwrap().unwrap();
+
+        assert_eq!(next().await, "sub");
+        conn.set_subscribe_msg(Some("sub2".into()));
+        conn.send("bye".into()).await.unwrap();
+        assert_eq!(next().await, "bye");
+        assert_eq!(next().await, "sub2");
+        conn.stop().await;
+    }
+
+    /// Serve WebSocket clients, answering text `"ping"` with `"pong"` if
+    /// `answer` and echoing other text.
+    async fn ping_server(answer: bool) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                while let Some(Ok(msg)) = ws.next().await {
+                    match msg.to_text() {
+                        Ok("ping") if answer => ws.send(Message::Text("pong".into())).await.unwrap(),
+                        Ok("ping") => {}
+                        Ok(_) if msg.is_text() => ws.send(msg).await.unwrap(),
+                        _ => {}
+                    }
+                }
+            }
+        });
+        port
+    }
+
+    fn text_ping(mut cfg: WsConnConfig, pong_timeout: Duration) -> WsConnConfig {
+        cfg.ping_interval = Some(Duration::from_millis(20));
+        cfg.ping_payload = Some(PingPayload::Text("ping".into()));
+        cfg.pong = Some(PongMatcher::Exact("pong".into()));
+        cfg.pong_timeout = Some(pong_timeout);
+        cfg
+    }
+
+    #[tokio::test]
+    async fn text_pong_measures_rtt_and_is_consumed() {
+        let port = ping_server(true).await;
+        let (tx, _rx) = broadcast::channel(64);
+        let cfg = config(format!("ws://127.0.0.1:{port}/ws"), ReconnectPolicy::default(), tx);
+        let mut conn = WsConnection::new(text_ping(cfg, Duration::from_millis(500)));
+        let (msg_tx, mut msg_rx) = tokio::sync::mpsc::unbounded_channel();
+        conn.start(
+            Arc::new(move |_, text| {
+                let _ = msg_tx.send(text.to_string());
+            }),
+            None,
+        );
+
+        tokio::time::sleep(Duration::from_millis(150)).await;
+        let stats = conn.stats();
+        assert!(stats.ping_rtt.is_some_and(|rtt| rtt < Duration::from_millis(500)), "{stats}");
+        assert_eq!((stats.reconnects, stats.msgs_received), (0, 1));
+        conn.stop().await;
+        // Only the subscription echo reached the callback.
+        assert_eq!(msg_rx.recv().await.as_deref(), Some("sub"));
+        assert_eq!(msg_rx.recv().await, None);
+    }
+
+    #[tokio::test]
+    async fn missing_pong_drops_connection() {
+        let port = ping_server(false).await;
+        let (tx, mut rx) = broadcast::channel(64);
+        let cfg = config(format!("ws://127.0.0.1:{port}/ws"), ReconnectPolicy::default(), tx);
+        let mut conn = WsConnection::new(text_ping(cfg, Duration::from_millis(50)));
+        conn.start(Arc::new(|_, _| {}), None);
+
+        assert_eq!(next_kind(&mut rx).await, WsEventKind::Connecting { attempt: 1 });
+        assert_eq!(next_kind(&mut rx).await, WsEventKind::Connected);
+        assert_eq!(
+            next_kind(&mut rx).await,
+            WsEventKind::Disconnected { reason: DisconnectReason::PongTimeout(Duration::from_millis(50)) }
+        );
+        conn.stop().await;
+    }
+}
@@ -0,0 +1,589 @@
+//! WebSocket compression: permessage-deflate (RFC 7692) and compressed
+//! binary payloads.
+//!
+//! tungstenite rejects frames with the RSV1 bit set, so permessage-deflate is
+//! handled underneath it. [`InflateStream`] sits between the (TLS) transport
+//! and tungstenite: it reads the server's handshake response to learn whether
+//! the extension was accepted, and from then on rewrites every compressed
+//! data frame into a plain one. Outgoing frames are never compressed, which
+//! the RFC allows.
+//!
+//! Some venues instead send gzip/zlib/deflate data inside ordinary binary
+//! frames; [`BinaryCompression`] inflates those.
+//!
+//! Both refuse input that would exceed a size limit (see
+//! [`DEFAULT_MAX_MESSAGE_SIZE`]) with an [`io::ErrorKind::InvalidData`]
+//! error, so neither a huge frame header nor a compression bomb can make the
+//! connection allocate without bound.
+
+use std::{
+    io::{self, Read},
+    pin::Pin,
+    task::{Context, Poll, ready},
+};
+
+use flate2::{Decompress, FlushDecompress};
+use serde::Deserialize;
+use tokio::io::{AsyncRead, AsyncWrite, ReadBuf};
+
+/// `Sec-WebSocket-Extensions` value offered by the client.
+pub(crate) const EXTENSION_OFFER: &str = "permessage-deflate; client_max_window_bits";
+
+/// Trailer stripped by the sender from every compressed message.
+const DEFLATE_TAIL: [u8; 4] = [0x00, 0x00, 0xff, 0xff];
+
+/// Bytes read from the transport at a time.
+const READ_CHUNK: usize = 16 * 1024;
+
+/// Default limit on a frame's payload and on an (inflated) message.
+pub const DEFAULT_MAX_MESSAGE_SIZE: usize = 16 << 20;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    /// Waiting for the end of the HTTP handshake response.
+    Handshake,
+    /// permessage-deflate is active: parse and rewrite frames.
+    Frames,
+    /// No compression: hand bytes through untouched.
+    Passthrough,
+}
+
+/// Transport wrapper that undoes permessage-deflate for tungstenite.
+pub struct InflateStream<S> {
+    inner: S,
+    mode: Mode,
+    /// Raw bytes read; those before `input_pos` are processed. Compacted
+    /// once per transport read rather than per frame.
+    input: Vec<u8>,
+    input_pos: usize,
+    /// Processed bytes not yet handed to the reader.
+    out: Vec<u8>,
+    out_pos: usize,
+    /// Inflated payload of the current frame, reused across frames.
+    payload: Vec<u8>,
+    inflater: Decompress,
+    /// The server resets its compressor after every message.
+    reset_per_message: bool,
+    /// Inside a fragmented compressed message.
+    compressed_msg: bool,
+    /// Inflated bytes of the current compressed message so far.
+    msg_len: usize,
+    /// Largest frame payload and inflated message accepted.
+    max_size: usize,
+}
+
+impl<S> InflateStream<S> {
+    /// Wrap `inner`. With `offered` the handshake response is inspected for
+    /// permessage-deflate; without it every byte passes through untouched.
+    /// Frames and inflated messages over `max_size` bytes are errors.
+    pub(crate) fn new(inner: S, offered: bool, max_size: usize) -> Self {
+        Self {
+            inner,
+            mode: if offered { Mode::Handshake } else { Mode::Passthrough },
+            input: Vec::new(),
+            input_pos: 0,
+            out: Vec::new(),
+            out_pos: 0,
+            payload: Vec::new(),
+            inflater: Decompress::new(false),
+            reset_per_message: false,
+            compressed_msg: false,
+            msg_len: 0,
+            max_size,
+        }
+    }
+
+    /// Whether the server accepted permessage-deflate.
+    pub fn deflate_active(&self) -> bool {
+        self.mode == Mode::Frames
+    }
+
+    /// Turn buffered input into output. Returns `false` if more input is
+    /// needed first.
+    fn process(&mut self) -> io::Result<bool> {
+        match self.mode {
+            Mode::Passthrough => Ok(false),
+            Mode::Handshake => {
+                let pending = &self.input[self.input_pos..];
+                let Some(end) = pending.windows(4).position(|w| w == b"\r\n\r\n") else {
+                    return Ok(false);
+                };
+                let head = &pending[..end + 4];
+                match accepted_deflate(head) {
+                    Some(no_context_takeover) => {
+                        self.mode = Mode::Frames;
+                        self.reset_per_message = no_context_takeover;
+                    }
+                    None => self.mode = Mode::Passthrough,
+                }
+                self.out.extend_from_slice(head);
+                self.input_pos += head.len();
+                if self.mode == Mode::Passthrough {
+                    self.hand_over_input();
+                }
+                Ok(true)
+            }
+            Mode::Frames => self.process_frame(),
+        }
+    }
+
+    /// Move all unprocessed input to `out`, untouched.
+    fn hand_over_input(&mut self) {
+        self.out.extend_from_slice(&self.input[self.input_pos..]);
+        self.input.clear();
+        self.input_pos = 0;
+    }
+
+    /// Rewrite one complete frame from `input` into `out`.
+    fn process_frame(&mut self) -> io::Result<bool> {
+        let frame = &mut self.input[self.input_pos..];
+        let Some(h) = FrameHeader::parse(frame) else {
+            return Ok(false);
+        };
+        if h.payload_len > self.max_size {
+            return Err(too_large("frame", h.payload_len, self.max_size));
+        }
+        let total = h.header_len + h.payload_len;
+        if frame.len() < total {
+            return Ok(false);
+        }
+        self.input_pos += total;
+        let compressed = match h.opcode {
+            0x1 | 0x2 => h.rsv1,
+            0x0 => self.compressed_msg,
+            _ => false, // control frames are never compressed
+        };
+        if !compressed {
+            self.out.extend_from_slice(&frame[..total]);
+            return Ok(true);
+        }
+
+        let payload = &mut frame[h.header_len..total];
+        if let Some(mask) = h.mask {
+            payload.iter_mut().enumerate().for_each(|(i, b)| *b ^= mask[i % 4]);
+        }
+        if !self.compressed_msg {
+            self.msg_len = 0;
+        }
+        self.compressed_msg = !h.fin;
+
+        let data = &mut self.payload;
+        data.clear();
+        let limit = self.max_size - self.msg_len;
+        inflate(&mut self.inflater, payload, data, limit)?;
+        if h.fin {
+            inflate(&mut self.inflater, &DEFLATE_TAIL, data, limit)?;
+            if self.reset_per_message {
+                self.inflater.reset(false);
+            }
+        }
+        self.msg_len += data.len();
+
+        // Same FIN and opcode, RSV1 cleared, unmasked.
+        self.out.push(if h.fin { 0x80 } else { 0 } | h.opcode);
+        match data.len() {
+            n if n < 126 => self.out.push(n as u8),
+            n if n <= u16::MAX as usize => {
+                self.out.push(126);
+                self.out.extend_from_slice(&(n as u16).to_be_bytes());
+            }
+            n => {
+                self.out.push(127);
+                self.out.extend_from_slice(&(n as u64).to_be_bytes());
+            }
+        }
+        self.out.extend_from_slice(data);
+        Ok(true)
+    }
+}
+
+/// Inflate `input` with `inflater`, appending to `out`, which must not grow
+/// beyond `limit` bytes.
+fn inflate(inflater: &mut Decompress, mut input: &[u8], out: &mut Vec<u8>, limit: usize) -> io::Result<()> {
+    loop {
+        if out.len() > limit {
+            return Err(too_large("inflated message", out.len(), limit));
+        }
+        if out.capacity() - out.len() < 1024 {
+            out.reserve_exact(input.len().max(4096).min(limit + 1 - out.len()));
+        }
+        let (in_before, out_before) = (inflater.total_in(), out.len());
+        inflater
+            .decompress_vec(input, out, FlushDecompress::Sync)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        input = &input[(inflater.total_in() - in_before) as usize..];
+        // Done once the input is used up and the output did not fill up.
+        if input.is_empty() && out.len() < out.capacity() {
+            return Ok(());
+        }
+        if out.len() == out_before && inflater.total_in() == in_before {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "permessage-deflate: inflater stalled"));
+        }
+    }
+}
+
+fn too_large(what: &str, len: usize, max: usize) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("{what} of {len} bytes exceeds the {max} byte limit"))
+}
+
+/// The part of a frame header needed to rewrite it.
+struct FrameHeader {
+    fin: bool,
+    rsv1: bool,
+    opcode: u8,
+    mask: Option<[u8; 4]>,
+    header_len: usize,
+    payload_len: usize,
+}
+
+impl FrameHeader {
+    /// Parse the header at the start of `buf`, `None` if incomplete.
+    fn parse(buf: &[u8]) -> Option<Self> {
+        let (&b0, &b1) = (buf.first()?, buf.get(1)?);
+        let (mut len, mut pos) = (u64::from(b1 & 0x7f), 2);
+        if len == 126 {
+            len = u64::from(u16::from_be_bytes(buf.get(2..4)?.try_into().ok()?));
+            pos = 4;
+        } else if len == 127 {
+            len = u64::from_be_bytes(buf.get(2..10)?.try_into().ok()?);
+            pos = 10;
+        }
+        let mask = if b1 & 0x80 != 0 {
+            let m = buf.get(pos..pos + 4)?.try_into().ok()?;
+            pos += 4;
+            Some(m)
+        } else {
+            None
+        };
+        Some(Self {
+            fin: b0 & 0x80 != 0,
+            rsv1: b0 & 0x40 != 0,
+            opcode: b0 & 0x0f,
+            mask,
+            header_len: pos,
+            payload_len: usize::try_from(len).unwrap_or(usize::MAX),
+        })
+    }
+}
+
+/// Whether a handshake response accepts permessage-deflate, and if so
+/// whether it asks for `server_no_context_takeover`. `None` if declined.
+fn accepted_deflate(head: &[u8]) -> Option<bool> {
+    let head = String::from_utf8_lossy(head).to_ascii_lowercase();
+    let value = head.lines().find_map(|l| l.strip_prefix("sec-websocket-extensions:"))?;
+    value.contains("permessage-deflate").then(|| value.contains("server_no_context_takeover"))
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for InflateStream<S> {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        loop {
+            if this.out_pos < this.out.len() {
+                let n = buf.remaining().min(this.out.len() - this.out_pos);
+                buf.put_slice(&this.out[this.out_pos..this.out_pos + n]);
+                this.out_pos += n;
+                if this.out_pos == this.out.len() {
+                    this.out.clear();
+                    this.out_pos = 0;
+                }
+                return Poll::Ready(Ok(()));
+            }
+            if this.mode == Mode::Passthrough {
+                return Pin::new(&mut this.inner).poll_read(cx, buf);
+            }
+            if this.process()? {
+                continue;
+            }
+
+            let mut chunk = [0u8; READ_CHUNK];
+            let mut chunk_buf = ReadBuf::new(&mut chunk);
+            ready!(Pin::new(&mut this.inner).poll_read(cx, &mut chunk_buf))?;
+            if chunk_buf.filled().is_empty() {
+                // EOF: hand over any partial frame and let tungstenite report it.
+                this.hand_over_input();
+                this.mode = Mode::Passthrough;
+                if this.out.is_empty() {
+                    return Poll::Ready(Ok(()));
+                }
+                continue;
+            }
+            // Drop the processed frames before appending.
+            this.input.drain(..this.input_pos);
+            this.input_pos = 0;
+            this.input.extend_from_slice(chunk_buf.filled());
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for InflateStream<S> {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().inner).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+/// Compression applied by the venue to binary frame payloads.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BinaryCompression {
+    /// gzip (RFC 1952), e.g. HTX.
+    Gzip,
+    /// zlib (RFC 1950).
+    Zlib,
+    /// Raw deflate (RFC 1951).
+    Deflate,
+    /// Detect gzip/zlib from the header, else raw deflate.
+    Auto,
+}
+
+impl BinaryCompression {
+    /// Decompress `data`, appending to `out`. Output beyond `max_len` bytes
+    /// is an error.
+    pub fn decompress(self, data: &[u8], out: &mut Vec<u8>, max_len: usize) -> io::Result<()> {
+        let kind = match self {
+            Self::Auto => Self::detect(data),
+            kind => kind,
+        };
+        // One byte past the limit tells an exact fit from an overflow.
+        let take = max_len as u64 + 1;
+        let n = match kind {
+            Self::Gzip => flate2::read::GzDecoder::new(data).take(take).read_to_end(out),
+            Self::Zlib => flate2::read::ZlibDecoder::new(data).take(take).read_to_end(out),
+            Self::Deflate | Self::Auto => flate2::read::DeflateDecoder::new(data).take(take).read_to_end(out),
+        }?;
+        if n > max_len {
+            return Err(too_large("decompressed payload", n, max_len));
+        }
+        Ok(())
+    }
+
+    fn detect(data: &[u8]) -> Self {
+        match data {
+            [0x1f, 0x8b, ..] => Self::Gzip,
+            // CM = 8 (deflate) and the header checksum holds.
+            [cmf, flg, ..] if cmf & 0x0f == 8 && u16::from_be_bytes([*cmf, *flg]) % 31 == 0 => Self::Zlib,
+            _ => Self::Deflate,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{Compress, Compression, FlushCompress};
+    use futures_util::StreamExt;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+    use tokio_tungstenite::tungstenite::{Message, handshake::derive_accept_key};
+
+    use super::*;
+
+    /// Compress one message the way a permessage-deflate server does:
+    /// sync flush, trailer stripped, context kept in `c`.
+    fn deflate_msg(c: &mut Compress, msg: &[u8]) -> Vec<u8> {
+        let mut out = Vec::with_capacity(msg.len() + 64);
+        c.compress_vec(msg, &mut out, FlushCompress::Sync).unwrap();
+        assert!(out.ends_with(&DEFLATE_TAIL));
+        out.truncate(out.len() - 4);
+        out
+    }
+
+    fn frame(fin: bool, rsv1: bool, opcode: u8, payload: &[u8]) -> Vec<u8> {
+        let mut f = vec![if fin { 0x80 } else { 0 } | if rsv1 { 0x40 } else { 0 } | opcode];
+        match payload.len() {
+            n if n < 126 => f.push(n as u8),
+            n => {
+                f.push(126);
+                f.extend_from_slice(&(n as u16).to_be_bytes());
+            }
+        }
+        f.extend_from_slice(payload);
+        f
+    }
+
+    /// Minimal server that accepts permessage-deflate (with context
+    /// takeover) and sends compressed messages, one of them fragmented.
+    async fn deflate_server(messages: Vec<String>) -> u16 {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let (mut tcp, _) = listener.accept().await.unwrap();
+            let mut req = Vec::new();
+            while !req.ends_with(b"\r\n\r\n") {
+                req.push(tcp.read_u8().await.unwrap());
+            }
+            let req = String::from_utf8(req).unwrap();
+            assert!(req.to_ascii_lowercase().contains("sec-websocket-extensions: permessage-deflate"));
+            let key = req
+                .lines()
+                .find_map(|l| l.strip_prefix("Sec-WebSocket-Key: ").or(l.strip_prefix("sec-websocket-key: ")));
+            let accept = derive_accept_key(key.unwrap().trim().as_bytes());
+            let resp = format!(
+                "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+                 Sec-WebSocket-Accept: {accept}\r\nSec-WebSocket-Extensions: permessage-deflate\r\n\r\n"
+            );
+            let mut wire = resp.into_bytes();
+
+            let mut c = Compress::new(Compression::default(), false);
+            for (i, msg) in messages.iter().enumerate() {
+                let z = deflate_msg(&mut c, msg.as_bytes());
+                if i == 1 {
+                    // Fragmented: RSV1 only on the first frame.
+                    let (a, b) = z.split_at(z.len() / 2);
+                    wire.extend(frame(false, true, 0x1, a));
+                    wire.extend(frame(true, false, 0x0, b));
+                } else {
+                    wire.extend(frame(true, true, 0x1, &z));
+                }
+                // An uncompressed control frame in between.
+                wire.extend(frame(true, false, 0x9, b"hi"));
+            }
+            // Write in small pieces to exercise partial frames.
+            for piece in wire.chunks(7) {
+                tcp.write_all(piece).await.unwrap();
+                tcp.flush().await.unwrap();
+            }
+            // Drain until the client goes away.
+            let mut sink = [0u8; 1024];
+            while tcp.read(&mut sink).await.is_ok_and(|n| n > 0) {}
+        });
+        port
+    }
+
+    #[tokio::test]
+    async fn inflates_permessage_deflate_frames() {
+        let messages: Vec<String> = vec![
+            r#"{"arg":{"channel":"books"},"data":[{"asks":[["41006.8","0.6","0","1"]]}]}"#.into(),
+            "x".repeat(5000),
+            r#"{"arg":{"channel":"books"},"data":[{"asks":[["41006.9","0.7","0","1"]]}]}"#.into(),
+        ];
+        let port = deflate_server(messages.clone()).await;
+
+        let tcp = tokio::net::TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let stream = InflateStream::new(tcp, true, DEFAULT_MAX_MESSAGE_SIZE);
+        let mut request = tokio_tungstenite::tungstenite::client::IntoClientRequest::into_client_request(format!(
+            "ws://127.0.0.1:{port}/"
+        ))
+        .unwrap();
+        request.headers_mut().insert("Sec-WebSocket-Extensions", EXTENSION_OFFER.parse().unwrap());
+        let (mut ws, _) = tokio_tungstenite::client_async(request, stream).await.unwrap();
+        assert!(ws.get_ref().deflate_active());
+
+        let mut got = Vec::new();
+        while got.len() < messages.len() {
+            match ws.next().await.unwrap().unwrap() {
+                Message::Text(t) => got.push(t.to_string()),
+                Message::Ping(p) => assert_eq!(&p[..], b"hi"),
+                other => panic!("unexpected {other:?}"),
+            }
+        }
+        assert_eq!(got, messages);
+    }
+
+    #[test]
+    fn decompresses_binary_payloads() {
+        let text = br#"{"ch":"market.btcusdt.bbo","tick":{"bid":41000.1}}"#;
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(text).unwrap();
+        let mut zl = flate2::write::ZlibEncoder::new(Vec::new(), Compression::default());
+        zl.write_all(text).unwrap();
+        let mut raw = flate2::write::DeflateEncoder::new(Vec::new(), Compression::default());
+        raw.write_all(text).unwrap();
+
+        for (data, kind) in [
+            (gz.finish().unwrap(), BinaryCompression::Gzip),
+            (zl.finish().unwrap(), BinaryCompression::Zlib),
+            (raw.finish().unwrap(), BinaryCompression::Deflate),
+        ] {
+            assert_eq!(BinaryCompression::detect(&data), kind);
+            for mode in [kind, BinaryCompression::Auto] {
+                let mut out = Vec::new();
+                mode.decompress(&data, &mut out, text.len()).unwrap();
+                assert_eq!(out, text);
+            }
+        }
+        assert!(BinaryCompression::Gzip.decompress(b"not gzip", &mut Vec::new(), 1024).is_err());
+    }
+
+    #[test]
+    fn oversized_frames_and_messages_are_rejected() {
+        // A frame header announcing more than the limit, before any payload.
+        let mut stream = InflateStream::new((), true, 4096);
+        stream.mode = Mode::Frames;
+        stream.input.extend([0x82, 127]);
+        stream.input.extend(u64::MAX.to_be_bytes());
+        assert_eq!(stream.process().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        // A small compressed frame inflating past the limit.
+        let mut stream = InflateStream::new((), true, 4096);
+        stream.mode = Mode::Frames;
+        let mut c = Compress::new(Compression::default(), false);
+        stream.input.extend(frame(true, true, 0x1, &deflate_msg(&mut c, &[b'z'; 100_000])));
+        assert!(stream.input.len() < 4096);
+        assert_eq!(stream.process().unwrap_err().kind(), io::ErrorKind::InvalidData);
+        assert!(stream.payload.capacity() <= 4097, "inflation stops at the limit");
+
+        // Fragments count toward one message.
+        let mut stream = InflateStream::new((), true, 4096);
+        stream.mode = Mode::Frames;
+        let mut c = Compress::new(Compression::default(), false);
+        let z = deflate_msg(&mut c, &[b'z'; 3000]);
+        let z2 = deflate_msg(&mut c, &[b'z'; 3000]);
+        stream.input.extend(frame(false, true, 0x1, &z));
+        stream.input.extend(frame(true, false, 0x0, &z2));
+        assert!(stream.process().unwrap());
+        assert_eq!(stream.process().unwrap_err().kind(), io::ErrorKind::InvalidData);
+
+        let mut gz = flate2::write::GzEncoder::new(Vec::new(), Compression::default());
+        gz.write_all(&[b'z'; 100_000]).unwrap();
+        let gz = gz.finish().unwrap();
+        let mut out = Vec::new();
+        assert!(BinaryCompression::Gzip.decompress(&gz, &mut out, 4096).is_err());
+        out.clear();
+        BinaryCompression::Gzip.decompress(&gz, &mut out, 100_000).unwrap();
+        assert_eq!(out.len(), 100_000);
+    }
+
+    #[test]
+    fn frames_are_inflated_into_reused_buffers() {
+        let mut stream = InflateStream::new((), true, DEFAULT_MAX_MESSAGE_SIZE);
+        stream.mode = Mode::Frames;
+        let mut c = Compress::new(Compression::default(), false);
+        let msg = "y".repeat(3000);
+        let mut payload_ptr = None;
+        for _ in 0..10 {
+            stream.input.extend(frame(true, true, 0x1, &deflate_msg(&mut c, msg.as_bytes())));
+            assert!(stream.process().unwrap());
+            assert!(!stream.process().unwrap(), "one frame per message");
+            assert_eq!(stream.input_pos, stream.input.len());
+            assert_eq!(&stream.out[4..], msg.as_bytes());
+            // Consumed as the reader would, and compacted as before a read.
+            stream.out.clear();
+            stream.input.drain(..stream.input_pos);
+            stream.input_pos = 0;
+            // The inflate buffer is allocated once.
+            assert_eq!(*payload_ptr.get_or_insert(stream.payload.as_ptr()), stream.payload.as_ptr());
+        }
+    }
+
+    #[test]
+    fn handshake_without_extension_passes_through() {
+        assert_eq!(accepted_deflate(b"HTTP/1.1 101 OK\r\nUpgrade: websocket\r\n\r\n"), None);
+        assert_eq!(
+            accepted_deflate(
+                b"HTTP/1.1 101 OK\r\nSec-WebSocket-Extensions: permessage-deflate; server_no_context_takeover\r\n\r\n"
+            ),
+            Some(true)
+        );
+    }
+}
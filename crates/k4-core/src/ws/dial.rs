@@ -0,0 +1,136 @@
+//! Outgoing TCP connection setup for WebSocket connections.
+//!
+//! [`dial`] resolves the target and connects from an optional
+//! [`LocalBind`] — a source IP or a network interface — so redundant
+//! connections can be spread across NICs or upstream providers.
+
+use std::{
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use serde::Deserialize;
+use tokio::net::{TcpSocket, TcpStream};
+
+/// Local endpoint an outgoing connection is bound to.
+///
+/// Parsed from a string: an IP literal binds to that source address,
+/// anything else is taken as an interface name (`SO_BINDTODEVICE`, Linux).
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "String")]
+pub enum LocalBind {
+    /// Bind to this source IP (ephemeral port).
+    Addr(IpAddr),
+    /// Bind to this network interface.
+    Interface(String),
+}
+
+impl FromStr for LocalBind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s.is_empty() {
+            bail!("empty local bind");
+        }
+        Ok(match s.parse::<IpAddr>() {
+            Ok(ip) => Self::Addr(ip),
+            Err(_) => Self::Interface(s.to_string()),
+        })
+    }
+}
+
+impl TryFrom<String> for LocalBind {
+    type Error = anyhow::Error;
+
+    fn try_from(s: String) -> Result<Self> {
+        s.parse()
+    }
+}
+
+impl fmt::Display for LocalBind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Addr(ip) => write!(f, "{ip}"),
+            Self::Interface(name) => write!(f, "dev {name}"),
+        }
+    }
+}
+
+/// Resolve `host:port` and connect to the first address that accepts,
+/// bound to `bind` if given. With a source IP, only addresses of the same
+/// family are tried.
+pub async fn dial(host: &str, port: u16, bind: Option<&LocalBind>) -> Result<TcpStream> {
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port))
+        .await
+        .with_context(|| format!("resolving {host}"))?
+        .filter(|a| match bind {
+            Some(LocalBind::Addr(ip)) => ip.is_ipv4() == a.is_ipv4(),
+            _ => true,
+        })
+        .collect();
+
+    let mut last_err = None;
+    for addr in &addrs {
+        match connect_from(*addr, bind).await {
+            Ok(stream) => return Ok(stream),
+            Err(e) => last_err = Some(e),
+        }
+    }
+    Err(match last_err {
+        Some(e) => e.context(format!("connecting to {host}:{port}")),
+        None => anyhow!("no usable address for {host}:{port}"),
+    })
+}
+
+/// Connect to `addr` from `bind`.
+async fn connect_from(addr: SocketAddr, bind: Option<&LocalBind>) -> Result<TcpStream> {
+    let socket = if addr.is_ipv4() { TcpSocket::new_v4()? } else { TcpSocket::new_v6()? };
+    match bind {
+        Some(LocalBind::Addr(ip)) => {
+            socket.bind(SocketAddr::new(*ip, 0)).with_context(|| format!("binding to {ip}"))?;
+        }
+        #[cfg(target_os = "linux")]
+        Some(LocalBind::Interface(name)) => {
+            socket.bind_device(Some(name.as_bytes())).with_context(|| format!("binding to interface {name}"))?;
+        }
+        #[cfg(not(target_os = "linux"))]
+        Some(LocalBind::Interface(name)) => bail!("binding to interface {name} is only supported on Linux"),
+        None => {}
+    }
+    let stream = socket.connect(addr).await?;
+    stream.set_nodelay(true)?;
+    Ok(stream)
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[test]
+    fn parse_local_bind() {
+        assert_eq!("10.0.0.5".parse::<LocalBind>().unwrap(), LocalBind::Addr("10.0.0.5".parse().unwrap()));
+        assert_eq!("eth1".parse::<LocalBind>().unwrap(), LocalBind::Interface("eth1".into()));
+        assert!("".parse::<LocalBind>().is_err());
+    }
+
+    #[tokio::test]
+    async fn dial_binds_source_address() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+
+        // Any 127/8 address is local on Linux; elsewhere stick to 127.0.0.1.
+        let src: IpAddr = if cfg!(target_os = "linux") { "127.0.0.2" } else { "127.0.0.1" }.parse().unwrap();
+        let stream = dial("127.0.0.1", port, Some(&LocalBind::Addr(src))).await.unwrap();
+        let (_, peer) = listener.accept().await.unwrap();
+        assert_eq!(stream.local_addr().unwrap().ip(), src);
+        assert_eq!(peer.ip(), src);
+
+        // An IPv6 source cannot reach an IPv4-only target.
+        let err = dial("127.0.0.1", port, Some(&LocalBind::Addr("::1".parse().unwrap()))).await;
+        assert!(err.is_err());
+    }
+}
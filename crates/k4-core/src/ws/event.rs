@@ -0,0 +1,117 @@
+//! Connection lifecycle events.
+//!
+//! A [`WsConnection`](super::WsConnection) with an [`WsEventSink`] publishes
+//! a [`WsEvent`] whenever it starts connecting, connects, disconnects or
+//! gives up. Events go to a `tokio::sync::broadcast` channel, so any number
+//! of observers (the owning module, the runner) can subscribe; with no
+//! subscriber they are dropped.
+
+use std::{fmt, sync::Arc, time::Duration};
+
+use tokio::sync::broadcast;
+
+use crate::time_util;
+
+/// Events buffered per subscriber before the oldest are dropped.
+pub const EVENT_CAPACITY: usize = 1024;
+
+/// One lifecycle event of one connection.
+#[derive(Debug, Clone)]
+pub struct WsEvent {
+    /// Name of the stream the connection belongs to (e.g. `"okx_spot"`).
+    pub source: Arc<str>,
+    /// Connection id (see [`WsConnConfig::id`](super::WsConnConfig::id)).
+    pub conn_id: usize,
+    /// Wall-clock time of the event, µs since the epoch.
+    pub time_us: u64,
+    pub kind: WsEventKind,
+}
+
+/// What happened.
+#[derive(Debug, Clone, PartialEq)]
+pub enum WsEventKind {
+    /// A connect attempt is starting; `attempt` counts from 1 since the last
+    /// successful session.
+    Connecting { attempt: u32 },
+    /// The handshake completed and the subscription was sent.
+    Connected,
+    /// The connection was lost or could not be established.
+    Disconnected { reason: DisconnectReason },
+    /// The reconnect policy's failure cap was hit. With `retry_in` the
+    /// connection retries after that cool-down; without it the connection
+    /// task has ended.
+    GivingUp { failures: u32, retry_in: Option<Duration> },
+}
+
+/// Why a connection ended.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DisconnectReason {
+    /// TCP, proxy, TLS or WebSocket handshake failed.
+    ConnectFailed(String),
+    /// The peer sent a close frame.
+    Closed { code: Option<u16>, reason: String },
+    /// Reading from the socket failed.
+    ReadError(String),
+    /// The stream ended without a close frame.
+    StreamEnded,
+    /// Writing (subscription, ping or outbound message) failed.
+    SendFailed(String),
+    /// A ping went unanswered for this long.
+    PongTimeout(Duration),
+    /// The connection was stopped locally.
+    Shutdown,
+}
+
+impl fmt::Display for DisconnectReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ConnectFailed(e) => write!(f, "connect failed: {e}"),
+            Self::Closed { code: Some(code), reason } => write!(f, "closed by peer ({code} {reason})"),
+            Self::Closed { code: None, .. } => write!(f, "closed by peer"),
+            Self::ReadError(e) => write!(f, "read error: {e}"),
+            Self::StreamEnded => write!(f, "stream ended"),
+            Self::SendFailed(e) => write!(f, "send failed: {e}"),
+            Self::PongTimeout(d) => write!(f, "no pong within {d:?}"),
+            Self::Shutdown => write!(f, "shutdown"),
+        }
+    }
+}
+
+impl fmt::Display for WsEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "[{} ws-{}] ", self.source, self.conn_id)?;
+        match &self.kind {
+            WsEventKind::Connecting { attempt } => write!(f, "connecting (attempt {attempt})"),
+            WsEventKind::Connected => write!(f, "connected"),
+            WsEventKind::Disconnected { reason } => write!(f, "disconnected: {reason}"),
+            WsEventKind::GivingUp { failures, retry_in: Some(d) } => {
+                write!(f, "{failures} consecutive failures, cooling down for {d:?}")
+            }
+            WsEventKind::GivingUp { failures, retry_in: None } => write!(f, "giving up after {failures} failures"),
+        }
+    }
+}
+
+/// Where a connection publishes its events.
+#[derive(Debug, Clone)]
+pub struct WsEventSink {
+    source: Arc<str>,
+    tx: broadcast::Sender<WsEvent>,
+}
+
+impl WsEventSink {
+    /// Publish events tagged with `source` on `tx`.
+    pub fn new(source: impl Into<Arc<str>>, tx: broadcast::Sender<WsEvent>) -> Self {
+        Self { source: source.into(), tx }
+    }
+
+    /// Receive events published through this sink from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<WsEvent> {
+        self.tx.subscribe()
+    }
+
+    pub(crate) fn emit(&self, conn_id: usize, kind: WsEventKind) {
+        // No subscribers is not an error.
+        let _ = self.tx.send(WsEvent { source: self.source.clone(), conn_id, time_us: time_util::now_us(), kind });
+    }
+}
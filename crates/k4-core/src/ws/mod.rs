@@ -1,7 +1,23 @@
 //! WebSocket client with auto-reconnect and redundancy support.
 
 pub mod client;
+pub mod compression;
+pub mod dial;
+pub mod event;
+pub mod poll;
+pub mod proxy;
+pub mod reconnect;
 pub mod redundant;
+pub mod resolver;
+pub mod stats;
 
-pub use client::{OnBinaryCallback, OnMessageCallback, PingPayload, WsConnConfig, WsConnection};
+pub use client::{OnBinaryCallback, OnMessageCallback, PingPayload, PongMatcher, WsConnConfig, WsConnection};
+pub use compression::{BinaryCompression, DEFAULT_MAX_MESSAGE_SIZE};
+pub use dial::LocalBind;
+pub use event::{DisconnectReason, EVENT_CAPACITY, WsEvent, WsEventKind, WsEventSink};
+pub use poll::{Frame, PollingConnection};
+pub use proxy::{ProxyConfig, ProxyKind};
+pub use reconnect::ReconnectPolicy;
 pub use redundant::RedundantWsClient;
+pub use resolver::EndpointResolver;
+pub use stats::{WsConnStats, WsStats};
@@ -0,0 +1,580 @@
+//! Busy-polled WebSocket connection.
+//!
+//! [`PollingConnection`] is the non-async counterpart of
+//! [`WsConnection`](super::WsConnection) for latency-critical streams.
+//! Instead of running as a tokio task, its owner calls
+//! [`poll`](PollingConnection::poll) in a loop on its own (typically pinned)
+//! thread. Each call reads the frames the non-blocking socket has ready,
+//! hands them to a callback on the calling thread, and services pings and
+//! reconnects.
+//!
+//! Connecting (DNS, TCP, TLS, handshake, subscription) blocks, so it runs on
+//! a short-lived helper thread and the socket is handed over once it is
+//! ready; the polling thread itself never blocks.
+//!
+//! Ping/pong, the reconnect policy, lifecycle events, stats and binary
+//! payload compression behave as on `WsConnection`. Proxies, local binds and
+//! permessage-deflate are not supported.
+
+use std::{
+    io::ErrorKind,
+    net::{SocketAddr, TcpStream, ToSocketAddrs},
+    sync::mpsc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, anyhow, bail};
+use tokio_tungstenite::tungstenite::{
+    self, Message, WebSocket,
+    client::IntoClientRequest,
+    http::{HeaderName, HeaderValue},
+    protocol::WebSocketConfig,
+    stream::MaybeTlsStream,
+};
+use tracing::{error, info, warn};
+
+use super::{
+    client::WsConnConfig,
+    event::{DisconnectReason, WsEventKind},
+    stats::{WsConnStats, WsStats},
+};
+use crate::time_util;
+
+/// Timeout of each TCP connect and of the blocking handshake.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Frames read per [`PollingConnection::poll`], so one busy connection cannot
+/// starve the others polled on the same thread.
+const MAX_FRAMES_PER_POLL: usize = 64;
+
+type Ws = WebSocket<MaybeTlsStream<TcpStream>>;
+
+/// A data frame handed to the [`PollingConnection::poll`] callback.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Frame<'a> {
+    Text(&'a str),
+    Binary(&'a [u8]),
+}
+
+// One per connection and mostly `Open`; boxing the session would only add
+// an indirection to every poll.
+#[allow(clippy::large_enum_variant)]
+enum State {
+    /// The next connect attempt is due at this instant.
+    Waiting(Instant),
+    /// A helper thread is connecting.
+    Connecting(mpsc::Receiver<anyhow::Result<Ws>>),
+    Open(Session),
+    /// The reconnect policy gave up, or the connection was closed.
+    Done,
+}
+
+/// One connected session.
+struct Session {
+    ws: Ws,
+    got_data: bool,
+    next_ping: Option<Instant>,
+    /// Monotonic µs of the oldest unanswered ping.
+    ping_sent: Option<u64>,
+    pong_deadline: Option<Instant>,
+}
+
+/// A WebSocket connection driven by [`poll`](Self::poll) calls.
+pub struct PollingConnection {
+    config: WsConnConfig,
+    stats: WsStats,
+    state: State,
+    failures: u32,
+    /// Reused buffer for compressed binary payloads.
+    inflated: Vec<u8>,
+    /// Messages to send once the pending connect completes.
+    pending: Vec<String>,
+}
+
+impl PollingConnection {
+    /// Create a connection recording into `stats`; the first connect attempt
+    /// starts on the first poll.
+    pub fn new(config: WsConnConfig, stats: WsStats) -> Self {
+        if config.proxy.is_some() || !config.local_binds.is_empty() || config.permessage_deflate {
+            warn!("[ws-{}] polled connections ignore proxy, local binds and permessage-deflate", config.id);
+        }
+        Self {
+            config,
+            stats,
+            state: State::Waiting(Instant::now()),
+            failures: 0,
+            inflated: Vec::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    /// Replace the subscription sent on every later (re)connect (`None` =
+    /// send nothing). Use [`send`](Self::send) to change the live session.
+    pub fn set_subscribe_msg(&mut self, msg: Option<String>) {
+        self.config.subscribe_msg = msg;
+    }
+
+    /// Queue a text message on the live session; it goes out on a later
+    /// poll. While a connect is in flight (it may have subscribed with the
+    /// previous subscription) the message waits for the session. Between
+    /// sessions it is dropped, as the next connect sends the current
+    /// subscription anyway.
+    pub fn send(&mut self, msg: String) {
+        match &mut self.state {
+            State::Open(session) => {
+                if let Err(reason) = write_ok(session.ws.write(Message::Text(msg.into()))) {
+                    warn!("[ws-{}] send failed: {reason:?}", self.config.id);
+                }
+            }
+            State::Connecting(_) => self.pending.push(msg),
+            State::Waiting(_) | State::Done => {}
+        }
+    }
+
+    /// Current stats of this connection.
+    pub fn stats(&self) -> WsConnStats {
+        self.stats.snapshot()
+    }
+
+    /// Whether a session is up.
+    pub fn is_open(&self) -> bool {
+        matches!(self.state, State::Open(_))
+    }
+
+    /// Whether the connection has ended for good (reconnect policy gave up,
+    /// or [`close`](Self::close) was called).
+    pub fn is_done(&self) -> bool {
+        matches!(self.state, State::Done)
+    }
+
+    /// Make progress without blocking: start or complete a connect attempt,
+    /// or read ready frames and service pings. `on_frame` is called for each
+    /// data frame. Returns whether any frame was read.
+    pub fn poll(&mut self, on_frame: &mut impl FnMut(Frame<'_>)) -> bool {
+        let (reason, got_data) = match &mut self.state {
+            State::Waiting(at) => {
+                if Instant::now() >= *at {
+                    self.start_connect();
+                }
+                return false;
+            }
+            State::Connecting(rx) => match rx.try_recv() {
+                Ok(Ok(ws)) => {
+                    self.on_open(ws);
+                    return false;
+                }
+                Ok(Err(e)) => {
+                    error!("[ws-{}] connection failed: {e:#}", self.config.id);
+                    (DisconnectReason::ConnectFailed(format!("{e:#}")), false)
+                }
+                Err(mpsc::TryRecvError::Empty) => return false,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    (DisconnectReason::ConnectFailed("connect thread exited".into()), false)
+                }
+            },
+            State::Open(session) => match session.service(&self.config, &self.stats, &mut self.inflated, on_frame) {
+                Ok(read) => return read,
+                Err(reason) => (reason, session.got_data),
+            },
+            State::Done => return false,
+        };
+        self.on_closed(reason, got_data);
+        false
+    }
+
+    /// Close the session (best effort, without blocking) and stop
+    /// reconnecting.
+    pub fn close(&mut self) {
+        match std::mem::replace(&mut self.state, State::Done) {
+            State::Open(mut session) => {
+                let _ = session.ws.close(None);
+                let _ = session.ws.flush();
+            }
+            // A pending connect finishes on its own; its socket is dropped.
+            State::Waiting(_) | State::Connecting(_) => {}
+            State::Done => return,
+        }
+        self.stats.on_disconnected(&DisconnectReason::Shutdown);
+        self.config.emit(WsEventKind::Disconnected { reason: DisconnectReason::Shutdown });
+    }
+
+    fn start_connect(&mut self) {
+        info!("[ws-{}] connecting to {} (polled)", self.config.id, self.config.url);
+        self.config.emit(WsEventKind::Connecting { attempt: self.failures + 1 });
+        let (tx, rx) = mpsc::channel();
+        let config = self.config.clone();
+        // If the thread cannot be spawned, `tx` is dropped with the closure
+        // and the next poll sees a failed attempt.
+        if let Err(e) = thread::Builder::new().name(format!("ws-connect-{}", config.id)).spawn(move || {
+            let _ = tx.send(connect_blocking(&config));
+        }) {
+            error!("[ws-{}] cannot spawn connect thread: {e}", self.config.id);
+        }
+        self.state = State::Connecting(rx);
+    }
+
+    fn on_open(&mut self, mut ws: Ws) {
+        info!("[ws-{}] connected (polled)", self.config.id);
+        for msg in self.pending.drain(..) {
+            // Flushed by the next service.
+            let _ = write_ok(ws.write(Message::Text(msg.into())));
+        }
+        self.stats.on_connected();
+        self.config.emit(WsEventKind::Connected);
+        let now = Instant::now();
+        self.state = State::Open(Session {
+            ws,
+            got_data: false,
+            next_ping: self.config.ping_interval.map(|d| now + d),
+            ping_sent: None,
+            pong_deadline: None,
+        });
+    }
+
+    /// Record the end of a session or connect attempt and schedule the next
+    /// attempt per the reconnect policy.
+    fn on_closed(&mut self, reason: DisconnectReason, got_data: bool) {
+        let conn_id = self.config.id;
+        self.pending.clear();
+        self.stats.on_disconnected(&reason);
+        self.config.emit(WsEventKind::Disconnected { reason });
+
+        // A session that delivered data resets the failure count.
+        self.failures = if got_data { 0 } else { self.failures + 1 };
+        let failures = self.failures;
+        let policy = &self.config.reconnect;
+        let delay = if policy.circuit_open(failures) {
+            let retry_in = policy.cool_down();
+            self.config.emit(WsEventKind::GivingUp { failures, retry_in });
+            let Some(cool_down) = retry_in else {
+                error!("[ws-{conn_id}] giving up after {failures} consecutive failures");
+                self.state = State::Done;
+                return;
+            };
+            warn!("[ws-{conn_id}] {failures} consecutive failures, cooling down for {cool_down:?}");
+            self.failures = 0;
+            cool_down
+        } else {
+            let delay = policy.delay(failures);
+            warn!("[ws-{conn_id}] disconnected, reconnecting in {delay:?}");
+            delay
+        };
+        self.state = State::Waiting(Instant::now() + delay);
+    }
+}
+
+impl Session {
+    /// Read up to [`MAX_FRAMES_PER_POLL`] ready frames, then send a due ping,
+    /// check the pong deadline and flush queued writes. Returns whether any
+    /// frame was read, or why the session ended.
+    fn service(
+        &mut self,
+        config: &WsConnConfig,
+        stats: &WsStats,
+        inflated: &mut Vec<u8>,
+        on_frame: &mut impl FnMut(Frame<'_>),
+    ) -> Result<bool, DisconnectReason> {
+        let mut read = false;
+        for _ in 0..MAX_FRAMES_PER_POLL {
+            let msg = match self.ws.read() {
+                Ok(msg) => msg,
+                Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(tungstenite::Error::ConnectionClosed | tungstenite::Error::AlreadyClosed) => {
+                    return Err(DisconnectReason::StreamEnded);
+                }
+                Err(e) => return Err(DisconnectReason::ReadError(e.to_string())),
+            };
+            read = true;
+            match msg {
+                Message::Text(text) => {
+                    if let (Some(sent), Some(pong)) = (self.ping_sent, &config.pong)
+                        && pong.matches(&text)
+                    {
+                        stats.on_ping_rtt(Duration::from_micros(time_util::monotonic_us().saturating_sub(sent)));
+                        self.ping_sent = None;
+                        self.pong_deadline = None;
+                        continue;
+                    }
+                    self.got_data = true;
+                    stats.on_message(text.len());
+                    on_frame(Frame::Text(&text));
+                }
+                Message::Binary(data) => {
+                    self.got_data = true;
+                    stats.on_message(data.len());
+                    let Some(kind) = config.binary_compression else {
+                        on_frame(Frame::Binary(&data));
+                        continue;
+                    };
+                    inflated.clear();
+                    if let Err(e) = kind.decompress(&data, inflated, config.max_message_size) {
+                        warn!("[ws-{}] dropping undecodable {kind:?} payload: {e}", config.id);
+                        continue;
+                    }
+                    match std::str::from_utf8(inflated) {
+                        Ok(text) => on_frame(Frame::Text(text)),
+                        Err(_) => on_frame(Frame::Binary(inflated)),
+                    }
+                }
+                Message::Pong(data) => {
+                    // Our pings carry their send time.
+                    if let Ok(sent) = <[u8; 8]>::try_from(&data[..]) {
+                        let rtt = time_util::monotonic_us().saturating_sub(u64::from_le_bytes(sent));
+                        stats.on_ping_rtt(Duration::from_micros(rtt));
+                    }
+                    self.ping_sent = None;
+                    self.pong_deadline = None;
+                }
+                Message::Close(frame) => {
+                    warn!("[ws-{}] received close frame", config.id);
+                    return Err(match frame {
+                        Some(f) => DisconnectReason::Closed { code: Some(f.code.into()), reason: f.reason.to_string() },
+                        None => DisconnectReason::Closed { code: None, reason: String::new() },
+                    });
+                }
+                // tungstenite queues the reply to a ping itself.
+                Message::Ping(_) | Message::Frame(_) => {}
+            }
+        }
+
+        let now = Instant::now();
+        if self.pong_deadline.is_some_and(|deadline| now >= deadline) {
+            let timeout = config.effective_pong_timeout().unwrap_or_default();
+            warn!("[ws-{}] no pong within {timeout:?}", config.id);
+            return Err(DisconnectReason::PongTimeout(timeout));
+        }
+        if let (Some(at), Some(interval)) = (self.next_ping, config.ping_interval)
+            && now >= at
+        {
+            self.next_ping = Some(now + interval);
+            write_ok(self.ws.write(config.ping_message()))?;
+            // Time from the oldest unanswered ping.
+            if self.ping_sent.is_none() {
+                self.ping_sent = Some(time_util::monotonic_us());
+                self.pong_deadline = config.effective_pong_timeout().map(|d| now + d);
+            }
+        }
+        // Push out queued pings and pong replies.
+        write_ok(self.ws.flush())?;
+        Ok(read)
+    }
+}
+
+/// Treat a write that would block as queued: tungstenite keeps the data and
+/// sends it on a later flush.
+fn write_ok(result: tungstenite::Result<()>) -> Result<(), DisconnectReason> {
+    match result {
+        Ok(()) => Ok(()),
+        Err(tungstenite::Error::Io(e)) if e.kind() == ErrorKind::WouldBlock => Ok(()),
+        Err(e) => Err(DisconnectReason::SendFailed(e.to_string())),
+    }
+}
+
+/// Connect, handshake and subscribe in blocking mode, then switch the socket
+/// to non-blocking for polling. TLS SNI and the `Host` header use the URL
+/// host; the TCP connection goes to the pinned IP if one is set.
+fn connect_blocking(config: &WsConnConfig) -> anyhow::Result<Ws> {
+    let mut request = config.url.as_str().into_client_request()?;
+    for (key, value) in &config.extra_headers {
+        request.headers_mut().insert(HeaderName::from_bytes(key.as_bytes())?, HeaderValue::from_str(value)?);
+    }
+
+    let uri = request.uri();
+    let url_host = uri.host().ok_or_else(|| anyhow!("no host in {}", config.url))?;
+    let url_host = url_host.trim_start_matches('[').trim_end_matches(']').to_string();
+    let tls = uri.scheme_str() == Some("wss");
+    let port = uri.port_u16().unwrap_or(if tls { 443 } else { 80 });
+
+    let addrs: Vec<SocketAddr> = match config.pinned_ip {
+        Some(ip) => vec![SocketAddr::new(ip, port)],
+        None => (url_host.as_str(), port).to_socket_addrs().with_context(|| format!("resolve {url_host}"))?.collect(),
+    };
+    let mut last_err = None;
+    let mut tcp = None;
+    for addr in &addrs {
+        match TcpStream::connect_timeout(addr, CONNECT_TIMEOUT) {
+            Ok(s) => {
+                tcp = Some(s);
+                break;
+            }
+            Err(e) => last_err = Some(e),
+        }
+    }
+    let Some(tcp) = tcp else {
+        match last_err {
+            Some(e) => bail!("connect to {url_host}:{port}: {e}"),
+            None => bail!("no addresses for {url_host}"),
+        }
+    };
+    tcp.set_nodelay(true)?;
+    tcp.set_read_timeout(Some(CONNECT_TIMEOUT))?;
+    tcp.set_write_timeout(Some(CONNECT_TIMEOUT))?;
+
+    let stream = if tls {
+        MaybeTlsStream::NativeTls(native_tls::TlsConnector::new()?.connect(&url_host, tcp)?)
+    } else {
+        MaybeTlsStream::Plain(tcp)
+    };
+    let limits = WebSocketConfig::default()
+        .max_message_size(Some(config.max_message_size))
+        .max_frame_size(Some(config.max_message_size));
+    let (mut ws, _response) = tungstenite::client::client_with_config(request, stream, Some(limits))
+        .map_err(|e| anyhow!("handshake: {e}"))?;
+    if let Some(sub_msg) = &config.subscribe_msg {
+        ws.send(Message::Text(sub_msg.clone().into())).context("subscribe")?;
+    }
+
+    let tcp = match ws.get_ref() {
+        MaybeTlsStream::Plain(s) => s,
+        MaybeTlsStream::NativeTls(s) => s.get_ref(),
+        _ => bail!("unsupported stream type"),
+    };
+    tcp.set_nonblocking(true)?;
+    Ok(ws)
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::{net::TcpListener, sync::broadcast};
+
+    use super::*;
+    use crate::ws::{PingPayload, PongMatcher, ReconnectPolicy, WsEvent, WsEventSink};
+
+    fn config(port: u16, tx: broadcast::Sender<WsEvent>) -> WsConnConfig {
+        WsConnConfig {
+            subscribe_msg: Some("sub".into()),
+            reconnect: ReconnectPolicy { initial_backoff_ms: 10, ..Default::default() },
+            events: Some(WsEventSink::new("test", tx)),
+            id: 3,
+            ..WsConnConfig::new(format!("ws://127.0.0.1:{port}/ws"))
+        }
+    }
+
+    /// Poll `conn` on a blocking thread, collecting frames as debug strings,
+    /// until `done` holds (or 5s pass).
+    async fn poll_until(
+        mut conn: PollingConnection,
+        frames: Vec<String>,
+        done: impl Fn(&PollingConnection, &[String]) -> bool + Send + 'static,
+    ) -> (PollingConnection, Vec<String>) {
+        tokio::task::spawn_blocking(move || {
+            let mut frames = frames;
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while !done(&conn, &frames) {
+                assert!(Instant::now() < deadline, "timed out; frames {frames:?}, stats {:?}", conn.stats());
+                conn.poll(&mut |frame| frames.push(format!("{frame:?}")));
+            }
+            (conn, frames)
+        })
+        .await
+        .unwrap()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn delivers_frames_and_reconnects() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            let mut n = 0;
+            while let Ok((tcp, _)) = listener.accept().await {
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                assert_eq!(ws.next().await.unwrap().unwrap(), Message::Text("sub".into()));
+                ws.send(Message::Text(format!("hello-{n}").into())).await.unwrap();
+                ws.send(Message::Binary(vec![1, 2].into())).await.unwrap();
+                let _ = ws.close(None).await;
+                n += 1;
+            }
+        });
+
+        let (tx, mut rx) = broadcast::channel(64);
+        let conn = PollingConnection::new(config(port, tx), WsStats::default());
+        let (mut conn, frames) = poll_until(conn, Vec::new(), |_, frames| frames.len() >= 4).await;
+        assert_eq!(frames, ["Text(\"hello-0\")", "Binary([1, 2])", "Text(\"hello-1\")", "Binary([1, 2])"]);
+        let stats = conn.stats();
+        assert_eq!(stats.msgs_received, 4);
+        assert_eq!(stats.reconnects, 1);
+        conn.close();
+        assert!(conn.is_done());
+
+        let mut kinds = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            assert_eq!((&*event.source, event.conn_id), ("test", 3));
+            kinds.push(event.kind);
+        }
+        assert_eq!(&kinds[..2], &[WsEventKind::Connecting { attempt: 1 }, WsEventKind::Connected]);
+        assert!(
+            kinds.iter().any(|k| matches!(k, WsEventKind::Disconnected { reason: DisconnectReason::Closed { .. } }))
+        );
+        assert_eq!(kinds.last(), Some(&WsEventKind::Disconnected { reason: DisconnectReason::Shutdown }));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn sends_and_resubscribes_with_the_updated_subscription() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            // Echo text; close the session after echoing "bye".
+            while let Ok((tcp, _)) = listener.accept().await {
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                while let Some(Ok(msg)) = ws.next().await {
+                    if msg.is_text() {
+                        let bye = msg.to_text().unwrap() == "bye";
+                        ws.send(msg).await.unwrap();
+                        if bye {
+                            let _ = ws.close(None).await;
+                        }
+                    }
+                }
+            }
+        });
+
+        let (tx, _rx) = broadcast::channel(64);
+        let conn = PollingConnection::new(config(port, tx), WsStats::default());
+        let (mut conn, frames) = poll_until(conn, Vec::new(), |_, frames| !frames.is_empty()).await;
+        conn.set_subscribe_msg(Some("sub2".into()));
+        conn.send("bye".into());
+        let (mut conn, frames) = poll_until(conn, frames, |_, frames| frames.len() >= 3).await;
+        assert_eq!(frames, ["Text(\"sub\")", "Text(\"bye\")", "Text(\"sub2\")"]);
+        conn.close();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn answered_pings_measure_rtt_and_silence_times_out() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                tokio::spawn(async move {
+                    // Answer the first ping only, then go silent.
+                    let mut answered = false;
+                    while let Some(Ok(msg)) = ws.next().await {
+                        if !answered && msg == Message::Text("ping".into()) {
+                            answered = true;
+                            let _ = ws.send(Message::Text("pong".into())).await;
+                        }
+                    }
+                });
+            }
+        });
+
+        let (tx, _rx) = broadcast::channel(64);
+        let mut cfg = config(port, tx);
+        cfg.ping_interval = Some(Duration::from_millis(20));
+        cfg.ping_payload = Some(PingPayload::Text("ping".into()));
+        cfg.pong = Some(PongMatcher::Exact("pong".into()));
+        cfg.pong_timeout = Some(Duration::from_millis(100));
+        let conn = PollingConnection::new(cfg, WsStats::default());
+
+        let (conn, frames) = poll_until(conn, Vec::new(), |conn, _| conn.stats().ping_rtt.is_some()).await;
+        // Pongs are consumed, never delivered as frames.
+        assert!(frames.is_empty(), "{frames:?}");
+        assert!(conn.is_open());
+
+        // The connection is dropped once a later pong is overdue.
+        let (mut conn, _) = poll_until(conn, frames, |conn, _| conn.stats().reconnects >= 1).await;
+        conn.close();
+    }
+}
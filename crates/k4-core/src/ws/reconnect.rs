@@ -0,0 +1,119 @@
+//! Reconnect policy for WebSocket connections.
+//!
+//! A [`ReconnectPolicy`] controls how long a connection waits before each
+//! reconnect attempt (exponential backoff with jitter) and when it stops
+//! retrying. After `max_failures` consecutive failed attempts the circuit
+//! opens: with a `cool_down_ms` the connection pauses that long and starts
+//! over with a fresh backoff, without one it gives up for good.
+//!
+//! An attempt fails if the connect fails or if the session ends before any
+//! data frame arrives (e.g. a rejected subscription); the failure count is
+//! cleared by the first data frame of a session.
+
+use std::{
+    hash::{BuildHasher, RandomState},
+    time::Duration,
+};
+
+use serde::Deserialize;
+
+/// Backoff, jitter and circuit-breaking settings for a connection.
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+#[serde(default)]
+pub struct ReconnectPolicy {
+    /// Delay before the first retry, in milliseconds (default: 100).
+    pub initial_backoff_ms: u64,
+    /// Upper bound of the doubling backoff, in milliseconds (default: 30000).
+    pub max_backoff_ms: u64,
+    /// Fraction of each delay that is randomised, `0.0..=1.0` (default:
+    /// 0.2). A delay `d` becomes a value in `[d * (1 - jitter), d]`, so
+    /// connections that dropped together do not reconnect in lockstep.
+    pub jitter: f64,
+    /// Open the circuit after this many consecutive failures (`None` =
+    /// retry forever).
+    pub max_failures: Option<u32>,
+    /// Pause after the circuit opens before trying again, in milliseconds
+    /// (`None` = give up).
+    pub cool_down_ms: Option<u64>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self { initial_backoff_ms: 100, max_backoff_ms: 30_000, jitter: 0.2, max_failures: None, cool_down_ms: None }
+    }
+}
+
+impl ReconnectPolicy {
+    /// Backoff before the next attempt after `failures` consecutive
+    /// failures, without jitter: the initial backoff doubled per failure
+    /// beyond the first, capped at the maximum.
+    pub fn backoff(&self, failures: u32) -> Duration {
+        let ms = self.initial_backoff_ms.saturating_mul(1u64 << failures.saturating_sub(1).min(32));
+        Duration::from_millis(ms.min(self.max_backoff_ms))
+    }
+
+    /// [`backoff`](Self::backoff) with jitter applied.
+    pub fn delay(&self, failures: u32) -> Duration {
+        self.jittered(self.backoff(failures), unit_random())
+    }
+
+    /// Whether `failures` consecutive failures open the circuit.
+    pub fn circuit_open(&self, failures: u32) -> bool {
+        self.max_failures.is_some_and(|max| failures >= max)
+    }
+
+    /// Pause after the circuit opens (`None` = give up).
+    pub fn cool_down(&self) -> Option<Duration> {
+        self.cool_down_ms.map(Duration::from_millis)
+    }
+
+    /// Scale `base` down by up to `jitter`, using `r` in `[0, 1)`.
+    fn jittered(&self, base: Duration, r: f64) -> Duration {
+        base.mul_f64(1.0 - self.jitter.clamp(0.0, 1.0) * r)
+    }
+}
+
+/// A uniformly distributed value in `[0, 1)`.
+///
+/// `RandomState` is seeded randomly per instance, which is plenty for
+/// spreading out reconnects without pulling in an RNG.
+fn unit_random() -> f64 {
+    (RandomState::new().hash_one(0u8) >> 11) as f64 / (1u64 << 53) as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_max() {
+        let p = ReconnectPolicy { initial_backoff_ms: 100, max_backoff_ms: 1_000, ..Default::default() };
+        let ms: Vec<u128> = (0..7).map(|n| p.backoff(n).as_millis()).collect();
+        assert_eq!(ms, vec![100, 100, 200, 400, 800, 1_000, 1_000]);
+        assert_eq!(p.backoff(u32::MAX), Duration::from_secs(1));
+    }
+
+    #[test]
+    fn jitter_stays_within_bounds() {
+        let p = ReconnectPolicy { jitter: 0.25, ..Default::default() };
+        let base = Duration::from_millis(1_000);
+        assert_eq!(p.jittered(base, 0.0), base);
+        assert_eq!(p.jittered(base, 0.5), Duration::from_millis(875));
+        for _ in 0..100 {
+            let d = p.delay(4);
+            assert!(d > Duration::from_millis(600) && d <= Duration::from_millis(800), "{d:?}");
+        }
+        let none = ReconnectPolicy { jitter: 0.0, ..Default::default() };
+        assert_eq!(none.delay(3), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn circuit_opens_at_max_failures() {
+        let p: ReconnectPolicy = serde_json::from_str(r#"{"max_failures": 3, "cool_down_ms": 5000}"#).unwrap();
+        assert_eq!(p.initial_backoff_ms, 100);
+        assert!(!p.circuit_open(2));
+        assert!(p.circuit_open(3));
+        assert_eq!(p.cool_down(), Some(Duration::from_secs(5)));
+        assert!(!ReconnectPolicy::default().circuit_open(u32::MAX));
+    }
+}
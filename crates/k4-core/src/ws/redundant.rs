@@ -4,16 +4,38 @@
 //! subscription. Market data messages flow through a deduplicator, so only the
 //! first (fastest) delivery of each update reaches downstream consumers.
 //!
-//! Periodically, the connection with the highest average latency is terminated
-//! and replaced with a fresh connection — this combats the exchange LB node
-//! jitter problem described in the project README.
+//! Periodically, the connection with the highest ping round-trip time is
+//! terminated and replaced with a fresh connection — this combats the
+//! exchange LB node jitter problem described in the project README. RTT is
+//! the one latency every connection measures the same way (see
+//! [`WsConnStats::ping_rtt`]); connections without one are not ranked.
+//!
+//! When the base config lists several `local_binds`, connections are spread
+//! across them round-robin by slot, so redundancy spans NICs or ISPs. A
+//! replacement keeps its slot's path and its [`WsStats`] handle, so a
+//! caller's per-slot stats survive replacements.
+//!
+//! With `dns_refresh` set, the endpoint hostname is resolved up front and each
+//! connection is pinned to a different exchange IP (see [`EndpointResolver`]).
+//! Every evaluation feeds the measured RTTs back into per-IP scores, so the
+//! replacement for the slowest connection goes to the best unused IP.
+//!
+//! Connection health comes first: a connection that is down, or silent for
+//! longer than `stale_after` while another one is healthy, is replaced before
+//! latency is considered. All connections publish lifecycle events to one
+//! shared channel and expose [`WsConnStats`] snapshots.
 
-use std::time::Duration;
+use std::{net::IpAddr, time::Duration};
 
+use tokio::sync::broadcast;
 use tracing::{info, warn};
 
-use super::client::{OnBinaryCallback, OnMessageCallback, WsConnConfig, WsConnection};
-use crate::latency::LatencyCollector;
+use super::{
+    client::{OnBinaryCallback, OnMessageCallback, WsConnConfig, WsConnection},
+    event::{EVENT_CAPACITY, WsEvent, WsEventSink},
+    resolver::EndpointResolver,
+    stats::{WsConnStats, WsStats},
+};
 
 /// Configuration for the redundancy manager.
 #[derive(Debug, Clone)]
@@ -22,49 +44,97 @@ pub struct RedundantConfig {
     pub base_config: WsConnConfig,
     /// Number of redundant connections.
     pub conn_count: u32,
-    /// Heartbeat interval — triggers latency evaluation.
+    /// Heartbeat interval — triggers evaluation.
     pub hb_interval: Option<Duration>,
     /// Whether to reset the slowest connection on each heartbeat.
     pub reset_on_hb: bool,
-    /// After this many data points, evaluate and reset slowest.
-    pub reset_threshold: u64,
+    /// Pin connections to distinct resolved IPs, re-resolving at most this
+    /// often. `None` lets every connect resolve the hostname itself.
+    pub dns_refresh: Option<Duration>,
+    /// Treat a connection with no data for this long as dead, provided
+    /// another connection is healthy. `None` = only disconnects count.
+    pub stale_after: Option<Duration>,
 }
 
 /// Manages redundant WebSocket connections.
 pub struct RedundantWsClient {
     config: RedundantConfig,
     connections: Vec<WsConnection>,
-    latency_collectors: Vec<LatencyCollector>,
+    /// Stats handle of each slot, shared by its successive connections.
+    slot_stats: Vec<WsStats>,
     next_conn_id: usize,
+    resolver: Option<EndpointResolver>,
 }
 
 impl RedundantWsClient {
     /// Create a new redundant client (connections are not started yet).
+    ///
+    /// Without an event sink in the base config, one is created, tagged
+    /// with the URL.
     pub fn new(config: RedundantConfig) -> Self {
-        let count = config.conn_count as usize;
-        Self {
-            config,
-            connections: Vec::with_capacity(count),
-            latency_collectors: (0..count).map(|_| LatencyCollector::new()).collect(),
-            next_conn_id: 0,
+        Self::with_stats(config, Vec::new())
+    }
+
+    /// Create a new redundant client whose connection in slot `i` records
+    /// into `stats[i]`; missing handles are created.
+    pub fn with_stats(mut config: RedundantConfig, mut stats: Vec<WsStats>) -> Self {
+        let base = &mut config.base_config;
+        if base.events.is_none() {
+            base.events = Some(WsEventSink::new(base.url.as_str(), broadcast::channel(EVENT_CAPACITY).0));
         }
+        let count = config.conn_count as usize;
+        stats.resize_with(count, WsStats::default);
+        let resolver = config.dns_refresh.and_then(|refresh| {
+            let url = url::Url::parse(&config.base_config.url).ok()?;
+            Some(EndpointResolver::new(url.host_str()?, url.port_or_known_default()?, refresh))
+        });
+        Self { config, connections: Vec::with_capacity(count), slot_stats: stats, next_conn_id: 0, resolver }
     }
 
     /// Start all redundant connections.
-    pub fn start(&mut self, on_text: OnMessageCallback, on_binary: Option<OnBinaryCallback>) {
+    ///
+    /// With DNS pinning enabled the hostname is resolved first; if that
+    /// fails, connections start unpinned.
+    pub async fn start(&mut self, on_text: OnMessageCallback, on_binary: Option<OnBinaryCallback>) {
+        if let Some(resolver) = &mut self.resolver
+            && let Err(e) = resolver.refresh().await
+        {
+            warn!("[redundant] {e:#}; connecting without IP pinning");
+        }
         for _ in 0..self.config.conn_count {
             self.add_and_start_connection(on_text.clone(), on_binary.clone());
         }
     }
 
-    /// Record a latency sample for a specific connection.
-    pub fn record_latency(&mut self, conn_idx: usize, latency_us: u64) {
-        if let Some(lc) = self.latency_collectors.get_mut(conn_idx) {
-            lc.record(latency_us);
+    /// Send a text message on every connection.
+    pub async fn send(&self, msg: &str) -> anyhow::Result<()> {
+        for conn in &self.connections {
+            conn.send(msg.to_string()).await?;
+        }
+        Ok(())
+    }
+
+    /// Replace the subscription sent on every later (re)connect, including
+    /// by replacement connections (see [`WsConnection::set_subscribe_msg`]).
+    pub fn set_subscribe_msg(&mut self, msg: Option<String>) {
+        for conn in &self.connections {
+            conn.set_subscribe_msg(msg.clone());
         }
+        self.config.base_config.subscribe_msg = msg;
+    }
+
+    /// Receive the lifecycle events of all connections from now on.
+    pub fn subscribe_events(&self) -> broadcast::Receiver<WsEvent> {
+        self.config.base_config.events.as_ref().map_or_else(|| broadcast::channel(1).1, WsEventSink::subscribe)
+    }
+
+    /// Current stats of each connection, with its connection id.
+    pub fn stats(&self) -> Vec<(usize, WsConnStats)> {
+        self.connections.iter().map(|c| (c.config.id, c.stats())).collect()
     }
 
-    /// Evaluate latencies and reset the slowest connection if configured.
+    /// Evaluate health and ping RTTs and reset one connection: an unhealthy
+    /// one if any, otherwise — with `reset_on_hb` — the slowest.
     ///
     /// Returns the index of the reset connection, or `None`.
     pub async fn evaluate_and_reset(
@@ -76,55 +146,20 @@ impl RedundantWsClient {
             return None;
         }
 
-        // Find the connection with the highest average latency
-        let mut worst_idx = None;
-        let mut worst_avg = 0.0f64;
-
-        for (i, lc) in self.latency_collectors.iter().enumerate() {
-            if let Some(stats) = lc.stats() {
-                info!(
-                    "[redundant] conn-{} latency: {}",
-                    self.connections.get(i).map(|c| c.config.id).unwrap_or(0),
-                    stats
-                );
-                if stats.avg_us > worst_avg {
-                    worst_avg = stats.avg_us;
-                    worst_idx = Some(i);
-                }
-            }
-        }
-
-        // Reset the worst one
-        if let Some(idx) = worst_idx
-            && self.connections.len() > 1
-        {
-            warn!("[redundant] resetting slowest connection (idx={idx}, avg={worst_avg:.0}µs)");
-            // Stop the old connection
-            if let Some(conn) = self.connections.get_mut(idx) {
-                conn.stop().await;
-            }
-            // Reset latency collector
-            if let Some(lc) = self.latency_collectors.get_mut(idx) {
-                lc.reset();
-            }
-            // Start a replacement
-            let mut new_config = self.config.base_config.clone();
-            new_config.id = self.next_conn_id;
-            self.next_conn_id += 1;
-
-            let mut new_conn = WsConnection::new(new_config);
-            new_conn.start(on_text, on_binary);
-            self.connections[idx] = new_conn;
-
+        if let Some(idx) = self.unhealthy_idx() {
+            let conn = &self.connections[idx];
+            warn!("[redundant] replacing unhealthy connection (idx={idx}, conn-{}: {})", conn.config.id, conn.stats());
+            self.replace(idx, on_text, on_binary).await;
             return Some(idx);
         }
 
-        // Reset all latency collectors for the next evaluation period
-        for lc in &mut self.latency_collectors {
-            lc.reset();
+        let (idx, rtt) = self.slowest()?;
+        if !self.config.reset_on_hb {
+            return None;
         }
-
-        None
+        warn!("[redundant] resetting slowest connection (idx={idx}, rtt={}µs)", rtt.as_micros());
+        self.replace(idx, on_text, on_binary).await;
+        Some(idx)
     }
 
     /// Stop all connections.
@@ -140,13 +175,184 @@ impl RedundantWsClient {
         self.connections.len()
     }
 
-    fn add_and_start_connection(&mut self, on_text: OnMessageCallback, on_binary: Option<OnBinaryCallback>) {
+    /// Index of the connection to replace for health reasons: the one down
+    /// or silent the longest, as long as some connection is healthy.
+    fn unhealthy_idx(&self) -> Option<usize> {
+        // Silence per connection in its current session; `None` =
+        // disconnected (worst). A slot's stats outlive its connections, so a
+        // last message older than the session does not count.
+        let silence: Vec<Option<Duration>> = self
+            .connections
+            .iter()
+            .map(|c| {
+                let s = c.stats();
+                s.connected.then(|| s.since_last_msg.into_iter().chain(s.uptime).min().unwrap_or_default())
+            })
+            .collect();
+        let healthy = |s: &Option<Duration>| s.is_some_and(|d| self.config.stale_after.is_none_or(|max| d <= max));
+        if !silence.iter().any(healthy) {
+            return None;
+        }
+        let (idx, worst) = silence.iter().enumerate().max_by_key(|(_, s)| s.map_or(Duration::MAX, |d| d))?;
+        (!healthy(worst)).then_some(idx)
+    }
+
+    /// Index and ping round-trip time of the slowest connection, once at
+    /// least two connections have measured one; the RTTs also update the
+    /// per-IP scores of pinned connections.
+    fn slowest(&mut self) -> Option<(usize, Duration)> {
+        let mut measured = Vec::new();
+        for (i, conn) in self.connections.iter().enumerate() {
+            let Some(rtt) = conn.stats().ping_rtt else { continue };
+            let pinned = conn.config.pinned_ip;
+            let path = pinned.map(|ip| format!(" ({ip})")).unwrap_or_default();
+            info!("[redundant] conn-{}{path} ping rtt: {}µs", conn.config.id, rtt.as_micros());
+            if let (Some(resolver), Some(ip)) = (&mut self.resolver, pinned) {
+                resolver.record(ip, rtt.as_micros() as f64);
+            }
+            measured.push((i, rtt));
+        }
+        if measured.len() < 2 {
+            return None;
+        }
+        measured.into_iter().max_by_key(|&(_, rtt)| rtt)
+    }
+
+    /// Stop the connection at `idx` and start a replacement on the same
+    /// path, pinned to the best IP not currently in use.
+    async fn replace(&mut self, idx: usize, on_text: OnMessageCallback, on_binary: Option<OnBinaryCallback>) {
+        self.connections[idx].stop().await;
+        if let Some(resolver) = &mut self.resolver {
+            resolver.refresh_if_stale().await;
+        }
+        let new_config = self.slot_config(idx);
+        let mut new_conn = WsConnection::with_stats(new_config, self.slot_stats[idx].clone());
+        new_conn.start(on_text, on_binary);
+        self.connections[idx] = new_conn;
+    }
+
+    /// Config for a new connection in `slot`: a fresh id, the local binds
+    /// rotated so the slot's own path comes first (the rest remain as
+    /// failover), and — with DNS pinning — an IP no live connection uses.
+    fn slot_config(&mut self, slot: usize) -> WsConnConfig {
         let mut config = self.config.base_config.clone();
         config.id = self.next_conn_id;
         self.next_conn_id += 1;
+        if let Some(resolver) = &self.resolver {
+            let in_use: Vec<IpAddr> = self.connections.iter().filter_map(|c| c.config.pinned_ip).collect();
+            config.pinned_ip = resolver.pick(&in_use).or(config.pinned_ip);
+        }
+        if !config.local_binds.is_empty() {
+            let n = config.local_binds.len();
+            config.local_binds.rotate_left(slot % n);
+        }
+        config
+    }
 
-        let mut conn = WsConnection::new(config);
+    fn add_and_start_connection(&mut self, on_text: OnMessageCallback, on_binary: Option<OnBinaryCallback>) {
+        let slot = self.connections.len();
+        let config = self.slot_config(slot);
+        let mut conn = WsConnection::with_stats(config, self.slot_stats[slot].clone());
         conn.start(on_text, on_binary);
         self.connections.push(conn);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ws::LocalBind;
+
+    fn client(local_binds: Vec<LocalBind>, dns_refresh: Option<Duration>) -> RedundantWsClient {
+        RedundantWsClient::new(RedundantConfig {
+            base_config: WsConnConfig { local_binds, ..WsConnConfig::new("wss://example.com/ws") },
+            conn_count: 4,
+            hb_interval: None,
+            reset_on_hb: false,
+            dns_refresh,
+            stale_after: Some(Duration::from_secs(30)),
+        })
+    }
+
+    #[test]
+    fn connections_spread_across_local_binds() {
+        let binds: Vec<LocalBind> = ["10.0.0.1", "10.0.0.2", "eth2"].iter().map(|s| s.parse().unwrap()).collect();
+        let mut client = client(binds.clone(), None);
+
+        let firsts: Vec<_> = (0..4).map(|slot| client.slot_config(slot).local_binds[0].clone()).collect();
+        assert_eq!(firsts, vec![binds[0].clone(), binds[1].clone(), binds[2].clone(), binds[0].clone()]);
+        // Remaining binds stay available as failover, in order.
+        assert_eq!(client.slot_config(1).local_binds, vec![binds[1].clone(), binds[2].clone(), binds[0].clone()]);
+        // Every connection gets a fresh id.
+        assert_eq!(client.slot_config(0).id, 5);
+    }
+
+    #[test]
+    fn connections_pinned_to_distinct_ips() {
+        let ips: Vec<IpAddr> = ["10.0.0.1", "10.0.0.2"].iter().map(|s| s.parse().unwrap()).collect();
+        let mut client = client(Vec::new(), Some(Duration::from_secs(60)));
+        client.resolver.as_mut().unwrap().set_addrs(ips.clone());
+
+        for _ in 0..2 {
+            let config = client.slot_config(client.connections.len());
+            client.connections.push(WsConnection::new(config));
+        }
+        let pins: Vec<_> = client.connections.iter().map(|c| c.config.pinned_ip.unwrap()).collect();
+        assert_eq!(pins, ips);
+
+        // With every IP in use, a replacement shares the fastest one.
+        let resolver = client.resolver.as_mut().unwrap();
+        resolver.record(ips[0], 800.0);
+        resolver.record(ips[1], 200.0);
+        assert_eq!(client.slot_config(0).pinned_ip, Some(ips[1]));
+    }
+
+    #[test]
+    fn unhealthy_connection_chosen_first() {
+        let mut client = client(Vec::new(), None);
+        for _ in 0..3 {
+            let config = client.slot_config(client.connections.len());
+            client.connections.push(WsConnection::new(config));
+        }
+        // Nothing healthy: replacing would not help.
+        assert_eq!(client.unhealthy_idx(), None);
+
+        for conn in &client.connections[..2] {
+            let stats = conn.stats_handle();
+            stats.on_connected();
+            stats.on_message(10);
+        }
+        assert_eq!(client.unhealthy_idx(), Some(2));
+        let (id, stats) = client.stats()[0].clone();
+        assert_eq!((id, stats.connected, stats.msgs_received, stats.bytes_received), (0, true, 1, 10));
+
+        client.connections[2].stats_handle().on_connected();
+        assert_eq!(client.unhealthy_idx(), None);
+    }
+
+    #[test]
+    fn slowest_ranked_by_ping_rtt_alone() {
+        let ips: Vec<IpAddr> = ["10.0.0.1", "10.0.0.2", "10.0.0.3"].iter().map(|s| s.parse().unwrap()).collect();
+        let mut client = client(Vec::new(), Some(Duration::from_secs(60)));
+        client.resolver.as_mut().unwrap().set_addrs(ips.clone());
+        for _ in 0..3 {
+            let config = client.slot_config(client.connections.len());
+            client.connections.push(WsConnection::new(config));
+        }
+        let rtt = |client: &RedundantWsClient, idx: usize, ms: u64| {
+            client.connections[idx].stats_handle().on_ping_rtt(Duration::from_millis(ms))
+        };
+
+        // One measured connection has nothing to be compared with.
+        rtt(&client, 0, 9);
+        assert_eq!(client.slowest(), None);
+        rtt(&client, 2, 4);
+        assert_eq!(client.slowest(), Some((0, Duration::from_millis(9))));
+        let resolver = client.resolver.as_ref().unwrap();
+        assert_eq!((resolver.score(ips[0]), resolver.score(ips[1])), (Some(9000.0), None));
+
+        // A new session does not inherit the previous one's RTT.
+        client.connections[0].stats_handle().on_connected();
+        assert_eq!(client.slowest(), None);
+    }
+}
@@ -0,0 +1,194 @@
+//! Endpoint resolution and per-connection IP pinning.
+//!
+//! Exchanges put many IPs behind one hostname and their latency differs. An
+//! [`EndpointResolver`] enumerates the addresses of a host, hands out a
+//! distinct IP per redundant connection (see
+//! [`WsConnConfig::pinned_ip`](super::WsConnConfig::pinned_ip)) and keeps a
+//! latency score per IP so replacements go to the fastest known endpoints.
+//! The TLS SNI and `Host` header keep using the hostname from the URL.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Context, Result, bail};
+use tracing::{debug, info};
+
+/// Weight of a new latency measurement in an IP's score.
+const SCORE_ALPHA: f64 = 0.5;
+
+/// Resolved addresses of one host plus a latency score per address.
+#[derive(Debug)]
+pub struct EndpointResolver {
+    host: String,
+    port: u16,
+    refresh_interval: Duration,
+    addrs: Vec<IpAddr>,
+    resolved_at: Option<Instant>,
+    /// Smoothed average latency per IP, in µs.
+    scores: HashMap<IpAddr, f64>,
+}
+
+impl EndpointResolver {
+    /// Create a resolver for `host:port` that re-resolves at most every
+    /// `refresh_interval`. Nothing is resolved until [`refresh`](Self::refresh).
+    pub fn new(host: impl Into<String>, port: u16, refresh_interval: Duration) -> Self {
+        Self { host: host.into(), port, refresh_interval, addrs: Vec::new(), resolved_at: None, scores: HashMap::new() }
+    }
+
+    /// Resolve the host now. Scores are kept for addresses that are still
+    /// returned and dropped for the rest. On failure the previous address
+    /// list is kept.
+    pub async fn refresh(&mut self) -> Result<()> {
+        let mut addrs: Vec<IpAddr> = tokio::net::lookup_host((self.host.as_str(), self.port))
+            .await
+            .with_context(|| format!("resolving {}", self.host))?
+            .map(|a| a.ip())
+            .collect();
+        addrs.sort();
+        addrs.dedup();
+        if addrs.is_empty() {
+            bail!("{} resolved to no addresses", self.host);
+        }
+        if addrs != self.addrs {
+            info!("[resolver] {} → {addrs:?}", self.host);
+        }
+        self.set_addrs(addrs);
+        Ok(())
+    }
+
+    /// Re-resolve if the last resolution is older than the refresh interval.
+    pub async fn refresh_if_stale(&mut self) {
+        if self.resolved_at.is_some_and(|t| t.elapsed() < self.refresh_interval) {
+            return;
+        }
+        if let Err(e) = self.refresh().await {
+            debug!("[resolver] {e:#}");
+        }
+    }
+
+    pub(crate) fn set_addrs(&mut self, addrs: Vec<IpAddr>) {
+        self.scores.retain(|ip, _| addrs.contains(ip));
+        self.addrs = addrs;
+        self.resolved_at = Some(Instant::now());
+    }
+
+    /// Currently known addresses.
+    pub fn addrs(&self) -> &[IpAddr] {
+        &self.addrs
+    }
+
+    /// Fold an average latency measured over a connection pinned to `ip`
+    /// into that IP's score.
+    pub fn record(&mut self, ip: IpAddr, avg_us: f64) {
+        self.scores.entry(ip).and_modify(|s| *s += SCORE_ALPHA * (avg_us - *s)).or_insert(avg_us);
+    }
+
+    /// Smoothed latency of `ip` in µs, if measured.
+    pub fn score(&self, ip: IpAddr) -> Option<f64> {
+        self.scores.get(&ip).copied()
+    }
+
+    /// Choose an IP for a new connection.
+    ///
+    /// Prefers addresses not in `in_use`; among those, unmeasured addresses
+    /// come first (so every endpoint gets tried), then the lowest score.
+    /// When every address is in use, the best-scored one is shared. `None`
+    /// if nothing has been resolved.
+    pub fn pick(&self, in_use: &[IpAddr]) -> Option<IpAddr> {
+        let rank = |ip: &&IpAddr| self.score(**ip).unwrap_or(f64::NEG_INFINITY);
+        let best = |it: &mut dyn Iterator<Item = &IpAddr>| it.min_by(|a, b| rank(a).total_cmp(&rank(b))).copied();
+        best(&mut self.addrs.iter().filter(|ip| !in_use.contains(ip))).or_else(|| best(&mut self.addrs.iter()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use futures_util::{SinkExt, StreamExt};
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::handshake::server::{Request, Response};
+
+    use super::*;
+    use crate::ws::{OnMessageCallback, WsConnConfig, WsConnection};
+
+    fn ip(s: &str) -> IpAddr {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn pick_spreads_then_prefers_fastest() {
+        let mut r = EndpointResolver::new("example.com", 443, Duration::from_secs(60));
+        assert_eq!(r.pick(&[]), None);
+        r.set_addrs(vec![ip("10.0.0.1"), ip("10.0.0.2"), ip("10.0.0.3")]);
+
+        // Distinct IPs while available.
+        let a = r.pick(&[]).unwrap();
+        let b = r.pick(&[a]).unwrap();
+        let c = r.pick(&[a, b]).unwrap();
+        assert!(a != b && b != c && a != c);
+
+        r.record(ip("10.0.0.1"), 900.0);
+        r.record(ip("10.0.0.2"), 300.0);
+        // Unmeasured .3 is explored before measured ones.
+        assert_eq!(r.pick(&[]), Some(ip("10.0.0.3")));
+        r.record(ip("10.0.0.3"), 600.0);
+        assert_eq!(r.pick(&[]), Some(ip("10.0.0.2")));
+        assert_eq!(r.pick(&[ip("10.0.0.2")]), Some(ip("10.0.0.3")));
+        // All in use: share the fastest.
+        assert_eq!(r.pick(r.addrs()), Some(ip("10.0.0.2")));
+
+        // Scores are smoothed, and dropped for IPs that disappear.
+        r.record(ip("10.0.0.2"), 1100.0);
+        assert_eq!(r.score(ip("10.0.0.2")), Some(700.0));
+        r.set_addrs(vec![ip("10.0.0.1")]);
+        assert_eq!(r.score(ip("10.0.0.2")), None);
+    }
+
+    #[tokio::test]
+    async fn refresh_resolves_literal() {
+        let mut r = EndpointResolver::new("127.0.0.1", 443, Duration::from_secs(60));
+        r.refresh().await.unwrap();
+        assert_eq!(r.addrs(), &[ip("127.0.0.1")]);
+    }
+
+    #[tokio::test]
+    async fn pinned_connection_keeps_hostname() {
+        // The hostname does not resolve; only the pinned IP makes this work.
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (host_tx, mut host_rx) = tokio::sync::mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            // The error type is fixed by tungstenite's callback signature.
+            #[allow(clippy::result_large_err)]
+            let capture = |req: &Request, resp: Response| {
+                let _ = host_tx.send(req.headers()["host"].to_str().unwrap().to_string());
+                Ok(resp)
+            };
+            let mut ws = tokio_tungstenite::accept_hdr_async(tcp, capture).await.unwrap();
+            while let Some(Ok(msg)) = ws.next().await {
+                let _ = ws.send(msg).await;
+            }
+        });
+
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let on_text: OnMessageCallback = Arc::new(move |_, text| {
+            let _ = tx.send(text.to_string());
+        });
+        let mut conn = WsConnection::new(WsConnConfig {
+            subscribe_msg: Some("sub".into()),
+            pinned_ip: Some(ip("127.0.0.1")),
+            ..WsConnConfig::new(format!("ws://stream.exchange.invalid:{port}/ws"))
+        });
+        conn.start(on_text, None);
+
+        let got = tokio::time::timeout(Duration::from_secs(5), rx.recv()).await.unwrap().unwrap();
+        assert_eq!(got, "sub");
+        assert_eq!(host_rx.recv().await.unwrap(), format!("stream.exchange.invalid:{port}"));
+        conn.stop().await;
+    }
+}
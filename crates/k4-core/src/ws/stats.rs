@@ -0,0 +1,161 @@
+//! Per-connection health counters.
+//!
+//! Every [`WsConnection`](super::WsConnection) owns a [`WsStats`] handle that
+//! its task updates with relaxed atomics; [`WsStats::snapshot`] turns it into
+//! a plain [`WsConnStats`] for monitoring or redundancy decisions.
+
+use std::{
+    fmt,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, AtomicU32, AtomicU64, Ordering::Relaxed},
+    },
+    time::Duration,
+};
+
+use super::event::DisconnectReason;
+use crate::time_util;
+
+/// Sentinel for "not measured yet" in the RTT slot.
+const NO_RTT: u64 = u64::MAX;
+
+#[derive(Debug)]
+struct Counters {
+    connected: AtomicBool,
+    connects: AtomicU64,
+    connect_failures: AtomicU64,
+    /// Close code of the last close frame (0 = none yet).
+    last_close_code: AtomicU32,
+    msgs: AtomicU64,
+    bytes: AtomicU64,
+    /// Monotonic µs of the last data frame (0 = none yet).
+    last_msg_us: AtomicU64,
+    /// Monotonic µs the current session started (0 = not connected).
+    connected_at_us: AtomicU64,
+    ping_rtt_us: AtomicU64,
+}
+
+/// Shared, cheaply cloneable stats of one connection.
+#[derive(Debug, Clone)]
+pub struct WsStats(Arc<Counters>);
+
+impl Default for WsStats {
+    fn default() -> Self {
+        Self(Arc::new(Counters {
+            connected: AtomicBool::new(false),
+            connects: AtomicU64::new(0),
+            connect_failures: AtomicU64::new(0),
+            last_close_code: AtomicU32::new(0),
+            msgs: AtomicU64::new(0),
+            bytes: AtomicU64::new(0),
+            last_msg_us: AtomicU64::new(0),
+            connected_at_us: AtomicU64::new(0),
+            ping_rtt_us: AtomicU64::new(NO_RTT),
+        }))
+    }
+}
+
+impl WsStats {
+    pub(crate) fn on_connected(&self) {
+        self.0.connects.fetch_add(1, Relaxed);
+        self.0.connected_at_us.store(time_util::monotonic_us(), Relaxed);
+        self.0.ping_rtt_us.store(NO_RTT, Relaxed);
+        self.0.connected.store(true, Relaxed);
+    }
+
+    pub(crate) fn on_disconnected(&self, reason: &DisconnectReason) {
+        match reason {
+            DisconnectReason::ConnectFailed(_) => {
+                self.0.connect_failures.fetch_add(1, Relaxed);
+            }
+            DisconnectReason::Closed { code: Some(code), .. } => {
+                self.0.last_close_code.store(u32::from(*code), Relaxed)
+            }
+            _ => {}
+        }
+        self.0.connected.store(false, Relaxed);
+        self.0.connected_at_us.store(0, Relaxed);
+    }
+
+    #[inline]
+    pub(crate) fn on_message(&self, len: usize) {
+        self.0.msgs.fetch_add(1, Relaxed);
+        self.0.bytes.fetch_add(len as u64, Relaxed);
+        self.0.last_msg_us.store(time_util::monotonic_us(), Relaxed);
+    }
+
+    pub(crate) fn on_ping_rtt(&self, rtt: Duration) {
+        self.0.ping_rtt_us.store(rtt.as_micros() as u64, Relaxed);
+    }
+
+    /// Current values.
+    pub fn snapshot(&self) -> WsConnStats {
+        let c = &self.0;
+        let now = time_util::monotonic_us();
+        let since = |us: u64| (us != 0).then(|| Duration::from_micros(now.saturating_sub(us)));
+        WsConnStats {
+            connected: c.connected.load(Relaxed),
+            reconnects: c.connects.load(Relaxed).saturating_sub(1),
+            connect_failures: c.connect_failures.load(Relaxed),
+            last_close_code: match c.last_close_code.load(Relaxed) {
+                0 => None,
+                code => Some(code as u16),
+            },
+            msgs_received: c.msgs.load(Relaxed),
+            bytes_received: c.bytes.load(Relaxed),
+            ping_rtt: match c.ping_rtt_us.load(Relaxed) {
+                NO_RTT => None,
+                us => Some(Duration::from_micros(us)),
+            },
+            since_last_msg: since(c.last_msg_us.load(Relaxed)),
+            uptime: since(c.connected_at_us.load(Relaxed)),
+        }
+    }
+}
+
+/// Point-in-time stats of one connection.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct WsConnStats {
+    /// Whether a session is currently up.
+    pub connected: bool,
+    /// Successful connects after the first.
+    pub reconnects: u64,
+    /// Connect attempts that failed before the handshake completed.
+    pub connect_failures: u64,
+    /// Code of the last close frame received.
+    pub last_close_code: Option<u16>,
+    /// Text and binary frames received over the connection's lifetime.
+    pub msgs_received: u64,
+    /// Payload bytes of those frames.
+    pub bytes_received: u64,
+    /// Last ping round-trip time measured in the current session.
+    pub ping_rtt: Option<Duration>,
+    /// Time since the last text or binary frame.
+    pub since_last_msg: Option<Duration>,
+    /// Age of the current session (`None` while disconnected).
+    pub uptime: Option<Duration>,
+}
+
+impl fmt::Display for WsConnStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} msgs={} bytes={} reconnects={} connect_failures={}",
+            if self.connected { "up" } else { "down" },
+            self.msgs_received,
+            self.bytes_received,
+            self.reconnects,
+            self.connect_failures,
+        )?;
+        if let Some(code) = self.last_close_code {
+            write!(f, " last_close={code}")?;
+        }
+        if let Some(rtt) = self.ping_rtt {
+            write!(f, " rtt={}µs", rtt.as_micros())?;
+        }
+        if let Some(d) = self.since_last_msg {
+            write!(f, " idle={}ms", d.as_millis())?;
+        }
+        Ok(())
+    }
+}
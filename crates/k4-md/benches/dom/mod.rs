@@ -0,0 +1,338 @@
+//! The exchange parsers as they were before the tape parsers: simd-json
+//! deserializes the message into a `serde_json::Value`, then fields are
+//! looked up on it. Kept only as the benchmark baseline; they produce the
+//! same messages as the production parsers for the benchmarked samples.
+
+use k4_core::{time_util, *};
+use serde_json::Value;
+
+fn parse_str_f64(v: Option<&Value>) -> Option<f64> {
+    let v = v?;
+    if let Some(s) = v.as_str() { fast_float2::parse(s).ok() } else { v.as_f64() }
+}
+
+fn parse_str_u64(v: Option<&Value>) -> Option<u64> {
+    let v = v?;
+    if let Some(s) = v.as_str() { s.parse().ok() } else { v.as_u64() }
+}
+
+fn parse_str_i32(v: Option<&Value>) -> Option<i32> {
+    let v = v?;
+    if let Some(s) = v.as_str() { s.parse().ok() } else { v.as_i64().map(|n| n as i32) }
+}
+
+fn fill_depth5_levels(depth: &mut Depth5, bids: &[Value], asks: &[Value]) {
+    depth.bid_level = bids.len().min(5) as u32;
+    depth.ask_level = asks.len().min(5) as u32;
+
+    for (i, level) in bids.iter().take(5).enumerate() {
+        if let Some(arr) = level.as_array() {
+            depth.bid_prices[i] = parse_str_f64(arr.first()).unwrap_or(0.0);
+            depth.bid_vols[i] = parse_str_f64(arr.get(1)).unwrap_or(0.0);
+            if let Some(count) = parse_str_i32(arr.get(3)) {
+                depth.bid_order_counts[i] = count;
+            }
+        }
+    }
+    for (i, level) in asks.iter().take(5).enumerate() {
+        if let Some(arr) = level.as_array() {
+            depth.ask_prices[i] = parse_str_f64(arr.first()).unwrap_or(0.0);
+            depth.ask_vols[i] = parse_str_f64(arr.get(1)).unwrap_or(0.0);
+            if let Some(count) = parse_str_i32(arr.get(3)) {
+                depth.ask_order_counts[i] = count;
+            }
+        }
+    }
+}
+
+fn depth5(symbol: &str, product_type: ProductType, event_ts_ms: u64, trade_ts_ms: u64, update_id: u64) -> Depth5 {
+    Depth5 {
+        symbol: symbol_to_bytes(symbol),
+        product_type,
+        event_timestamp_us: event_ts_ms * 1000,
+        trade_timestamp_us: trade_ts_ms * 1000,
+        update_id,
+        bid_level: 0,
+        ask_level: 0,
+        last_price: 0.0,
+        bid_prices: [0.0; 5],
+        bid_vols: [0.0; 5],
+        ask_prices: [0.0; 5],
+        ask_vols: [0.0; 5],
+        bid_order_counts: [0; 5],
+        ask_order_counts: [0; 5],
+        local_time_us: time_util::now_us(),
+        kernel_rx_us: 0,
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Binance
+// ---------------------------------------------------------------------------
+
+pub mod binance {
+    use super::*;
+
+    pub fn parse_message(data: &mut [u8]) -> Option<MarketDataMsg> {
+        let v: Value = simd_json::serde::from_slice(data).ok()?;
+        let sym = v.get("s")?.as_str()?;
+        let product_type = if v.get("ps").is_some() { ProductType::Futures } else { ProductType::Spot };
+        let event_ts = v.get("E").and_then(|e| e.as_u64()).unwrap_or(0);
+
+        match v.get("e")?.as_str()? {
+            "aggTrade" => Some(MarketDataMsg::AggTrade(AggTrade {
+                symbol: symbol_to_bytes(sym),
+                product_type,
+                event_timestamp_us: v.get("E")?.as_u64()? * 1000,
+                trade_timestamp_us: v.get("T")?.as_u64()? * 1000,
+                first_trade_id: v.get("f")?.as_u64()?,
+                last_trade_id: v.get("l")?.as_u64()?,
+                agg_trade_id: v.get("a")?.as_u64()?,
+                price: parse_str_f64(v.get("p"))?,
+                vol: parse_str_f64(v.get("q"))?,
+                trade_count: 0,
+                is_buyer_maker: v.get("m")?.as_bool()?,
+                backfilled: false,
+                local_time_us: time_util::now_us(),
+                kernel_rx_us: 0,
+            })),
+            "bookTicker" => Some(MarketDataMsg::Bbo(Bookticker {
+                symbol: symbol_to_bytes(sym),
+                product_type,
+                event_timestamp_us: event_ts * 1000,
+                trade_timestamp_us: v.get("T").and_then(|t| t.as_u64()).unwrap_or(0) * 1000,
+                update_id: v.get("u")?.as_u64()?,
+                bid_price: parse_str_f64(v.get("b"))?,
+                bid_vol: parse_str_f64(v.get("B"))?,
+                ask_price: parse_str_f64(v.get("a"))?,
+                ask_vol: parse_str_f64(v.get("A"))?,
+                bid_order_count: 0,
+                ask_order_count: 0,
+                local_time_us: time_util::now_us(),
+                kernel_rx_us: 0,
+            })),
+            "depthUpdate" => {
+                let trade_ts = v.get("T").and_then(|t| t.as_u64()).unwrap_or(0);
+                let mut depth = depth5(sym, product_type, event_ts, trade_ts, v.get("u")?.as_u64()?);
+                fill_depth5_levels(&mut depth, v.get("b")?.as_array()?, v.get("a")?.as_array()?);
+                Some(MarketDataMsg::Depth5(depth))
+            }
+            _ => None,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// OKX
+// ---------------------------------------------------------------------------
+
+pub mod okx {
+    use super::*;
+
+    pub fn parse_message(data: &mut [u8]) -> Option<MarketDataMsg> {
+        let v: Value = simd_json::serde::from_slice(data).ok()?;
+        let arg = v.get("arg")?;
+        let inst_id = arg.get("instId")?.as_str()?;
+        let product_type = if inst_id.ends_with("-SWAP") { ProductType::Futures } else { ProductType::Spot };
+        let data = v.get("data")?.as_array()?.first()?;
+        let ts_ms = parse_str_u64(data.get("ts"))?;
+
+        match arg.get("channel")?.as_str()? {
+            "bbo-tbt" => {
+                let ask0 = data.get("asks")?.as_array()?.first()?.as_array()?;
+                let bid0 = data.get("bids")?.as_array()?.first()?.as_array()?;
+                Some(MarketDataMsg::Bbo(Bookticker {
+                    symbol: symbol_to_bytes(inst_id),
+                    product_type,
+                    event_timestamp_us: ts_ms * 1000,
+                    trade_timestamp_us: ts_ms * 1000,
+                    update_id: parse_str_u64(data.get("seqId"))?,
+                    ask_price: parse_str_f64(ask0.first())?,
+                    ask_vol: parse_str_f64(ask0.get(1))?,
+                    bid_price: parse_str_f64(bid0.first())?,
+                    bid_vol: parse_str_f64(bid0.get(1))?,
+                    ask_order_count: parse_str_i32(ask0.get(3)).unwrap_or(0),
+                    bid_order_count: parse_str_i32(bid0.get(3)).unwrap_or(0),
+                    local_time_us: time_util::now_us(),
+                    kernel_rx_us: 0,
+                }))
+            }
+            "trades" => Some(MarketDataMsg::Trade(Trade {
+                symbol: symbol_to_bytes(inst_id),
+                product_type,
+                event_timestamp_us: ts_ms * 1000,
+                trade_timestamp_us: ts_ms * 1000,
+                trade_id: parse_str_u64(data.get("tradeId"))?,
+                price: parse_str_f64(data.get("px"))?,
+                vol: parse_str_f64(data.get("sz"))?,
+                is_buyer_maker: data.get("side")?.as_str()? == "sell",
+                backfilled: false,
+                cross_seq: 0,
+                local_time_us: time_util::now_us(),
+                kernel_rx_us: 0,
+            })),
+            "books5" => {
+                let mut depth = depth5(inst_id, product_type, ts_ms, ts_ms, parse_str_u64(data.get("seqId"))?);
+                fill_depth5_levels(&mut depth, data.get("bids")?.as_array()?, data.get("asks")?.as_array()?);
+                Some(MarketDataMsg::Depth5(depth))
+            }
+            _ => None,
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Bitget
+// ---------------------------------------------------------------------------
+
+pub mod bitget {
+    use super::*;
+
+    pub fn parse_message(data: &mut [u8], out: &mut Vec<MarketDataMsg>) {
+        let Ok(v) = simd_json::serde::from_slice::<Value>(data) else { return };
+        parse_value(&v, out);
+    }
+
+    fn parse_value(v: &Value, out: &mut Vec<MarketDataMsg>) -> Option<()> {
+        let arg = v.get("arg")?;
+        let inst_id = arg.get("instId")?.as_str()?;
+        let product_type = match arg.get("instType").and_then(|t| t.as_str()) {
+            Some("USDT-FUTURES") => ProductType::Futures,
+            Some("COIN-FUTURES") => ProductType::CoinMargin,
+            _ => ProductType::Spot,
+        };
+        let items = v.get("data")?.as_array()?;
+
+        match arg.get("channel")?.as_str()? {
+            "books1" => {
+                let data = items.first()?;
+                let ask0 = data.get("asks")?.as_array()?.first()?.as_array()?;
+                let bid0 = data.get("bids")?.as_array()?.first()?.as_array()?;
+                out.push(MarketDataMsg::Bbo(Bookticker {
+                    symbol: symbol_to_bytes(inst_id),
+                    product_type,
+                    event_timestamp_us: parse_str_u64(v.get("ts"))? * 1000,
+                    trade_timestamp_us: parse_str_u64(data.get("ts"))? * 1000,
+                    update_id: parse_str_u64(data.get("seq"))?,
+                    ask_price: parse_str_f64(ask0.first())?,
+                    ask_vol: parse_str_f64(ask0.get(1))?,
+                    bid_price: parse_str_f64(bid0.first())?,
+                    bid_vol: parse_str_f64(bid0.get(1))?,
+                    ask_order_count: 0,
+                    bid_order_count: 0,
+                    local_time_us: time_util::now_us(),
+                    kernel_rx_us: 0,
+                }));
+            }
+            "trade" => {
+                let local_time = time_util::now_us();
+                // Newest first; publish oldest first.
+                out.extend(
+                    items
+                        .iter()
+                        .rev()
+                        .filter_map(|item| {
+                            let ts_ms = parse_str_u64(item.get("ts"))?;
+                            Some(Trade {
+                                symbol: symbol_to_bytes(inst_id),
+                                product_type,
+                                event_timestamp_us: ts_ms * 1000,
+                                trade_timestamp_us: ts_ms * 1000,
+                                trade_id: parse_str_u64(item.get("tradeId"))?,
+                                price: parse_str_f64(item.get("price"))?,
+                                vol: parse_str_f64(item.get("size"))?,
+                                is_buyer_maker: item.get("side")?.as_str()? == "sell",
+                                backfilled: false,
+                                cross_seq: 0,
+                                local_time_us: local_time,
+                                kernel_rx_us: 0,
+                            })
+                        })
+                        .map(MarketDataMsg::Trade),
+                );
+            }
+            "books5" => {
+                let data = items.first()?;
+                let event_ts = parse_str_u64(v.get("ts"))?;
+                let trade_ts = parse_str_u64(data.get("ts"))?;
+                let mut depth = depth5(inst_id, product_type, event_ts, trade_ts, parse_str_u64(data.get("seq"))?);
+                fill_depth5_levels(&mut depth, data.get("bids")?.as_array()?, data.get("asks")?.as_array()?);
+                out.push(MarketDataMsg::Depth5(depth));
+            }
+            _ => {}
+        }
+        Some(())
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Bybit
+// ---------------------------------------------------------------------------
+
+pub mod bybit {
+    use super::*;
+
+    fn parse_level(v: Option<&Value>) -> Option<(f64, f64)> {
+        let arr = v?.as_array()?;
+        Some((parse_str_f64(arr.first())?, parse_str_f64(arr.get(1))?))
+    }
+
+    /// An `orderbook.1` message.
+    pub fn parse_bbo(data: &mut [u8], product_type: ProductType) -> Option<Bookticker> {
+        let v: Value = simd_json::serde::from_slice(data).ok()?;
+        let data = v.get("data")?;
+        let ts = v.get("ts")?.as_u64()?;
+        let cts = v.get("cts").and_then(|c| c.as_u64()).unwrap_or(ts);
+        let (bid_price, bid_vol) = parse_level(data.get("b")?.as_array()?.first())?;
+        let (ask_price, ask_vol) = parse_level(data.get("a")?.as_array()?.first())?;
+
+        Some(Bookticker {
+            symbol: symbol_to_bytes(data.get("s")?.as_str()?),
+            product_type,
+            event_timestamp_us: ts * 1000,
+            trade_timestamp_us: cts * 1000,
+            update_id: data.get("seq").or_else(|| data.get("u"))?.as_u64()?,
+            bid_price,
+            bid_vol,
+            ask_price,
+            ask_vol,
+            bid_order_count: 0,
+            ask_order_count: 0,
+            local_time_us: time_util::now_us(),
+            kernel_rx_us: 0,
+        })
+    }
+
+    /// A `publicTrade` message.
+    pub fn parse_trades(data: &mut [u8], product_type: ProductType, out: &mut Vec<MarketDataMsg>) {
+        let Ok(v) = simd_json::serde::from_slice::<Value>(data) else { return };
+        let local_time = time_util::now_us();
+        let ts = v.get("ts").and_then(|t| t.as_u64()).unwrap_or(0);
+        let Some(items) = v.get("data").and_then(|d| d.as_array()) else { return };
+
+        out.extend(
+            items
+                .iter()
+                .filter_map(|item| {
+                    let raw_id = item.get("i")?.as_str()?;
+                    // Spot: numeric trade ID. Futures: UUID -> xxhash64.
+                    let trade_id = raw_id.parse().unwrap_or_else(|_| xxhash_rust::xxh64::xxh64(raw_id.as_bytes(), 0));
+                    Some(Trade {
+                        symbol: symbol_to_bytes(item.get("s")?.as_str()?),
+                        product_type,
+                        event_timestamp_us: ts * 1000,
+                        trade_timestamp_us: item.get("T")?.as_u64()? * 1000,
+                        trade_id,
+                        price: parse_str_f64(item.get("p"))?,
+                        vol: parse_str_f64(item.get("v"))?,
+                        is_buyer_maker: item.get("S")?.as_str()? == "Sell",
+                        backfilled: false,
+                        cross_seq: item.get("seq").and_then(|q| q.as_u64()).unwrap_or(0),
+                        local_time_us: local_time,
+                        kernel_rx_us: 0,
+                    })
+                })
+                .map(MarketDataMsg::Trade),
+        );
+    }
+}
@@ -0,0 +1,140 @@
+//! JSON parser benchmarks over captured exchange messages.
+//!
+//! Each sample is parsed two ways:
+//!
+//! - `tape`: the exchange parser as used in production (simd-json tape, reused buffers).
+//! - `dom`: the previous parser (see [`dom`]), which deserialized into a `serde_json::Value` via
+//!   simd-json and looked fields up on that.
+//!
+//! Run with `cargo bench -p k4-md --bench parsers`.
+
+mod dom;
+
+use std::hint::black_box;
+
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use k4_core::types::{MarketDataMsg, ProductType};
+use k4_md::{binance, bitget, bybit, json_util::with_json, okx};
+
+type Parser = fn(&mut [u8], &mut Vec<MarketDataMsg>);
+
+/// Name, sample, tape parser, dom parser.
+const SAMPLES: &[(&str, &str, Parser, Parser)] = &[
+    (
+        "binance_agg_trade",
+        include_str!("samples/binance_agg_trade.json"),
+        |d, out| out.extend(binance::json_parser::parse_message(d)),
+        |d, out| out.extend(dom::binance::parse_message(d)),
+    ),
+    (
+        "binance_book_ticker",
+        include_str!("samples/binance_book_ticker.json"),
+        |d, out| out.extend(binance::json_parser::parse_message(d)),
+        |d, out| out.extend(dom::binance::parse_message(d)),
+    ),
+    (
+        "binance_depth5",
+        include_str!("samples/binance_depth5.json"),
+        |d, out| out.extend(binance::json_parser::parse_message(d)),
+        |d, out| out.extend(dom::binance::parse_message(d)),
+    ),
+    (
+        "okx_bbo_tbt",
+        include_str!("samples/okx_bbo_tbt.json"),
+        |d, out| out.extend(okx::json_parser::parse_message(d)),
+        |d, out| out.extend(dom::okx::parse_message(d)),
+    ),
+    (
+        "okx_trades",
+        include_str!("samples/okx_trades.json"),
+        |d, out| out.extend(okx::json_parser::parse_message(d)),
+        |d, out| out.extend(dom::okx::parse_message(d)),
+    ),
+    (
+        "okx_books5",
+        include_str!("samples/okx_books5.json"),
+        |d, out| out.extend(okx::json_parser::parse_message(d)),
+        |d, out| out.extend(dom::okx::parse_message(d)),
+    ),
+    (
+        "bitget_books1",
+        include_str!("samples/bitget_books1.json"),
+        bitget::json_parser::parse_message,
+        dom::bitget::parse_message,
+    ),
+    (
+        "bitget_trade",
+        include_str!("samples/bitget_trade.json"),
+        bitget::json_parser::parse_message,
+        dom::bitget::parse_message,
+    ),
+    (
+        "bitget_books5",
+        include_str!("samples/bitget_books5.json"),
+        bitget::json_parser::parse_message,
+        dom::bitget::parse_message,
+    ),
+    (
+        "bybit_orderbook1",
+        include_str!("samples/bybit_orderbook1.json"),
+        |d, out| {
+            with_json(d, |v| {
+                out.extend(bybit::json_parser::parse_bbo(v, ProductType::Futures).map(MarketDataMsg::Bbo))
+            });
+        },
+        |d, out| out.extend(dom::bybit::parse_bbo(d, ProductType::Futures).map(MarketDataMsg::Bbo)),
+    ),
+    (
+        "bybit_public_trade",
+        include_str!("samples/bybit_public_trade.json"),
+        |d, out| {
+            with_json(d, |v| bybit::json_parser::parse_trades_to_md(v, ProductType::Futures, out));
+        },
+        |d, out| dom::bybit::parse_trades(d, ProductType::Futures, out),
+    ),
+];
+
+/// `parser`'s output for `sample`, without receive timestamps.
+fn parsed(sample: &str, parser: Parser) -> String {
+    let mut out = Vec::new();
+    parser(&mut sample.as_bytes().to_vec(), &mut out);
+    for msg in &mut out {
+        match msg {
+            MarketDataMsg::Bbo(m) => m.local_time_us = 0,
+            MarketDataMsg::Trade(m) => m.local_time_us = 0,
+            MarketDataMsg::AggTrade(m) => m.local_time_us = 0,
+            MarketDataMsg::Depth5(m) => m.local_time_us = 0,
+            MarketDataMsg::DepthN(m) => m.local_time_us = 0,
+        }
+    }
+    format!("{out:?}")
+}
+
+fn parsers(c: &mut Criterion) {
+    let mut group = c.benchmark_group("json");
+    let mut buf = Vec::new();
+    let mut out = Vec::new();
+
+    for &(name, sample, tape, dom) in SAMPLES {
+        // Make sure the sample produces data, and the same from both parsers, before timing it.
+        let expected = parsed(sample, tape);
+        assert_ne!(expected, "[]", "{name} produced no messages");
+        assert_eq!(parsed(sample, dom), expected, "{name}: dom and tape parsers disagree");
+
+        for (kind, parser) in [("tape", tape), ("dom", dom)] {
+            group.bench_with_input(BenchmarkId::new(kind, name), sample, |b, sample| {
+                b.iter(|| {
+                    buf.clear();
+                    buf.extend_from_slice(sample.as_bytes());
+                    out.clear();
+                    parser(&mut buf, &mut out);
+                    black_box(&out);
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parsers);
+criterion_main!(benches);
@@ -0,0 +1,314 @@
+//! REST backfill of trades missed by every redundant connection.
+//!
+//! When the dedup worker detects a sequence gap in trade or aggTrade ids
+//! (see [`seq_gap`](crate::seq_gap)), the gap hook installed by
+//! [`spawn_backfill`] queues it for a background task. The task fetches the
+//! missed trades over REST with the exchange's [`TradeFetcher`], keeps those
+//! in the gap, marks them `backfilled`, and injects them into the dedup
+//! worker in id order. The dedup worker accepts backfilled trades without
+//! the usual monotonic id check, so they land in SHM and UDP after the live
+//! trades that revealed the gap.
+//!
+//! Some exchanges only serve their most recent trades. Missed ids older than
+//! the oldest trade a fetch returned have scrolled out of that window; they
+//! are counted as unrecoverable in the stream's [`GapStats`] rather than
+//! reported as backfilled.
+
+use std::sync::Arc;
+
+use anyhow::Result;
+use async_trait::async_trait;
+use crossbeam_channel::{Sender, TrySendError};
+use k4_core::{time_util, types::*};
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::seq_gap::{GapHook, GapStats, SeqGap};
+
+/// Gaps queued for fetching before new ones are dropped.
+const GAP_QUEUE: usize = 256;
+
+/// Requests a fetcher may make for one gap.
+pub const MAX_PAGES: usize = 10;
+
+/// Default for the most trades fetched per gap.
+pub const DEFAULT_MAX_TRADES_PER_GAP: u64 = 1000;
+
+/// Fetches an exchange's trades over REST.
+#[async_trait]
+pub trait TradeFetcher: Send + Sync {
+    /// Fetch the trades of `gap.symbol` with ids in `gap.from..gap.to`, in at
+    /// most [`MAX_PAGES`] requests. May return trades outside the range or
+    /// fewer than asked for; the caller filters, sorts and drops duplicates.
+    async fn fetch(&self, gap: &SeqGap) -> Result<Vec<MarketDataMsg>>;
+}
+
+/// Start the backfill task of one stream, injecting recovered trades into
+/// `tx` (the stream's dedup channel) and counting ids it cannot recover in
+/// `stats`. Returns the gap hook to install on the stream's deduper.
+///
+/// Gaps wider than `max_trades` are backfilled only for their first
+/// `max_trades` ids.
+pub fn spawn_backfill(
+    label: String,
+    fetcher: Arc<dyn TradeFetcher>,
+    tx: Sender<MarketDataMsg>,
+    stats: GapStats,
+    max_trades: u64,
+) -> (GapHook, tokio::task::JoinHandle<()>) {
+    let (gap_tx, mut gap_rx) = mpsc::channel::<SeqGap>(GAP_QUEUE);
+    let hook_label = label.clone();
+    let hook: GapHook = Box::new(move |gap| {
+        if matches!(gap.msg_type, MessageType::Trade | MessageType::AggTrade) && gap_tx.try_send(gap.clone()).is_err() {
+            warn!("[{hook_label}] backfill queue full, not backfilling {:?} {}", gap.msg_type, gap.symbol);
+        }
+    });
+
+    let task = tokio::spawn(async move {
+        while let Some(mut gap) = gap_rx.recv().await {
+            if gap.missing() > max_trades {
+                warn!("[{label}] backfilling only {max_trades} of {} missed {} trades", gap.missing(), gap.symbol);
+                gap.to = gap.from + max_trades;
+            }
+            let (msgs, unrecoverable) = match fetcher.fetch(&gap).await {
+                Ok(msgs) => prepare(&gap, msgs, time_util::now_us()),
+                Err(e) => {
+                    warn!(
+                        "[{label}] backfill of {:?} {} {}..{} failed: {e:#}",
+                        gap.msg_type, gap.symbol, gap.from, gap.to
+                    );
+                    continue;
+                }
+            };
+            if unrecoverable > 0 {
+                stats.record_unrecoverable(&gap, unrecoverable);
+                warn!(
+                    "[{label}] {unrecoverable} missed {:?} {} trades from {} are no longer served over REST",
+                    gap.msg_type, gap.symbol, gap.from
+                );
+            }
+            info!(
+                "[{label}] backfilled {} of {} missed {:?} {} ({}..{})",
+                msgs.len(),
+                gap.missing(),
+                gap.msg_type,
+                gap.symbol,
+                gap.from,
+                gap.to
+            );
+            for msg in msgs {
+                if !inject(&tx, msg).await {
+                    return;
+                }
+            }
+        }
+    });
+    (hook, task)
+}
+
+/// Keep the fetched trades that belong to `gap`, in id order and without
+/// duplicates, marked as backfilled and stamped with `local_time_us`. Also
+/// returns how many of the gap's ids precede the oldest fetched trade of the
+/// symbol (all of them if none was fetched): those have left the exchange's
+/// REST window and cannot be recovered.
+fn prepare(gap: &SeqGap, msgs: Vec<MarketDataMsg>, local_time_us: u64) -> (Vec<MarketDataMsg>, u64) {
+    let mut msgs: Vec<(u64, MarketDataMsg)> = msgs
+        .into_iter()
+        .filter(|msg| msg.msg_type() == gap.msg_type && symbol_from_bytes(msg.symbol()) == gap.symbol)
+        .filter_map(|msg| match msg {
+            MarketDataMsg::Trade(t) => Some((t.trade_id, MarketDataMsg::Trade(t))),
+            MarketDataMsg::AggTrade(a) => Some((a.agg_trade_id, MarketDataMsg::AggTrade(a))),
+            _ => None,
+        })
+        .collect();
+    let oldest = msgs.iter().map(|(id, _)| *id).min().unwrap_or(gap.to);
+    let unrecoverable = oldest.clamp(gap.from, gap.to) - gap.from;
+    msgs.retain(|(id, _)| (gap.from..gap.to).contains(id));
+    msgs.sort_by_key(|(id, _)| *id);
+    msgs.dedup_by_key(|(id, _)| *id);
+    let msgs = msgs
+        .into_iter()
+        .map(|(_, mut msg)| {
+            match &mut msg {
+                MarketDataMsg::Trade(t) => {
+                    t.backfilled = true;
+                    t.local_time_us = local_time_us;
+                }
+                MarketDataMsg::AggTrade(a) => {
+                    a.backfilled = true;
+                    a.local_time_us = local_time_us;
+                }
+                _ => {}
+            }
+            msg
+        })
+        .collect();
+    (msgs, unrecoverable)
+}
+
+/// Send without blocking the runtime, waiting out a full channel. Returns
+/// `false` once the dedup worker is gone.
+async fn inject(tx: &Sender<MarketDataMsg>, mut msg: MarketDataMsg) -> bool {
+    loop {
+        match tx.try_send(msg) {
+            Ok(()) => return true,
+            Err(TrySendError::Full(m)) => {
+                msg = m;
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            }
+            Err(TrySendError::Disconnected(_)) => return false,
+        }
+    }
+}
+
+/// A canned-response HTTP server standing in for exchange REST APIs.
+#[cfg(test)]
+pub(crate) mod stand_in {
+    use std::sync::Mutex;
+
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    use super::*;
+
+    /// Serves `respond(path_and_query)` as a JSON 200 to every request and
+    /// records the requested paths. Returns the base URL.
+    pub async fn serve(respond: impl Fn(&str) -> String + Send + Sync + 'static) -> (String, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let seen = requests.clone();
+        let respond = Arc::new(respond);
+        tokio::spawn(async move {
+            while let Ok((mut tcp, _)) = listener.accept().await {
+                let respond = respond.clone();
+                let seen = seen.clone();
+                tokio::spawn(async move {
+                    let mut buf = vec![0; 8192];
+                    let n = tcp.read(&mut buf).await.unwrap();
+                    let head = String::from_utf8_lossy(&buf[..n]);
+                    let path = head.split_whitespace().nth(1).unwrap_or_default().to_string();
+                    let body = respond(&path);
+                    seen.lock().unwrap().push(path);
+                    let resp = format!(
+                        "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                        body.len()
+                    );
+                    let _ = tcp.write_all(resp.as_bytes()).await;
+                });
+            }
+        });
+        (base, requests)
+    }
+
+    /// The value of query parameter `key` in `path`.
+    pub fn param<'a>(path: &'a str, key: &str) -> Option<&'a str> {
+        path.split_once('?')?.1.split('&').find_map(|kv| kv.strip_prefix(key)?.strip_prefix('='))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(symbol: &str, id: u64) -> MarketDataMsg {
+        MarketDataMsg::Trade(Trade { symbol: symbol_to_bytes(symbol), trade_id: id, ..Default::default() })
+    }
+
+    fn ids(msgs: &[MarketDataMsg]) -> Vec<u64> {
+        msgs.iter()
+            .map(|m| match m {
+                MarketDataMsg::Trade(t) => t.trade_id,
+                _ => unreachable!(),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn prepare_keeps_gap_trades_in_order() {
+        let gap = SeqGap { msg_type: MessageType::Trade, symbol: "BTCUSDT".into(), from: 10, to: 13 };
+        let fetched = vec![
+            trade("BTCUSDT", 12),
+            trade("BTCUSDT", 9),
+            trade("BTCUSDT", 10),
+            trade("ETHUSDT", 11),
+            trade("BTCUSDT", 12),
+            trade("BTCUSDT", 13),
+        ];
+        let (msgs, unrecoverable) = prepare(&gap, fetched, 77);
+        assert_eq!(ids(&msgs), [10, 12]);
+        assert_eq!(unrecoverable, 0);
+        assert!(msgs.iter().all(|m| matches!(m, MarketDataMsg::Trade(t) if t.backfilled && t.local_time_us == 77)));
+    }
+
+    #[test]
+    fn prepare_counts_ids_scrolled_out_of_the_window() {
+        // Only the latest trades are served: 10..12 are gone.
+        let gap = SeqGap { msg_type: MessageType::Trade, symbol: "BTCUSDT".into(), from: 10, to: 15 };
+        let fetched = vec![trade("BTCUSDT", 12), trade("BTCUSDT", 13), trade("BTCUSDT", 20), trade("ETHUSDT", 1)];
+        let (msgs, unrecoverable) = prepare(&gap, fetched, 0);
+        assert_eq!(ids(&msgs), [12, 13]);
+        assert_eq!(unrecoverable, 2);
+
+        // Nothing at all for the symbol: the whole gap is lost.
+        assert_eq!(prepare(&gap, vec![trade("ETHUSDT", 11)], 0).1, 5);
+        // The window starts after the gap.
+        assert_eq!(prepare(&gap, vec![trade("BTCUSDT", 30)], 0).1, 5);
+    }
+
+    struct Canned(Vec<MarketDataMsg>);
+
+    #[async_trait]
+    impl TradeFetcher for Canned {
+        async fn fetch(&self, _gap: &SeqGap) -> Result<Vec<MarketDataMsg>> {
+            Ok(self.0.clone())
+        }
+    }
+
+    #[tokio::test]
+    async fn hook_triggers_fetch_and_injection() {
+        let fetched = (1..=20).map(|id| trade("BTCUSDT", id)).collect();
+        let (tx, rx) = crossbeam_channel::bounded(64);
+        let stats = GapStats::default();
+        let (mut hook, task) = spawn_backfill("test".into(), Arc::new(Canned(fetched)), tx, stats.clone(), 5);
+
+        // Depth gaps are not backfilled; the trade gap is capped at 5 ids.
+        hook(&SeqGap { msg_type: MessageType::Depth5, symbol: "BTCUSDT".into(), from: 1, to: 3 });
+        hook(&SeqGap { msg_type: MessageType::Trade, symbol: "BTCUSDT".into(), from: 4, to: 15 });
+
+        let mut got = Vec::new();
+        while got.len() < 5 {
+            got.push(
+                tokio::time::timeout(std::time::Duration::from_secs(5), async {
+                    loop {
+                        if let Ok(msg) = rx.try_recv() {
+                            return msg;
+                        }
+                        tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+                    }
+                })
+                .await
+                .unwrap(),
+            );
+        }
+        assert_eq!(ids(&got), [4, 5, 6, 7, 8]);
+        assert!(stats.snapshot().is_empty());
+
+        // Trades 1..=20 are all that is still served: 0 has scrolled out.
+        hook(&SeqGap { msg_type: MessageType::Trade, symbol: "BTCUSDT".into(), from: 0, to: 2 });
+        let lost = tokio::time::timeout(std::time::Duration::from_secs(5), async {
+            loop {
+                if let Some(count) = stats.snapshot().pop() {
+                    return count.unrecoverable;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .unwrap();
+        assert_eq!(lost, 1);
+        task.abort();
+    }
+}
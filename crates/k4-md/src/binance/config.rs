@@ -3,7 +3,10 @@
 use std::collections::HashMap;
 
 use anyhow::Result;
-use k4_core::config::ConnectionConfig;
+use k4_core::{
+    config::{ConnectionConfig, DiffDepthConfig},
+    ws::{LocalBind, ProxyConfig},
+};
 
 /// Parsed Binance configuration.
 #[derive(Debug, Clone)]
@@ -23,15 +26,31 @@ pub struct BinanceConfig {
     pub ubase_agg_shm_name: Option<String>,
     pub ubase_trade_shm_name: Option<String>,
     pub ubase_depth5_shm_name: Option<String>,
+    pub spot_depth_n_shm_name: Option<String>,
+    pub ubase_depth_n_shm_name: Option<String>,
+
+    // DepthN levels (`None` = DepthN off)
+    pub spot_depth_n_levels: Option<usize>,
+    pub ubase_depth_n_levels: Option<usize>,
+
+    // Local diff-depth books (`None` = partial depth snapshots)
+    pub spot_diff_depth: Option<DiffDepthConfig>,
+    pub ubase_diff_depth: Option<DiffDepthConfig>,
 
     // Extra HTTP headers
     pub spot_extra_headers: HashMap<String, String>,
     pub ubase_extra_headers: HashMap<String, String>,
 
-    // Redundancy
-    pub hb_interval_sec: u64,
-    pub reset_on_hb: bool,
-    pub reset_threshold: u64,
+    // Egress proxies
+    pub spot_proxy: Option<ProxyConfig>,
+    pub ubase_proxy: Option<ProxyConfig>,
+
+    // Local bind addresses
+    pub spot_local_binds: Vec<LocalBind>,
+    pub ubase_local_binds: Vec<LocalBind>,
+
+    /// Interval of the WebSocket pings that measure each connection's RTT.
+    pub ping_interval_sec: u64,
 }
 
 impl BinanceConfig {
@@ -39,7 +58,7 @@ impl BinanceConfig {
         let md_size = conn.effective_md_size();
 
         // Spot config
-        let (spot_symbols, spot_conn_count, spot_bbo, spot_agg, spot_trade, spot_depth5, spot_headers) =
+        let (spot_symbols, spot_conn_count, spot_bbo, spot_agg, spot_trade, spot_depth5, spot_headers, spot_proxy) =
             if let Some(ref spot) = conn.spot {
                 (
                     spot.symbols.clone().unwrap_or_default(),
@@ -49,13 +68,14 @@ impl BinanceConfig {
                     spot.trade_shm_name.clone(),
                     spot.depth5_shm_name.clone(),
                     spot.extra_headers.clone().unwrap_or_default(),
+                    spot.proxy.clone(),
                 )
             } else {
-                (vec![], 1, None, None, None, None, HashMap::new())
+                (vec![], 1, None, None, None, None, HashMap::new(), None)
             };
 
         // Futures/UBase config
-        let (ubase_symbols, ubase_conn_count, ub_bbo, ub_agg, ub_trade, ub_depth5, ub_headers) =
+        let (ubase_symbols, ubase_conn_count, ub_bbo, ub_agg, ub_trade, ub_depth5, ub_headers, ubase_proxy) =
             if let Some(ref fut) = conn.futures {
                 (
                     fut.effective_symbols(),
@@ -65,9 +85,10 @@ impl BinanceConfig {
                     fut.trade_shm_name.clone(),
                     fut.depth5_shm_name.clone(),
                     fut.extra_headers.clone().unwrap_or_default(),
+                    fut.proxy.clone(),
                 )
             } else {
-                (vec![], 1, None, None, None, None, HashMap::new())
+                (vec![], 1, None, None, None, None, HashMap::new(), None)
             };
 
         Ok(Self {
@@ -84,11 +105,19 @@ impl BinanceConfig {
             ubase_agg_shm_name: ub_agg,
             ubase_trade_shm_name: ub_trade,
             ubase_depth5_shm_name: ub_depth5,
+            spot_depth_n_shm_name: conn.spot.as_ref().and_then(|c| c.depth_n_shm_name.clone()),
+            ubase_depth_n_shm_name: conn.futures.as_ref().and_then(|c| c.depth_n_shm_name.clone()),
+            spot_depth_n_levels: conn.spot.as_ref().and_then(|c| c.depth_n_levels()),
+            ubase_depth_n_levels: conn.futures.as_ref().and_then(|c| c.depth_n_levels()),
+            spot_diff_depth: conn.spot.as_ref().and_then(|c| c.diff_depth.clone()),
+            ubase_diff_depth: conn.futures.as_ref().and_then(|c| c.diff_depth.clone()),
             spot_extra_headers: spot_headers,
             ubase_extra_headers: ub_headers,
-            hb_interval_sec: conn.hb_interval_sec.unwrap_or(30),
-            reset_on_hb: conn.redun_reset_on_hb.unwrap_or(false),
-            reset_threshold: conn.redun_reset_on_threshold.unwrap_or(10_000),
+            spot_proxy,
+            ubase_proxy,
+            spot_local_binds: conn.spot.as_ref().and_then(|c| c.local_binds.clone()).unwrap_or_default(),
+            ubase_local_binds: conn.futures.as_ref().and_then(|c| c.local_binds.clone()).unwrap_or_default(),
+            ping_interval_sec: conn.ping_interval_sec.unwrap_or(30),
         })
     }
 }
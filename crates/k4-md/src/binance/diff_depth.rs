@@ -0,0 +1,510 @@
+//! Binance local order books from diff depth.
+//!
+//! `<symbol>@depth@<speed>` carries the price levels changed since the
+//! previous event, numbered by book update id: `U` is the first update in
+//! the event, `u` the last and, on futures, `pu` the previous event's `u`.
+//! A book is built as Binance documents it:
+//!
+//! 1. Buffer events and fetch a REST snapshot (`/api/v3/depth`, `/fapi/v1/depth`) with its
+//!    `lastUpdateId`.
+//! 2. Drop buffered events the snapshot already contains (`u` <= `lastUpdateId`).
+//! 3. The first event applied must straddle the snapshot: `U` <= `lastUpdateId + 1` <= `u`
+//!    (futures: `U` <= `lastUpdateId` <= `u`).
+//! 4. Every later event must follow on: `U` == previous `u + 1` (futures: `pu` == previous `u`).
+//!
+//! A break in the chain, or a snapshot older than the buffered events,
+//! discards the book and starts again from 1. Nothing is published while a
+//! book is out of sync; once in sync, every event publishes the top of the
+//! book as a Depth5 and, with DepthN enabled, a DepthN.
+//!
+//! Redundant connections share one [`DiffDepthBooks`]; events already
+//! applied from another connection are dropped.
+
+use std::{
+    borrow::Cow,
+    collections::{HashMap, VecDeque},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use ahash::AHashMap;
+use anyhow::{Result, anyhow};
+use k4_core::types::*;
+use serde_json::Value;
+use simd_json::prelude::*;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::{
+    json_util::{Json, with_json, with_levels},
+    order_book::{OrderBook, TickSizes},
+    pipeline::TextParser,
+};
+
+/// Most levels per side requested in a snapshot, and kept in a book.
+pub const MAX_SNAPSHOT_LIMIT: u32 = 1000;
+
+/// Events buffered per symbol while waiting for a snapshot; older ones are
+/// dropped, which at worst makes the snapshot too new and forces a refetch.
+const MAX_BUFFERED_EVENTS: usize = 10_000;
+
+/// Delay before retrying a failed snapshot request.
+const SNAPSHOT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// One diff depth event, borrowing its levels until it is buffered.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffEvent<'a> {
+    /// `U`
+    pub first_update_id: u64,
+    /// `u`
+    pub last_update_id: u64,
+    /// `pu` (futures only).
+    pub prev_update_id: Option<u64>,
+    pub event_timestamp_us: u64,
+    pub trade_timestamp_us: u64,
+    pub bids: Cow<'a, [[f64; 2]]>,
+    pub asks: Cow<'a, [[f64; 2]]>,
+}
+
+impl<'a> DiffEvent<'a> {
+    /// Parse a `depthUpdate` event with its already parsed levels.
+    fn parse(v: Json<'_, '_>, bids: &'a [[f64; 2]], asks: &'a [[f64; 2]]) -> Option<Self> {
+        let event_ms = v.get("E")?.as_u64()?;
+        Some(Self {
+            first_update_id: v.get("U")?.as_u64()?,
+            last_update_id: v.get("u")?.as_u64()?,
+            prev_update_id: v.get("pu").and_then(|p| p.as_u64()),
+            event_timestamp_us: event_ms * 1000,
+            trade_timestamp_us: v.get("T").and_then(|t| t.as_u64()).unwrap_or(event_ms) * 1000,
+            bids: Cow::Borrowed(bids),
+            asks: Cow::Borrowed(asks),
+        })
+    }
+
+    /// A copy that owns its levels, for buffering.
+    fn into_owned(self) -> DiffEvent<'static> {
+        DiffEvent { bids: Cow::Owned(self.bids.into_owned()), asks: Cow::Owned(self.asks.into_owned()), ..self }
+    }
+}
+
+/// A REST order book snapshot.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DepthSnapshot {
+    pub last_update_id: u64,
+    pub bids: Vec<[f64; 2]>,
+    pub asks: Vec<[f64; 2]>,
+}
+
+enum SyncState {
+    /// Waiting for a snapshot, buffering events meanwhile.
+    Pending { buffer: VecDeque<DiffEvent<'static>> },
+    /// The book is current up to `last_update_id`; `first` until an event
+    /// has been applied on top of the snapshot.
+    Synced { last_update_id: u64, first: bool },
+}
+
+struct SymbolBook {
+    book: OrderBook,
+    state: SyncState,
+}
+
+/// The local books of one market, shared by its parser and snapshot task.
+pub struct DiffDepthBooks {
+    product_type: ProductType,
+    depth_n_levels: Option<usize>,
+    ticks: TickSizes,
+    books: Mutex<AHashMap<String, SymbolBook>>,
+    /// Symbols needing a snapshot, consumed by [`run_snapshot_task`].
+    requests: mpsc::UnboundedSender<String>,
+    resyncs: AtomicU64,
+}
+
+impl DiffDepthBooks {
+    /// Empty books, and the receiver of their snapshot requests.
+    pub fn new(
+        product_type: ProductType,
+        depth_n_levels: Option<usize>,
+        ticks: TickSizes,
+    ) -> (Arc<Self>, mpsc::UnboundedReceiver<String>) {
+        let (requests, rx) = mpsc::unbounded_channel();
+        let books = Self {
+            product_type,
+            depth_n_levels,
+            ticks,
+            books: Mutex::new(AHashMap::new()),
+            requests,
+            resyncs: AtomicU64::new(0),
+        };
+        (Arc::new(books), rx)
+    }
+
+    /// Times a book lost sync after having had it.
+    pub fn resyncs(&self) -> u64 {
+        self.resyncs.load(Ordering::Relaxed)
+    }
+
+    /// Apply or buffer `event`, publishing the book onto `out` if it is in
+    /// sync afterwards.
+    pub fn on_event(&self, symbol: &str, event: DiffEvent<'_>, out: &mut Vec<MarketDataMsg>) {
+        let mut books = self.books.lock().unwrap();
+        let entry = match books.get_mut(symbol) {
+            Some(entry) => entry,
+            None => {
+                self.request_snapshot(symbol);
+                let state = SyncState::Pending { buffer: VecDeque::new() };
+                let book = OrderBook::new(self.ticks.get(symbol), MAX_SNAPSHOT_LIMIT as usize);
+                books.entry(symbol.to_string()).or_insert(SymbolBook { book, state })
+            }
+        };
+        match &mut entry.state {
+            SyncState::Pending { buffer } => {
+                if buffer.len() == MAX_BUFFERED_EVENTS {
+                    buffer.pop_front();
+                }
+                buffer.push_back(event.into_owned());
+            }
+            SyncState::Synced { last_update_id, .. } if event.last_update_id <= *last_update_id => {}
+            SyncState::Synced { last_update_id, first } => {
+                if !self.follows(&event, *last_update_id, *first) {
+                    warn!(
+                        "[binance] {symbol} {:?} depth gap after update {last_update_id}: U={} u={} pu={:?}, resyncing",
+                        self.product_type, event.first_update_id, event.last_update_id, event.prev_update_id
+                    );
+                    self.resyncs.fetch_add(1, Ordering::Relaxed);
+                    entry.state = SyncState::Pending { buffer: VecDeque::from([event.into_owned()]) };
+                    self.request_snapshot(symbol);
+                    return;
+                }
+                entry.book.update(&event.bids, &event.asks);
+                entry.state = SyncState::Synced { last_update_id: event.last_update_id, first: false };
+                self.publish(symbol, &entry.book, &event, out);
+            }
+        }
+    }
+
+    /// Start `symbol`'s book from `snapshot` and the events buffered since
+    /// it was requested. Requests another snapshot if this one is too old.
+    pub fn on_snapshot(&self, symbol: &str, snapshot: DepthSnapshot) {
+        let mut books = self.books.lock().unwrap();
+        let Some(entry) = books.get_mut(symbol) else { return };
+        let SyncState::Pending { buffer } = &mut entry.state else { return };
+
+        let mut events = std::mem::take(buffer);
+        events.retain(|e| e.last_update_id > snapshot.last_update_id);
+        if let Some(e) = events.front()
+            && !self.follows(e, snapshot.last_update_id, true)
+        {
+            info!(
+                "[binance] {symbol} {:?} depth snapshot {} predates buffered update {}, refetching",
+                self.product_type, snapshot.last_update_id, e.first_update_id
+            );
+            entry.state = SyncState::Pending { buffer: events };
+            self.request_snapshot(symbol);
+            return;
+        }
+
+        entry.book.set_snapshot(&snapshot.bids, &snapshot.asks);
+        let (mut last_update_id, mut first) = (snapshot.last_update_id, true);
+        while let Some(e) = events.pop_front() {
+            if !self.follows(&e, last_update_id, first) {
+                warn!("[binance] {symbol} {:?} depth gap in buffered updates, resyncing", self.product_type);
+                events.push_front(e);
+                entry.state = SyncState::Pending { buffer: events };
+                self.request_snapshot(symbol);
+                return;
+            }
+            entry.book.update(&e.bids, &e.asks);
+            (last_update_id, first) = (e.last_update_id, false);
+        }
+        entry.state = SyncState::Synced { last_update_id, first };
+    }
+
+    /// Whether `symbol` is still waiting for a snapshot.
+    pub fn needs_snapshot(&self, symbol: &str) -> bool {
+        matches!(self.books.lock().unwrap().get(symbol), Some(SymbolBook { state: SyncState::Pending { .. }, .. }))
+    }
+
+    fn request_snapshot(&self, symbol: &str) {
+        let _ = self.requests.send(symbol.to_string());
+    }
+
+    /// Whether `e` (newer than the book) continues a book at
+    /// `last_update_id`; `first` if that is a snapshot.
+    fn follows(&self, e: &DiffEvent<'_>, last_update_id: u64, first: bool) -> bool {
+        match self.product_type {
+            ProductType::Futures => {
+                e.prev_update_id == Some(last_update_id) || (first && e.first_update_id <= last_update_id)
+            }
+            _ => e.first_update_id <= last_update_id + 1,
+        }
+    }
+
+    fn publish(&self, symbol: &str, book: &OrderBook, e: &DiffEvent<'_>, out: &mut Vec<MarketDataMsg>) {
+        let (bid_prices, bid_vols, ask_prices, ask_vols, bid_level, ask_level) = book.get_depth5();
+        let local_time_us = k4_core::time_util::now_us();
+        let depth = Depth5 {
+            symbol: symbol_to_bytes(symbol),
+            product_type: self.product_type,
+            event_timestamp_us: e.event_timestamp_us,
+            trade_timestamp_us: e.trade_timestamp_us,
+            update_id: e.last_update_id,
+            bid_level,
+            ask_level,
+            last_price: 0.0,
+            bid_prices,
+            bid_vols,
+            ask_prices,
+            ask_vols,
+            bid_order_counts: [0; 5],
+            ask_order_counts: [0; 5],
+            local_time_us,
+            kernel_rx_us: 0,
+        };
+        out.push(MarketDataMsg::Depth5(depth));
+
+        if let Some(levels) = self.depth_n_levels {
+            let mut depth_n = DepthNBox::new(DepthN {
+                symbol: depth.symbol,
+                product_type: self.product_type,
+                event_timestamp_us: e.event_timestamp_us,
+                trade_timestamp_us: e.trade_timestamp_us,
+                update_id: e.last_update_id,
+                local_time_us,
+                ..Default::default()
+            });
+            book.fill_depth_n(&mut depth_n, levels);
+            out.push(MarketDataMsg::DepthN(depth_n));
+        }
+    }
+}
+
+/// Parser for a diff depth stream feeding `books`.
+pub fn parser(books: Arc<DiffDepthBooks>) -> TextParser {
+    Box::new(move |data, out| {
+        with_json(data, |v| {
+            if v.get("e").and_then(|e| e.into_string()) != Some("depthUpdate") {
+                return;
+            }
+            let Some(symbol) = v.get("s").and_then(|s| s.into_string()) else { return };
+            with_levels(v.get("b"), v.get("a"), |bids, asks| {
+                if let Some(event) = DiffEvent::parse(v, bids, asks) {
+                    books.on_event(symbol, event, out);
+                }
+            });
+        });
+    })
+}
+
+/// Fetch the snapshots `books` requests, one at a time, retrying failures
+/// after a delay. Runs until the stream stops.
+pub async fn run_snapshot_task(
+    books: Arc<DiffDepthBooks>,
+    fetcher: SnapshotFetcher,
+    mut requests: mpsc::UnboundedReceiver<String>,
+) {
+    while let Some(symbol) = requests.recv().await {
+        // Resyncs can queue the same symbol more than once.
+        if !books.needs_snapshot(&symbol) {
+            continue;
+        }
+        match fetcher.fetch(&symbol).await {
+            Ok(snapshot) => books.on_snapshot(&symbol, snapshot),
+            Err(e) => {
+                warn!("[binance] {symbol} depth snapshot failed: {e:#}");
+                tokio::time::sleep(SNAPSHOT_RETRY_DELAY).await;
+                books.request_snapshot(&symbol);
+            }
+        }
+    }
+}
+
+/// Fetches spot (`/api/v3/depth`) or USDⓈ-M futures (`/fapi/v1/depth`)
+/// order book snapshots.
+pub struct SnapshotFetcher {
+    http: reqwest::Client,
+    base_url: String,
+    /// `/api/v3` or `/fapi/v1`.
+    api_prefix: &'static str,
+    /// Levels per side, at most [`MAX_SNAPSHOT_LIMIT`].
+    limit: u32,
+    /// Sent with every request (e.g. `X-MBX-APIKEY`).
+    headers: HashMap<String, String>,
+}
+
+impl SnapshotFetcher {
+    /// Spot snapshots from `base_url` (normally
+    /// [`SPOT_REST_URL`](super::SPOT_REST_URL)).
+    pub fn spot(base_url: impl Into<String>, limit: u32, headers: HashMap<String, String>) -> Self {
+        Self::new(base_url.into(), "/api/v3", limit, headers)
+    }
+
+    /// USDⓈ-M futures snapshots from `base_url` (normally
+    /// [`UBASE_REST_URL`](super::UBASE_REST_URL)).
+    pub fn ubase(base_url: impl Into<String>, limit: u32, headers: HashMap<String, String>) -> Self {
+        Self::new(base_url.into(), "/fapi/v1", limit, headers)
+    }
+
+    fn new(base_url: String, api_prefix: &'static str, limit: u32, headers: HashMap<String, String>) -> Self {
+        let limit = limit.clamp(1, MAX_SNAPSHOT_LIMIT);
+        Self { http: reqwest::Client::new(), base_url, api_prefix, limit, headers }
+    }
+
+    pub async fn fetch(&self, symbol: &str) -> Result<DepthSnapshot> {
+        let url = format!("{}{}/depth?symbol={symbol}&limit={}", self.base_url, self.api_prefix, self.limit);
+        let mut req = self.http.get(&url);
+        for (k, v) in &self.headers {
+            req = req.header(k, v);
+        }
+        let resp: Value = req.send().await?.error_for_status()?.json().await?;
+        let levels = |key| -> Option<Vec<[f64; 2]>> {
+            resp.get(key)?
+                .as_array()?
+                .iter()
+                .map(|l| Some([l.get(0)?.as_str()?.parse().ok()?, l.get(1)?.as_str()?.parse().ok()?]))
+                .collect()
+        };
+        let parse = || {
+            Some(DepthSnapshot {
+                last_update_id: resp.get("lastUpdateId")?.as_u64()?,
+                bids: levels("bids")?,
+                asks: levels("asks")?,
+            })
+        };
+        parse().ok_or_else(|| anyhow!("unexpected depth response: {resp}"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backfill::stand_in::serve;
+
+    fn event(first: u64, last: u64, prev: Option<u64>, bids: &[[f64; 2]]) -> DiffEvent<'_> {
+        DiffEvent {
+            first_update_id: first,
+            last_update_id: last,
+            prev_update_id: prev,
+            event_timestamp_us: 1_000,
+            trade_timestamp_us: 1_000,
+            bids: bids.into(),
+            asks: Cow::Owned(vec![[101.0, 1.0]]),
+        }
+    }
+
+    fn snapshot(last_update_id: u64) -> DepthSnapshot {
+        DepthSnapshot { last_update_id, bids: vec![[100.0, 1.0], [99.0, 2.0]], asks: vec![[101.0, 3.0]] }
+    }
+
+    fn best_bid(out: &[MarketDataMsg]) -> (f64, f64, u64) {
+        match out.last() {
+            Some(MarketDataMsg::Depth5(d)) => (d.bid_prices[0], d.bid_vols[0], d.update_id),
+            other => panic!("expected Depth5, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn spot_book_syncs_from_buffered_events() {
+        let (books, mut requests) = DiffDepthBooks::new(ProductType::Spot, None, TickSizes::default());
+        let mut out = Vec::new();
+
+        // Buffered: 90-95 predates the snapshot, 96-102 straddles it.
+        books.on_event("BTCUSDT", event(90, 95, None, &[[100.0, 5.0]]), &mut out);
+        books.on_event("BTCUSDT", event(96, 102, None, &[[100.0, 7.0]]), &mut out);
+        assert!(out.is_empty());
+        assert_eq!(requests.try_recv().unwrap(), "BTCUSDT");
+        assert!(books.needs_snapshot("BTCUSDT"));
+
+        books.on_snapshot("BTCUSDT", snapshot(100));
+        assert!(!books.needs_snapshot("BTCUSDT"));
+
+        books.on_event("BTCUSDT", event(103, 105, None, &[[100.5, 1.0]]), &mut out);
+        assert_eq!(best_bid(&out), (100.5, 1.0, 105));
+        let MarketDataMsg::Depth5(d) = &out[0] else { unreachable!() };
+        assert_eq!((d.bid_prices[1], d.bid_vols[1]), (100.0, 7.0));
+
+        // Already applied on another connection.
+        books.on_event("BTCUSDT", event(103, 105, None, &[[100.5, 9.0]]), &mut out);
+        assert_eq!(out.len(), 1);
+        assert_eq!(books.resyncs(), 0);
+    }
+
+    #[test]
+    fn spot_gap_resyncs() {
+        let (books, mut requests) = DiffDepthBooks::new(ProductType::Spot, Some(10), TickSizes::default());
+        let mut out = Vec::new();
+        books.on_event("BTCUSDT", event(101, 101, None, &[]), &mut out);
+        books.on_snapshot("BTCUSDT", snapshot(100));
+        books.on_event("BTCUSDT", event(102, 102, None, &[]), &mut out);
+        assert!(matches!(&out[..], [MarketDataMsg::Depth5(_), MarketDataMsg::DepthN(d)] if d.bid_level == 2));
+
+        out.clear();
+        books.on_event("BTCUSDT", event(104, 104, None, &[]), &mut out);
+        assert!(out.is_empty());
+        assert_eq!(books.resyncs(), 1);
+        assert_eq!([requests.try_recv().unwrap(), requests.try_recv().unwrap()], ["BTCUSDT", "BTCUSDT"]);
+
+        // A snapshot older than the buffered event is refetched.
+        books.on_snapshot("BTCUSDT", snapshot(101));
+        assert!(books.needs_snapshot("BTCUSDT"));
+        assert_eq!(requests.try_recv().unwrap(), "BTCUSDT");
+
+        books.on_snapshot("BTCUSDT", snapshot(103));
+        books.on_event("BTCUSDT", event(105, 106, None, &[[99.0, 0.0]]), &mut out);
+        assert!(matches!(&out[..], [MarketDataMsg::Depth5(d), _] if d.bid_level == 1 && d.update_id == 106));
+    }
+
+    #[test]
+    fn futures_book_chains_on_previous_update_id() {
+        let (books, _requests) = DiffDepthBooks::new(ProductType::Futures, None, TickSizes::default());
+        let mut out = Vec::new();
+        books.on_event("BTCUSDT", event(95, 99, Some(94), &[]), &mut out);
+        books.on_snapshot("BTCUSDT", snapshot(100));
+
+        // The first event straddles the snapshot rather than following it.
+        books.on_event("BTCUSDT", event(98, 104, Some(97), &[[100.0, 4.0]]), &mut out);
+        assert_eq!(best_bid(&out), (100.0, 4.0, 104));
+        // Update ids need not be contiguous, only chained through `pu`.
+        books.on_event("BTCUSDT", event(110, 112, Some(104), &[[100.0, 5.0]]), &mut out);
+        assert_eq!(best_bid(&out), (100.0, 5.0, 112));
+
+        books.on_event("BTCUSDT", event(120, 125, Some(115), &[[100.0, 6.0]]), &mut out);
+        assert_eq!(out.len(), 2);
+        assert_eq!(books.resyncs(), 1);
+        assert!(books.needs_snapshot("BTCUSDT"));
+    }
+
+    #[test]
+    fn parser_reads_depth_update() {
+        let (books, _requests) = DiffDepthBooks::new(ProductType::Futures, None, TickSizes::default());
+        let parse = parser(books.clone());
+        let mut out = Vec::new();
+        let mut json = br#"{"e":"depthUpdate","E":1700000000123,"T":1700000000120,"s":"BTCUSDT","U":101,"u":103,"pu":100,"b":[["100.0","2.5"]],"a":[]}"#.to_vec();
+        parse(&mut json, &mut out);
+        books.on_snapshot("BTCUSDT", snapshot(101));
+
+        let mut json = br#"{"e":"depthUpdate","E":1700000000223,"T":1700000000220,"s":"BTCUSDT","U":104,"u":104,"pu":103,"b":[],"a":[["101.0","0"],["101.5","1"]]}"#.to_vec();
+        parse(&mut json, &mut out);
+        let [MarketDataMsg::Depth5(d)] = &out[..] else { panic!("{out:?}") };
+        assert_eq!((d.bid_prices[0], d.bid_vols[0], d.ask_prices[0], d.update_id), (100.0, 2.5, 101.5, 104));
+        assert_eq!((d.event_timestamp_us, d.trade_timestamp_us), (1700000000223000, 1700000000220000));
+        assert_eq!(symbol_from_bytes(&d.symbol), "BTCUSDT");
+    }
+
+    #[tokio::test]
+    async fn fetches_snapshot() {
+        let (base, requests) = serve(|_| {
+            r#"{"lastUpdateId":1027024,"E":1589436922972,"T":1589436922959,"bids":[["4.00000000","431.00000000"]],"asks":[["4.00000200","12.00000000"],["4.1","1"]]}"#.into()
+        })
+        .await;
+        let fetcher = SnapshotFetcher::ubase(base, 5000, HashMap::new());
+        let snapshot = fetcher.fetch("BTCUSDT").await.unwrap();
+
+        assert_eq!(snapshot.last_update_id, 1027024);
+        assert_eq!(snapshot.bids, [[4.0, 431.0]]);
+        assert_eq!(snapshot.asks, [[4.000002, 12.0], [4.1, 1.0]]);
+        assert_eq!(*requests.lock().unwrap(), ["/fapi/v1/depth?symbol=BTCUSDT&limit=1000"]);
+    }
+}
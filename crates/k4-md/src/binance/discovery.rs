@@ -0,0 +1,98 @@
+//! Binance instrument listing for symbol discovery.
+//!
+//! Instruments come from `exchangeInfo`, 24h quote volumes from
+//! `ticker/24hr`. Futures are the USDⓈ-M contracts.
+
+use anyhow::Result;
+use async_trait::async_trait;
+use k4_core::{instrument::binance::parse_exchange_info, types::ProductType};
+use serde_json::Value;
+
+use crate::discovery::{InstrumentSource, Listing, set_volumes};
+
+/// Lists spot (`/api/v3`) and USDⓈ-M futures (`/fapi/v1`) instruments.
+pub struct BinanceInstruments {
+    http: reqwest::Client,
+    spot_url: String,
+    ubase_url: String,
+}
+
+impl BinanceInstruments {
+    /// Instruments from `spot_url` and `ubase_url` (normally
+    /// [`SPOT_REST_URL`](super::SPOT_REST_URL) and
+    /// [`UBASE_REST_URL`](super::UBASE_REST_URL)).
+    pub fn new(spot_url: impl Into<String>, ubase_url: impl Into<String>) -> Self {
+        Self { http: reqwest::Client::new(), spot_url: spot_url.into(), ubase_url: ubase_url.into() }
+    }
+
+    async fn get(&self, url: &str) -> Result<Value> {
+        Ok(self.http.get(url).send().await?.error_for_status()?.json().await?)
+    }
+}
+
+#[async_trait]
+impl InstrumentSource for BinanceInstruments {
+    async fn instruments(&self, product_type: ProductType, with_volume: bool) -> Result<Vec<Listing>> {
+        let (base, prefix, product_type) = match product_type {
+            ProductType::Spot => (&self.spot_url, "/api/v3", ProductType::Spot),
+            _ => (&self.ubase_url, "/fapi/v1", ProductType::Futures),
+        };
+        let info = self.get(&format!("{base}{prefix}/exchangeInfo")).await?;
+        let mut listings: Vec<Listing> =
+            parse_exchange_info(&info, product_type).into_iter().map(Listing::from).collect();
+        if with_volume {
+            let tickers = self.get(&format!("{base}{prefix}/ticker/24hr")).await?;
+            let volumes = tickers
+                .as_array()
+                .into_iter()
+                .flatten()
+                .filter_map(|t| {
+                    Some((t.get("symbol")?.as_str()?.to_string(), t.get("quoteVolume")?.as_str()?.parse().ok()?))
+                })
+                .collect();
+            set_volumes(&mut listings, volumes);
+        }
+        Ok(listings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k4_core::instrument::InstrumentKind;
+
+    use super::*;
+    use crate::backfill::stand_in::serve;
+
+    #[tokio::test]
+    async fn lists_futures_with_volume() {
+        let (base, requests) = serve(|path| {
+            let filters = serde_json::json!([
+                {"filterType": "PRICE_FILTER", "tickSize": "0.10"},
+                {"filterType": "LOT_SIZE", "stepSize": "0.001", "minQty": "0.001"}
+            ]);
+            match path {
+                "/fapi/v1/exchangeInfo" => serde_json::json!({"symbols": [
+                    {"symbol": "BTCUSDT", "status": "TRADING", "contractType": "PERPETUAL", "deliveryDate": 4133404800000u64,
+                     "baseAsset": "BTC", "quoteAsset": "USDT", "marginAsset": "USDT", "filters": filters},
+                    {"symbol": "BTCUSDT_250627", "status": "TRADING", "contractType": "CURRENT_QUARTER",
+                     "deliveryDate": 1751011200000u64, "baseAsset": "BTC", "quoteAsset": "USDT", "marginAsset": "USDT",
+                     "filters": filters},
+                ]}),
+                _ => serde_json::json!([
+                    {"symbol": "BTCUSDT", "quoteVolume": "12345.5"},
+                    {"symbol": "ETHUSDT", "quoteVolume": "10"},
+                ]),
+            }
+            .to_string()
+        })
+        .await;
+
+        let listings = BinanceInstruments::new("unused", base).instruments(ProductType::Futures, true).await.unwrap();
+
+        assert_eq!(listings.len(), 2);
+        assert_eq!(listings[0].instrument.canonical_id(), "BINANCE:BTC-USDT:PERP");
+        assert_eq!((listings[0].instrument.tick_size, listings[0].volume_24h), (0.1, Some(12345.5)));
+        assert_eq!((listings[1].instrument.kind, listings[1].volume_24h), (InstrumentKind::Future, None));
+        assert_eq!(*requests.lock().unwrap(), ["/fapi/v1/exchangeInfo", "/fapi/v1/ticker/24hr"]);
+    }
+}
@@ -1,70 +1,168 @@
 //! Binance JSON message parser.
 //!
 //! Parses WebSocket JSON messages from Binance Spot and UBase streams into
-//! `MarketDataMsg` variants. Uses `simd-json` for SIMD-accelerated parsing
-//! and `fast-float` for high-performance string-to-f64 conversion.
+//! `MarketDataMsg` variants. Uses `simd-json` tape access for
+//! SIMD-accelerated, allocation-free parsing and `fast-float` for
+//! high-performance string-to-f64 conversion.
 
 use k4_core::{time_util, *};
+use simd_json::prelude::*;
 
-use crate::json_util::{fill_depth5_levels, parse_f64_field};
+use crate::{
+    json_util::{Json, fill_depth_n_levels, fill_depth5_levels, parse_f64_field, with_json},
+    subscription::SubscriptionMsgs,
+};
 
 /// Parse a Binance JSON WebSocket message into a MarketDataMsg.
 ///
 /// Accepts `&mut [u8]` for simd-json in-place parsing.
 /// Returns `None` for messages that are not market data (e.g. subscription acks).
 pub fn parse_message(data: &mut [u8]) -> Option<MarketDataMsg> {
-    let v: serde_json::Value = simd_json::serde::from_slice(data).ok()?;
-
-    let event_type = v.get("e")?.as_str()?;
-    match event_type {
-        "aggTrade" => parse_agg_trade(&v),
-        "bookTicker" => parse_book_ticker(&v),
-        "trade" => parse_trade(&v),
-        "depthUpdate" => parse_depth_update(&v),
+    with_json(data, |v| match v.get("e")?.into_string()? {
+        "aggTrade" => parse_agg_trade(v),
+        "bookTicker" => parse_book_ticker(v),
+        "trade" => parse_trade(v),
+        "depthUpdate" => parse_depth_update(v),
         _ => None,
-    }
+    })?
+}
+
+/// [`parse_message`] onto `out`, also emitting each depth update as a
+/// [`DepthN`] of up to `depth_n_levels` levels.
+pub fn parse_message_with_depth_n(data: &mut [u8], depth_n_levels: usize, out: &mut Vec<MarketDataMsg>) {
+    with_json(data, |v| match v.get("e").and_then(|e| e.into_string()) {
+        Some("aggTrade") => out.extend(parse_agg_trade(v)),
+        Some("bookTicker") => out.extend(parse_book_ticker(v)),
+        Some("trade") => out.extend(parse_trade(v)),
+        Some("depthUpdate") => {
+            out.extend(parse_depth_update(v));
+            out.extend(parse_depth_update_n(v, depth_n_levels));
+        }
+        _ => {}
+    });
 }
 
 /// Build subscription message for Spot JSON (aggTrade only).
 pub fn build_spot_json_subscribe(symbols: &[String]) -> String {
-    let params: Vec<String> = symbols.iter().map(|s| format!("{}@aggTrade", s.to_lowercase())).collect();
-    serde_json::json!({
-        "method": "SUBSCRIBE",
-        "params": params,
-        "id": 1
-    })
-    .to_string()
+    request("SUBSCRIBE", spot_json_params(symbols))
+}
+
+/// Build unsubscription message for Spot JSON.
+pub fn build_spot_json_unsubscribe(symbols: &[String]) -> String {
+    request("UNSUBSCRIBE", spot_json_params(symbols))
 }
 
 /// Build subscription message for Spot SBE (bookTicker, trade, depth).
 pub fn build_spot_sbe_subscribe(symbols: &[String]) -> String {
+    request("SUBSCRIBE", spot_sbe_params(symbols, true))
+}
+
+/// Build unsubscription message for Spot SBE.
+pub fn build_spot_sbe_unsubscribe(symbols: &[String]) -> String {
+    request("UNSUBSCRIBE", spot_sbe_params(symbols, true))
+}
+
+/// Build subscription message for Spot SBE without depth, when the book
+/// comes from diff depth instead.
+pub fn build_spot_sbe_no_depth_subscribe(symbols: &[String]) -> String {
+    request("SUBSCRIBE", spot_sbe_params(symbols, false))
+}
+
+/// Build unsubscription message for Spot SBE without depth.
+pub fn build_spot_sbe_no_depth_unsubscribe(symbols: &[String]) -> String {
+    request("UNSUBSCRIBE", spot_sbe_params(symbols, false))
+}
+
+/// Build subscription message for UBase with 20-level partial depth in
+/// place of 5, for DepthN.
+pub fn build_ubase_depth20_subscribe(symbols: &[String]) -> String {
+    request("SUBSCRIBE", ubase_params(symbols, Some(20)))
+}
+
+/// Build unsubscription message for UBase with 20-level partial depth.
+pub fn build_ubase_depth20_unsubscribe(symbols: &[String]) -> String {
+    request("UNSUBSCRIBE", ubase_params(symbols, Some(20)))
+}
+
+/// Build subscription message for UBase JSON.
+pub fn build_ubase_subscribe(symbols: &[String]) -> String {
+    request("SUBSCRIBE", ubase_params(symbols, Some(5)))
+}
+
+/// Build unsubscription message for UBase JSON.
+pub fn build_ubase_unsubscribe(symbols: &[String]) -> String {
+    request("UNSUBSCRIBE", ubase_params(symbols, Some(5)))
+}
+
+/// Build subscription message for UBase JSON without depth, when the book
+/// comes from diff depth instead.
+pub fn build_ubase_no_depth_subscribe(symbols: &[String]) -> String {
+    request("SUBSCRIBE", ubase_params(symbols, None))
+}
+
+/// Build unsubscription message for UBase JSON without depth.
+pub fn build_ubase_no_depth_unsubscribe(symbols: &[String]) -> String {
+    request("UNSUBSCRIBE", ubase_params(symbols, None))
+}
+
+/// Subscription messages for `<symbol>@depth@<speed>` diff depth, or `None`
+/// if Binance has no stream at `update_speed_ms`.
+pub fn diff_depth_subscription(update_speed_ms: u32) -> Option<SubscriptionMsgs> {
+    if ![0, 100, 250, 500, 1000].contains(&update_speed_ms) {
+        return None;
+    }
+    Some(SubscriptionMsgs::new(
+        move |s| request("SUBSCRIBE", diff_depth_params(s, update_speed_ms)),
+        move |s| request("UNSUBSCRIBE", diff_depth_params(s, update_speed_ms)),
+        str::to_uppercase,
+    ))
+}
+
+fn spot_json_params(symbols: &[String]) -> Vec<String> {
+    symbols.iter().map(|s| format!("{}@aggTrade", s.to_lowercase())).collect()
+}
+
+fn spot_sbe_params(symbols: &[String], depth: bool) -> Vec<String> {
     let mut params = Vec::new();
     for s in symbols {
         let lower = s.to_lowercase();
         params.push(format!("{lower}@bestBidAsk"));
         params.push(format!("{lower}@trade"));
-        params.push(format!("{lower}@depth20"));
+        if depth {
+            params.push(format!("{lower}@depth20"));
+        }
     }
-    serde_json::json!({
-        "method": "SUBSCRIBE",
-        "params": params,
-        "id": 1
-    })
-    .to_string()
+    params
 }
 
-/// Build subscription message for UBase JSON.
-pub fn build_ubase_subscribe(symbols: &[String]) -> String {
+fn ubase_params(symbols: &[String], depth: Option<usize>) -> Vec<String> {
     let mut params = Vec::new();
     for s in symbols {
         let lower = s.to_lowercase();
         params.push(format!("{lower}@aggTrade"));
         params.push(format!("{lower}@bookTicker"));
         params.push(format!("{lower}@trade"));
-        params.push(format!("{lower}@depth5@100ms"));
+        if let Some(depth) = depth {
+            params.push(format!("{lower}@depth{depth}@100ms"));
+        }
     }
+    params
+}
+
+/// Spot's 1000ms stream is the plain `@depth`; every other speed is spelt out.
+fn diff_depth_params(symbols: &[String], update_speed_ms: u32) -> Vec<String> {
+    symbols
+        .iter()
+        .map(|s| match update_speed_ms {
+            1000 => format!("{}@depth", s.to_lowercase()),
+            ms => format!("{}@depth@{ms}ms", s.to_lowercase()),
+        })
+        .collect()
+}
+
+fn request(method: &str, params: Vec<String>) -> String {
     serde_json::json!({
-        "method": "SUBSCRIBE",
+        "method": method,
         "params": params,
         "id": 1
     })
@@ -75,10 +173,10 @@ pub fn build_ubase_subscribe(symbols: &[String]) -> String {
 // Individual parsers
 // ---------------------------------------------------------------------------
 
-fn parse_agg_trade(v: &serde_json::Value) -> Option<MarketDataMsg> {
+fn parse_agg_trade(v: Json<'_, '_>) -> Option<MarketDataMsg> {
     let local_time = time_util::now_us();
-    let sym = v.get("s")?.as_str()?;
-    let product_type = if v.get("ps").is_some() { ProductType::Futures } else { ProductType::Spot };
+    let sym = v.get("s")?.into_string()?;
+    let product_type = if v.contains_key("ps") { ProductType::Futures } else { ProductType::Spot };
 
     let agg = AggTrade {
         symbol: symbol_to_bytes(sym),
@@ -92,16 +190,18 @@ fn parse_agg_trade(v: &serde_json::Value) -> Option<MarketDataMsg> {
         vol: parse_f64_field(v, "q")?,
         trade_count: 0, // Not provided in the message
         is_buyer_maker: v.get("m")?.as_bool()?,
+        backfilled: false,
         local_time_us: local_time,
+        kernel_rx_us: 0,
     };
 
     Some(MarketDataMsg::AggTrade(agg))
 }
 
-fn parse_book_ticker(v: &serde_json::Value) -> Option<MarketDataMsg> {
+fn parse_book_ticker(v: Json<'_, '_>) -> Option<MarketDataMsg> {
     let local_time = time_util::now_us();
-    let sym = v.get("s")?.as_str()?;
-    let product_type = if v.get("ps").is_some() { ProductType::Futures } else { ProductType::Spot };
+    let sym = v.get("s")?.into_string()?;
+    let product_type = if v.contains_key("ps") { ProductType::Futures } else { ProductType::Spot };
 
     let bbo = Bookticker {
         symbol: symbol_to_bytes(sym),
@@ -116,15 +216,16 @@ fn parse_book_ticker(v: &serde_json::Value) -> Option<MarketDataMsg> {
         bid_order_count: 0,
         ask_order_count: 0,
         local_time_us: local_time,
+        kernel_rx_us: 0,
     };
 
     Some(MarketDataMsg::Bbo(bbo))
 }
 
-fn parse_trade(v: &serde_json::Value) -> Option<MarketDataMsg> {
+fn parse_trade(v: Json<'_, '_>) -> Option<MarketDataMsg> {
     let local_time = time_util::now_us();
-    let sym = v.get("s")?.as_str()?;
-    let product_type = if v.get("ps").is_some() { ProductType::Futures } else { ProductType::Spot };
+    let sym = v.get("s")?.into_string()?;
+    let product_type = if v.contains_key("ps") { ProductType::Futures } else { ProductType::Spot };
 
     let trade = Trade {
         symbol: symbol_to_bytes(sym),
@@ -135,16 +236,19 @@ fn parse_trade(v: &serde_json::Value) -> Option<MarketDataMsg> {
         price: parse_f64_field(v, "p")?,
         vol: parse_f64_field(v, "q")?,
         is_buyer_maker: v.get("m")?.as_bool()?,
+        backfilled: false,
+        cross_seq: 0,
         local_time_us: local_time,
+        kernel_rx_us: 0,
     };
 
     Some(MarketDataMsg::Trade(trade))
 }
 
-fn parse_depth_update(v: &serde_json::Value) -> Option<MarketDataMsg> {
+fn parse_depth_update(v: Json<'_, '_>) -> Option<MarketDataMsg> {
     let local_time = time_util::now_us();
-    let sym = v.get("s")?.as_str()?;
-    let product_type = if v.get("ps").is_some() { ProductType::Futures } else { ProductType::Spot };
+    let sym = v.get("s")?.into_string()?;
+    let product_type = if v.contains_key("ps") { ProductType::Futures } else { ProductType::Spot };
 
     let bids = v.get("b")?.as_array()?;
     let asks = v.get("a")?.as_array()?;
@@ -165,6 +269,7 @@ fn parse_depth_update(v: &serde_json::Value) -> Option<MarketDataMsg> {
         bid_order_counts: [0; 5],
         ask_order_counts: [0; 5],
         local_time_us: local_time,
+        kernel_rx_us: 0,
     };
 
     fill_depth5_levels(&mut depth, bids, asks);
@@ -172,6 +277,21 @@ fn parse_depth_update(v: &serde_json::Value) -> Option<MarketDataMsg> {
     Some(MarketDataMsg::Depth5(depth))
 }
 
+fn parse_depth_update_n(v: Json<'_, '_>, levels: usize) -> Option<MarketDataMsg> {
+    let sym = v.get("s")?.into_string()?;
+    let mut depth = DepthNBox::new(DepthN {
+        symbol: symbol_to_bytes(sym),
+        product_type: if v.contains_key("ps") { ProductType::Futures } else { ProductType::Spot },
+        event_timestamp_us: v.get("E").and_then(|e| e.as_u64()).unwrap_or(0) * 1000,
+        trade_timestamp_us: v.get("T").and_then(|t| t.as_u64()).unwrap_or(0) * 1000,
+        update_id: v.get("u")?.as_u64()?,
+        local_time_us: time_util::now_us(),
+        ..Default::default()
+    });
+    fill_depth_n_levels(&mut depth, levels, v.get("b")?.as_array()?, v.get("a")?.as_array()?);
+    Some(MarketDataMsg::DepthN(depth))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -204,4 +324,35 @@ mod tests {
             _ => panic!("expected Bbo"),
         }
     }
+
+    #[test]
+    fn depth_update_fills_depth5_and_depth_n() {
+        let mut json = br#"{"e":"depthUpdate","E":1718000000156,"T":1718000000150,"s":"BTCUSDT","u":42,"pu":41,"ps":"BTCUSDT","b":[["100.5","1"],["100.4","2"],["100.3","3"],["100.2","4"],["100.1","5"],["100.0","6"],["99.9","7"]],"a":[["100.6","1"]]}"#.to_vec();
+        let mut out = Vec::new();
+        parse_message_with_depth_n(&mut json, 6, &mut out);
+
+        assert_eq!(out.len(), 2);
+        assert!(matches!(&out[0], MarketDataMsg::Depth5(d) if d.bid_level == 5 && d.ask_level == 1));
+        match &out[1] {
+            MarketDataMsg::DepthN(d) => {
+                assert_eq!((d.bid_level, d.ask_level, d.update_id), (6, 1, 42));
+                assert_eq!(d.product_type, ProductType::Futures);
+                assert_eq!((d.bid_prices[5], d.bid_vols[5], d.bid_prices[6]), (100.0, 6.0, 0.0));
+            }
+            _ => panic!("expected DepthN"),
+        }
+        assert!(build_ubase_depth20_subscribe(&["BTCUSDT".into()]).contains("btcusdt@depth20@100ms"));
+    }
+
+    #[test]
+    fn diff_depth_subscriptions() {
+        let symbols = ["BTCUSDT".to_string()];
+        let msgs = diff_depth_subscription(100).unwrap();
+        assert!((msgs.subscribe)(&symbols).contains(r#""btcusdt@depth@100ms""#));
+        assert!((msgs.unsubscribe)(&symbols).contains("UNSUBSCRIBE"));
+        assert!((diff_depth_subscription(1000).unwrap().subscribe)(&symbols).contains(r#""btcusdt@depth""#));
+        assert!(diff_depth_subscription(10).is_none());
+        assert!(!build_ubase_no_depth_subscribe(&symbols).contains("@depth"));
+        assert!(!build_spot_sbe_no_depth_subscribe(&symbols).contains("@depth"));
+    }
 }
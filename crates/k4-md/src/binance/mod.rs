@@ -1,23 +1,70 @@
 //! Binance market data — stream definitions.
 //!
-//! Produces up to 3 [`StreamDef`]s:
+//! Produces up to 5 [`StreamDef`]s:
 //! - Spot JSON (`stream.binance.com`) — aggTrade
 //! - Spot SBE (`stream-sbe.binance.com`) — bookTicker, trade, depth (binary)
 //! - UBase JSON (`fstream.binance.com`) — aggTrade, bookTicker, trade, depth5
+//! - Spot / UBase diff depth (`diff_depth` configured) — see [`diff_depth`]
+//!
+//! Spot depth arrives as 20-level snapshots; with DepthN enabled, UBase
+//! subscribes 20-level partial depth instead of 5, and both fill a
+//! [`DepthN`](k4_core::DepthN) alongside the Depth5. With `diff_depth`, the
+//! depth comes from local books instead, and the SBE and UBase streams drop
+//! their depth subscriptions.
+//!
+//! Trade and aggTrade ids are contiguous per symbol, so any jump is checked
+//! as a sequence gap.
 
+pub mod backfill;
 pub mod config;
+pub mod diff_depth;
+pub mod discovery;
 pub mod json_parser;
 pub mod sbe_parser;
 
-use anyhow::Result;
-use k4_core::config::ConnectionConfig;
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Result, anyhow};
+use k4_core::{
+    config::{ConnectionConfig, DiffDepthConfig},
+    instrument::InstrumentRegistry,
+    types::{Exchange, ProductType},
+    ws::PingPayload,
+};
 
-use self::config::BinanceConfig;
-use crate::pipeline::{ShmNames, StreamDef};
+use self::{
+    backfill::BinanceTradeFetcher,
+    config::BinanceConfig,
+    diff_depth::{DiffDepthBooks, SnapshotFetcher},
+};
+use crate::{
+    backfill::TradeFetcher,
+    order_book::TickSizes,
+    pipeline::{PingConfig, ShmNames, StreamDef},
+    seq_gap::GapTolerance,
+    subscription::SubscriptionMsgs,
+};
 
-/// Build Binance stream definitions from the connection config.
-pub fn build(conn_config: &ConnectionConfig) -> Result<Vec<StreamDef>> {
+/// Spot REST API.
+pub const SPOT_REST_URL: &str = "https://api.binance.com";
+/// USDⓈ-M futures REST API.
+pub const UBASE_REST_URL: &str = "https://fapi.binance.com";
+
+/// Build Binance stream definitions from the connection config, with tick
+/// sizes for local books from `instruments`.
+pub fn build(conn_config: &ConnectionConfig, instruments: &InstrumentRegistry) -> Result<Vec<StreamDef>> {
     let cfg = BinanceConfig::from_connection(conn_config)?;
+    let spot_backfill: Option<Arc<dyn TradeFetcher>> = conn_config
+        .backfill_enabled()
+        .then(|| Arc::new(BinanceTradeFetcher::spot(SPOT_REST_URL, cfg.spot_extra_headers.clone())) as _);
+    // Binance only pings from its side; our WebSocket pings measure each
+    // connection's RTT for redundancy ranking.
+    let ping = PingConfig {
+        interval: Duration::from_secs(cfg.ping_interval_sec),
+        payload: PingPayload::WebSocketPing,
+        pong: None,
+        pong_timeout: conn_config.pong_timeout(),
+    };
     let mut streams = Vec::new();
 
     // --- Spot streams ---
@@ -27,61 +74,227 @@ pub fn build(conn_config: &ConnectionConfig) -> Result<Vec<StreamDef>> {
             label: "binance_spot_json".into(),
             ws_url: "wss://stream.binance.com:443/ws".into(),
             subscribe_msg: json_parser::build_spot_json_subscribe(&cfg.spot_symbols),
-            ping: None,
+            subscription: Some(SubscriptionMsgs::new(
+                json_parser::build_spot_json_subscribe,
+                json_parser::build_spot_json_unsubscribe,
+                str::to_uppercase,
+            )),
+            ping: Some(ping.clone()),
             extra_headers: cfg.spot_extra_headers.clone(),
+            proxy: cfg.spot_proxy.clone(),
+            local_binds: cfg.spot_local_binds.clone(),
+            conn_count: cfg.spot_conn_count,
             shm: ShmNames { agg: cfg.spot_agg_shm_name.clone(), ..Default::default() },
             symbols: cfg.spot_symbols.clone(),
+            product_type: ProductType::Spot,
             md_size: cfg.md_size,
-            text_parser: Some(Box::new(|data| json_parser::parse_message(data).into_iter().collect())),
+            text_parser: Some(Box::new(|data, out| out.extend(json_parser::parse_message(data)))),
             binary_parser: None,
             custom_trade_dedup: None,
             dedup_cpu_core: None,
+            busy_poll: None,
+            gap_tolerance: GapTolerance { agg: Some(1), ..Default::default() },
+            gap_hook: None,
+            trade_backfill: spot_backfill.clone(),
+            background: Vec::new(),
+            conn_requests: None,
+            ticks: None,
         });
 
         // Stream 2: Spot SBE (bbo, trade, depth — binary protocol)
+        let sbe_subscription = match cfg.spot_diff_depth {
+            None => SubscriptionMsgs::new(
+                json_parser::build_spot_sbe_subscribe,
+                json_parser::build_spot_sbe_unsubscribe,
+                str::to_uppercase,
+            ),
+            Some(_) => SubscriptionMsgs::new(
+                json_parser::build_spot_sbe_no_depth_subscribe,
+                json_parser::build_spot_sbe_no_depth_unsubscribe,
+                str::to_uppercase,
+            ),
+        };
+        let sbe_depth = cfg.spot_diff_depth.is_none();
         streams.push(StreamDef {
             label: "binance_spot_sbe".into(),
             ws_url: "wss://stream-sbe.binance.com:9443/stream".into(),
-            subscribe_msg: json_parser::build_spot_sbe_subscribe(&cfg.spot_symbols),
-            ping: None,
+            subscribe_msg: (sbe_subscription.subscribe)(&cfg.spot_symbols),
+            subscription: Some(sbe_subscription),
+            ping: Some(ping.clone()),
             extra_headers: cfg.spot_extra_headers.clone(),
+            proxy: cfg.spot_proxy.clone(),
+            local_binds: cfg.spot_local_binds.clone(),
+            conn_count: cfg.spot_conn_count,
             shm: ShmNames {
                 bbo: cfg.spot_bbo_shm_name.clone(),
                 trade: cfg.spot_trade_shm_name.clone(),
-                depth5: cfg.spot_depth5_shm_name.clone(),
+                depth5: cfg.spot_depth5_shm_name.clone().filter(|_| sbe_depth),
+                depth_n: cfg.spot_depth_n_shm_name.clone().filter(|_| sbe_depth),
                 ..Default::default()
             },
             symbols: cfg.spot_symbols.clone(),
+            product_type: ProductType::Spot,
             md_size: cfg.md_size,
             text_parser: None,
-            binary_parser: Some(Box::new(sbe_parser::parse_sbe_message)),
+            binary_parser: Some(match cfg.spot_depth_n_levels.filter(|_| sbe_depth) {
+                None => Box::new(sbe_parser::parse_sbe_message),
+                levels => Box::new(move |data, out| sbe_parser::parse_sbe_message_with_depth_n(data, levels, out)),
+            }),
             custom_trade_dedup: None,
             dedup_cpu_core: None,
+            busy_poll: None,
+            gap_tolerance: GapTolerance { trade: Some(1), ..Default::default() },
+            gap_hook: None,
+            trade_backfill: spot_backfill.clone(),
+            background: Vec::new(),
+            conn_requests: None,
+            ticks: None,
         });
+
+        // Stream 3: Spot diff depth (local books)
+        if let Some(diff) = &cfg.spot_diff_depth {
+            let fetcher = SnapshotFetcher::spot(SPOT_REST_URL, snapshot_limit(diff), cfg.spot_extra_headers.clone());
+            let ticks = TickSizes::from_registry(instruments, Exchange::Binance, ProductType::Spot);
+            let (books, requests) = DiffDepthBooks::new(ProductType::Spot, cfg.spot_depth_n_levels, ticks.clone());
+            let subscription = diff_depth_subscription(diff)?;
+            streams.push(StreamDef {
+                label: "binance_spot_depth".into(),
+                ws_url: "wss://stream.binance.com:443/ws".into(),
+                subscribe_msg: (subscription.subscribe)(&cfg.spot_symbols),
+                subscription: Some(subscription),
+                ping: Some(ping.clone()),
+                extra_headers: cfg.spot_extra_headers.clone(),
+                proxy: cfg.spot_proxy.clone(),
+                local_binds: cfg.spot_local_binds.clone(),
+                conn_count: cfg.spot_conn_count,
+                shm: ShmNames {
+                    depth5: cfg.spot_depth5_shm_name.clone(),
+                    depth_n: cfg.spot_depth_n_shm_name.clone(),
+                    ..Default::default()
+                },
+                symbols: cfg.spot_symbols.clone(),
+                product_type: ProductType::Spot,
+                md_size: cfg.md_size,
+                text_parser: Some(diff_depth::parser(books.clone())),
+                binary_parser: None,
+                custom_trade_dedup: None,
+                dedup_cpu_core: None,
+                busy_poll: None,
+                gap_tolerance: GapTolerance::default(),
+                gap_hook: None,
+                trade_backfill: None,
+                background: vec![Box::pin(diff_depth::run_snapshot_task(books, fetcher, requests))],
+                conn_requests: None,
+                ticks: Some(ticks),
+            });
+        }
     }
 
     // --- UBase stream ---
     if !cfg.ubase_symbols.is_empty() {
+        let ubase_depth = cfg.ubase_diff_depth.is_none();
+        let subscription = match (ubase_depth, cfg.ubase_depth_n_levels) {
+            (false, _) => SubscriptionMsgs::new(
+                json_parser::build_ubase_no_depth_subscribe,
+                json_parser::build_ubase_no_depth_unsubscribe,
+                str::to_uppercase,
+            ),
+            (true, None) => SubscriptionMsgs::new(
+                json_parser::build_ubase_subscribe,
+                json_parser::build_ubase_unsubscribe,
+                str::to_uppercase,
+            ),
+            (true, Some(_)) => SubscriptionMsgs::new(
+                json_parser::build_ubase_depth20_subscribe,
+                json_parser::build_ubase_depth20_unsubscribe,
+                str::to_uppercase,
+            ),
+        };
         streams.push(StreamDef {
             label: "binance_ubase".into(),
             ws_url: "wss://fstream.binance.com:443/ws".into(),
-            subscribe_msg: json_parser::build_ubase_subscribe(&cfg.ubase_symbols),
-            ping: None,
+            subscribe_msg: (subscription.subscribe)(&cfg.ubase_symbols),
+            subscription: Some(subscription),
+            ping: Some(ping.clone()),
             extra_headers: cfg.ubase_extra_headers.clone(),
+            proxy: cfg.ubase_proxy.clone(),
+            local_binds: cfg.ubase_local_binds.clone(),
+            conn_count: cfg.ubase_conn_count,
             shm: ShmNames {
                 bbo: cfg.ubase_bbo_shm_name.clone(),
                 agg: cfg.ubase_agg_shm_name.clone(),
                 trade: cfg.ubase_trade_shm_name.clone(),
-                depth5: cfg.ubase_depth5_shm_name.clone(),
+                depth5: cfg.ubase_depth5_shm_name.clone().filter(|_| ubase_depth),
+                depth_n: cfg.ubase_depth_n_shm_name.clone().filter(|_| ubase_depth),
             },
             symbols: cfg.ubase_symbols.clone(),
+            product_type: ProductType::Futures,
             md_size: cfg.md_size,
-            text_parser: Some(Box::new(|data| json_parser::parse_message(data).into_iter().collect())),
+            text_parser: Some(match cfg.ubase_depth_n_levels.filter(|_| ubase_depth) {
+                None => Box::new(|data, out| out.extend(json_parser::parse_message(data))),
+                Some(levels) => Box::new(move |data, out| json_parser::parse_message_with_depth_n(data, levels, out)),
+            }),
             binary_parser: None,
             custom_trade_dedup: None,
             dedup_cpu_core: None,
+            busy_poll: None,
+            gap_tolerance: GapTolerance { agg: Some(1), trade: Some(1), ..Default::default() },
+            gap_hook: None,
+            trade_backfill: conn_config
+                .backfill_enabled()
+                .then(|| Arc::new(BinanceTradeFetcher::ubase(UBASE_REST_URL, cfg.ubase_extra_headers.clone())) as _),
+            background: Vec::new(),
+            conn_requests: None,
+            ticks: None,
         });
+
+        // UBase diff depth (local books)
+        if let Some(diff) = &cfg.ubase_diff_depth {
+            let fetcher = SnapshotFetcher::ubase(UBASE_REST_URL, snapshot_limit(diff), cfg.ubase_extra_headers.clone());
+            let ticks = TickSizes::from_registry(instruments, Exchange::Binance, ProductType::Futures);
+            let (books, requests) = DiffDepthBooks::new(ProductType::Futures, cfg.ubase_depth_n_levels, ticks.clone());
+            let subscription = diff_depth_subscription(diff)?;
+            streams.push(StreamDef {
+                label: "binance_ubase_depth".into(),
+                ws_url: "wss://fstream.binance.com:443/ws".into(),
+                subscribe_msg: (subscription.subscribe)(&cfg.ubase_symbols),
+                subscription: Some(subscription),
+                ping: Some(ping.clone()),
+                extra_headers: cfg.ubase_extra_headers.clone(),
+                proxy: cfg.ubase_proxy.clone(),
+                local_binds: cfg.ubase_local_binds.clone(),
+                conn_count: cfg.ubase_conn_count,
+                shm: ShmNames {
+                    depth5: cfg.ubase_depth5_shm_name.clone(),
+                    depth_n: cfg.ubase_depth_n_shm_name.clone(),
+                    ..Default::default()
+                },
+                symbols: cfg.ubase_symbols.clone(),
+                product_type: ProductType::Futures,
+                md_size: cfg.md_size,
+                text_parser: Some(diff_depth::parser(books.clone())),
+                binary_parser: None,
+                custom_trade_dedup: None,
+                dedup_cpu_core: None,
+                busy_poll: None,
+                gap_tolerance: GapTolerance::default(),
+                gap_hook: None,
+                trade_backfill: None,
+                background: vec![Box::pin(diff_depth::run_snapshot_task(books, fetcher, requests))],
+                conn_requests: None,
+                ticks: Some(ticks),
+            });
+        }
     }
 
     Ok(streams)
 }
+
+fn diff_depth_subscription(diff: &DiffDepthConfig) -> Result<SubscriptionMsgs> {
+    let speed = diff.update_speed_ms.unwrap_or(100);
+    json_parser::diff_depth_subscription(speed).ok_or_else(|| anyhow!("unsupported diff depth update_speed_ms {speed}"))
+}
+
+fn snapshot_limit(diff: &DiffDepthConfig) -> u32 {
+    diff.snapshot_limit.unwrap_or(diff_depth::MAX_SNAPSHOT_LIMIT)
+}
@@ -33,23 +33,31 @@ const TEMPLATE_TRADES: u16 = 10000;
 const TEMPLATE_BEST_BID_ASK: u16 = 10001;
 const TEMPLATE_DEPTH: u16 = 10002;
 
-/// Parse an SBE binary message, returning zero or more MarketDataMsg items.
+/// Parse an SBE binary message, pushing zero or more MarketDataMsg items
+/// onto `out`.
 ///
 /// A single SBE message may contain multiple trades (via group encoding),
-/// hence the Vec return type.
-pub fn parse_sbe_message(data: &[u8]) -> Vec<MarketDataMsg> {
+/// hence the output `Vec`. Nothing here allocates once `out` has grown to
+/// the largest batch seen.
+pub fn parse_sbe_message(data: &[u8], out: &mut Vec<MarketDataMsg>) {
+    parse_sbe_message_with_depth_n(data, None, out)
+}
+
+/// [`parse_sbe_message`], also emitting each depth snapshot as a
+/// [`DepthN`] of up to `depth_n_levels` levels if set.
+pub fn parse_sbe_message_with_depth_n(data: &[u8], depth_n_levels: Option<usize>, out: &mut Vec<MarketDataMsg>) {
     if data.len() < SBE_HEADER_SIZE {
-        return vec![];
+        return;
     }
 
     let template_id = read_u16_le(data, 2);
     let body = &data[SBE_HEADER_SIZE..];
 
     match template_id {
-        TEMPLATE_BEST_BID_ASK => parse_best_bid_ask(body).into_iter().collect(),
-        TEMPLATE_TRADES => parse_trades(body),
-        TEMPLATE_DEPTH => parse_depth(body).into_iter().collect(),
-        _ => vec![],
+        TEMPLATE_BEST_BID_ASK => out.extend(parse_best_bid_ask(body)),
+        TEMPLATE_TRADES => parse_trades(body, out),
+        TEMPLATE_DEPTH => parse_depth(body, depth_n_levels, out),
+        _ => {}
     }
 }
 
@@ -149,6 +157,7 @@ fn parse_best_bid_ask(body: &[u8]) -> Option<MarketDataMsg> {
         bid_order_count: 0,
         ask_order_count: 0,
         local_time_us: local_time,
+        kernel_rx_us: 0,
     }))
 }
 
@@ -172,9 +181,9 @@ fn parse_best_bid_ask(body: &[u8]) -> Option<MarketDataMsg> {
 const TRADES_ROOT_SIZE: usize = 18;
 const TRADES_GROUP_HEADER_SIZE: usize = 6; // groupSizeEncoding: u16 + u32
 
-fn parse_trades(body: &[u8]) -> Vec<MarketDataMsg> {
+fn parse_trades(body: &[u8], out: &mut Vec<MarketDataMsg>) {
     if body.len() < TRADES_ROOT_SIZE + TRADES_GROUP_HEADER_SIZE {
-        return vec![];
+        return;
     }
 
     let local_time = time_util::now_us();
@@ -190,14 +199,14 @@ fn parse_trades(body: &[u8]) -> Vec<MarketDataMsg> {
     let trades_start = TRADES_ROOT_SIZE + TRADES_GROUP_HEADER_SIZE;
     let trades_end = trades_start + block_length * num_trades;
     if trades_end > body.len() {
-        return vec![];
+        return;
     }
 
     let sym_bytes = read_var_string8(body, trades_end);
     let sym = std::str::from_utf8(sym_bytes).unwrap_or("");
     let sym_arr = symbol_to_bytes(sym);
 
-    let mut results = Vec::with_capacity(num_trades);
+    out.reserve(num_trades);
     let mut offset = trades_start;
 
     for _ in 0..num_trades {
@@ -210,7 +219,7 @@ fn parse_trades(body: &[u8]) -> Vec<MarketDataMsg> {
         let vol = decode_decimal128(read_i64_le(body, offset + 16), qty_exp);
         let is_buyer_maker = body.get(offset + 24).copied().unwrap_or(0) != 0;
 
-        results.push(MarketDataMsg::Trade(Trade {
+        out.push(MarketDataMsg::Trade(Trade {
             symbol: sym_arr,
             product_type: ProductType::Spot,
             event_timestamp_us: event_time_us,
@@ -219,13 +228,14 @@ fn parse_trades(body: &[u8]) -> Vec<MarketDataMsg> {
             price,
             vol,
             is_buyer_maker,
+            backfilled: false,
+            cross_seq: 0,
             local_time_us: local_time,
+            kernel_rx_us: 0,
         }));
 
         offset += block_length;
     }
-
-    results
 }
 
 // ---------------------------------------------------------------------------
@@ -246,9 +256,11 @@ fn parse_trades(body: &[u8]) -> Vec<MarketDataMsg> {
 const DEPTH_ROOT_SIZE: usize = 18;
 const DEPTH_GROUP_HEADER_SIZE: usize = 4; // groupSize16Encoding: u16 + u16
 
-fn parse_depth(body: &[u8]) -> Option<MarketDataMsg> {
+/// Parse a depth snapshot into a [`Depth5`] and, if `depth_n_levels` is
+/// set, a [`DepthN`] of that many levels.
+fn parse_depth(body: &[u8], depth_n_levels: Option<usize>, out: &mut Vec<MarketDataMsg>) {
     if body.len() < DEPTH_ROOT_SIZE + DEPTH_GROUP_HEADER_SIZE {
-        return None;
+        return;
     }
 
     let local_time = time_util::now_us();
@@ -274,71 +286,106 @@ fn parse_depth(body: &[u8]) -> Option<MarketDataMsg> {
         bid_order_counts: [0; 5],
         ask_order_counts: [0; 5],
         local_time_us: local_time,
+        kernel_rx_us: 0,
     };
-
-    let mut offset = DEPTH_ROOT_SIZE;
-
-    // --- Bids group ---
-    if offset + DEPTH_GROUP_HEADER_SIZE > body.len() {
-        return Some(MarketDataMsg::Depth5(depth));
-    }
-    let bid_block_len = read_u16_le(body, offset) as usize;
-    let num_bids = read_u16_le(body, offset + 2) as usize;
-    offset += DEPTH_GROUP_HEADER_SIZE;
-
-    let bid_levels = num_bids.min(5);
-    for i in 0..bid_levels {
-        if offset + bid_block_len > body.len() {
-            break;
+    let mut depth_n = depth_n_levels.map(|levels| {
+        let d = DepthN {
+            product_type: ProductType::Spot,
+            event_timestamp_us: event_time_us,
+            trade_timestamp_us: event_time_us,
+            update_id,
+            local_time_us: local_time,
+            ..Default::default()
+        };
+        (levels.min(MAX_DEPTH_LEVELS), DepthNBox::new(d))
+    });
+
+    // --- Bids group, then asks group, then symbol (VarString8 at end) ---
+    let bids_end = read_levels(body, DEPTH_ROOT_SIZE, price_exp, qty_exp, |i, price, qty| {
+        if i < 5 {
+            depth.bid_prices[i] = price;
+            depth.bid_vols[i] = qty;
+            depth.bid_level = (i + 1) as u32;
+        }
+        if let Some((levels, d)) = &mut depth_n
+            && i < *levels
+        {
+            d.bid_prices[i] = price;
+            d.bid_vols[i] = qty;
+            d.bid_level = (i + 1) as u32;
         }
-        depth.bid_prices[i] = decode_decimal128(read_i64_le(body, offset), price_exp);
-        depth.bid_vols[i] = decode_decimal128(read_i64_le(body, offset + 8), qty_exp);
-        depth.bid_level = (i + 1) as u32;
-        offset += bid_block_len;
+    });
+    let asks_end = bids_end.and_then(|offset| {
+        read_levels(body, offset, price_exp, qty_exp, |i, price, qty| {
+            if i < 5 {
+                depth.ask_prices[i] = price;
+                depth.ask_vols[i] = qty;
+                depth.ask_level = (i + 1) as u32;
+            }
+            if let Some((levels, d)) = &mut depth_n
+                && i < *levels
+            {
+                d.ask_prices[i] = price;
+                d.ask_vols[i] = qty;
+                d.ask_level = (i + 1) as u32;
+            }
+        })
+    });
+    if let Some(offset) = asks_end {
+        let sym_bytes = read_var_string8(body, offset);
+        depth.symbol = symbol_to_bytes(std::str::from_utf8(sym_bytes).unwrap_or(""));
     }
-    // Skip remaining bid levels beyond 5
-    for _ in bid_levels..num_bids {
-        offset += bid_block_len;
+
+    out.push(MarketDataMsg::Depth5(depth));
+    if let Some((_, mut d)) = depth_n {
+        d.symbol = depth.symbol;
+        out.push(MarketDataMsg::DepthN(d));
     }
+}
 
-    // --- Asks group ---
+/// Pass `(index, price, qty)` of each level in the group at `offset` to `f`
+/// and return the offset past the group, `None` if its header is cut off.
+fn read_levels(
+    body: &[u8],
+    offset: usize,
+    price_exp: i8,
+    qty_exp: i8,
+    mut f: impl FnMut(usize, f64, f64),
+) -> Option<usize> {
     if offset + DEPTH_GROUP_HEADER_SIZE > body.len() {
-        return Some(MarketDataMsg::Depth5(depth));
+        return None;
     }
-    let ask_block_len = read_u16_le(body, offset) as usize;
-    let num_asks = read_u16_le(body, offset + 2) as usize;
-    offset += DEPTH_GROUP_HEADER_SIZE;
-
-    let ask_levels = num_asks.min(5);
-    for i in 0..ask_levels {
-        if offset + ask_block_len > body.len() {
+    let block_len = read_u16_le(body, offset) as usize;
+    let count = read_u16_le(body, offset + 2) as usize;
+    let start = offset + DEPTH_GROUP_HEADER_SIZE;
+    for i in 0..count {
+        let level = start + i * block_len;
+        if level + block_len > body.len() {
             break;
         }
-        depth.ask_prices[i] = decode_decimal128(read_i64_le(body, offset), price_exp);
-        depth.ask_vols[i] = decode_decimal128(read_i64_le(body, offset + 8), qty_exp);
-        depth.ask_level = (i + 1) as u32;
-        offset += ask_block_len;
-    }
-    for _ in ask_levels..num_asks {
-        offset += ask_block_len;
+        f(
+            i,
+            decode_decimal128(read_i64_le(body, level), price_exp),
+            decode_decimal128(read_i64_le(body, level + 8), qty_exp),
+        );
     }
-
-    // --- Symbol (VarString8 at end) ---
-    let sym_bytes = read_var_string8(body, offset);
-    let sym = std::str::from_utf8(sym_bytes).unwrap_or("");
-    depth.symbol = symbol_to_bytes(sym);
-
-    Some(MarketDataMsg::Depth5(depth))
+    Some(start + count * block_len)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    fn parse(data: &[u8]) -> Vec<MarketDataMsg> {
+        let mut out = Vec::new();
+        parse_sbe_message(data, &mut out);
+        out
+    }
+
     #[test]
     fn too_short_returns_empty() {
-        assert!(parse_sbe_message(&[0; 4]).is_empty());
-        assert!(parse_sbe_message(&[]).is_empty());
+        assert!(parse(&[0; 4]).is_empty());
+        assert!(parse(&[]).is_empty());
     }
 
     #[test]
@@ -346,7 +393,7 @@ mod tests {
         let mut data = vec![0u8; 16];
         data[2] = 0x0F;
         data[3] = 0x27; // templateId = 9999 LE
-        assert!(parse_sbe_message(&data).is_empty());
+        assert!(parse(&data).is_empty());
     }
 
     #[test]
@@ -410,7 +457,7 @@ mod tests {
         append_var_string8(&mut body, "BTCUSDT"); // offset 50
 
         let data = make_sbe_msg(TEMPLATE_BEST_BID_ASK, &body);
-        let msgs = parse_sbe_message(&data);
+        let msgs = parse(&data);
         assert_eq!(msgs.len(), 1);
         match &msgs[0] {
             MarketDataMsg::Bbo(bbo) => {
@@ -462,7 +509,7 @@ mod tests {
         append_var_string8(&mut body, "ETHUSDT");
 
         let data = make_sbe_msg(TEMPLATE_TRADES, &body);
-        let msgs = parse_sbe_message(&data);
+        let msgs = parse(&data);
         assert_eq!(msgs.len(), 2);
 
         match &msgs[0] {
@@ -487,6 +534,37 @@ mod tests {
         }
     }
 
+    #[test]
+    fn reused_output_does_not_allocate() {
+        let mut body = Vec::new();
+        append_i64(&mut body, 1_000_000); // eventTime
+        append_i64(&mut body, 1_000_000); // transactTime
+        body.extend_from_slice(&[-2i8 as u8, -3i8 as u8]);
+        append_u16(&mut body, 25);
+        append_u32(&mut body, 3);
+        for id in 1..=3 {
+            append_i64(&mut body, id);
+            append_i64(&mut body, 3000050);
+            append_i64(&mut body, 1500);
+            body.push(0);
+        }
+        append_var_string8(&mut body, "BTCUSDT");
+        let data = make_sbe_msg(TEMPLATE_TRADES, &body);
+
+        let mut out = Vec::new();
+        parse_sbe_message(&data, &mut out);
+        assert_eq!(out.len(), 3);
+
+        let n = crate::alloc_counter::allocations(|| {
+            for _ in 0..100 {
+                out.clear();
+                parse_sbe_message(&data, &mut out);
+                assert_eq!(out.len(), 3);
+            }
+        });
+        assert_eq!(n, 0);
+    }
+
     #[test]
     fn parse_trades_empty_group() {
         let mut body = Vec::new();
@@ -499,7 +577,7 @@ mod tests {
         append_var_string8(&mut body, "BTCUSDT");
 
         let data = make_sbe_msg(TEMPLATE_TRADES, &body);
-        let msgs = parse_sbe_message(&data);
+        let msgs = parse(&data);
         assert!(msgs.is_empty());
     }
 
@@ -545,7 +623,7 @@ mod tests {
         append_var_string8(&mut body, "BTCUSDT");
 
         let data = make_sbe_msg(TEMPLATE_DEPTH, &body);
-        let msgs = parse_sbe_message(&data);
+        let msgs = parse(&data);
         assert_eq!(msgs.len(), 1);
 
         match &msgs[0] {
@@ -592,7 +670,7 @@ mod tests {
         append_var_string8(&mut body, "BTCUSDT");
 
         let data = make_sbe_msg(TEMPLATE_DEPTH, &body);
-        let msgs = parse_sbe_message(&data);
+        let msgs = parse(&data);
         assert_eq!(msgs.len(), 1);
 
         match &msgs[0] {
@@ -605,4 +683,39 @@ mod tests {
             _ => panic!("expected Depth5"),
         }
     }
+
+    #[test]
+    fn parse_depth_n_up_to_the_configured_levels() {
+        let mut body = Vec::new();
+        append_i64(&mut body, 1_000_000);
+        append_i64(&mut body, 7);
+        body.push(-2i8 as u8);
+        body.push(-3i8 as u8);
+        for base in [3000000i64, 3000100] {
+            append_u16(&mut body, 16);
+            append_u16(&mut body, 20);
+            for i in 0..20i64 {
+                append_i64(&mut body, if base == 3000000 { base - i * 100 } else { base + i * 100 });
+                append_i64(&mut body, 1000 + i);
+            }
+        }
+        append_var_string8(&mut body, "BTCUSDT");
+
+        let mut msgs = Vec::new();
+        parse_sbe_message_with_depth_n(&make_sbe_msg(TEMPLATE_DEPTH, &body), Some(10), &mut msgs);
+
+        assert_eq!(msgs.len(), 2);
+        assert!(matches!(&msgs[0], MarketDataMsg::Depth5(d) if d.bid_level == 5 && d.update_id == 7));
+        match &msgs[1] {
+            MarketDataMsg::DepthN(d) => {
+                assert_eq!(symbol_from_bytes(&d.symbol), "BTCUSDT");
+                assert_eq!((d.bid_level, d.ask_level, d.update_id), (10, 10, 7));
+                assert!((d.bid_prices[9] - 29991.00).abs() < 0.01);
+                assert!((d.ask_prices[9] - 30010.00).abs() < 0.01);
+                assert!((d.ask_vols[9] - 1.009).abs() < 1e-9);
+                assert_eq!(d.bid_prices[10], 0.0);
+            }
+            _ => panic!("expected DepthN"),
+        }
+    }
 }
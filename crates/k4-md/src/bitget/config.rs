@@ -4,7 +4,10 @@
 //! settings. Bitget uses standard symbol format (`BTCUSDT`) natively.
 
 use anyhow::Result;
-use k4_core::config::ConnectionConfig;
+use k4_core::{
+    config::ConnectionConfig,
+    ws::{LocalBind, ProxyConfig},
+};
 
 /// Parsed Bitget configuration.
 #[derive(Debug, Clone)]
@@ -33,6 +36,28 @@ pub struct BitgetConfig {
     pub futures_trade_shm_name: Option<String>,
     /// SHM name for futures Depth5 data.
     pub futures_depth5_shm_name: Option<String>,
+    /// SHM name for spot DepthN data.
+    pub spot_depth_n_shm_name: Option<String>,
+    /// SHM name for futures DepthN data.
+    pub futures_depth_n_shm_name: Option<String>,
+
+    /// Spot DepthN levels (`None` = DepthN off).
+    pub spot_depth_n_levels: Option<usize>,
+    /// Futures DepthN levels (`None` = DepthN off).
+    pub futures_depth_n_levels: Option<usize>,
+    /// Spot depth channel (default: `"books5"`).
+    pub spot_depth_channel: String,
+    /// Futures depth channel (default: `"books5"`).
+    pub futures_depth_channel: String,
+
+    /// Egress proxy for spot connections.
+    pub spot_proxy: Option<ProxyConfig>,
+    /// Egress proxy for futures connections.
+    pub futures_proxy: Option<ProxyConfig>,
+    /// Local bind addresses for spot connections.
+    pub spot_local_binds: Vec<LocalBind>,
+    /// Local bind addresses for futures connections.
+    pub futures_local_binds: Vec<LocalBind>,
 
     /// Ping interval in seconds (default: 25).
     pub ping_interval_sec: u64,
@@ -45,31 +70,34 @@ impl BitgetConfig {
         let ping_interval_sec = conn.ping_interval_sec.unwrap_or(25);
 
         // Spot config
-        let (spot_symbols, spot_conn_count, spot_bbo, spot_trade, spot_depth5) = if let Some(ref spot) = conn.spot {
-            (
-                spot.symbols.clone().unwrap_or_default(),
-                spot.redun_conn_count.unwrap_or(1),
-                spot.bbo_shm_name.clone(),
-                spot.trade_shm_name.clone(),
-                spot.depth5_shm_name.clone(),
-            )
-        } else {
-            (vec![], 1, None, None, None)
-        };
+        let (spot_symbols, spot_conn_count, spot_bbo, spot_trade, spot_depth5, spot_proxy) =
+            if let Some(ref spot) = conn.spot {
+                (
+                    spot.symbols.clone().unwrap_or_default(),
+                    spot.redun_conn_count.unwrap_or(1),
+                    spot.bbo_shm_name.clone(),
+                    spot.trade_shm_name.clone(),
+                    spot.depth5_shm_name.clone(),
+                    spot.proxy.clone(),
+                )
+            } else {
+                (vec![], 1, None, None, None, None)
+            };
 
         // Futures config
-        let (futures_symbols, futures_conn_count, fut_bbo, fut_trade, fut_depth5) = if let Some(ref fut) = conn.futures
-        {
-            (
-                fut.effective_symbols(),
-                fut.effective_conn_count(),
-                fut.bbo_shm_name.clone(),
-                fut.trade_shm_name.clone(),
-                fut.depth5_shm_name.clone(),
-            )
-        } else {
-            (vec![], 1, None, None, None)
-        };
+        let (futures_symbols, futures_conn_count, fut_bbo, fut_trade, fut_depth5, futures_proxy) =
+            if let Some(ref fut) = conn.futures {
+                (
+                    fut.effective_symbols(),
+                    fut.effective_conn_count(),
+                    fut.bbo_shm_name.clone(),
+                    fut.trade_shm_name.clone(),
+                    fut.depth5_shm_name.clone(),
+                    fut.proxy.clone(),
+                )
+            } else {
+                (vec![], 1, None, None, None, None)
+            };
 
         Ok(Self {
             md_size,
@@ -83,6 +111,24 @@ impl BitgetConfig {
             futures_bbo_shm_name: fut_bbo,
             futures_trade_shm_name: fut_trade,
             futures_depth5_shm_name: fut_depth5,
+            spot_depth_n_shm_name: conn.spot.as_ref().and_then(|c| c.depth_n_shm_name.clone()),
+            futures_depth_n_shm_name: conn.futures.as_ref().and_then(|c| c.depth_n_shm_name.clone()),
+            spot_depth_n_levels: conn.spot.as_ref().and_then(|c| c.depth_n_levels()),
+            futures_depth_n_levels: conn.futures.as_ref().and_then(|c| c.depth_n_levels()),
+            spot_depth_channel: conn
+                .spot
+                .as_ref()
+                .and_then(|c| c.depth_channel.clone())
+                .unwrap_or_else(|| "books5".into()),
+            futures_depth_channel: conn
+                .futures
+                .as_ref()
+                .and_then(|c| c.depth_channel.clone())
+                .unwrap_or_else(|| "books5".into()),
+            spot_proxy,
+            futures_proxy,
+            spot_local_binds: conn.spot.as_ref().and_then(|c| c.local_binds.clone()).unwrap_or_default(),
+            futures_local_binds: conn.futures.as_ref().and_then(|c| c.local_binds.clone()).unwrap_or_default(),
             ping_interval_sec,
         })
     }
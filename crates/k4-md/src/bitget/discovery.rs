@@ -0,0 +1,97 @@
+//! Bitget instrument listing for symbol discovery.
+//!
+//! Spot instruments come from `spot/public/symbols`, futures from
+//! `mix/market/contracts` (USDT-margined); 24h quote volumes from
+//! the matching `tickers` endpoint.
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use k4_core::{
+    instrument::bitget::{parse_contracts, parse_spot_symbols},
+    types::ProductType,
+};
+use serde_json::Value;
+
+use crate::discovery::{InstrumentSource, Listing, set_volumes};
+
+/// Lists spot and USDT-M futures instruments.
+pub struct BitgetInstruments {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl BitgetInstruments {
+    /// Instruments from `base_url` (normally [`REST_URL`](super::REST_URL)).
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self { http: reqwest::Client::new(), base_url: base_url.into() }
+    }
+
+    /// The `data` array of a response.
+    async fn get(&self, path: &str) -> Result<Vec<Value>> {
+        let url = format!("{}{path}", self.base_url);
+        let resp: Value = self.http.get(&url).send().await?.error_for_status()?.json().await?;
+        if resp.get("code").and_then(Value::as_str) != Some("00000") {
+            return Err(anyhow!("{path} error: {resp}"));
+        }
+        Ok(resp.get("data").and_then(Value::as_array).cloned().unwrap_or_default())
+    }
+}
+
+#[async_trait]
+impl InstrumentSource for BitgetInstruments {
+    async fn instruments(&self, product_type: ProductType, with_volume: bool) -> Result<Vec<Listing>> {
+        let (symbols_path, tickers_path, futures) = match product_type {
+            ProductType::Spot => ("/api/v2/spot/public/symbols", "/api/v2/spot/market/tickers", false),
+            _ => (
+                "/api/v2/mix/market/contracts?productType=USDT-FUTURES",
+                "/api/v2/mix/market/tickers?productType=USDT-FUTURES",
+                true,
+            ),
+        };
+        let data = self.get(symbols_path).await?;
+        let instruments = if futures { parse_contracts(&data) } else { parse_spot_symbols(&data) };
+        let mut listings: Vec<Listing> = instruments.into_iter().map(Listing::from).collect();
+        if with_volume {
+            let volumes = self
+                .get(tickers_path)
+                .await?
+                .iter()
+                .filter_map(|t| {
+                    Some((t.get("symbol")?.as_str()?.to_string(), t.get("quoteVolume")?.as_str()?.parse().ok()?))
+                })
+                .collect();
+            set_volumes(&mut listings, volumes);
+        }
+        Ok(listings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backfill::stand_in::serve;
+
+    #[tokio::test]
+    async fn lists_spot_symbols_with_volume() {
+        let (base, _) = serve(|path| {
+            let data = if path == "/api/v2/spot/public/symbols" {
+                serde_json::json!([
+                    {"symbol": "BTCUSDT", "baseCoin": "BTC", "quoteCoin": "USDT", "pricePrecision": "2",
+                     "quantityPrecision": "6", "minTradeAmount": "0", "status": "online"},
+                    {"symbol": "XYZUSDT", "baseCoin": "XYZ", "quoteCoin": "USDT", "pricePrecision": "4",
+                     "quantityPrecision": "2", "minTradeAmount": "1", "status": "halt"},
+                ])
+            } else {
+                serde_json::json!([{"symbol": "BTCUSDT", "quoteVolume": "987.5"}])
+            };
+            serde_json::json!({"code": "00000", "msg": "success", "data": data}).to_string()
+        })
+        .await;
+
+        let listings = BitgetInstruments::new(base).instruments(ProductType::Spot, true).await.unwrap();
+
+        assert_eq!(listings[0].instrument.canonical_id(), "BITGET:BTC-USDT:SPOT");
+        assert_eq!(listings[0].volume_24h, Some(987.5));
+        assert_eq!((listings[1].instrument.trading, listings[1].volume_24h), (false, None));
+    }
+}
@@ -6,90 +6,128 @@
 //! - `books1` → [`Bookticker`]
 //! - `trade` → [`Trade`] (batch — may return multiple trades per message)
 //! - `books5` → [`Depth5`]
+//! - `books` → [`Depth5`] / [`DepthN`] from a [`ChecksumBooks`] local book
 
 use k4_core::{time_util, *};
+use simd_json::prelude::*;
 
-use crate::json_util::{fill_depth5_levels, parse_str_f64, parse_str_u64};
+use crate::{
+    checksum_book::{BookMsg, ChecksumBooks, with_raw_levels},
+    json_util::{Json, fill_depth5_levels, parse_str_f64, parse_str_u64, with_json},
+    subscription::SubscriptionMsgs,
+};
 
-/// Parse a Bitget JSON WebSocket message into zero or more [`MarketDataMsg`].
+/// Depth channels: the `books5` snapshots, then the full book kept as a
+/// checksummed local book.
+pub const DEPTH_CHANNELS: [&str; 2] = ["books5", "books"];
+
+/// Parse a Bitget JSON WebSocket message, pushing zero or more
+/// [`MarketDataMsg`] onto `out`.
 ///
 /// Accepts `&mut [u8]` for simd-json in-place parsing.
-/// Returns an empty `Vec` for non-data messages (subscription acks, pong, etc.).
+/// Pushes nothing for non-data messages (subscription acks, pong, etc.).
 /// Trade messages may produce multiple results since Bitget batches trades.
-pub fn parse_message(data: &mut [u8]) -> Vec<MarketDataMsg> {
-    if data == b"pong" {
-        return vec![];
-    }
-
-    let v: serde_json::Value = match simd_json::serde::from_slice(data) {
-        Ok(v) => v,
-        Err(_) => return vec![],
-    };
-
-    let arg = match v.get("arg") {
-        Some(a) => a,
-        None => return vec![],
-    };
-
-    let channel = match arg.get("channel").and_then(|c| c.as_str()) {
-        Some(c) => c,
-        None => return vec![],
-    };
-
-    let inst_id = match arg.get("instId").and_then(|i| i.as_str()) {
-        Some(i) => i,
-        None => return vec![],
-    };
+pub fn parse_message(data: &mut [u8], out: &mut Vec<MarketDataMsg>) {
+    parse_with_books(data, None, out);
+}
 
-    let product_type = product_type_from_inst_type(arg);
+/// [`parse_message`], with the `books` channel applied to `books`.
+pub fn parse_message_with_books(data: &mut [u8], books: &ChecksumBooks, out: &mut Vec<MarketDataMsg>) {
+    parse_with_books(data, Some(books), out);
+}
 
-    match channel {
-        "books1" => parse_book_ticker(&v, inst_id, product_type).into_iter().collect(),
-        "trade" => parse_trades(&v, inst_id, product_type),
-        "books5" => parse_depth5(&v, inst_id, product_type).into_iter().collect(),
-        _ => vec![],
+fn parse_with_books(data: &mut [u8], books: Option<&ChecksumBooks>, out: &mut Vec<MarketDataMsg>) {
+    if data == b"pong" {
+        return;
     }
+
+    with_json(data, |v| {
+        let Some(arg) = v.get("arg") else { return };
+        let Some(channel) = arg.get("channel").and_then(|c| c.into_string()) else { return };
+        let Some(inst_id) = arg.get("instId").and_then(|i| i.into_string()) else { return };
+
+        let product_type = product_type_from_inst_type(arg);
+
+        match channel {
+            "books1" => out.extend(parse_book_ticker(v, inst_id, product_type)),
+            "trade" => parse_trades(v, inst_id, product_type, out),
+            "books5" => out.extend(parse_depth5(v, inst_id, product_type)),
+            "books" => {
+                if let Some(books) = books {
+                    on_book(v, inst_id, books, out);
+                }
+            }
+            _ => {}
+        }
+    });
 }
 
 /// Build subscription message for Bitget spot symbols.
 ///
 /// Subscribes to `books1` (BBO), `trade`, and `books5` for each symbol.
 pub fn build_spot_subscribe(symbols: &[String]) -> String {
-    let args: Vec<serde_json::Value> = symbols
-        .iter()
-        .flat_map(|s| {
-            vec![
-                serde_json::json!({"instType": "SPOT", "channel": "books1", "instId": s}),
-                serde_json::json!({"instType": "SPOT", "channel": "trade", "instId": s}),
-                serde_json::json!({"instType": "SPOT", "channel": "books5", "instId": s}),
-            ]
-        })
-        .collect();
+    request("subscribe", "SPOT", symbols)
+}
 
-    serde_json::json!({
-        "op": "subscribe",
-        "args": args
-    })
-    .to_string()
+/// Build unsubscription message for Bitget spot symbols.
+pub fn build_spot_unsubscribe(symbols: &[String]) -> String {
+    request("unsubscribe", "SPOT", symbols)
 }
 
 /// Build subscription message for Bitget futures symbols.
 ///
 /// Uses `USDT-FUTURES` as the `instType`.
 pub fn build_futures_subscribe(symbols: &[String]) -> String {
+    request("subscribe", "USDT-FUTURES", symbols)
+}
+
+/// Build unsubscription message for Bitget futures symbols.
+pub fn build_futures_unsubscribe(symbols: &[String]) -> String {
+    request("unsubscribe", "USDT-FUTURES", symbols)
+}
+
+/// Subscription messages for spot (`futures = false`) or futures symbols
+/// with `depth_channel` in place of `books5`, or `None` if it is not one of
+/// [`DEPTH_CHANNELS`].
+pub fn subscription(futures: bool, depth_channel: &str) -> Option<SubscriptionMsgs> {
+    let channel = *DEPTH_CHANNELS.iter().find(|c| **c == depth_channel)?;
+    let inst_type = inst_type(futures);
+    Some(SubscriptionMsgs::new(
+        move |s| request_with_depth("subscribe", inst_type, s, channel),
+        move |s| request_with_depth("unsubscribe", inst_type, s, channel),
+        str::to_uppercase,
+    ))
+}
+
+/// Unsubscribe then resubscribe `inst_id`'s `books` channel, for a fresh
+/// book snapshot.
+pub fn build_book_resubscribe(futures: bool, inst_id: &str) -> Vec<String> {
+    let arg = serde_json::json!({"instType": inst_type(futures), "channel": "books", "instId": inst_id});
+    ["unsubscribe", "subscribe"].map(|op| serde_json::json!({"op": op, "args": [arg]}).to_string()).into()
+}
+
+fn inst_type(futures: bool) -> &'static str {
+    if futures { "USDT-FUTURES" } else { "SPOT" }
+}
+
+fn request(op: &str, inst_type: &str, symbols: &[String]) -> String {
+    request_with_depth(op, inst_type, symbols, "books5")
+}
+
+fn request_with_depth(op: &str, inst_type: &str, symbols: &[String], depth_channel: &str) -> String {
     let args: Vec<serde_json::Value> = symbols
         .iter()
         .flat_map(|s| {
             vec![
-                serde_json::json!({"instType": "USDT-FUTURES", "channel": "books1", "instId": s}),
-                serde_json::json!({"instType": "USDT-FUTURES", "channel": "trade", "instId": s}),
-                serde_json::json!({"instType": "USDT-FUTURES", "channel": "books5", "instId": s}),
+                serde_json::json!({"instType": inst_type, "channel": "books1", "instId": s}),
+                serde_json::json!({"instType": inst_type, "channel": "trade", "instId": s}),
+                serde_json::json!({"instType": inst_type, "channel": depth_channel, "instId": s}),
             ]
         })
         .collect();
 
     serde_json::json!({
-        "op": "subscribe",
+        "op": op,
         "args": args
     })
     .to_string()
@@ -99,9 +137,9 @@ pub fn build_futures_subscribe(symbols: &[String]) -> String {
 // Individual parsers
 // ---------------------------------------------------------------------------
 
-fn parse_book_ticker(v: &serde_json::Value, inst_id: &str, product_type: ProductType) -> Option<MarketDataMsg> {
+fn parse_book_ticker(v: Json<'_, '_>, inst_id: &str, product_type: ProductType) -> Option<MarketDataMsg> {
     let local_time = time_util::now_us();
-    let data = v.get("data")?.as_array()?.first()?;
+    let data = v.get("data")?.get_idx(0)?;
 
     // Root-level ts is event timestamp, data-level ts is trade timestamp.
     let event_ts_ms = parse_str_u64(v.get("ts"))?;
@@ -110,8 +148,8 @@ fn parse_book_ticker(v: &serde_json::Value, inst_id: &str, product_type: Product
 
     let asks = data.get("asks")?.as_array()?;
     let bids = data.get("bids")?.as_array()?;
-    let ask0 = asks.first()?.as_array()?;
-    let bid0 = bids.first()?.as_array()?;
+    let ask0 = asks.get(0)?.as_array()?;
+    let bid0 = bids.get(0)?.as_array()?;
 
     let bbo = Bookticker {
         symbol: symbol_to_bytes(inst_id),
@@ -119,44 +157,39 @@ fn parse_book_ticker(v: &serde_json::Value, inst_id: &str, product_type: Product
         event_timestamp_us: event_ts_ms * 1000,
         trade_timestamp_us: trade_ts_ms * 1000,
         update_id: seq,
-        ask_price: parse_str_f64(ask0.first())?,
+        ask_price: parse_str_f64(ask0.get(0))?,
         ask_vol: parse_str_f64(ask0.get(1))?,
-        bid_price: parse_str_f64(bid0.first())?,
+        bid_price: parse_str_f64(bid0.get(0))?,
         bid_vol: parse_str_f64(bid0.get(1))?,
         ask_order_count: 0,
         bid_order_count: 0,
         local_time_us: local_time,
+        kernel_rx_us: 0,
     };
 
     Some(MarketDataMsg::Bbo(bbo))
 }
 
-fn parse_trades(v: &serde_json::Value, inst_id: &str, product_type: ProductType) -> Vec<MarketDataMsg> {
+fn parse_trades(v: Json<'_, '_>, inst_id: &str, product_type: ProductType, out: &mut Vec<MarketDataMsg>) {
     let local_time = time_util::now_us();
     let data = match v.get("data").and_then(|d| d.as_array()) {
         Some(arr) => arr,
-        None => return vec![],
+        None => return,
     };
 
     // Bitget sends trades in reverse order (newest first), iterate backwards
-    // to process oldest first.
-    let mut result = Vec::with_capacity(data.len());
-    for item in data.iter().rev() {
+    // to process oldest first. Tape arrays only iterate forwards, so index;
+    // batches are small.
+    for item in (0..data.len()).rev().filter_map(|i| data.get(i)) {
         if let Some(trade) = parse_single_trade(item, inst_id, product_type, local_time) {
-            result.push(MarketDataMsg::Trade(trade));
+            out.push(MarketDataMsg::Trade(trade));
         }
     }
-    result
 }
 
-fn parse_single_trade(
-    item: &serde_json::Value,
-    inst_id: &str,
-    product_type: ProductType,
-    local_time: u64,
-) -> Option<Trade> {
+fn parse_single_trade(item: Json<'_, '_>, inst_id: &str, product_type: ProductType, local_time: u64) -> Option<Trade> {
     let ts_ms = parse_str_u64(item.get("ts"))?;
-    let side = item.get("side")?.as_str()?;
+    let side = item.get("side")?.into_string()?;
 
     Some(Trade {
         symbol: symbol_to_bytes(inst_id),
@@ -167,13 +200,16 @@ fn parse_single_trade(
         price: parse_str_f64(item.get("price"))?,
         vol: parse_str_f64(item.get("size"))?,
         is_buyer_maker: side == "sell",
+        backfilled: false,
+        cross_seq: 0,
         local_time_us: local_time,
+        kernel_rx_us: 0,
     })
 }
 
-fn parse_depth5(v: &serde_json::Value, inst_id: &str, product_type: ProductType) -> Option<MarketDataMsg> {
+fn parse_depth5(v: Json<'_, '_>, inst_id: &str, product_type: ProductType) -> Option<MarketDataMsg> {
     let local_time = time_util::now_us();
-    let data = v.get("data")?.as_array()?.first()?;
+    let data = v.get("data")?.get_idx(0)?;
 
     let event_ts_ms = parse_str_u64(v.get("ts"))?;
     let trade_ts_ms = parse_str_u64(data.get("ts"))?;
@@ -198,6 +234,7 @@ fn parse_depth5(v: &serde_json::Value, inst_id: &str, product_type: ProductType)
         bid_order_counts: [0; 5],
         ask_order_counts: [0; 5],
         local_time_us: local_time,
+        kernel_rx_us: 0,
     };
 
     fill_depth5_levels(&mut depth, bids, asks);
@@ -205,13 +242,30 @@ fn parse_depth5(v: &serde_json::Value, inst_id: &str, product_type: ProductType)
     Some(MarketDataMsg::Depth5(depth))
 }
 
+/// Apply a `books` channel message to `books`. Bitget sends no previous
+/// `seq`, so the checksum alone catches missed updates.
+fn on_book(v: Json<'_, '_>, inst_id: &str, books: &ChecksumBooks, out: &mut Vec<MarketDataMsg>) -> Option<()> {
+    let data = v.get("data")?.get_idx(0)?;
+    let snapshot = v.get("action")?.into_string()? == "snapshot";
+    let seq = parse_str_u64(data.get("seq"))?;
+    let checksum = data.get("checksum")?.as_i64()? as i32;
+    let event_timestamp_us = parse_str_u64(v.get("ts"))? * 1000;
+    let trade_timestamp_us = parse_str_u64(data.get("ts"))? * 1000;
+    with_raw_levels(data.get("bids"), data.get("asks"), |bids, asks| {
+        let msg =
+            BookMsg { snapshot, seq, prev_seq: None, checksum, event_timestamp_us, trade_timestamp_us, bids, asks };
+        books.on_message(inst_id, msg, out);
+    });
+    Some(())
+}
+
 // ---------------------------------------------------------------------------
 // Helpers
 // ---------------------------------------------------------------------------
 
 /// Determine product type from Bitget `arg.instType` field.
-fn product_type_from_inst_type(arg: &serde_json::Value) -> ProductType {
-    match arg.get("instType").and_then(|t| t.as_str()) {
+fn product_type_from_inst_type(arg: Json<'_, '_>) -> ProductType {
+    match arg.get("instType").and_then(|t| t.into_string()) {
         Some("USDT-FUTURES") => ProductType::Futures,
         Some("COIN-FUTURES") => ProductType::CoinMargin,
         _ => ProductType::Spot,
@@ -221,6 +275,13 @@ fn product_type_from_inst_type(arg: &serde_json::Value) -> ProductType {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::order_book::TickSizes;
+
+    fn parse(data: &mut [u8]) -> Vec<MarketDataMsg> {
+        let mut out = Vec::new();
+        parse_message(data, &mut out);
+        out
+    }
 
     #[test]
     fn parse_books1_bbo() {
@@ -235,7 +296,7 @@ mod tests {
             }]
         }"#
         .to_vec();
-        let msgs = parse_message(&mut json);
+        let msgs = parse(&mut json);
         assert_eq!(msgs.len(), 1);
         match &msgs[0] {
             MarketDataMsg::Bbo(bbo) => {
@@ -259,7 +320,7 @@ mod tests {
             ]
         }"#
         .to_vec();
-        let msgs = parse_message(&mut json);
+        let msgs = parse(&mut json);
         assert_eq!(msgs.len(), 3);
         // Should be reversed: oldest first
         match &msgs[0] {
@@ -272,8 +333,63 @@ mod tests {
         }
     }
 
+    #[test]
+    fn books_channel_feeds_checksummed_book() {
+        let resubscribe = Box::new(|s: &str| build_book_resubscribe(true, s));
+        let (books, mut requests) = ChecksumBooks::new(
+            "bitget_futures",
+            ProductType::Futures,
+            usize::MAX,
+            Some(5),
+            TickSizes::default(),
+            resubscribe,
+        );
+        let mut out = Vec::new();
+        let mut json = br#"{
+            "action": "snapshot",
+            "arg": {"instType": "USDT-FUTURES", "channel": "books", "instId": "BTCUSDT"},
+            "data": [{
+                "asks": [["3366.8", "9"], ["3368", "8"]],
+                "bids": [["3366.1", "7"], ["3366", "6"]],
+                "checksum": -1881014294,
+                "seq": 10,
+                "ts": "1695716059516"
+            }],
+            "ts": 1695716059520
+        }"#
+        .to_vec();
+        parse_message_with_books(&mut json, &books, &mut out);
+        let [MarketDataMsg::Depth5(d), MarketDataMsg::DepthN(n)] = &out[..] else { panic!("{out:?}") };
+        assert_eq!((d.bid_prices[1], d.ask_vols[1], d.update_id), (3366.0, 8.0, 10));
+        assert_eq!((d.event_timestamp_us, d.trade_timestamp_us), (1695716059520000, 1695716059516000));
+        assert_eq!(n.product_type, ProductType::Futures);
+
+        let mut json = br#"{
+            "action": "update",
+            "arg": {"instType": "USDT-FUTURES", "channel": "books", "instId": "BTCUSDT"},
+            "data": [{"asks": [["3368", "2"]], "bids": [], "checksum": 1865684724, "seq": 11, "ts": "1695716059616"}],
+            "ts": 1695716059620
+        }"#
+        .to_vec();
+        parse_message_with_books(&mut json, &books, &mut out);
+        assert_eq!(out.len(), 4);
+        assert!(requests.try_recv().is_err());
+
+        // Ignored without a book, as in `parse_message`.
+        assert!(parse(&mut json).is_empty());
+    }
+
+    #[test]
+    fn subscription_uses_depth_channel() {
+        let sub = (subscription(false, "books").unwrap().subscribe)(&["BTCUSDT".into()]);
+        assert!(sub.contains(r#""channel":"books","instId":"BTCUSDT","instType":"SPOT""#));
+        assert!(!sub.contains("books5"));
+        assert!(subscription(true, "books15").is_none());
+        assert!(build_book_resubscribe(true, "BTCUSDT")[0].contains(r#""op":"unsubscribe""#));
+    }
+
     #[test]
     fn pong_returns_empty() {
-        assert!(parse_message(&mut b"pong".to_vec()).is_empty());
+        assert!(parse(&mut b"pong".to_vec()).is_empty());
     }
 }
@@ -3,70 +3,158 @@
 //! Produces up to 2 [`StreamDef`]s (same URL, different subscriptions):
 //! - Spot (`instType: "SPOT"`) — books1, trade, books5
 //! - Futures (`instType: "USDT-FUTURES"`) — books1, trade, books5
+//!
+//! `depth_channel: "books"` swaps books5 for the full book, kept as a
+//! checksum-verified local book (see [`checksum_book`](crate::checksum_book))
+//! that also feeds DepthN.
 
 pub mod config;
+pub mod discovery;
 pub mod json_parser;
 
 use std::time::Duration;
 
-use anyhow::Result;
-use k4_core::{config::ConnectionConfig, ws::PingPayload};
+use anyhow::{Result, anyhow};
+use k4_core::{
+    config::ConnectionConfig,
+    instrument::InstrumentRegistry,
+    types::{Exchange, ProductType},
+    ws::{PingPayload, PongMatcher},
+};
+use tokio::sync::mpsc;
+use tracing::warn;
 
 use self::config::BitgetConfig;
-use crate::pipeline::{PingConfig, ShmNames, StreamDef};
+use crate::{
+    checksum_book::ChecksumBooks,
+    order_book::TickSizes,
+    pipeline::{PingConfig, ShmNames, StreamDef, TextParser},
+    seq_gap::GapTolerance,
+};
+
+/// Public REST API.
+pub const REST_URL: &str = "https://api.bitget.com";
 
 const BITGET_WS_URL: &str = "wss://ws.bitget.com:443/v2/ws/public";
 
-/// Build Bitget stream definitions from the connection config.
-pub fn build(conn_config: &ConnectionConfig) -> Result<Vec<StreamDef>> {
+/// Build Bitget stream definitions from the connection config, with tick
+/// sizes for local books from `instruments`.
+pub fn build(conn_config: &ConnectionConfig, instruments: &InstrumentRegistry) -> Result<Vec<StreamDef>> {
     let cfg = BitgetConfig::from_connection(conn_config)?;
-    let ping =
-        PingConfig { interval: Duration::from_secs(cfg.ping_interval_sec), payload: PingPayload::Text("ping".into()) };
+    for (product, depth_channel, levels) in [
+        ("spot", &cfg.spot_depth_channel, cfg.spot_depth_n_levels),
+        ("futures", &cfg.futures_depth_channel, cfg.futures_depth_n_levels),
+    ] {
+        if depth_channel == "books5" && levels.is_some() {
+            warn!("[bitget] {product}: DepthN is not available from books5; depth_n_shm_name / depth_levels ignored");
+        }
+    }
+    let ping = PingConfig {
+        interval: Duration::from_secs(cfg.ping_interval_sec),
+        payload: PingPayload::Text("ping".into()),
+        pong: Some(PongMatcher::Exact("pong".into())),
+        pong_timeout: conn_config.pong_timeout(),
+    };
     let mut streams = Vec::new();
 
     if !cfg.spot_symbols.is_empty() {
+        let subscription = json_parser::subscription(false, &cfg.spot_depth_channel)
+            .ok_or_else(|| anyhow!("[bitget] unknown spot depth_channel {:?}", cfg.spot_depth_channel))?;
+        let ticks = TickSizes::from_registry(instruments, Exchange::Bitget, ProductType::Spot);
+        let (parser, conn_requests) = parser(false, &cfg.spot_depth_channel, cfg.spot_depth_n_levels, ticks.clone());
         streams.push(StreamDef {
             label: "bitget_spot".into(),
             ws_url: BITGET_WS_URL.into(),
-            subscribe_msg: json_parser::build_spot_subscribe(&cfg.spot_symbols),
+            subscribe_msg: (subscription.subscribe)(&cfg.spot_symbols),
+            subscription: Some(subscription),
             ping: Some(ping.clone()),
             extra_headers: Default::default(),
+            proxy: cfg.spot_proxy.clone(),
+            local_binds: cfg.spot_local_binds.clone(),
+            conn_count: cfg.spot_conn_count,
             shm: ShmNames {
                 bbo: cfg.spot_bbo_shm_name.clone(),
                 trade: cfg.spot_trade_shm_name.clone(),
                 depth5: cfg.spot_depth5_shm_name.clone(),
+                depth_n: cfg.spot_depth_n_shm_name.clone().filter(|_| conn_requests.is_some()),
                 ..Default::default()
             },
             symbols: cfg.spot_symbols.clone(),
+            product_type: ProductType::Spot,
             md_size: cfg.md_size,
-            text_parser: Some(Box::new(json_parser::parse_message)),
+            text_parser: Some(parser),
             binary_parser: None,
             custom_trade_dedup: None,
             dedup_cpu_core: None,
+            busy_poll: None,
+            // Not gap-checked; see the `seq_gap` module doc.
+            gap_tolerance: GapTolerance::default(),
+            gap_hook: None,
+            trade_backfill: None,
+            background: Vec::new(),
+            ticks: conn_requests.is_some().then_some(ticks),
+            conn_requests,
         });
     }
 
     if !cfg.futures_symbols.is_empty() {
+        let subscription = json_parser::subscription(true, &cfg.futures_depth_channel)
+            .ok_or_else(|| anyhow!("[bitget] unknown futures depth_channel {:?}", cfg.futures_depth_channel))?;
+        let ticks = TickSizes::from_registry(instruments, Exchange::Bitget, ProductType::Futures);
+        let (parser, conn_requests) =
+            parser(true, &cfg.futures_depth_channel, cfg.futures_depth_n_levels, ticks.clone());
         streams.push(StreamDef {
             label: "bitget_futures".into(),
             ws_url: BITGET_WS_URL.into(),
-            subscribe_msg: json_parser::build_futures_subscribe(&cfg.futures_symbols),
+            subscribe_msg: (subscription.subscribe)(&cfg.futures_symbols),
+            subscription: Some(subscription),
             ping: Some(ping.clone()),
             extra_headers: Default::default(),
+            proxy: cfg.futures_proxy.clone(),
+            local_binds: cfg.futures_local_binds.clone(),
+            conn_count: cfg.futures_conn_count,
             shm: ShmNames {
                 bbo: cfg.futures_bbo_shm_name.clone(),
                 trade: cfg.futures_trade_shm_name.clone(),
                 depth5: cfg.futures_depth5_shm_name.clone(),
+                depth_n: cfg.futures_depth_n_shm_name.clone().filter(|_| conn_requests.is_some()),
                 ..Default::default()
             },
             symbols: cfg.futures_symbols.clone(),
+            product_type: ProductType::Futures,
             md_size: cfg.md_size,
-            text_parser: Some(Box::new(json_parser::parse_message)),
+            text_parser: Some(parser),
             binary_parser: None,
             custom_trade_dedup: None,
             dedup_cpu_core: None,
+            busy_poll: None,
+            gap_tolerance: GapTolerance::default(),
+            gap_hook: None,
+            trade_backfill: None,
+            background: Vec::new(),
+            ticks: conn_requests.is_some().then_some(ticks),
+            conn_requests,
         });
     }
 
     Ok(streams)
 }
+
+/// Parser for a stream subscribed to `depth_channel`, and for `books` the
+/// receiver of its book resubscriptions.
+fn parser(
+    futures: bool,
+    depth_channel: &str,
+    depth_n_levels: Option<usize>,
+    ticks: TickSizes,
+) -> (TextParser, Option<mpsc::UnboundedReceiver<String>>) {
+    if depth_channel == "books5" {
+        return (Box::new(json_parser::parse_message), None);
+    }
+    let (label, product_type) =
+        if futures { ("bitget_futures", ProductType::Futures) } else { ("bitget_spot", ProductType::Spot) };
+    let resubscribe = Box::new(move |inst_id: &str| json_parser::build_book_resubscribe(futures, inst_id));
+    // `books` carries every level, so the book is not cut to a depth.
+    let (books, requests) = ChecksumBooks::new(label, product_type, usize::MAX, depth_n_levels, ticks, resubscribe);
+    (Box::new(move |data, out| json_parser::parse_message_with_books(data, &books, out)), Some(requests))
+}
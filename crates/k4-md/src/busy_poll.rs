@@ -0,0 +1,261 @@
+//! Busy-poll runtime for latency-critical streams.
+//!
+//! The default pipeline reads each stream on the tokio runtime and hands
+//! messages through a channel to a dedup thread. A busy-polled stream
+//! instead gets one dedicated (optionally pinned) thread that owns all of
+//! its redundant connections as [`PollingConnection`]s, spins over their
+//! non-blocking sockets, and parses, dedups and writes SHM inline: no
+//! channel and no thread wakeups between socket and SHM.
+
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+};
+
+use crossbeam_channel::Receiver;
+use k4_core::{
+    types::MarketDataMsg,
+    ws::{BinaryCompression, Frame, PollingConnection, ReconnectPolicy, WsConnConfig, WsEvent, WsEventSink, WsStats},
+};
+use tokio::sync::{broadcast, mpsc};
+use tracing::info;
+
+use crate::{
+    dedup_worker::Deduper,
+    pipeline::{BinaryParser, PingConfig, TextParser},
+    subscription::ConnRequest,
+};
+
+/// Parser of a busy-polled stream. Frames of the other kind are ignored.
+pub enum StreamParser {
+    Text(TextParser),
+    Binary(BinaryParser),
+}
+
+/// Parameters for a busy-polled MD stream.
+pub struct BusyPollParams {
+    pub url: String,
+    pub subscribe_msg: String,
+    pub extra_headers: HashMap<String, String>,
+    pub reconnect: ReconnectPolicy,
+    pub binary_compression: Option<BinaryCompression>,
+    pub max_message_size: usize,
+    pub ping: Option<PingConfig>,
+    /// Lifecycle events are published here, tagged with `label`.
+    pub events: Option<broadcast::Sender<WsEvent>>,
+    /// One stats handle per redundant connection; also sets the connection
+    /// count.
+    pub stats: Vec<WsStats>,
+    pub parser: StreamParser,
+    pub deduper: Deduper,
+    /// Messages to dedup besides the stream's own (REST backfill).
+    pub inject: Receiver<MarketDataMsg>,
+    /// Subscription changes and requests to apply to every connection while
+    /// running.
+    pub control: Option<mpsc::UnboundedReceiver<ConnRequest>>,
+    /// CPU core to pin the polling thread to.
+    pub cpu_core: Option<i32>,
+    /// Set to make the loop close its connections and return.
+    pub stop: Arc<AtomicBool>,
+    pub label: String,
+}
+
+/// Poll the stream's connections on the calling thread until `stop` is set.
+pub fn run_busy_poll_stream(params: BusyPollParams) {
+    let BusyPollParams {
+        url,
+        subscribe_msg,
+        extra_headers,
+        reconnect,
+        binary_compression,
+        max_message_size,
+        ping,
+        events,
+        stats,
+        parser,
+        mut deduper,
+        inject,
+        mut control,
+        cpu_core,
+        stop,
+        label,
+    } = params;
+
+    k4_core::cpu_affinity::maybe_bind(cpu_core);
+    let events = events.map(|tx| WsEventSink::new(label.as_str(), tx));
+    let mut conns: Vec<PollingConnection> = stats
+        .into_iter()
+        .enumerate()
+        .map(|(id, stats)| {
+            let config = WsConnConfig {
+                subscribe_msg: Some(subscribe_msg.clone()),
+                extra_headers: extra_headers.clone(),
+                ping_interval: ping.as_ref().map(|p| p.interval),
+                ping_payload: ping.as_ref().map(|p| p.payload.clone()),
+                pong: ping.as_ref().and_then(|p| p.pong.clone()),
+                pong_timeout: ping.as_ref().and_then(|p| p.pong_timeout),
+                binary_compression,
+                max_message_size,
+                reconnect: reconnect.clone(),
+                events: events.clone(),
+                id,
+                ..WsConnConfig::new(url.as_str())
+            };
+            PollingConnection::new(config, stats)
+        })
+        .collect();
+
+    info!("[{label}] busy-poll loop started ({} connections)", conns.len());
+
+    // Reused across frames so steady-state processing does not allocate.
+    let mut buf = Vec::new();
+    let mut out = Vec::new();
+    while !stop.load(Ordering::Relaxed) {
+        let mut busy = false;
+        let mut on_frame = |frame: Frame<'_>| {
+            match (&parser, frame) {
+                (StreamParser::Text(parse), Frame::Text(text)) => {
+                    buf.clear();
+                    buf.extend_from_slice(text.as_bytes());
+                    parse(&mut buf, &mut out);
+                }
+                (StreamParser::Binary(parse), Frame::Binary(data)) => parse(data, &mut out),
+                _ => return,
+            }
+            for msg in out.drain(..) {
+                deduper.handle(msg);
+            }
+        };
+        for conn in &mut conns {
+            busy |= conn.poll(&mut on_frame);
+        }
+        while let Ok(msg) = inject.try_recv() {
+            deduper.handle(msg);
+            busy = true;
+        }
+        while let Some(request) = control.as_mut().and_then(|c| c.try_recv().ok()) {
+            for conn in &mut conns {
+                match &request {
+                    ConnRequest::Resubscribe(change) => {
+                        conn.set_subscribe_msg(change.on_connect.clone());
+                        conn.send(change.now.clone());
+                    }
+                    ConnRequest::Send(msg) => conn.send(msg.clone()),
+                }
+            }
+            busy = true;
+        }
+        if !busy {
+            std::hint::spin_loop();
+        }
+    }
+
+    for conn in &mut conns {
+        conn.close();
+    }
+    info!("[{label}] busy-poll loop exited");
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use futures_util::{SinkExt, StreamExt};
+    use k4_core::{
+        dedup::UpdateIdDedup,
+        types::{ProductType, Trade, symbol_to_bytes},
+        ws::DEFAULT_MAX_MESSAGE_SIZE,
+    };
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::tungstenite::Message;
+
+    use super::*;
+    use crate::dedup_worker::{ProductShmStores, TradeDeduper};
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn redundant_connections_are_deduped_inline() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        // Every connection sends the same trades, as redundant exchange
+        // connections do.
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                    assert_eq!(ws.next().await.unwrap().unwrap(), Message::Text("sub".into()));
+                    for id in 1..=3 {
+                        ws.send(Message::Text(id.to_string().into())).await.unwrap();
+                    }
+                    while ws.next().await.is_some() {}
+                });
+            }
+        });
+
+        let stores = ProductShmStores { bbo: None, agg: None, trade: None, depth5: None, depth_n: None };
+        let forwarded = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let seen = forwarded.clone();
+        // Records the trades that pass dedup.
+        let mut inner = UpdateIdDedup::new();
+        let custom: TradeDeduper = Box::new(move |sym, id| {
+            let is_new = inner.check_and_update(sym, id);
+            if is_new {
+                seen.lock().unwrap().push(id);
+            }
+            is_new
+        });
+
+        let stats = vec![WsStats::default(), WsStats::default()];
+        let stop = Arc::new(AtomicBool::new(false));
+        let params = BusyPollParams {
+            url: format!("ws://127.0.0.1:{port}/ws"),
+            subscribe_msg: "sub".into(),
+            extra_headers: HashMap::new(),
+            reconnect: ReconnectPolicy::default(),
+            binary_compression: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            ping: None,
+            events: None,
+            stats: stats.clone(),
+            parser: StreamParser::Text(Box::new(|data, out| {
+                let id = std::str::from_utf8(data).unwrap().parse().unwrap();
+                out.push(MarketDataMsg::Trade(Trade {
+                    symbol: symbol_to_bytes("BTCUSDT"),
+                    product_type: ProductType::Spot,
+                    event_timestamp_us: 1,
+                    trade_timestamp_us: 1,
+                    trade_id: id,
+                    price: 1.0,
+                    vol: 1.0,
+                    is_buyer_maker: false,
+                    backfilled: false,
+                    cross_seq: 0,
+                    local_time_us: 1,
+                    kernel_rx_us: 0,
+                }));
+            })),
+            deduper: Deduper::new(stores, None, Some(custom)),
+            inject: crossbeam_channel::never(),
+            control: None,
+            cpu_core: None,
+            stop: stop.clone(),
+            label: "test".into(),
+        };
+        let handle = std::thread::spawn(move || run_busy_poll_stream(params));
+
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while stats.iter().map(|s| s.snapshot().msgs_received).sum::<u64>() < 6 {
+            assert!(Instant::now() < deadline, "timed out");
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        stop.store(true, Ordering::Relaxed);
+        handle.join().unwrap();
+
+        // Both connections delivered all three; each id passed dedup once.
+        assert!(stats.iter().all(|s| s.snapshot().msgs_received == 3));
+        assert_eq!(*forwarded.lock().unwrap(), [1, 2, 3]);
+        assert!(stats.iter().all(|s| !s.snapshot().connected));
+    }
+}
@@ -5,7 +5,10 @@
 //! separate WebSocket endpoints for spot and linear (USDT) futures.
 
 use anyhow::Result;
-use k4_core::config::ConnectionConfig;
+use k4_core::{
+    config::ConnectionConfig,
+    ws::{LocalBind, ProxyConfig},
+};
 
 /// Parsed Bybit configuration.
 #[derive(Debug, Clone)]
@@ -34,6 +37,28 @@ pub struct BybitConfig {
     pub futures_trade_shm_name: Option<String>,
     /// SHM name for futures Depth5 data.
     pub futures_depth5_shm_name: Option<String>,
+    /// SHM name for spot DepthN data.
+    pub spot_depth_n_shm_name: Option<String>,
+    /// SHM name for futures DepthN data.
+    pub futures_depth_n_shm_name: Option<String>,
+
+    /// Spot DepthN levels (`None` = DepthN off).
+    pub spot_depth_n_levels: Option<usize>,
+    /// Futures DepthN levels (`None` = DepthN off).
+    pub futures_depth_n_levels: Option<usize>,
+    /// Spot book channel (default: `"orderbook.50"`).
+    pub spot_depth_channel: String,
+    /// Futures book channel (default: `"orderbook.50"`).
+    pub futures_depth_channel: String,
+
+    /// Egress proxy for spot connections.
+    pub spot_proxy: Option<ProxyConfig>,
+    /// Egress proxy for futures connections.
+    pub futures_proxy: Option<ProxyConfig>,
+    /// Local bind addresses for spot connections.
+    pub spot_local_binds: Vec<LocalBind>,
+    /// Local bind addresses for futures connections.
+    pub futures_local_binds: Vec<LocalBind>,
 
     /// Ping interval in seconds (default: 20).
     pub ping_interval_sec: u64,
@@ -46,31 +71,34 @@ impl BybitConfig {
         let ping_interval_sec = conn.ping_interval_sec.unwrap_or(20);
 
         // Spot config
-        let (spot_symbols, spot_conn_count, spot_bbo, spot_trade, spot_depth5) = if let Some(ref spot) = conn.spot {
-            (
-                spot.symbols.clone().unwrap_or_default(),
-                spot.redun_conn_count.unwrap_or(1),
-                spot.bbo_shm_name.clone(),
-                spot.trade_shm_name.clone(),
-                spot.depth5_shm_name.clone(),
-            )
-        } else {
-            (vec![], 1, None, None, None)
-        };
+        let (spot_symbols, spot_conn_count, spot_bbo, spot_trade, spot_depth5, spot_proxy) =
+            if let Some(ref spot) = conn.spot {
+                (
+                    spot.symbols.clone().unwrap_or_default(),
+                    spot.redun_conn_count.unwrap_or(1),
+                    spot.bbo_shm_name.clone(),
+                    spot.trade_shm_name.clone(),
+                    spot.depth5_shm_name.clone(),
+                    spot.proxy.clone(),
+                )
+            } else {
+                (vec![], 1, None, None, None, None)
+            };
 
         // Futures config (linear / USDT-margined)
-        let (futures_symbols, futures_conn_count, fut_bbo, fut_trade, fut_depth5) = if let Some(ref fut) = conn.futures
-        {
-            (
-                fut.effective_symbols(),
-                fut.effective_conn_count(),
-                fut.bbo_shm_name.clone(),
-                fut.trade_shm_name.clone(),
-                fut.depth5_shm_name.clone(),
-            )
-        } else {
-            (vec![], 1, None, None, None)
-        };
+        let (futures_symbols, futures_conn_count, fut_bbo, fut_trade, fut_depth5, futures_proxy) =
+            if let Some(ref fut) = conn.futures {
+                (
+                    fut.effective_symbols(),
+                    fut.effective_conn_count(),
+                    fut.bbo_shm_name.clone(),
+                    fut.trade_shm_name.clone(),
+                    fut.depth5_shm_name.clone(),
+                    fut.proxy.clone(),
+                )
+            } else {
+                (vec![], 1, None, None, None, None)
+            };
 
         Ok(Self {
             md_size,
@@ -84,6 +112,24 @@ impl BybitConfig {
             futures_bbo_shm_name: fut_bbo,
             futures_trade_shm_name: fut_trade,
             futures_depth5_shm_name: fut_depth5,
+            spot_depth_n_shm_name: conn.spot.as_ref().and_then(|c| c.depth_n_shm_name.clone()),
+            futures_depth_n_shm_name: conn.futures.as_ref().and_then(|c| c.depth_n_shm_name.clone()),
+            spot_depth_n_levels: conn.spot.as_ref().and_then(|c| c.depth_n_levels()),
+            futures_depth_n_levels: conn.futures.as_ref().and_then(|c| c.depth_n_levels()),
+            spot_depth_channel: conn
+                .spot
+                .as_ref()
+                .and_then(|c| c.depth_channel.clone())
+                .unwrap_or_else(|| "orderbook.50".into()),
+            futures_depth_channel: conn
+                .futures
+                .as_ref()
+                .and_then(|c| c.depth_channel.clone())
+                .unwrap_or_else(|| "orderbook.50".into()),
+            spot_proxy,
+            futures_proxy,
+            spot_local_binds: conn.spot.as_ref().and_then(|c| c.local_binds.clone()).unwrap_or_default(),
+            futures_local_binds: conn.futures.as_ref().and_then(|c| c.local_binds.clone()).unwrap_or_default(),
             ping_interval_sec,
         })
     }
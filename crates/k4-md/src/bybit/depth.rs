@@ -0,0 +1,271 @@
+//! Bybit local order books from `orderbook.{50,200,1000}`.
+//!
+//! A book channel sends a snapshot on subscribe, then deltas whose `u` steps
+//! by one. [`DepthBooks`] applies them in order and publishes Depth5 (and
+//! DepthN) after every message:
+//!
+//! - a delta before the symbol's first snapshot is ignored;
+//! - a snapshot replaces the book, unless it trails the book already held (a redundant connection's
+//!   copy). Any message with `u == 1` is a snapshot: Bybit restarts `u` after a service restart;
+//! - a delta at or behind the book's `u` was already applied and is dropped;
+//! - a delta that skips ahead drops the book, counts a resync and resubscribes the channel on the
+//!   live connections; the fresh snapshot restarts it.
+//!
+//! Book updates publish the cross sequence as their `update_id` (see
+//! [`cross_seq`](super::json_parser::cross_seq)), so they order against each
+//! other across restarts and against trades, and the matching-engine time
+//! `cts` as their trade timestamp.
+
+use std::sync::{
+    Mutex,
+    atomic::{AtomicU64, Ordering},
+};
+
+use ahash::AHashMap;
+use k4_core::types::*;
+use simd_json::prelude::*;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use super::json_parser;
+use crate::{
+    json_util::{Json, with_levels},
+    order_book::{OrderBook, TickSizes},
+};
+
+/// Levels per side of the deepest channel (`orderbook.1000`).
+pub const MAX_LEVELS: usize = 1000;
+
+struct Book {
+    levels: OrderBook,
+    /// `u` of the last applied message.
+    update_id: u64,
+    /// Cross sequence of the last applied message.
+    seq: u64,
+}
+
+/// The local books of one stream's book channel, shared by its connections.
+pub struct DepthBooks {
+    label: &'static str,
+    product_type: ProductType,
+    channel: String,
+    /// Levels per side the channel maintains.
+    depth: usize,
+    depth_n_levels: Option<usize>,
+    ticks: TickSizes,
+    books: Mutex<AHashMap<String, Book>>,
+    /// Requests for the stream's live connections
+    /// ([`StreamDef::conn_requests`](crate::pipeline::StreamDef)).
+    requests: mpsc::UnboundedSender<String>,
+    resyncs: AtomicU64,
+}
+
+impl DepthBooks {
+    /// Empty books for `channel` (e.g. `"orderbook.200"`), and the receiver
+    /// of their resubscription requests.
+    pub fn new(
+        label: &'static str,
+        product_type: ProductType,
+        channel: &str,
+        depth_n_levels: Option<usize>,
+        ticks: TickSizes,
+    ) -> (Self, mpsc::UnboundedReceiver<String>) {
+        let (requests, rx) = mpsc::unbounded_channel();
+        let books = Self {
+            label,
+            product_type,
+            channel: channel.to_string(),
+            depth: channel.strip_prefix("orderbook.").and_then(|d| d.parse().ok()).unwrap_or(MAX_LEVELS),
+            depth_n_levels,
+            ticks,
+            books: Mutex::new(AHashMap::new()),
+            requests,
+            resyncs: AtomicU64::new(0),
+        };
+        (books, rx)
+    }
+
+    /// Books dropped because a delta skipped ahead of them.
+    pub fn resyncs(&self) -> u64 {
+        self.resyncs.load(Ordering::Relaxed)
+    }
+
+    /// Apply a book channel message, publishing the book onto `out`.
+    pub fn on_message(&self, v: Json<'_, '_>, out: &mut Vec<MarketDataMsg>) {
+        let Some(data) = v.get("data") else { return };
+        let Some(sym) = data.get("s").and_then(|s| s.into_string()) else { return };
+        let Some(u) = data.get("u").and_then(|u| u.as_u64()) else { return };
+        let Some(seq) = json_parser::cross_seq(data) else { return };
+        let snapshot = u == 1 || v.get("type").and_then(|t| t.into_string()).unwrap_or("snapshot") == "snapshot";
+
+        let ts = v.get("ts").and_then(|t| t.as_u64()).unwrap_or(0);
+        let cts = v.get("cts").and_then(|c| c.as_u64()).unwrap_or(ts);
+
+        with_levels(data.get("b"), data.get("a"), |bids, asks| {
+            let mut books = self.books.lock().unwrap();
+            let book = if snapshot {
+                // Compared by `seq`, which unlike `u` survives a restart.
+                if books.get(sym).is_some_and(|b| b.seq >= seq) {
+                    return;
+                }
+                let book = books.entry(sym.to_string()).or_insert_with(|| Book {
+                    levels: OrderBook::new(self.ticks.get(sym), self.depth),
+                    update_id: 0,
+                    seq: 0,
+                });
+                book.levels.set_snapshot(bids, asks);
+                book
+            } else {
+                // No book until the (re)subscription's snapshot arrives.
+                let Some(book) = books.get_mut(sym) else { return };
+                if u <= book.update_id {
+                    return; // already applied
+                }
+                if u != book.update_id + 1 {
+                    let reason = format!("delta {u} follows {}", book.update_id);
+                    return self.resync(&mut books, sym, &reason);
+                }
+                book.levels.update(bids, asks);
+                book
+            };
+            book.update_id = u;
+            book.seq = seq;
+            self.publish(sym, &book.levels, seq, ts * 1000, cts * 1000, out);
+        });
+    }
+
+    /// Drop `symbol`'s book and resubscribe its channel.
+    fn resync(&self, books: &mut AHashMap<String, Book>, symbol: &str, reason: &str) {
+        let resyncs = self.resyncs.fetch_add(1, Ordering::Relaxed) + 1;
+        warn!("[{}] {symbol} book out of sync: {reason} ({resyncs} resyncs), resubscribing", self.label);
+        books.remove(symbol);
+        for request in json_parser::build_book_resubscribe(&self.channel, symbol) {
+            if self.requests.send(request).is_err() {
+                info!("[{}] {symbol}: stream stopped, not resubscribing", self.label);
+                break;
+            }
+        }
+    }
+
+    fn publish(
+        &self,
+        symbol: &str,
+        book: &OrderBook,
+        update_id: u64,
+        event_timestamp_us: u64,
+        trade_timestamp_us: u64,
+        out: &mut Vec<MarketDataMsg>,
+    ) {
+        let (bid_prices, bid_vols, ask_prices, ask_vols, bid_level, ask_level) = book.get_depth5();
+
+        let local_time_us = k4_core::time_util::now_us();
+        let depth = Depth5 {
+            symbol: symbol_to_bytes(symbol),
+            product_type: self.product_type,
+            event_timestamp_us,
+            trade_timestamp_us,
+            update_id,
+            bid_level,
+            ask_level,
+            last_price: 0.0,
+            bid_prices,
+            bid_vols,
+            ask_prices,
+            ask_vols,
+            bid_order_counts: [0; 5],
+            ask_order_counts: [0; 5],
+            local_time_us,
+            kernel_rx_us: 0,
+        };
+        out.push(MarketDataMsg::Depth5(depth));
+
+        if let Some(levels) = self.depth_n_levels {
+            let mut depth_n = DepthNBox::new(DepthN {
+                symbol: depth.symbol,
+                product_type: self.product_type,
+                event_timestamp_us,
+                trade_timestamp_us,
+                update_id,
+                local_time_us,
+                ..Default::default()
+            });
+            book.fill_depth_n(&mut depth_n, levels);
+            out.push(MarketDataMsg::DepthN(depth_n));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::json_util::with_json;
+
+    fn msg(kind: &str, u: u64, seq: u64, bid: &str, ask: &str) -> String {
+        format!(
+            r#"{{"topic":"orderbook.200.BTCUSDT","type":"{kind}","ts":1000,"cts":999,
+                "data":{{"s":"BTCUSDT","b":[["{bid}","1"]],"a":[["{ask}","2"]],"u":{u},"seq":{seq}}}}}"#
+        )
+    }
+
+    fn apply(books: &DepthBooks, json: String) -> Vec<MarketDataMsg> {
+        let mut out = Vec::new();
+        with_json(&mut json.into_bytes(), |v| books.on_message(v, &mut out)).unwrap();
+        out
+    }
+
+    fn best(out: &[MarketDataMsg]) -> (f64, f64, u64) {
+        match out {
+            [MarketDataMsg::Depth5(d)] => (d.bid_prices[0], d.ask_prices[0], d.update_id),
+            _ => panic!("expected one Depth5, got {out:?}"),
+        }
+    }
+
+    #[test]
+    fn applies_contiguous_deltas_after_snapshot() {
+        let (books, _rx) =
+            DepthBooks::new("bybit_test", ProductType::Futures, "orderbook.200", None, TickSizes::default());
+        assert!(apply(&books, msg("delta", 7, 70, "99", "101")).is_empty(), "no book before the snapshot");
+
+        let out = apply(&books, msg("snapshot", 10, 100, "100", "102"));
+        assert_eq!(best(&out), (100.0, 102.0, 100));
+        let MarketDataMsg::Depth5(d) = &out[0] else { unreachable!() };
+        assert_eq!((d.event_timestamp_us, d.trade_timestamp_us), (1_000_000, 999_000));
+
+        assert_eq!(best(&apply(&books, msg("delta", 11, 105, "100.5", "101.5"))), (100.5, 101.5, 105));
+        // The same delta from a redundant connection.
+        assert!(apply(&books, msg("delta", 11, 105, "100.5", "101.5")).is_empty());
+        assert_eq!(books.resyncs(), 0);
+    }
+
+    #[test]
+    fn gap_drops_book_and_resubscribes() {
+        let (books, mut rx) =
+            DepthBooks::new("bybit_test", ProductType::Spot, "orderbook.200", Some(10), TickSizes::default());
+        assert_eq!(apply(&books, msg("snapshot", 10, 100, "100", "102")).len(), 2, "Depth5 and DepthN");
+
+        assert!(apply(&books, msg("delta", 12, 110, "100", "101")).is_empty());
+        assert_eq!(books.resyncs(), 1);
+        let requests: Vec<String> = std::iter::from_fn(|| rx.try_recv().ok()).collect();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].contains(r#""op":"unsubscribe""#) && requests[1].contains(r#""op":"subscribe""#));
+        assert!(requests[1].contains(r#""args":["orderbook.200.BTCUSDT"]"#));
+
+        // Deltas wait for the resubscription's snapshot.
+        assert!(apply(&books, msg("delta", 13, 120, "100", "101")).is_empty());
+        assert_eq!(best(&apply(&books, msg("snapshot", 20, 200, "98", "99"))[..1]), (98.0, 99.0, 200));
+    }
+
+    #[test]
+    fn restart_snapshot_resets_book_and_stale_snapshots_are_dropped() {
+        let (books, _rx) =
+            DepthBooks::new("bybit_test", ProductType::Futures, "orderbook.50", None, TickSizes::default());
+        apply(&books, msg("snapshot", 500, 1000, "100", "102"));
+        // A later-subscribed connection's snapshot behind the book.
+        assert!(apply(&books, msg("snapshot", 499, 990, "1", "2")).is_empty());
+
+        // After a service restart `u` starts again from 1; `seq` carries on.
+        assert_eq!(best(&apply(&books, msg("delta", 1, 1010, "90", "91"))), (90.0, 91.0, 1010));
+        assert_eq!(best(&apply(&books, msg("delta", 2, 1011, "90", "90.5"))), (90.0, 90.5, 1011));
+        assert_eq!(books.resyncs(), 0);
+    }
+}
@@ -0,0 +1,112 @@
+//! Bybit instrument listing for symbol discovery.
+//!
+//! Instruments come from `instruments-info` (paged by cursor for linear),
+//! 24h quote volumes (`turnover24h`) from `tickers`. Futures are the linear
+//! contracts.
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use k4_core::{instrument::bybit::parse_instruments, types::ProductType};
+use serde_json::Value;
+
+use crate::discovery::{InstrumentSource, Listing, set_volumes};
+
+/// Pages of `instruments-info` followed before giving up.
+const MAX_PAGES: usize = 20;
+
+/// Lists spot and linear instruments.
+pub struct BybitInstruments {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl BybitInstruments {
+    /// Instruments from `base_url` (normally
+    /// [`REST_URL`](super::REST_URL)).
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self { http: reqwest::Client::new(), base_url: base_url.into() }
+    }
+
+    /// The `result` object of a response.
+    async fn get(&self, path: &str) -> Result<Value> {
+        let url = format!("{}{path}", self.base_url);
+        let resp: Value = self.http.get(&url).send().await?.error_for_status()?.json().await?;
+        if resp.get("retCode").and_then(Value::as_i64) != Some(0) {
+            return Err(anyhow!("{path} error: {resp}"));
+        }
+        resp.get("result").cloned().ok_or_else(|| anyhow!("{path}: no result"))
+    }
+}
+
+fn list(result: &Value) -> &[Value] {
+    result.get("list").and_then(Value::as_array).map(Vec::as_slice).unwrap_or_default()
+}
+
+#[async_trait]
+impl InstrumentSource for BybitInstruments {
+    async fn instruments(&self, product_type: ProductType, with_volume: bool) -> Result<Vec<Listing>> {
+        let category = match product_type {
+            ProductType::Spot => "spot",
+            _ => "linear",
+        };
+        let mut listings = Vec::new();
+        let mut cursor = String::new();
+        for _ in 0..MAX_PAGES {
+            let result = self
+                .get(&format!("/v5/market/instruments-info?category={category}&limit=1000&cursor={cursor}"))
+                .await?;
+            listings.extend(parse_instruments(list(&result), category).into_iter().map(Listing::from));
+            match result.get("nextPageCursor").and_then(Value::as_str) {
+                Some(next) if !next.is_empty() => cursor = next.to_string(),
+                _ => break,
+            }
+        }
+        if with_volume {
+            let tickers = self.get(&format!("/v5/market/tickers?category={category}")).await?;
+            let volumes = list(&tickers)
+                .iter()
+                .filter_map(|t| {
+                    Some((t.get("symbol")?.as_str()?.to_string(), t.get("turnover24h")?.as_str()?.parse().ok()?))
+                })
+                .collect();
+            set_volumes(&mut listings, volumes);
+        }
+        Ok(listings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backfill::stand_in::{param, serve};
+
+    #[tokio::test]
+    async fn follows_the_page_cursor() {
+        let (base, requests) = serve(|path| {
+            let (symbol, next) = match param(path, "cursor") {
+                Some("") => ("BTCUSDT", "page2"),
+                _ => ("ETHPERP", ""),
+            };
+            let list = serde_json::json!([{
+                "symbol": symbol, "contractType": "LinearPerpetual", "status": "Trading", "baseCoin": "BTC",
+                "quoteCoin": "USDT", "settleCoin": "USDT", "deliveryTime": "0", "priceFilter": {"tickSize": "0.10"},
+                "lotSizeFilter": {"qtyStep": "0.001", "minOrderQty": "0.001"}
+            }]);
+            serde_json::json!({"retCode": 0, "retMsg": "OK", "result": {"list": list, "nextPageCursor": next}})
+                .to_string()
+        })
+        .await;
+
+        let listings = BybitInstruments::new(base).instruments(ProductType::Futures, false).await.unwrap();
+
+        let symbols: Vec<&str> = listings.iter().map(|l| l.instrument.symbol.as_str()).collect();
+        assert_eq!(symbols, ["BTCUSDT", "ETHPERP"]);
+        assert_eq!(
+            *requests.lock().unwrap(),
+            [
+                "/v5/market/instruments-info?category=linear&limit=1000&cursor=",
+                "/v5/market/instruments-info?category=linear&limit=1000&cursor=page2",
+            ]
+        );
+    }
+}
@@ -2,22 +2,47 @@
 //!
 //! Provides parsing functions called by the Bybit `build()` module. BBO and
 //! trade parsing produce `MarketDataMsg` directly. Depth parsing is handled
-//! in `mod.rs` via the stateful OrderBook closure.
+//! by the local books in [`depth`](super::depth).
 
 use k4_core::{time_util, *};
+use simd_json::prelude::*;
 
-use crate::json_util::parse_str_f64;
+use crate::{
+    json_util::{Json, parse_str_f64},
+    subscription::SubscriptionMsgs,
+};
 
-/// Build subscription message for Bybit symbols.
-pub fn build_subscribe(symbols: &[String]) -> String {
+/// Incremental book channels: `orderbook.{depth}`, kept as local books.
+pub const DEPTH_CHANNELS: [&str; 3] = ["orderbook.50", "orderbook.200", "orderbook.1000"];
+
+/// Subscription messages with `depth_channel` as the book channel, or
+/// `None` if it is not one of [`DEPTH_CHANNELS`].
+pub fn subscription(depth_channel: &str) -> Option<SubscriptionMsgs> {
+    let channel = *DEPTH_CHANNELS.iter().find(|c| **c == depth_channel)?;
+    Some(SubscriptionMsgs::new(
+        move |s| request("subscribe", s, channel),
+        move |s| request("unsubscribe", s, channel),
+        str::to_uppercase,
+    ))
+}
+
+/// Unsubscribe then resubscribe `symbol`'s `channel`, for a fresh book
+/// snapshot.
+pub fn build_book_resubscribe(channel: &str, symbol: &str) -> Vec<String> {
+    ["unsubscribe", "subscribe"]
+        .map(|op| serde_json::json!({"req_id": "3001", "op": op, "args": [format!("{channel}.{symbol}")]}).to_string())
+        .into()
+}
+
+fn request(op: &str, symbols: &[String], depth_channel: &str) -> String {
     let args: Vec<String> = symbols
         .iter()
-        .flat_map(|s| vec![format!("publicTrade.{s}"), format!("orderbook.1.{s}"), format!("orderbook.50.{s}")])
+        .flat_map(|s| vec![format!("publicTrade.{s}"), format!("orderbook.1.{s}"), format!("{depth_channel}.{s}")])
         .collect();
 
     serde_json::json!({
         "req_id": "3000",
-        "op": "subscribe",
+        "op": op,
         "args": args
     })
     .to_string()
@@ -28,18 +53,18 @@ pub fn build_subscribe(symbols: &[String]) -> String {
 // ---------------------------------------------------------------------------
 
 /// Parse an `orderbook.1` message into a Bookticker.
-pub fn parse_bbo(v: &serde_json::Value, product_type: ProductType) -> Option<Bookticker> {
+pub fn parse_bbo(v: Json<'_, '_>, product_type: ProductType) -> Option<Bookticker> {
     let data = v.get("data")?;
-    let sym = data.get("s")?.as_str()?;
+    let sym = data.get("s")?.into_string()?;
     let ts = v.get("ts")?.as_u64()?;
     let cts = v.get("cts").and_then(|c| c.as_u64()).unwrap_or(ts);
-    let update_id = data.get("u")?.as_u64()?;
+    let update_id = cross_seq(data)?;
 
     let bids = data.get("b")?.as_array()?;
     let asks = data.get("a")?.as_array()?;
 
-    let (bid_price, bid_vol) = parse_level(bids.first())?;
-    let (ask_price, ask_vol) = parse_level(asks.first())?;
+    let (bid_price, bid_vol) = parse_level(bids.get(0))?;
+    let (ask_price, ask_vol) = parse_level(asks.get(0))?;
 
     Some(Bookticker {
         symbol: symbol_to_bytes(sym),
@@ -54,6 +79,7 @@ pub fn parse_bbo(v: &serde_json::Value, product_type: ProductType) -> Option<Boo
         bid_order_count: 0,
         ask_order_count: 0,
         local_time_us: time_util::now_us(),
+        kernel_rx_us: 0,
     })
 }
 
@@ -61,33 +87,28 @@ pub fn parse_bbo(v: &serde_json::Value, product_type: ProductType) -> Option<Boo
 // Trade parsing
 // ---------------------------------------------------------------------------
 
-/// Parse a `publicTrade` message into `Vec<MarketDataMsg::Trade>`.
-pub fn parse_trades_to_md(v: &serde_json::Value, product_type: ProductType) -> Vec<MarketDataMsg> {
+/// Parse a `publicTrade` message, pushing one `MarketDataMsg::Trade` per
+/// trade onto `out`.
+pub fn parse_trades_to_md(v: Json<'_, '_>, product_type: ProductType, out: &mut Vec<MarketDataMsg>) {
     let local_time = time_util::now_us();
     let ts = v.get("ts").and_then(|t| t.as_u64()).unwrap_or(0);
 
     let data = match v.get("data").and_then(|d| d.as_array()) {
         Some(arr) => arr,
-        None => return vec![],
+        None => return,
     };
 
-    data.iter()
-        .filter_map(|item| parse_single_trade(item, product_type, ts, local_time))
-        .map(MarketDataMsg::Trade)
-        .collect()
+    out.extend(
+        data.iter().filter_map(|item| parse_single_trade(item, product_type, ts, local_time)).map(MarketDataMsg::Trade),
+    );
 }
 
 /// Parse a single trade from the `data` array.
-fn parse_single_trade(
-    item: &serde_json::Value,
-    product_type: ProductType,
-    event_ts: u64,
-    local_time: u64,
-) -> Option<Trade> {
-    let raw_id = item.get("i")?.as_str()?;
-    let sym = item.get("s")?.as_str()?;
+fn parse_single_trade(item: Json<'_, '_>, product_type: ProductType, event_ts: u64, local_time: u64) -> Option<Trade> {
+    let raw_id = item.get("i")?.into_string()?;
+    let sym = item.get("s")?.into_string()?;
     let trade_ts = item.get("T")?.as_u64()?;
-    let side = item.get("S")?.as_str()?;
+    let side = item.get("S")?.into_string()?;
 
     // Spot: numeric trade ID. Futures: UUID -> xxhash64.
     let trade_id: u64 = raw_id.parse().unwrap_or_else(|_| xxhash_rust::xxh64::xxh64(raw_id.as_bytes(), 0));
@@ -101,7 +122,10 @@ fn parse_single_trade(
         price: parse_str_f64(item.get("p"))?,
         vol: parse_str_f64(item.get("v"))?,
         is_buyer_maker: side == "Sell",
+        backfilled: false,
+        cross_seq: item.get("seq").and_then(|q| q.as_u64()).unwrap_or(0),
         local_time_us: local_time,
+        kernel_rx_us: 0,
     })
 }
 
@@ -109,9 +133,19 @@ fn parse_single_trade(
 // Helpers
 // ---------------------------------------------------------------------------
 
-fn parse_level(v: Option<&serde_json::Value>) -> Option<(f64, f64)> {
+/// A book message's `seq`, or `None` if it has none.
+///
+/// The cross sequence is shared by all of a symbol's book channels and
+/// trades, and unlike `u` carries on across a service restart, so it is
+/// what book updates publish as their `update_id`. `u` counts on another
+/// scale, so it is no substitute.
+pub fn cross_seq(data: Json<'_, '_>) -> Option<u64> {
+    data.get("seq")?.as_u64()
+}
+
+fn parse_level(v: Option<Json<'_, '_>>) -> Option<(f64, f64)> {
     let arr = v?.as_array()?;
-    let price = parse_str_f64(arr.first())?;
+    let price = parse_str_f64(arr.get(0))?;
     let vol = parse_str_f64(arr.get(1))?;
     Some((price, vol))
 }
@@ -119,6 +153,7 @@ fn parse_level(v: Option<&serde_json::Value>) -> Option<(f64, f64)> {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::json_util::with_json;
 
     #[test]
     fn parse_orderbook_1_bbo() {
@@ -130,16 +165,16 @@ mod tests {
                 "s": "BTCUSDT",
                 "b": [["29999.9", "0.3"]],
                 "a": [["30000.1", "0.5"]],
-                "u": 123456789
+                "u": 123456789,
+                "seq": 7961638724
             },
             "cts": 1672515782135
         }"#;
-        let v: serde_json::Value = serde_json::from_str(json).unwrap();
-        let bbo = parse_bbo(&v, ProductType::Spot).unwrap();
+        let bbo = with_json(&mut json.as_bytes().to_vec(), |v| parse_bbo(v, ProductType::Spot)).flatten().unwrap();
         assert_eq!(symbol_from_bytes(&bbo.symbol), "BTCUSDT");
         assert!((bbo.bid_price - 29999.9).abs() < 0.01);
         assert!((bbo.ask_price - 30000.1).abs() < 0.01);
-        assert_eq!(bbo.update_id, 123456789);
+        assert_eq!(bbo.update_id, 7961638724);
     }
 
     #[test]
@@ -157,8 +192,8 @@ mod tests {
                 "s": "BTCUSDT"
             }]
         }"#;
-        let v: serde_json::Value = serde_json::from_str(json).unwrap();
-        let trades = parse_trades_to_md(&v, ProductType::Spot);
+        let mut trades = Vec::new();
+        with_json(&mut json.as_bytes().to_vec(), |v| parse_trades_to_md(v, ProductType::Spot, &mut trades)).unwrap();
         assert_eq!(trades.len(), 1);
         match &trades[0] {
             MarketDataMsg::Trade(trade) => {
@@ -182,18 +217,41 @@ mod tests {
                 "p": "30000.00",
                 "v": "0.01",
                 "S": "Sell",
-                "s": "BTCUSDT"
+                "s": "BTCUSDT",
+                "seq": 1783284617
             }]
         }"#;
-        let v: serde_json::Value = serde_json::from_str(json).unwrap();
-        let trades = parse_trades_to_md(&v, ProductType::Futures);
+        let mut trades = Vec::new();
+        with_json(&mut json.as_bytes().to_vec(), |v| parse_trades_to_md(v, ProductType::Futures, &mut trades)).unwrap();
         assert_eq!(trades.len(), 1);
         match &trades[0] {
             MarketDataMsg::Trade(trade) => {
                 assert!(trade.is_buyer_maker);
                 assert_ne!(trade.trade_id, 0);
+                assert_eq!(trade.cross_seq, 1783284617);
             }
             _ => panic!("expected Trade"),
         }
     }
+
+    #[test]
+    fn subscription_uses_depth_channel() {
+        let msgs = subscription("orderbook.1000").unwrap();
+        let sub = (msgs.subscribe)(&["BTCUSDT".into()]);
+        assert!(sub.contains(r#""orderbook.1000.BTCUSDT""#) && sub.contains(r#""orderbook.1.BTCUSDT""#));
+        assert!(!sub.contains("orderbook.50."));
+        assert!(subscription("orderbook.500").is_none());
+    }
+
+    #[test]
+    fn book_update_id_is_cross_sequence() {
+        let json = r#"{"topic":"orderbook.1.BTCUSDT","type":"snapshot","ts":1,
+            "data":{"s":"BTCUSDT","b":[["1","1"]],"a":[["2","1"]],"u":1,"seq":7000}}"#;
+        let bbo = with_json(&mut json.as_bytes().to_vec(), |v| parse_bbo(v, ProductType::Spot)).flatten().unwrap();
+        assert_eq!(bbo.update_id, 7000);
+
+        let json = r#"{"topic":"orderbook.1.BTCUSDT","type":"snapshot","ts":1,
+            "data":{"s":"BTCUSDT","b":[["1","1"]],"a":[["2","1"]],"u":1}}"#;
+        assert!(with_json(&mut json.as_bytes().to_vec(), |v| parse_bbo(v, ProductType::Spot)).flatten().is_none());
+    }
 }
@@ -4,68 +4,128 @@
 //! - Spot (`/v5/public/spot`) — publicTrade, orderbook.1, orderbook.50
 //! - Futures (`/v5/public/linear`) — publicTrade, orderbook.1, orderbook.50
 //!
+//! `depth_channel` swaps `orderbook.50` for `orderbook.200` or
+//! `orderbook.1000`. The book channel is kept as a sequence-checked local
+//! book (see [`depth`]) that feeds Depth5 and, if enabled, a DepthN.
+//!
 //! Bybit is the most complex exchange due to:
-//! - Incremental `orderbook.50` requiring local [`OrderBook`] state
+//! - Incremental book channels requiring local [`OrderBook`](crate::order_book::OrderBook) state
 //! - UUID-based trade IDs on futures (vs numeric on spot)
 //!
 //! Both complexities are handled via **stateful parser closures** that capture
-//! the order book and UUID dedup state, producing standard `MarketDataMsg`
+//! the order books and UUID dedup state, producing standard `MarketDataMsg`
 //! output compatible with the generic pipeline.
 
+pub mod backfill;
 pub mod config;
+pub mod depth;
+pub mod discovery;
 pub mod json_parser;
-pub mod order_book;
 pub mod uuid_dedup;
 
-use std::{sync::Mutex, time::Duration};
-
-use ahash::AHashMap;
-use anyhow::Result;
-use k4_core::{config::ConnectionConfig, dedup::UuidDedup, types::*, ws::PingPayload};
-
-use self::{config::BybitConfig, order_book::OrderBook};
-use crate::pipeline::{PingConfig, ShmNames, StreamDef};
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use anyhow::{Result, anyhow};
+use k4_core::{
+    config::ConnectionConfig,
+    dedup::UuidDedup,
+    instrument::InstrumentRegistry,
+    types::*,
+    ws::{PingPayload, PongMatcher},
+};
+use simd_json::prelude::*;
+use tokio::sync::mpsc;
+
+use self::{backfill::BybitTradeFetcher, config::BybitConfig, depth::DepthBooks};
+use crate::{
+    backfill::TradeFetcher,
+    json_util::with_json,
+    order_book::TickSizes,
+    pipeline::{PingConfig, ShmNames, StreamDef, TextParser},
+    seq_gap::GapTolerance,
+};
+
+/// Public REST API.
+pub const REST_URL: &str = "https://api.bybit.com";
 
 const BYBIT_SPOT_WS_URL: &str = "wss://stream.bybit.com:443/v5/public/spot";
 const BYBIT_LINEAR_WS_URL: &str = "wss://stream.bybit.com:443/v5/public/linear";
 
-/// Build Bybit stream definitions from the connection config.
-pub fn build(conn_config: &ConnectionConfig) -> Result<Vec<StreamDef>> {
+/// Build Bybit stream definitions from the connection config, with tick
+/// sizes for local books from `instruments`.
+pub fn build(conn_config: &ConnectionConfig, instruments: &InstrumentRegistry) -> Result<Vec<StreamDef>> {
     let cfg = BybitConfig::from_connection(conn_config)?;
     let ping = PingConfig {
         interval: Duration::from_secs(cfg.ping_interval_sec),
         payload: PingPayload::Json(serde_json::json!({"req_id": "3002", "op": "ping"})),
+        // Spot replies `"op":"pong"`, linear `"ret_msg":"pong"`.
+        pong: Some(PongMatcher::Contains("\"pong\"".into())),
+        pong_timeout: conn_config.pong_timeout(),
     };
+    // Spot trade ids are not gap-checked by default; `seq_gap_tolerance` can
+    // enable it. Linear trade ids are UUIDs, so only spot is backfilled.
+    let spot_backfill = conn_config
+        .backfill_enabled()
+        .then(|| Arc::new(BybitTradeFetcher::new(REST_URL, ProductType::Spot)) as Arc<dyn TradeFetcher>);
     let mut streams = Vec::new();
 
     // --- Spot ---
     if !cfg.spot_symbols.is_empty() {
-        let parser = make_bybit_parser(ProductType::Spot);
+        let subscription = json_parser::subscription(&cfg.spot_depth_channel)
+            .ok_or_else(|| anyhow!("[bybit] unknown spot depth_channel {:?}", cfg.spot_depth_channel))?;
+        let ticks = TickSizes::from_registry(instruments, Exchange::Bybit, ProductType::Spot);
+        let (parser, conn_requests) =
+            parser("bybit_spot", ProductType::Spot, &cfg.spot_depth_channel, cfg.spot_depth_n_levels, ticks.clone());
 
         streams.push(StreamDef {
             label: "bybit_spot".into(),
             ws_url: BYBIT_SPOT_WS_URL.into(),
-            subscribe_msg: json_parser::build_subscribe(&cfg.spot_symbols),
+            subscribe_msg: (subscription.subscribe)(&cfg.spot_symbols),
+            subscription: Some(subscription),
             ping: Some(ping.clone()),
             extra_headers: Default::default(),
+            proxy: cfg.spot_proxy.clone(),
+            local_binds: cfg.spot_local_binds.clone(),
+            conn_count: cfg.spot_conn_count,
             shm: ShmNames {
                 bbo: cfg.spot_bbo_shm_name.clone(),
                 trade: cfg.spot_trade_shm_name.clone(),
                 depth5: cfg.spot_depth5_shm_name.clone(),
+                depth_n: cfg.spot_depth_n_shm_name.clone(),
                 ..Default::default()
             },
             symbols: cfg.spot_symbols.clone(),
+            product_type: ProductType::Spot,
             md_size: cfg.md_size,
             text_parser: Some(parser),
             binary_parser: None,
             custom_trade_dedup: None, // spot uses standard numeric dedup
             dedup_cpu_core: None,
+            busy_poll: None,
+            gap_tolerance: GapTolerance::default(),
+            gap_hook: None,
+            trade_backfill: spot_backfill,
+            background: Vec::new(),
+            conn_requests: Some(conn_requests),
+            ticks: Some(ticks),
         });
     }
 
     // --- Futures ---
     if !cfg.futures_symbols.is_empty() {
-        let parser = make_bybit_parser(ProductType::Futures);
+        let subscription = json_parser::subscription(&cfg.futures_depth_channel)
+            .ok_or_else(|| anyhow!("[bybit] unknown futures depth_channel {:?}", cfg.futures_depth_channel))?;
+        let ticks = TickSizes::from_registry(instruments, Exchange::Bybit, ProductType::Futures);
+        let (parser, conn_requests) = parser(
+            "bybit_futures",
+            ProductType::Futures,
+            &cfg.futures_depth_channel,
+            cfg.futures_depth_n_levels,
+            ticks.clone(),
+        );
 
         // UUID dedup for futures trades (wrapped in Mutex for Fn closure)
         let uuid_dedup = Mutex::new(UuidDedup::new());
@@ -79,137 +139,72 @@ pub fn build(conn_config: &ConnectionConfig) -> Result<Vec<StreamDef>> {
         streams.push(StreamDef {
             label: "bybit_futures".into(),
             ws_url: BYBIT_LINEAR_WS_URL.into(),
-            subscribe_msg: json_parser::build_subscribe(&cfg.futures_symbols),
+            subscribe_msg: (subscription.subscribe)(&cfg.futures_symbols),
+            subscription: Some(subscription),
             ping: Some(ping.clone()),
             extra_headers: Default::default(),
+            proxy: cfg.futures_proxy.clone(),
+            local_binds: cfg.futures_local_binds.clone(),
+            conn_count: cfg.futures_conn_count,
             shm: ShmNames {
                 bbo: cfg.futures_bbo_shm_name.clone(),
                 trade: cfg.futures_trade_shm_name.clone(),
                 depth5: cfg.futures_depth5_shm_name.clone(),
+                depth_n: cfg.futures_depth_n_shm_name.clone(),
                 ..Default::default()
             },
             symbols: cfg.futures_symbols.clone(),
+            product_type: ProductType::Futures,
             md_size: cfg.md_size,
             text_parser: Some(parser),
             binary_parser: None,
             custom_trade_dedup: Some(custom_dedup),
             dedup_cpu_core: None,
+            busy_poll: None,
+            gap_tolerance: GapTolerance::default(),
+            gap_hook: None,
+            trade_backfill: None,
+            background: Vec::new(),
+            conn_requests: Some(conn_requests),
+            ticks: Some(ticks),
         });
     }
 
     Ok(streams)
 }
 
-/// Create a stateful Bybit parser closure that manages OrderBook state
-/// internally and outputs `Vec<MarketDataMsg>` directly.
+/// Create a stateful Bybit parser closure for a stream subscribed to
+/// `depth_channel`, and the receiver of its book resubscriptions.
 ///
-/// The closure captures a per-symbol `OrderBook<50>` map. When an
-/// `orderbook.50` snapshot or delta arrives, the closure updates the book
-/// and emits a `Depth5` message.
-fn make_bybit_parser(product_type: ProductType) -> crate::pipeline::TextParser {
-    let books: Mutex<AHashMap<String, OrderBook<50>>> = Mutex::new(AHashMap::new());
-
-    Box::new(move |data| parse_to_market_data(data, product_type, &books))
-}
-
-/// Parse a Bybit JSON message into `Vec<MarketDataMsg>`, managing OrderBook
-/// state for incremental depth updates.
-fn parse_to_market_data(
-    data: &mut [u8],
+/// The closure captures the stream's [`DepthBooks`]. Every book channel
+/// message updates the symbol's book and emits a `Depth5` message, plus a
+/// `DepthN` of `depth_n_levels` levels if set.
+pub(crate) fn parser(
+    label: &'static str,
     product_type: ProductType,
-    books: &Mutex<AHashMap<String, OrderBook<50>>>,
-) -> Vec<MarketDataMsg> {
-    let v: serde_json::Value = match simd_json::serde::from_slice(data) {
-        Ok(v) => v,
-        Err(_) => return vec![],
-    };
-
-    let topic = match v.get("topic").and_then(|t| t.as_str()) {
-        Some(t) => t,
-        None => return vec![],
-    };
-
-    if topic.starts_with("orderbook.1.") {
-        // BBO — pass through directly
-        json_parser::parse_bbo(&v, product_type).into_iter().map(MarketDataMsg::Bbo).collect()
-    } else if topic.starts_with("publicTrade.") {
-        // Trades — convert to MarketDataMsg::Trade
-        json_parser::parse_trades_to_md(&v, product_type)
-    } else if topic.starts_with("orderbook.50.") {
-        // Depth — update OrderBook and emit Depth5
-        parse_depth_to_md(&v, product_type, books)
-    } else {
-        vec![]
-    }
+    depth_channel: &str,
+    depth_n_levels: Option<usize>,
+    ticks: TickSizes,
+) -> (TextParser, mpsc::UnboundedReceiver<String>) {
+    let (books, requests) = DepthBooks::new(label, product_type, depth_channel, depth_n_levels, ticks);
+    (Box::new(move |data, out| parse_to_market_data(data, product_type, &books, out)), requests)
 }
 
-/// Parse an `orderbook.50` message, update the local OrderBook, and emit Depth5.
-fn parse_depth_to_md(
-    v: &serde_json::Value,
-    product_type: ProductType,
-    books: &Mutex<AHashMap<String, OrderBook<50>>>,
-) -> Vec<MarketDataMsg> {
-    let msg_type = v.get("type").and_then(|t| t.as_str()).unwrap_or("snapshot");
-    let data = match v.get("data") {
-        Some(d) => d,
-        None => return vec![],
-    };
-
-    let sym = match data.get("s").and_then(|s| s.as_str()) {
-        Some(s) => s,
-        None => return vec![],
-    };
-    let ts = v.get("ts").and_then(|t| t.as_u64()).unwrap_or(0);
-    let cts = v.get("cts").and_then(|c| c.as_u64()).unwrap_or(ts);
-    let update_id = data.get("u").and_then(|u| u.as_u64()).unwrap_or(0);
-
-    let bids = parse_levels(data.get("b"));
-    let asks = parse_levels(data.get("a"));
-
-    let mut books_guard = books.lock().unwrap();
-    let book = books_guard.entry(sym.to_string()).or_default();
-
-    if msg_type == "snapshot" {
-        book.set_snapshot(&bids, &asks);
-    } else {
-        book.update(&bids, &asks);
-    }
-
-    let (bid_prices, bid_vols, ask_prices, ask_vols, bid_level, ask_level) = book.get_depth5();
-
-    let depth = Depth5 {
-        symbol: symbol_to_bytes(sym),
-        product_type,
-        event_timestamp_us: ts * 1000,
-        trade_timestamp_us: cts * 1000,
-        update_id,
-        bid_level,
-        ask_level,
-        last_price: 0.0,
-        bid_prices,
-        bid_vols,
-        ask_prices,
-        ask_vols,
-        bid_order_counts: [0; 5],
-        ask_order_counts: [0; 5],
-        local_time_us: k4_core::time_util::now_us(),
-    };
-
-    vec![MarketDataMsg::Depth5(depth)]
-}
-
-/// Parse `[price_str, vol_str]` arrays from JSON.
-fn parse_levels(v: Option<&serde_json::Value>) -> Vec<[f64; 2]> {
-    let arr = match v.and_then(|a| a.as_array()) {
-        Some(a) => a,
-        None => return vec![],
-    };
-    arr.iter()
-        .filter_map(|level| {
-            let a = level.as_array()?;
-            let price = crate::json_util::parse_str_f64(a.first())?;
-            let vol = crate::json_util::parse_str_f64(a.get(1))?;
-            Some([price, vol])
-        })
-        .collect()
+/// Parse a Bybit JSON message onto `out`, managing OrderBook state for
+/// incremental depth updates.
+fn parse_to_market_data(data: &mut [u8], product_type: ProductType, books: &DepthBooks, out: &mut Vec<MarketDataMsg>) {
+    with_json(data, |v| {
+        let Some(topic) = v.get("topic").and_then(|t| t.into_string()) else { return };
+
+        if topic.starts_with("orderbook.1.") {
+            // BBO — pass through directly
+            out.extend(json_parser::parse_bbo(v, product_type).map(MarketDataMsg::Bbo));
+        } else if topic.starts_with("publicTrade.") {
+            // Trades — convert to MarketDataMsg::Trade
+            json_parser::parse_trades_to_md(v, product_type, out);
+        } else if topic.starts_with("orderbook.") {
+            // Depth — update the local book and emit Depth5 (and DepthN)
+            books.on_message(v, out);
+        }
+    });
 }
@@ -0,0 +1,529 @@
+//! Local order books verified by exchange checksums.
+//!
+//! OKX (`books`, `books-l2-tbt`) and Bitget (`books`) send a full snapshot
+//! when a book channel is subscribed, then updates. Every message carries a
+//! CRC32 of the top 25 levels of the resulting book, taken over the price
+//! and size strings exactly as the exchange sent them (`"0.50"` is not
+//! `"0.5"`), interleaved bid/ask:
+//!
+//! ```text
+//! bid1px:bid1sz:ask1px:ask1sz:bid2px:bid2sz:ask2px:ask2sz:...
+//! ```
+//!
+//! with the deeper side's remaining levels appended once the other runs
+//! out. [`ChecksumBooks`] keeps those strings next to the parsed values,
+//! checks every message, and publishes Depth5 (and DepthN) only from books
+//! that match. A mismatch, or an update that does not continue the book's
+//! sequence, drops the book, counts the failure and resubscribes the
+//! channel on the live connections; the fresh snapshot restarts it.
+
+use std::{
+    cell::RefCell,
+    fmt,
+    sync::{
+        Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+};
+
+use ahash::AHashMap;
+use k4_core::types::*;
+use simd_json::prelude::*;
+use tokio::sync::mpsc;
+use tracing::{info, warn};
+
+use crate::{
+    json_util::{Json, parse_str_i32},
+    order_book::{BookSide, TickSize, TickSizes},
+};
+
+/// Levels per side covered by the checksum.
+const CHECKSUM_LEVELS: usize = 25;
+
+/// Longest price or size text a level keeps.
+pub const LEVEL_TEXT_LEN: usize = 24;
+
+/// A price or size exactly as the exchange sent it, stored inline.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub struct LevelText {
+    len: u8,
+    bytes: [u8; LEVEL_TEXT_LEN],
+}
+
+impl LevelText {
+    /// `None` if `text` is longer than [`LEVEL_TEXT_LEN`] bytes.
+    pub fn new(text: &str) -> Option<Self> {
+        let mut bytes = [0; LEVEL_TEXT_LEN];
+        bytes.get_mut(..text.len())?.copy_from_slice(text.as_bytes());
+        Some(Self { len: text.len() as u8, bytes })
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.bytes[..self.len as usize]
+    }
+}
+
+impl fmt::Debug for LevelText {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&*String::from_utf8_lossy(self.as_bytes()), f)
+    }
+}
+
+/// One level as sent: `[price, size, ..., orders]`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RawLevel {
+    pub price: LevelText,
+    pub size: LevelText,
+    /// Order count where the exchange sends one (OKX), else 0.
+    pub orders: i32,
+}
+
+/// Parse a JSON array of levels into `out`, skipping malformed ones and
+/// those with text longer than [`LEVEL_TEXT_LEN`].
+pub fn parse_raw_levels(v: Option<Json<'_, '_>>, out: &mut Vec<RawLevel>) {
+    out.clear();
+    let Some(arr) = v.and_then(|a| a.as_array()) else { return };
+    out.extend(arr.iter().filter_map(|level| {
+        let level = level.as_array()?;
+        Some(RawLevel {
+            price: LevelText::new(level.get(0)?.into_string()?)?,
+            size: LevelText::new(level.get(1)?.into_string()?)?,
+            orders: parse_str_i32(level.get(3)).unwrap_or(0),
+        })
+    }));
+}
+
+thread_local! {
+    /// Bid and ask buffers reused by [`with_raw_levels`].
+    static RAW_LEVELS: RefCell<(Vec<RawLevel>, Vec<RawLevel>)> = RefCell::default();
+}
+
+/// Parse the `bids` and `asks` level arrays into this thread's reused
+/// buffers and pass them to `f`.
+pub fn with_raw_levels<R>(
+    bids: Option<Json<'_, '_>>,
+    asks: Option<Json<'_, '_>>,
+    f: impl FnOnce(&[RawLevel], &[RawLevel]) -> R,
+) -> R {
+    RAW_LEVELS.with_borrow_mut(|(bid_levels, ask_levels)| {
+        parse_raw_levels(bids, bid_levels);
+        parse_raw_levels(asks, ask_levels);
+        f(bid_levels, ask_levels)
+    })
+}
+
+/// A book message: the snapshot sent on subscribe, or an update.
+#[derive(Debug, Clone)]
+pub struct BookMsg<'a> {
+    pub snapshot: bool,
+    pub seq: u64,
+    /// The `seq` of the message this update follows, where the exchange
+    /// sends it (OKX `prevSeqId`); without it, updates only need a higher
+    /// `seq`.
+    pub prev_seq: Option<u64>,
+    pub checksum: i32,
+    pub event_timestamp_us: u64,
+    pub trade_timestamp_us: u64,
+    pub bids: &'a [RawLevel],
+    pub asks: &'a [RawLevel],
+}
+
+#[derive(Debug, Clone)]
+struct Level {
+    price: f64,
+    size: f64,
+    orders: i32,
+    price_text: LevelText,
+    size_text: LevelText,
+}
+
+/// One symbol's book, keeping each level's text for the checksum.
+#[derive(Debug)]
+pub struct ChecksumBook {
+    tick: TickSize,
+    max_levels: usize,
+    bids: BookSide<Level>,
+    asks: BookSide<Level>,
+    seq: u64,
+}
+
+impl ChecksumBook {
+    /// An empty book on `tick`'s grid. `max_levels` is the depth of the
+    /// channel (e.g. 400 for OKX `books`); levels beyond it are dropped.
+    pub fn new(tick: TickSize, max_levels: usize) -> Self {
+        Self { tick, max_levels, bids: BookSide::bids(), asks: BookSide::asks(), seq: 0 }
+    }
+
+    /// Replace the book with a snapshot. `None` if a level does not parse.
+    pub fn set_snapshot(&mut self, bids: &[RawLevel], asks: &[RawLevel], seq: u64) -> Option<()> {
+        self.bids.clear();
+        self.asks.clear();
+        self.update(bids, asks)?;
+        self.seq = seq;
+        Some(())
+    }
+
+    /// Apply changed levels; size 0 removes a level. Each side is then cut
+    /// back to `max_levels`, as the exchange does without sending deletes.
+    /// `None` if a level does not parse.
+    pub fn update(&mut self, bids: &[RawLevel], asks: &[RawLevel]) -> Option<()> {
+        for level in bids {
+            apply(&mut self.bids, self.tick, level)?;
+        }
+        for level in asks {
+            apply(&mut self.asks, self.tick, level)?;
+        }
+        self.bids.truncate(self.max_levels);
+        self.asks.truncate(self.max_levels);
+        Some(())
+    }
+
+    /// CRC32 of the top [`CHECKSUM_LEVELS`] levels, as the exchanges compute
+    /// it.
+    pub fn checksum(&self) -> i32 {
+        let mut hasher = crc32fast::Hasher::new();
+        let mut first = true;
+        let (mut bids, mut asks) = (self.bids.iter(), self.asks.iter());
+        for _ in 0..CHECKSUM_LEVELS {
+            for level in [bids.next(), asks.next()].into_iter().flatten() {
+                if !first {
+                    hasher.update(b":");
+                }
+                first = false;
+                hasher.update(level.price_text.as_bytes());
+                hasher.update(b":");
+                hasher.update(level.size_text.as_bytes());
+            }
+        }
+        hasher.finalize() as i32
+    }
+
+    /// Copy the top levels into `depth`.
+    pub fn fill_depth5(&self, depth: &mut Depth5) {
+        depth.bid_level =
+            fill_side(&self.bids, &mut depth.bid_prices, &mut depth.bid_vols, &mut depth.bid_order_counts);
+        depth.ask_level =
+            fill_side(&self.asks, &mut depth.ask_prices, &mut depth.ask_vols, &mut depth.ask_order_counts);
+    }
+
+    /// Copy up to `levels` levels per side into `depth`.
+    pub fn fill_depth_n(&self, depth: &mut DepthN, levels: usize) {
+        let n = levels.min(MAX_DEPTH_LEVELS);
+        depth.bid_level = fill_side(
+            &self.bids,
+            &mut depth.bid_prices[..n],
+            &mut depth.bid_vols[..n],
+            &mut depth.bid_order_counts[..n],
+        );
+        depth.ask_level = fill_side(
+            &self.asks,
+            &mut depth.ask_prices[..n],
+            &mut depth.ask_vols[..n],
+            &mut depth.ask_order_counts[..n],
+        );
+    }
+}
+
+/// Update, insert or (size 0) remove `level` in `side`.
+fn apply(side: &mut BookSide<Level>, tick: TickSize, level: &RawLevel) -> Option<()> {
+    let price: f64 = fast_float2::parse(level.price.as_bytes()).ok()?;
+    let size: f64 = fast_float2::parse(level.size.as_bytes()).ok()?;
+    let ticks = tick.ticks(price);
+    let new = Level { price, size, orders: level.orders, price_text: level.price, size_text: level.size };
+    if size == 0.0 {
+        side.remove(ticks);
+    } else if let Some(l) = side.get_mut(ticks) {
+        *l = new;
+    } else {
+        side.insert(ticks, new);
+    }
+    Some(())
+}
+
+fn fill_side(side: &BookSide<Level>, prices: &mut [f64], vols: &mut [f64], orders: &mut [i32]) -> u32 {
+    let mut n = 0;
+    for (i, level) in side.iter().take(prices.len()).enumerate() {
+        (prices[i], vols[i], orders[i]) = (level.price, level.size, level.orders);
+        n += 1;
+    }
+    n
+}
+
+/// Builds the requests that resubscribe a symbol's book channel (e.g. an
+/// unsubscribe then a subscribe).
+pub type ResubscribeFn = Box<dyn Fn(&str) -> Vec<String> + Send + Sync>;
+
+/// The checksummed books of one stream, shared by its connections.
+pub struct ChecksumBooks {
+    label: &'static str,
+    product_type: ProductType,
+    max_levels: usize,
+    depth_n_levels: Option<usize>,
+    ticks: TickSizes,
+    books: Mutex<AHashMap<String, ChecksumBook>>,
+    resubscribe: ResubscribeFn,
+    /// Requests for the stream's live connections
+    /// ([`StreamDef::conn_requests`](crate::pipeline::StreamDef)).
+    requests: mpsc::UnboundedSender<String>,
+    checksum_failures: AtomicU64,
+}
+
+impl ChecksumBooks {
+    /// Empty books of `max_levels` per side, and the receiver of their
+    /// resubscription requests.
+    pub fn new(
+        label: &'static str,
+        product_type: ProductType,
+        max_levels: usize,
+        depth_n_levels: Option<usize>,
+        ticks: TickSizes,
+        resubscribe: ResubscribeFn,
+    ) -> (Self, mpsc::UnboundedReceiver<String>) {
+        let (requests, rx) = mpsc::unbounded_channel();
+        let books = Self {
+            label,
+            product_type,
+            max_levels,
+            depth_n_levels,
+            ticks,
+            books: Mutex::new(AHashMap::new()),
+            resubscribe,
+            requests,
+            checksum_failures: AtomicU64::new(0),
+        };
+        (books, rx)
+    }
+
+    /// Books dropped because their checksum did not match.
+    pub fn checksum_failures(&self) -> u64 {
+        self.checksum_failures.load(Ordering::Relaxed)
+    }
+
+    /// Apply `msg` to `symbol`'s book, publishing it onto `out` if its
+    /// checksum matches.
+    pub fn on_message(&self, symbol: &str, msg: BookMsg<'_>, out: &mut Vec<MarketDataMsg>) {
+        let mut books = self.books.lock().unwrap();
+        let book = if msg.snapshot {
+            // A redundant connection's snapshot may trail the live book.
+            if books.get(symbol).is_some_and(|b| b.seq >= msg.seq) {
+                return;
+            }
+            let book = books
+                .entry(symbol.to_string())
+                .or_insert_with(|| ChecksumBook::new(self.ticks.get(symbol), self.max_levels));
+            if book.set_snapshot(msg.bids, msg.asks, msg.seq).is_none() {
+                return self.resync(&mut books, symbol, "unparsable snapshot");
+            }
+            book
+        } else {
+            // No book until the (re)subscription's snapshot arrives.
+            let Some(book) = books.get_mut(symbol) else { return };
+            match msg.prev_seq {
+                Some(prev) if prev == book.seq => {}
+                _ if msg.seq <= book.seq => return, // already applied
+                Some(prev) => {
+                    let reason = format!("update {} follows {prev}, book is at {}", msg.seq, book.seq);
+                    return self.resync(&mut books, symbol, &reason);
+                }
+                None => {}
+            }
+            if book.update(msg.bids, msg.asks).is_none() {
+                return self.resync(&mut books, symbol, "unparsable update");
+            }
+            book.seq = msg.seq;
+            book
+        };
+
+        let checksum = book.checksum();
+        if checksum != msg.checksum {
+            let failures = self.checksum_failures.fetch_add(1, Ordering::Relaxed) + 1;
+            let reason = format!("checksum {checksum} != {} at seq {} ({failures} failures)", msg.checksum, msg.seq);
+            return self.resync(&mut books, symbol, &reason);
+        }
+        self.publish(symbol, book, &msg, out);
+    }
+
+    /// Drop `symbol`'s book and resubscribe its channel.
+    fn resync(&self, books: &mut AHashMap<String, ChecksumBook>, symbol: &str, reason: &str) {
+        warn!("[{}] {symbol} book out of sync: {reason}, resubscribing", self.label);
+        books.remove(symbol);
+        for request in (self.resubscribe)(symbol) {
+            if self.requests.send(request).is_err() {
+                info!("[{}] {symbol}: stream stopped, not resubscribing", self.label);
+                break;
+            }
+        }
+    }
+
+    fn publish(&self, symbol: &str, book: &ChecksumBook, msg: &BookMsg<'_>, out: &mut Vec<MarketDataMsg>) {
+        let local_time_us = k4_core::time_util::now_us();
+        let mut depth = Depth5 {
+            symbol: symbol_to_bytes(symbol),
+            product_type: self.product_type,
+            event_timestamp_us: msg.event_timestamp_us,
+            trade_timestamp_us: msg.trade_timestamp_us,
+            update_id: msg.seq,
+            bid_level: 0,
+            ask_level: 0,
+            last_price: 0.0,
+            bid_prices: [0.0; 5],
+            bid_vols: [0.0; 5],
+            ask_prices: [0.0; 5],
+            ask_vols: [0.0; 5],
+            bid_order_counts: [0; 5],
+            ask_order_counts: [0; 5],
+            local_time_us,
+            kernel_rx_us: 0,
+        };
+        book.fill_depth5(&mut depth);
+        out.push(MarketDataMsg::Depth5(depth));
+
+        if let Some(levels) = self.depth_n_levels {
+            let mut depth_n = DepthNBox::new(DepthN {
+                symbol: depth.symbol,
+                product_type: self.product_type,
+                event_timestamp_us: msg.event_timestamp_us,
+                trade_timestamp_us: msg.trade_timestamp_us,
+                update_id: msg.seq,
+                local_time_us,
+                ..Default::default()
+            });
+            book.fill_depth_n(&mut depth_n, levels);
+            out.push(MarketDataMsg::DepthN(depth_n));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn levels(raw: &[(&str, &str)]) -> Vec<RawLevel> {
+        let text = |t| LevelText::new(t).unwrap();
+        raw.iter().map(|&(price, size)| RawLevel { price: text(price), size: text(size), orders: 0 }).collect()
+    }
+
+    /// A book message with its levels.
+    struct Msg(bool, u64, Option<u64>, i32, Vec<RawLevel>, Vec<RawLevel>);
+
+    impl Msg {
+        fn book(&self) -> BookMsg<'_> {
+            let Self(snapshot, seq, prev_seq, checksum, bids, asks) = self;
+            BookMsg {
+                snapshot: *snapshot,
+                seq: *seq,
+                prev_seq: *prev_seq,
+                checksum: *checksum,
+                event_timestamp_us: 1,
+                trade_timestamp_us: 1,
+                bids,
+                asks,
+            }
+        }
+    }
+
+    fn msg(
+        snapshot: bool,
+        seq: u64,
+        prev_seq: Option<u64>,
+        checksum: i32,
+        bids: &[(&str, &str)],
+        asks: &[(&str, &str)],
+    ) -> Msg {
+        Msg(snapshot, seq, prev_seq, checksum, levels(bids), levels(asks))
+    }
+
+    fn books() -> (ChecksumBooks, mpsc::UnboundedReceiver<String>) {
+        let resubscribe: ResubscribeFn = Box::new(|s| vec![format!("unsub {s}"), format!("sub {s}")]);
+        ChecksumBooks::new("test", ProductType::Spot, 400, Some(3), TickSizes::default(), resubscribe)
+    }
+
+    #[test]
+    fn checksum_matches_okx_example() {
+        let mut book = ChecksumBook::new(TickSize::FALLBACK, 400);
+        book.set_snapshot(&levels(&[("3366.1", "7"), ("3366", "6")]), &levels(&[("3366.8", "9"), ("3368", "8")]), 1)
+            .unwrap();
+        assert_eq!(book.checksum(), -1881014294);
+
+        // Uneven sides: the deeper side's levels are appended.
+        book.update(&levels(&[("3365.5", "1")]), &[]).unwrap();
+        assert_eq!(book.checksum(), -1587199491);
+    }
+
+    #[test]
+    fn book_is_kept_to_channel_depth() {
+        let mut book = ChecksumBook::new(TickSize::FALLBACK, 2);
+        book.set_snapshot(&levels(&[("3366.1", "7"), ("3366", "6")]), &levels(&[("3366.8", "9")]), 1).unwrap();
+        // A better bid pushes the worst one out of the 2-level book.
+        book.update(&levels(&[("3366.5", "1")]), &levels(&[("3367", "1"), ("3368", "1")])).unwrap();
+        let mut depth = Depth5::default();
+        book.fill_depth5(&mut depth);
+        assert_eq!((depth.bid_level, depth.bid_prices[..2].to_vec()), (2, vec![3366.5, 3366.1]));
+        assert_eq!((depth.ask_level, depth.ask_prices[..2].to_vec()), (2, vec![3366.8, 3367.0]));
+    }
+
+    #[test]
+    fn publishes_only_matching_books() {
+        let (books, mut requests) = books();
+        let mut out = Vec::new();
+        let bids = [("3366.1", "7"), ("3366", "6")];
+        let asks = [("3366.8", "9"), ("3368", "8")];
+
+        books.on_message("BTC-USDT", msg(true, 10, None, -1881014294, &bids, &asks).book(), &mut out);
+        let [MarketDataMsg::Depth5(d), MarketDataMsg::DepthN(n)] = &out[..] else { panic!("{out:?}") };
+        assert_eq!((d.bid_level, d.bid_prices[0], d.ask_prices[1], d.update_id), (2, 3366.1, 3368.0, 10));
+        assert_eq!((n.bid_level, n.ask_vols[1]), (2, 8.0));
+
+        // Deep level removed, the other side's volume changed.
+        out.clear();
+        books.on_message("BTC-USDT", msg(false, 11, Some(10), 12345, &[], &[("3368", "2")]).book(), &mut out);
+        assert!(out.is_empty(), "wrong checksum must not publish");
+        assert_eq!(books.checksum_failures(), 1);
+        assert_eq!([requests.try_recv().unwrap(), requests.try_recv().unwrap()], ["unsub BTC-USDT", "sub BTC-USDT"]);
+
+        // Updates are ignored until the resubscription's snapshot.
+        books.on_message("BTC-USDT", msg(false, 12, Some(11), 0, &[], &[]).book(), &mut out);
+        assert!(out.is_empty() && requests.try_recv().is_err());
+        books.on_message("BTC-USDT", msg(true, 20, None, -1881014294, &bids, &asks).book(), &mut out);
+        books.on_message("BTC-USDT", msg(false, 21, Some(20), 1865684724, &[], &[("3368", "2")]).book(), &mut out);
+        assert!(matches!(&out[..], [_, _, MarketDataMsg::Depth5(d), _] if d.ask_vols[1] == 2.0 && d.update_id == 21));
+    }
+
+    #[test]
+    fn sequence_breaks_resubscribe_and_duplicates_are_dropped() {
+        let (books, mut requests) = books();
+        let mut out = Vec::new();
+        let bids = [("3366.1", "7"), ("3366", "6")];
+        let asks = [("3366.8", "9"), ("3368", "8")];
+        books.on_message("BTC-USDT", msg(true, 10, None, -1881014294, &bids, &asks).book(), &mut out);
+        books.on_message("BTC-USDT", msg(false, 11, Some(10), 1865684724, &[], &[("3368", "2")]).book(), &mut out);
+        assert_eq!(out.len(), 4);
+
+        // The same update, and a late snapshot, from a redundant connection.
+        books.on_message("BTC-USDT", msg(false, 11, Some(10), 1865684724, &[], &[("3368", "2")]).book(), &mut out);
+        books.on_message("BTC-USDT", msg(true, 10, None, -1881014294, &bids, &asks).book(), &mut out);
+        assert_eq!(out.len(), 4);
+        assert!(requests.try_recv().is_err());
+
+        books.on_message("BTC-USDT", msg(false, 14, Some(13), 0, &[], &[]).book(), &mut out);
+        assert_eq!(out.len(), 4);
+        assert_eq!(requests.try_recv().unwrap(), "unsub BTC-USDT");
+        assert_eq!(books.checksum_failures(), 0);
+    }
+
+    #[test]
+    fn parses_raw_levels_with_order_counts() {
+        let mut json = br#"[["3366.1","7","0","3"],["3366","6"],["bad"]]"#.to_vec();
+        let mut parsed = Vec::new();
+        crate::json_util::with_json(&mut json, |v| parse_raw_levels(Some(v), &mut parsed)).unwrap();
+        let text = |t| LevelText::new(t).unwrap();
+        assert_eq!(
+            parsed,
+            [
+                RawLevel { price: text("3366.1"), size: text("7"), orders: 3 },
+                RawLevel { price: text("3366"), size: text("6"), orders: 0 }
+            ]
+        );
+        assert_eq!(LevelText::new("0.1234567890123456789012345"), None);
+    }
+}
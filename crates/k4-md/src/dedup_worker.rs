@@ -7,8 +7,18 @@
 use std::sync::Arc;
 
 use crossbeam_channel::Receiver;
-use k4_core::{dedup::UpdateIdDedup, shm::ShmMdStore, types::*, udp::UdpSender};
-use tracing::info;
+use k4_core::{
+    dedup::{IdCheck, UpdateIdDedup},
+    shm::ShmMdStore,
+    types::*,
+    udp::UdpSender,
+};
+use tracing::{info, warn};
+
+use crate::{
+    seq_gap::{GapMonitor, GapTolerance},
+    subscription::SymbolChange,
+};
 
 /// Bundled SHM stores for one product (spot or futures).
 pub struct ProductShmStores {
@@ -16,6 +26,48 @@ pub struct ProductShmStores {
     pub agg: Option<ShmMdStore<AggTrade>>,
     pub trade: Option<ShmMdStore<Trade>>,
     pub depth5: Option<ShmMdStore<Depth5>>,
+    pub depth_n: Option<ShmMdStore<DepthN>>,
+}
+
+impl ProductShmStores {
+    /// Claim a slot for `symbol` in every store.
+    pub fn add_symbol(&mut self, symbol: &str) -> anyhow::Result<()> {
+        if let Some(s) = &mut self.bbo {
+            s.add_symbol(symbol)?;
+        }
+        if let Some(s) = &mut self.agg {
+            s.add_symbol(symbol)?;
+        }
+        if let Some(s) = &mut self.trade {
+            s.add_symbol(symbol)?;
+        }
+        if let Some(s) = &mut self.depth5 {
+            s.add_symbol(symbol)?;
+        }
+        if let Some(s) = &mut self.depth_n {
+            s.add_symbol(symbol)?;
+        }
+        Ok(())
+    }
+
+    /// Free the slots of `symbol` in every store.
+    pub fn remove_symbol(&mut self, symbol: &str) {
+        if let Some(s) = &mut self.bbo {
+            s.remove_symbol(symbol);
+        }
+        if let Some(s) = &mut self.agg {
+            s.remove_symbol(symbol);
+        }
+        if let Some(s) = &mut self.trade {
+            s.remove_symbol(symbol);
+        }
+        if let Some(s) = &mut self.depth5 {
+            s.remove_symbol(symbol);
+        }
+        if let Some(s) = &mut self.depth_n {
+            s.remove_symbol(symbol);
+        }
+    }
 }
 
 /// Optional custom trade dedup function (e.g. Bybit UUID dedup).
@@ -23,85 +75,254 @@ pub struct ProductShmStores {
 /// Returns `true` if the trade is new (should be forwarded), `false` if duplicate.
 pub type TradeDeduper = Box<dyn FnMut(&str, u64) -> bool + Send>;
 
-/// Run a dedup loop on the calling thread.
+/// Per-symbol dedup state plus the sinks accepted messages are written to.
 ///
-/// Reads messages from `rx`, checks each against an `UpdateIdDedup` per symbol,
-/// and writes accepted messages to the appropriate SHM store and UDP sender.
-///
-/// If `cpu_core` is `Some`, the thread is pinned to that CPU core before
-/// entering the hot loop. For most exchanges, pass `custom_trade_dedup = None`
-/// to use the standard `UpdateIdDedup`.
-pub fn run_dedup_loop(
-    label: &str,
-    rx: Receiver<MarketDataMsg>,
+/// [`run_dedup_loop`] feeds it from a channel; the busy-poll runtime calls
+/// [`handle`](Self::handle) inline on the socket thread.
+pub struct Deduper {
     stores: ProductShmStores,
     udp: Option<Arc<UdpSender>>,
     custom_trade_dedup: Option<TradeDeduper>,
-    cpu_core: Option<i32>,
-) {
-    // Pin this thread to a specific CPU core if configured.
-    k4_core::cpu_affinity::maybe_bind(cpu_core);
-    let mut bbo_dedup = UpdateIdDedup::new();
-    let mut agg_dedup = UpdateIdDedup::new();
-    let mut trade_dedup = UpdateIdDedup::new();
-    let mut depth5_dedup = UpdateIdDedup::new();
-    let mut custom_td = custom_trade_dedup;
+    bbo: UpdateIdDedup,
+    agg: UpdateIdDedup,
+    trade: UpdateIdDedup,
+    depth5: UpdateIdDedup,
+    depth_n: UpdateIdDedup,
+    gap_tolerance: GapTolerance,
+    gaps: Option<GapMonitor>,
+    symbol_changes: Option<Receiver<SymbolChange>>,
+}
 
-    info!("[{label}] dedup loop started");
+impl Deduper {
+    /// For most exchanges, pass `custom_trade_dedup = None` to use the
+    /// standard `UpdateIdDedup` for trades.
+    pub fn new(
+        stores: ProductShmStores,
+        udp: Option<Arc<UdpSender>>,
+        custom_trade_dedup: Option<TradeDeduper>,
+    ) -> Self {
+        Self {
+            stores,
+            udp,
+            custom_trade_dedup,
+            bbo: UpdateIdDedup::new(),
+            agg: UpdateIdDedup::new(),
+            trade: UpdateIdDedup::new(),
+            depth5: UpdateIdDedup::new(),
+            depth_n: UpdateIdDedup::new(),
+            gap_tolerance: GapTolerance::default(),
+            gaps: None,
+            symbol_changes: None,
+        }
+    }
 
-    while let Ok(msg) = rx.recv() {
-        match msg {
-            MarketDataMsg::Bbo(ref bbo) => {
-                let sym = symbol_from_bytes(&bbo.symbol);
-                if bbo_dedup.check_and_update(sym, bbo.update_id) {
-                    if let Some(ref shm) = stores.bbo {
-                        shm.write(sym, bbo);
+    /// Apply symbol additions and removals from `changes` to the SHM stores,
+    /// ahead of the next message.
+    pub fn with_symbol_changes(mut self, changes: Receiver<SymbolChange>) -> Self {
+        self.symbol_changes = Some(changes);
+        self
+    }
+
+    fn apply_symbol_changes(&mut self) {
+        let Some(changes) = &self.symbol_changes else { return };
+        while let Ok(change) = changes.try_recv() {
+            match change {
+                SymbolChange::Add(symbols) => {
+                    for sym in &symbols {
+                        if let Err(e) = self.stores.add_symbol(sym) {
+                            warn!("cannot add {sym} to SHM: {e:#}");
+                        }
                     }
-                    if let Some(ref u) = udp {
-                        u.send(msg);
+                }
+                SymbolChange::Remove(symbols) => {
+                    // A later re-subscription starts from the venue's current
+                    // ids; the stale ones would read as a gap.
+                    for sym in &symbols {
+                        self.stores.remove_symbol(sym);
+                        for dedup in
+                            [&mut self.bbo, &mut self.agg, &mut self.trade, &mut self.depth5, &mut self.depth_n]
+                        {
+                            dedup.remove(sym);
+                        }
                     }
                 }
             }
+        }
+    }
+
+    /// Report ID jumps beyond `tolerance` to `monitor`.
+    pub fn with_gap_check(mut self, tolerance: GapTolerance, monitor: GapMonitor) -> Self {
+        self.gap_tolerance = tolerance;
+        self.gaps = Some(monitor);
+        self
+    }
+
+    /// Check `msg` against its symbol's last id and, if new, write it to the
+    /// SHM store and UDP sender. Sequence gaps are reported to the gap
+    /// monitor, if any; the message is still forwarded.
+    pub fn handle(&mut self, msg: MarketDataMsg) {
+        self.apply_symbol_changes();
+        let is_new = match msg {
+            MarketDataMsg::Bbo(ref bbo) => {
+                let sym = symbol_from_bytes(&bbo.symbol);
+                let is_new = check(
+                    &mut self.bbo,
+                    &mut self.gaps,
+                    self.gap_tolerance.bbo,
+                    MessageType::BookTicker,
+                    sym,
+                    bbo.update_id,
+                );
+                if is_new && let Some(ref shm) = self.stores.bbo {
+                    shm.write(sym, bbo);
+                }
+                is_new
+            }
             MarketDataMsg::AggTrade(ref agg) => {
                 let sym = symbol_from_bytes(&agg.symbol);
-                if agg_dedup.check_and_update(sym, agg.agg_trade_id) {
-                    if let Some(ref shm) = stores.agg {
-                        shm.write(sym, agg);
-                    }
-                    if let Some(ref u) = udp {
-                        u.send(msg);
-                    }
+                // Backfilled ids lie behind the last live id, and the backfill
+                // only sends ids the stream skipped.
+                let is_new = agg.backfilled
+                    || check(
+                        &mut self.agg,
+                        &mut self.gaps,
+                        self.gap_tolerance.agg,
+                        MessageType::AggTrade,
+                        sym,
+                        agg.agg_trade_id,
+                    );
+                if is_new && let Some(ref shm) = self.stores.agg {
+                    shm.write(sym, agg);
                 }
+                is_new
             }
             MarketDataMsg::Trade(ref trade) => {
                 let sym = symbol_from_bytes(&trade.symbol);
-                let is_new = if let Some(ref mut dedup_fn) = custom_td {
+                let is_new = if trade.backfilled {
+                    true
+                } else if let Some(ref mut dedup_fn) = self.custom_trade_dedup {
                     dedup_fn(sym, trade.trade_id)
                 } else {
-                    trade_dedup.check_and_update(sym, trade.trade_id)
+                    let tolerance = self.gap_tolerance.trade;
+                    check(&mut self.trade, &mut self.gaps, tolerance, MessageType::Trade, sym, trade.trade_id)
                 };
-                if is_new {
-                    if let Some(ref shm) = stores.trade {
-                        shm.write(sym, trade);
-                    }
-                    if let Some(ref u) = udp {
-                        u.send(msg);
-                    }
+                if is_new && let Some(ref shm) = self.stores.trade {
+                    shm.write(sym, trade);
                 }
+                is_new
             }
             MarketDataMsg::Depth5(ref depth) => {
                 let sym = symbol_from_bytes(&depth.symbol);
-                if depth5_dedup.check_and_update(sym, depth.update_id) {
-                    if let Some(ref shm) = stores.depth5 {
-                        shm.write(sym, depth);
-                    }
-                    if let Some(ref u) = udp {
-                        u.send(msg);
-                    }
+                let is_new = check(
+                    &mut self.depth5,
+                    &mut self.gaps,
+                    self.gap_tolerance.depth5,
+                    MessageType::Depth5,
+                    sym,
+                    depth.update_id,
+                );
+                if is_new && let Some(ref shm) = self.stores.depth5 {
+                    shm.write(sym, depth);
                 }
+                is_new
             }
+            MarketDataMsg::DepthN(ref depth) => {
+                let sym = symbol_from_bytes(&depth.symbol);
+                let is_new = check(
+                    &mut self.depth_n,
+                    &mut self.gaps,
+                    self.gap_tolerance.depth_n,
+                    MessageType::DepthN,
+                    sym,
+                    depth.update_id,
+                );
+                if is_new && let Some(ref shm) = self.stores.depth_n {
+                    shm.write(sym, &**depth);
+                }
+                is_new
+            }
+        };
+        if is_new && let Some(ref u) = self.udp {
+            u.send(msg);
         }
     }
+}
+
+/// Dedup one ID, reporting a gap beyond `tolerance` to `gaps`.
+#[inline]
+fn check(
+    dedup: &mut UpdateIdDedup,
+    gaps: &mut Option<GapMonitor>,
+    tolerance: Option<u64>,
+    msg_type: MessageType,
+    sym: &str,
+    id: u64,
+) -> bool {
+    let result = dedup.check(sym, id, tolerance);
+    if let IdCheck::Gap { from, to } = result
+        && let Some(gaps) = gaps
+    {
+        gaps.on_gap(msg_type, sym, from, to);
+    }
+    result.is_new()
+}
+
+/// Run a dedup loop on the calling thread.
+///
+/// Reads messages from `rx` and passes each to `deduper`, which checks it
+/// against an `UpdateIdDedup` per symbol and writes accepted messages to the
+/// appropriate SHM store and UDP sender.
+///
+/// If `cpu_core` is `Some`, the thread is pinned to that CPU core before
+/// entering the hot loop.
+pub fn run_dedup_loop(label: &str, rx: Receiver<MarketDataMsg>, mut deduper: Deduper, cpu_core: Option<i32>) {
+    // Pin this thread to a specific CPU core if configured.
+    k4_core::cpu_affinity::maybe_bind(cpu_core);
+
+    info!("[{label}] dedup loop started");
+
+    while let Ok(msg) = rx.recv() {
+        deduper.handle(msg);
+    }
 
     info!("[{label}] dedup loop exited");
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use super::*;
+    use crate::seq_gap::{GapStats, SeqGap};
+
+    fn trade(id: u64) -> MarketDataMsg {
+        MarketDataMsg::Trade(Trade { symbol: symbol_to_bytes("BTCUSDT"), trade_id: id, ..Default::default() })
+    }
+
+    #[test]
+    fn resubscribed_symbol_starts_without_gap() {
+        let stores = ProductShmStores { bbo: None, agg: None, trade: None, depth5: None, depth_n: None };
+        // The gap hook is what starts a backfill.
+        let gaps = Arc::new(Mutex::new(Vec::<SeqGap>::new()));
+        let hook_gaps = gaps.clone();
+        let monitor = GapMonitor::new(
+            "test",
+            GapStats::default(),
+            Some(Box::new(move |g| hook_gaps.lock().unwrap().push(g.clone()))),
+        );
+        let (changes_tx, changes) = crossbeam_channel::unbounded();
+        let mut deduper = Deduper::new(stores, None, None)
+            .with_symbol_changes(changes)
+            .with_gap_check(GapTolerance { trade: Some(1), ..Default::default() }, monitor);
+
+        deduper.handle(trade(100));
+        deduper.handle(trade(101));
+        changes_tx.send(SymbolChange::Remove(vec!["BTCUSDT".into()])).unwrap();
+        changes_tx.send(SymbolChange::Add(vec!["BTCUSDT".into()])).unwrap();
+        deduper.handle(trade(5_000));
+        deduper.handle(trade(5_001));
+
+        assert!(gaps.lock().unwrap().is_empty(), "re-subscription reported {:?}", gaps.lock().unwrap());
+        assert_eq!(deduper.trade.last_id("BTCUSDT"), Some(5_001));
+    }
+}
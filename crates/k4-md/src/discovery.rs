@@ -0,0 +1,396 @@
+//! Startup symbol discovery from exchange instrument lists.
+//!
+//! A product whose config has a [`SymbolSelector`] gets its symbols from the
+//! exchange's instruments endpoint instead of a hand-maintained list.
+//! [`resolve_symbols`] fetches the instruments with the exchange's
+//! [`InstrumentSource`], applies the selector and writes the result into the
+//! product's `symbols`, from where `build()` turns it into
+//! [`StreamDef::symbols`](crate::pipeline::StreamDef). Each successful fetch
+//! is snapshotted to disk, and the snapshot stands in when the fetch fails so
+//! that a REST outage does not prevent a restart.
+//!
+//! The fetched contract specs are also returned as an
+//! [`InstrumentRegistry`], for publishing to SHM (see
+//! `instrument_shm_name`) and for the tick sizes local order books are keyed
+//! on (see [`TickSizes`](crate::order_book::TickSizes)). Products that keep
+//! local books have their instruments fetched even without a selector; if
+//! that fails, their books fall back to a fine price grid.
+
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Context, Result, anyhow, bail};
+use async_trait::async_trait;
+use k4_core::{
+    config::{ConnectionConfig, DiffDepthConfig, SymbolSelector},
+    instrument::{Instrument, InstrumentKind, InstrumentRegistry},
+    types::ProductType,
+};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use tracing::{info, warn};
+
+use crate::{binance, bitget, bybit, okx};
+
+/// An instrument as listed by an exchange, with its trading volume.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Listing {
+    #[serde(flatten)]
+    pub instrument: Instrument,
+    /// 24h volume in the quote currency, if requested.
+    pub volume_24h: Option<f64>,
+}
+
+impl From<Instrument> for Listing {
+    fn from(instrument: Instrument) -> Self {
+        Self { instrument, volume_24h: None }
+    }
+}
+
+/// Lists an exchange's instruments over REST.
+#[async_trait]
+pub trait InstrumentSource: Send + Sync {
+    /// List the spot (`Spot`) or linear derivative (`Futures`) instruments,
+    /// with their 24h volume if `with_volume`. Venues that list inverse
+    /// contracts alongside linear ones may return those too.
+    async fn instruments(&self, product_type: ProductType, with_volume: bool) -> Result<Vec<Listing>>;
+}
+
+/// The instrument source of `exchange`, against its public REST API.
+pub fn instrument_source(exchange: &str) -> Result<Box<dyn InstrumentSource>> {
+    Ok(match exchange {
+        "binance" => {
+            Box::new(binance::discovery::BinanceInstruments::new(binance::SPOT_REST_URL, binance::UBASE_REST_URL))
+        }
+        "okx" => Box::new(okx::discovery::OkxInstruments::new(okx::REST_URL)),
+        "bitget" => Box::new(bitget::discovery::BitgetInstruments::new(bitget::REST_URL)),
+        "bybit" => Box::new(bybit::discovery::BybitInstruments::new(bybit::REST_URL)),
+        other => bail!("instrument discovery is not supported for exchange '{other}'"),
+    })
+}
+
+/// Which instruments a product needs fetched besides those of its selector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Need {
+    /// Only for the selector, if any.
+    Selector,
+    /// For publishing (`instrument_shm_name`): a failed fetch is an error.
+    Required,
+    /// For local book tick sizes: a failed fetch only falls back.
+    Books,
+}
+
+/// Whether a product keeps local order books: Binance `diff_depth`, the OKX
+/// and Bitget full-book channels, and every Bybit book channel.
+fn keeps_local_books(exchange: &str, diff_depth: Option<&DiffDepthConfig>, depth_channel: Option<&str>) -> bool {
+    match exchange {
+        "binance" => diff_depth.is_some(),
+        "okx" | "bitget" => depth_channel.is_some_and(|c| c != "books5"),
+        "bybit" => true,
+        _ => false,
+    }
+}
+
+/// Resolve the `symbol_selector` of each product in `config`, replacing its
+/// `symbols`. Products without a selector keep their symbols, but their
+/// instruments are fetched too if `instrument_shm_name` is set or they keep
+/// local order books.
+///
+/// Returns the contract specs of every instrument fetched.
+pub async fn resolve_symbols(config: &mut ConnectionConfig) -> Result<InstrumentRegistry> {
+    let exchange = config.exchange.to_lowercase();
+    let need = |diff_depth, depth_channel| {
+        if config.instrument_shm_name.is_some() {
+            Need::Required
+        } else if keeps_local_books(&exchange, diff_depth, depth_channel) {
+            Need::Books
+        } else {
+            Need::Selector
+        }
+    };
+    let spot_need = config.spot.as_ref().map(|c| need(c.diff_depth.as_ref(), c.depth_channel.as_deref()));
+    let swap_need = config.swap.as_ref().map(|c| need(c.diff_depth.as_ref(), c.depth_channel.as_deref()));
+    let futures_need = config.futures.as_ref().map(|c| need(c.diff_depth.as_ref(), c.depth_channel.as_deref()));
+
+    let mut registry = InstrumentRegistry::new();
+    if let (Some(spot), Some(need)) = (config.spot.as_mut(), spot_need) {
+        let selector = spot.symbol_selector.clone();
+        discover(&exchange, "spot", ProductType::Spot, selector.as_ref(), need, &mut spot.symbols, &mut registry)
+            .await?;
+    }
+    if let (Some(swap), Some(need)) = (config.swap.as_mut(), swap_need) {
+        let selector = swap.symbol_selector.clone();
+        discover(&exchange, "swap", ProductType::Futures, selector.as_ref(), need, &mut swap.symbols, &mut registry)
+            .await?;
+    }
+    if let (Some(futures), Some(need)) = (config.futures.as_mut(), futures_need) {
+        // `symbols` takes precedence over Binance `ubase_symbols`.
+        let selector = futures.symbol_selector.clone();
+        discover(
+            &exchange,
+            "futures",
+            ProductType::Futures,
+            selector.as_ref(),
+            need,
+            &mut futures.symbols,
+            &mut registry,
+        )
+        .await?;
+    }
+    Ok(registry)
+}
+
+/// Fetch one product's instruments if it has a selector or `need`s them,
+/// resolve the selector into `symbols` and add the instruments to
+/// `registry`.
+async fn discover(
+    exchange: &str,
+    section: &str,
+    product_type: ProductType,
+    selector: Option<&SymbolSelector>,
+    need: Need,
+    symbols: &mut Option<Vec<String>>,
+    registry: &mut InstrumentRegistry,
+) -> Result<()> {
+    if selector.is_none() && need == Need::Selector {
+        return Ok(());
+    }
+    let cache = selector
+        .and_then(|s| s.cache_path.as_ref())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| default_cache_path(exchange, section));
+    let source = instrument_source(exchange)?;
+    let label = format!("{exchange} {section}");
+    let with_volume = selector.is_some_and(|s| s.top_n_by_volume.is_some());
+    let listings = match load(source.as_ref(), product_type, with_volume, &cache, &label).await {
+        Ok(listings) => listings,
+        Err(e) if selector.is_none() && need == Need::Books => {
+            warn!("[{label}] no instruments for local book tick sizes: {e:#}");
+            return Ok(());
+        }
+        Err(e) => return Err(e),
+    };
+    if let Some(selector) = selector {
+        *symbols = Some(pick(&listings, product_type, selector, &label)?);
+    }
+    registry.extend(listings.into_iter().map(|l| l.instrument));
+    Ok(())
+}
+
+fn default_cache_path(exchange: &str, section: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("k4_instruments_{exchange}_{section}.json"))
+}
+
+/// Fetch from `source`, falling back to (and refreshing) the snapshot at
+/// `cache`.
+async fn load(
+    source: &dyn InstrumentSource,
+    product_type: ProductType,
+    with_volume: bool,
+    cache: &Path,
+    label: &str,
+) -> Result<Vec<Listing>> {
+    match source.instruments(product_type, with_volume).await {
+        Ok(listings) => {
+            if let Err(e) = write_snapshot(cache, &listings) {
+                warn!("[{label}] failed to write instrument snapshot {}: {e:#}", cache.display());
+            }
+            Ok(listings)
+        }
+        Err(e) => {
+            warn!("[{label}] instrument request failed, using snapshot {}: {e:#}", cache.display());
+            read_snapshot(cache)
+                .with_context(|| format!("[{label}] instrument request failed ({e:#}) and no usable snapshot"))
+        }
+    }
+}
+
+/// Apply `selector` to the spot pairs or linear perpetuals among
+/// `listings`.
+fn pick(
+    listings: &[Listing],
+    product_type: ProductType,
+    selector: &SymbolSelector,
+    label: &str,
+) -> Result<Vec<String>> {
+    let candidates: Vec<Listing> = listings
+        .iter()
+        .filter(|l| l.instrument.product_type == product_type && l.instrument.kind != InstrumentKind::Future)
+        .cloned()
+        .collect();
+    let symbols = select(&candidates, selector)?;
+    if symbols.is_empty() {
+        bail!("[{label}] symbol_selector matched none of {} instruments", candidates.len());
+    }
+    info!("[{label}] symbol_selector resolved {} of {} instruments", symbols.len(), candidates.len());
+    Ok(symbols)
+}
+
+fn write_snapshot(path: &Path, listings: &[Listing]) -> Result<()> {
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    // Written aside and renamed so a crash never leaves a torn snapshot.
+    let tmp = path.with_extension("tmp");
+    std::fs::write(&tmp, serde_json::to_vec(listings)?)?;
+    std::fs::rename(&tmp, path)?;
+    Ok(())
+}
+
+fn read_snapshot(path: &Path) -> Result<Vec<Listing>> {
+    let data = std::fs::read(path).with_context(|| format!("reading {}", path.display()))?;
+    Ok(serde_json::from_slice(&data)?)
+}
+
+/// The venue symbols of `listings` matching `selector`, sorted. `quote`
+/// matches the settlement currency.
+pub fn select(listings: &[Listing], selector: &SymbolSelector) -> Result<Vec<String>> {
+    let regex = selector
+        .regex
+        .as_deref()
+        .map(Regex::new)
+        .transpose()
+        .map_err(|e| anyhow!("invalid symbol_selector regex: {e}"))?;
+    if let Some(listed) = &selector.symbols {
+        for symbol in listed.iter().filter(|s| !listings.iter().any(|l| &l.instrument.symbol == *s)) {
+            warn!("symbol_selector: {symbol} is not listed by the exchange, skipping");
+        }
+    }
+
+    let include_non_trading = selector.include_non_trading.unwrap_or(false);
+    let mut picked: Vec<&Listing> = listings
+        .iter()
+        .filter(|l| include_non_trading || l.instrument.trading)
+        .filter(|l| selector.quote.as_ref().is_none_or(|q| l.instrument.settle.eq_ignore_ascii_case(q)))
+        .filter(|l| regex.as_ref().is_none_or(|r| r.is_match(&l.instrument.symbol)))
+        .filter(|l| selector.symbols.as_ref().is_none_or(|s| s.contains(&l.instrument.symbol)))
+        .collect();
+    if let Some(n) = selector.top_n_by_volume {
+        picked.sort_by(|a, b| b.volume_24h.unwrap_or(0.0).total_cmp(&a.volume_24h.unwrap_or(0.0)));
+        picked.truncate(n);
+    }
+    let mut symbols: Vec<String> = picked.into_iter().map(|l| l.instrument.symbol.clone()).collect();
+    symbols.sort();
+    symbols.dedup();
+    Ok(symbols)
+}
+
+/// Set the 24h volume of each listing found in `volumes`, by venue symbol.
+pub(crate) fn set_volumes(listings: &mut [Listing], volumes: HashMap<String, f64>) {
+    for listing in listings {
+        listing.volume_24h = volumes.get(&listing.instrument.symbol).copied();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use k4_core::types::Exchange;
+
+    use super::*;
+
+    fn listing(symbol: &str, quote: &str, trading: bool, volume: f64) -> Listing {
+        Listing {
+            instrument: Instrument {
+                exchange: Exchange::Binance,
+                product_type: ProductType::Spot,
+                kind: InstrumentKind::Spot,
+                symbol: symbol.into(),
+                base: symbol.trim_end_matches(quote).into(),
+                quote: quote.into(),
+                settle: quote.into(),
+                tick_size: 0.01,
+                lot_size: 0.001,
+                min_qty: 0.001,
+                contract_multiplier: 1.0,
+                expiry_ms: None,
+                trading,
+            },
+            volume_24h: Some(volume),
+        }
+    }
+
+    fn listings() -> Vec<Listing> {
+        vec![
+            listing("BTCUSDT", "USDT", true, 900.0),
+            listing("ETHUSDT", "USDT", true, 500.0),
+            listing("SOLUSDT", "USDT", true, 700.0),
+            listing("LUNAUSDT", "USDT", false, 1000.0),
+            listing("BTCUSDC", "USDC", true, 800.0),
+        ]
+    }
+
+    #[test]
+    fn selects_by_quote_regex_volume_and_list() {
+        let select = |selector| select(&listings(), &selector).unwrap();
+
+        let usdt = SymbolSelector { quote: Some("usdt".into()), ..Default::default() };
+        assert_eq!(select(usdt), ["BTCUSDT", "ETHUSDT", "SOLUSDT"]);
+
+        let top = SymbolSelector { top_n_by_volume: Some(2), ..Default::default() };
+        assert_eq!(select(top), ["BTCUSDC", "BTCUSDT"]);
+
+        let all_btc =
+            SymbolSelector { regex: Some("^BTC".into()), include_non_trading: Some(true), ..Default::default() };
+        assert_eq!(select(all_btc), ["BTCUSDC", "BTCUSDT"]);
+
+        let listed = SymbolSelector {
+            symbols: Some(vec!["ETHUSDT".into(), "LUNAUSDT".into(), "NOPEUSDT".into()]),
+            ..Default::default()
+        };
+        assert_eq!(select(listed), ["ETHUSDT"]);
+
+        let bad = SymbolSelector { regex: Some("(".into()), ..Default::default() };
+        assert!(super::select(&listings(), &bad).is_err());
+    }
+
+    #[test]
+    fn picks_only_the_requested_product() {
+        let mut all = listings();
+        all[0].instrument.product_type = ProductType::Futures;
+        all[0].instrument.kind = InstrumentKind::Perpetual;
+        all[1].instrument.product_type = ProductType::Futures;
+        all[1].instrument.kind = InstrumentKind::Future;
+        let selector = SymbolSelector { quote: Some("USDT".into()), ..Default::default() };
+
+        assert_eq!(pick(&all, ProductType::Futures, &selector, "test").unwrap(), ["BTCUSDT"]);
+        assert_eq!(pick(&all, ProductType::Spot, &selector, "test").unwrap(), ["SOLUSDT"]);
+        assert!(pick(&all, ProductType::CoinMargin, &selector, "test").is_err());
+    }
+
+    #[test]
+    fn local_book_streams_need_tick_sizes() {
+        let diff = DiffDepthConfig::default();
+        assert!(keeps_local_books("binance", Some(&diff), None));
+        assert!(!keeps_local_books("binance", None, None));
+        assert!(keeps_local_books("okx", None, Some("books-l2-tbt")));
+        assert!(!keeps_local_books("okx", None, Some("books5")));
+        assert!(!keeps_local_books("bitget", None, None));
+        assert!(keeps_local_books("bybit", None, None));
+        assert!(!keeps_local_books("udp", None, None));
+    }
+
+    struct Canned(Option<Vec<Listing>>);
+
+    #[async_trait]
+    impl InstrumentSource for Canned {
+        async fn instruments(&self, _product_type: ProductType, _with_volume: bool) -> Result<Vec<Listing>> {
+            self.0.clone().ok_or_else(|| anyhow!("exchange unreachable"))
+        }
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_the_last_snapshot() {
+        let cache = std::env::temp_dir().join(format!("k4_discovery_test_{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&cache);
+
+        let down = Canned(None);
+        assert!(load(&down, ProductType::Spot, false, &cache, "test").await.is_err());
+
+        let up = Canned(Some(listings()));
+        assert_eq!(load(&up, ProductType::Spot, false, &cache, "test").await.unwrap(), listings());
+        assert_eq!(load(&down, ProductType::Spot, false, &cache, "test").await.unwrap(), listings());
+        std::fs::remove_file(&cache).unwrap();
+    }
+}
@@ -1,38 +1,75 @@
 //! Shared JSON parsing helpers used by all exchange modules.
 //!
-//! These utilities are extracted from the per-exchange parsers to eliminate
-//! duplication of common patterns like string-to-f64 conversion and
-//! depth-level filling.
+//! Messages are parsed with [`with_json`] into a simd-json tape: a flat,
+//! borrowed view of the document whose strings point into the input buffer.
+//! Unlike deserializing into a `serde_json::Value`, no per-message `String`s
+//! or maps are built, and the tape itself is reused across messages. Read
+//! strings with `into_string()`, which borrows from the input; `as_str()`
+//! borrows from the (temporary) value.
+//!
+//! The remaining helpers cover common patterns like string-to-f64
+//! conversion and depth-level filling.
+
+use std::cell::RefCell;
+
+use k4_core::types::{Depth5, DepthN, MAX_DEPTH_LEVELS};
+use simd_json::{Buffers, Tape, prelude::*, tape};
+
+/// A borrowed JSON value on a simd-json tape.
+pub type Json<'tape, 'input> = tape::Value<'tape, 'input>;
+
+/// A borrowed JSON array on a simd-json tape.
+pub type JsonArray<'tape, 'input> = tape::Array<'tape, 'input>;
+
+thread_local! {
+    /// Parser scratch space and tape, kept per thread and reused.
+    static PARSER: RefCell<(Buffers, Tape<'static>)> = RefCell::new((Buffers::default(), Tape(Vec::new())));
+}
 
-use k4_core::types::Depth5;
+/// Parse `data` in place and run `f` over the document.
+///
+/// Returns `None` if `data` is not valid JSON. Once the thread's buffers
+/// have grown to the largest message seen, this does not allocate. `f` must
+/// not call `with_json` itself.
+pub fn with_json<R>(data: &mut [u8], f: impl FnOnce(Json<'_, '_>) -> R) -> Option<R> {
+    PARSER.with_borrow_mut(|(buffers, slot)| {
+        let mut tape = std::mem::replace(slot, Tape(Vec::new())).reset();
+        let result = match simd_json::fill_tape(data, buffers, &mut tape) {
+            Ok(()) => Some(f(tape.as_value())),
+            Err(_) => None,
+        };
+        *slot = tape.reset();
+        result
+    })
+}
 
 /// Parse a JSON value (string or number) as `f64`.
 ///
 /// Handles the common exchange pattern where numeric values may be encoded
 /// as either JSON strings (`"30000.5"`) or native numbers (`30000.5`).
 #[inline]
-pub fn parse_str_f64(v: Option<&serde_json::Value>) -> Option<f64> {
+pub fn parse_str_f64(v: Option<Json<'_, '_>>) -> Option<f64> {
     let v = v?;
-    if let Some(s) = v.as_str() { fast_float2::parse(s).ok() } else { v.as_f64() }
+    if let Some(s) = v.as_str() { fast_float2::parse(s).ok() } else { v.cast_f64() }
 }
 
 /// Parse a JSON value (string or number) as `u64`.
 #[inline]
-pub fn parse_str_u64(v: Option<&serde_json::Value>) -> Option<u64> {
+pub fn parse_str_u64(v: Option<Json<'_, '_>>) -> Option<u64> {
     let v = v?;
     if let Some(s) = v.as_str() { s.parse().ok() } else { v.as_u64() }
 }
 
 /// Parse a JSON value (string or number) as `i32`.
 #[inline]
-pub fn parse_str_i32(v: Option<&serde_json::Value>) -> Option<i32> {
+pub fn parse_str_i32(v: Option<Json<'_, '_>>) -> Option<i32> {
     let v = v?;
     if let Some(s) = v.as_str() { s.parse().ok() } else { v.as_i64().map(|n| n as i32) }
 }
 
 /// Parse a named field on a JSON object as `f64` (string or number).
 #[inline]
-pub fn parse_f64_field(v: &serde_json::Value, key: &str) -> Option<f64> {
+pub fn parse_f64_field(v: Json<'_, '_>, key: &str) -> Option<f64> {
     parse_str_f64(v.get(key))
 }
 
@@ -40,26 +77,107 @@ pub fn parse_f64_field(v: &serde_json::Value, key: &str) -> Option<f64> {
 ///
 /// Each level is expected to be `["price", "vol"]` or `["price", "vol", "extra", "count"]`.
 /// The optional 4th element is treated as an order count.
-pub fn fill_depth5_levels(depth: &mut Depth5, bids: &[serde_json::Value], asks: &[serde_json::Value]) {
-    depth.bid_level = bids.len().min(5) as u32;
-    depth.ask_level = asks.len().min(5) as u32;
+pub fn fill_depth5_levels(depth: &mut Depth5, bids: JsonArray<'_, '_>, asks: JsonArray<'_, '_>) {
+    depth.bid_level = fill_side(&mut depth.bid_prices, &mut depth.bid_vols, &mut depth.bid_order_counts, bids);
+    depth.ask_level = fill_side(&mut depth.ask_prices, &mut depth.ask_vols, &mut depth.ask_order_counts, asks);
+}
 
-    for (i, level) in bids.iter().take(5).enumerate() {
+/// Fill the first `levels` bid/ask entries of a [`DepthN`] from JSON level
+/// arrays, as for [`fill_depth5_levels`].
+pub fn fill_depth_n_levels(depth: &mut DepthN, levels: usize, bids: JsonArray<'_, '_>, asks: JsonArray<'_, '_>) {
+    let n = levels.min(MAX_DEPTH_LEVELS);
+    depth.bid_level =
+        fill_side(&mut depth.bid_prices[..n], &mut depth.bid_vols[..n], &mut depth.bid_order_counts[..n], bids);
+    depth.ask_level =
+        fill_side(&mut depth.ask_prices[..n], &mut depth.ask_vols[..n], &mut depth.ask_order_counts[..n], asks);
+}
+
+/// Fill one side from `levels`, up to the length of the slices; returns the
+/// number of levels filled.
+fn fill_side(prices: &mut [f64], vols: &mut [f64], counts: &mut [i32], levels: JsonArray<'_, '_>) -> u32 {
+    let n = levels.len().min(prices.len());
+    for (i, level) in levels.iter().take(n).enumerate() {
         if let Some(arr) = level.as_array() {
-            depth.bid_prices[i] = parse_str_f64(arr.first()).unwrap_or(0.0);
-            depth.bid_vols[i] = parse_str_f64(arr.get(1)).unwrap_or(0.0);
+            prices[i] = parse_str_f64(arr.get(0)).unwrap_or(0.0);
+            vols[i] = parse_str_f64(arr.get(1)).unwrap_or(0.0);
             if let Some(count) = parse_str_i32(arr.get(3)) {
-                depth.bid_order_counts[i] = count;
+                counts[i] = count;
             }
         }
     }
-    for (i, level) in asks.iter().take(5).enumerate() {
-        if let Some(arr) = level.as_array() {
-            depth.ask_prices[i] = parse_str_f64(arr.first()).unwrap_or(0.0);
-            depth.ask_vols[i] = parse_str_f64(arr.get(1)).unwrap_or(0.0);
-            if let Some(count) = parse_str_i32(arr.get(3)) {
-                depth.ask_order_counts[i] = count;
+    n as u32
+}
+
+/// Parse a `[price, vol, ...]` level array into `[price, vol]` pairs in
+/// `out`, skipping malformed levels.
+pub fn parse_levels(v: Option<Json<'_, '_>>, out: &mut Vec<[f64; 2]>) {
+    out.clear();
+    let Some(arr) = v.and_then(|a| a.as_array()) else { return };
+    out.extend(arr.iter().filter_map(|level| {
+        let a = level.as_array()?;
+        Some([parse_str_f64(a.get(0))?, parse_str_f64(a.get(1))?])
+    }));
+}
+
+/// `[price, vol]` pairs of one side.
+type Levels = Vec<[f64; 2]>;
+
+thread_local! {
+    /// Bid and ask buffers reused by [`with_levels`].
+    static LEVELS: RefCell<(Levels, Levels)> = RefCell::default();
+}
+
+/// [`parse_levels`] the bid and ask arrays into this thread's reused
+/// buffers and pass them to `f`.
+pub fn with_levels<R>(
+    bids: Option<Json<'_, '_>>,
+    asks: Option<Json<'_, '_>>,
+    f: impl FnOnce(&[[f64; 2]], &[[f64; 2]]) -> R,
+) -> R {
+    LEVELS.with_borrow_mut(|(bid_levels, ask_levels)| {
+        parse_levels(bids, bid_levels);
+        parse_levels(asks, ask_levels);
+        f(bid_levels, ask_levels)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_strings_and_numbers() {
+        let mut json = br#"{"a": "1.5", "b": 2, "c": -3, "d": "x", "e": [["7", "8", "0", "4"]]}"#.to_vec();
+        with_json(&mut json, |v| {
+            assert_eq!(parse_f64_field(v, "a"), Some(1.5));
+            assert_eq!(parse_f64_field(v, "b"), Some(2.0));
+            assert_eq!(parse_str_u64(v.get("b")), Some(2));
+            assert_eq!(parse_str_i32(v.get("c")), Some(-3));
+            assert_eq!(parse_f64_field(v, "d"), None);
+            assert_eq!(parse_f64_field(v, "missing"), None);
+            let level = v.get("e").and_then(|e| e.get_idx(0)).unwrap();
+            assert_eq!(parse_str_i32(level.get_idx(3)), Some(4));
+        })
+        .unwrap();
+        assert!(with_json(&mut b"{not json".to_vec(), |_| ()).is_none());
+    }
+
+    #[test]
+    fn reused_tape_does_not_allocate() {
+        const MSG: &[u8] = br#"{"e":"trade","s":"BTCUSDT","p":"30000.5","q":"0.1","x":[1,2,3]}"#;
+        let mut buf = MSG.to_vec();
+        let parse = |buf: &mut Vec<u8>| {
+            buf.clear();
+            buf.extend_from_slice(MSG);
+            with_json(buf, |v| parse_f64_field(v, "p")).flatten()
+        };
+        assert_eq!(parse(&mut buf), Some(30000.5));
+
+        let n = crate::alloc_counter::allocations(|| {
+            for _ in 0..100 {
+                assert_eq!(parse(&mut buf), Some(30000.5));
             }
-        }
+        });
+        assert_eq!(n, 0);
     }
 }
@@ -13,22 +13,45 @@
 //!
 //! - [`pipeline`] — `StreamDef` + `GenericMd` data-driven engine
 //! - [`dedup_worker`] — generic dedup loop
+//! - [`seq_gap`] — sequence-gap detection, stats and hooks
+//! - [`backfill`] — REST backfill of trades missed in a gap
+//! - [`discovery`] — startup symbol selection from exchange instrument lists
+//! - [`subscription`] — runtime symbol subscribe/unsubscribe
 //! - [`ws_helper`] — WebSocket connection helpers
+//! - [`busy_poll`] — busy-poll runtime for latency-critical streams
 //! - [`json_util`] — JSON parsing helpers
+//! - [`order_book`] — incremental order books keyed on integer price ticks
+//! - [`checksum_book`] — order books verified by exchange checksums (OKX, Bitget)
 
+#[cfg(test)]
+mod alloc_counter;
+pub mod backfill;
 pub mod binance;
 pub mod bitget;
+pub mod busy_poll;
 pub mod bybit;
+pub mod checksum_book;
 pub mod dedup_worker;
+pub mod discovery;
 pub mod json_util;
 pub mod okx;
+pub mod order_book;
 pub mod pipeline;
 pub mod registry;
+pub mod seq_gap;
+pub mod subscription;
 pub mod udp;
 pub mod ws_helper;
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use async_trait::async_trait;
+use k4_core::{
+    types::ProductType,
+    ws::{WsConnStats, WsEvent},
+};
+use tokio::sync::broadcast;
+
+use crate::seq_gap::GapCount;
 
 /// Trait implemented by all market data modules.
 ///
@@ -44,4 +67,30 @@ pub trait MdModule: Send {
     async fn start(&mut self) -> Result<()>;
     /// Gracefully stop all connections and tasks.
     async fn stop(&mut self) -> Result<()>;
+    /// Start publishing `symbols` of `product_type` on the running module,
+    /// in spare SHM slots. Symbols already subscribed are skipped.
+    async fn subscribe(&mut self, product_type: ProductType, symbols: &[String]) -> Result<()> {
+        let _ = symbols;
+        bail!("{}: runtime subscription of {product_type:?} symbols is not supported", self.name())
+    }
+    /// Stop publishing `symbols` of `product_type` and free their SHM slots.
+    /// Unknown symbols are ignored.
+    async fn unsubscribe(&mut self, product_type: ProductType, symbols: &[String]) -> Result<()> {
+        let _ = symbols;
+        bail!("{}: runtime subscription of {product_type:?} symbols is not supported", self.name())
+    }
+    /// Subscribe to the WebSocket lifecycle events of this module's
+    /// connections. `None` if the module has no WebSocket connections.
+    fn subscribe_events(&self) -> Option<broadcast::Receiver<WsEvent>> {
+        None
+    }
+    /// Current stats of each WebSocket stream, by stream label.
+    fn ws_stats(&self) -> Vec<(String, WsConnStats)> {
+        Vec::new()
+    }
+    /// Sequence gaps detected so far per symbol and message type, by stream
+    /// label.
+    fn gap_stats(&self) -> Vec<(String, GapCount)> {
+        Vec::new()
+    }
 }
@@ -4,7 +4,10 @@
 //! settings, including symbol format conversion (`BTCUSDT` → `BTC-USDT`).
 
 use anyhow::Result;
-use k4_core::config::ConnectionConfig;
+use k4_core::{
+    config::ConnectionConfig,
+    ws::{LocalBind, ProxyConfig},
+};
 
 /// Parsed OKX configuration.
 #[derive(Debug, Clone)]
@@ -33,6 +36,28 @@ pub struct OkxConfig {
     pub swap_trade_shm_name: Option<String>,
     /// SHM name for swap Depth5 data.
     pub swap_depth5_shm_name: Option<String>,
+    /// SHM name for spot DepthN data.
+    pub spot_depth_n_shm_name: Option<String>,
+    /// SHM name for swap DepthN data.
+    pub swap_depth_n_shm_name: Option<String>,
+
+    /// Spot DepthN levels (`None` = DepthN off).
+    pub spot_depth_n_levels: Option<usize>,
+    /// Swap DepthN levels (`None` = DepthN off).
+    pub swap_depth_n_levels: Option<usize>,
+    /// Spot depth channel (default: `"books5"`).
+    pub spot_depth_channel: String,
+    /// Swap depth channel (default: `"books5"`).
+    pub swap_depth_channel: String,
+
+    /// Egress proxy for spot connections.
+    pub spot_proxy: Option<ProxyConfig>,
+    /// Egress proxy for swap connections.
+    pub swap_proxy: Option<ProxyConfig>,
+    /// Local bind addresses for spot connections.
+    pub spot_local_binds: Vec<LocalBind>,
+    /// Local bind addresses for swap connections.
+    pub swap_local_binds: Vec<LocalBind>,
 
     /// Ping interval in seconds (default: 25).
     pub ping_interval_sec: u64,
@@ -48,34 +73,38 @@ impl OkxConfig {
         let ping_interval_sec = conn.ping_interval_sec.unwrap_or(25);
 
         // Spot config
-        let (spot_symbols, spot_conn_count, spot_bbo, spot_trade, spot_depth5) = if let Some(ref spot) = conn.spot {
-            let raw = spot.symbols.clone().unwrap_or_default();
-            let converted: Vec<String> = raw.iter().map(|s| to_okx_inst_id(s)).collect();
-            (
-                converted,
-                spot.redun_conn_count.unwrap_or(1),
-                spot.bbo_shm_name.clone(),
-                spot.trade_shm_name.clone(),
-                spot.depth5_shm_name.clone(),
-            )
-        } else {
-            (vec![], 1, None, None, None)
-        };
+        let (spot_symbols, spot_conn_count, spot_bbo, spot_trade, spot_depth5, spot_proxy) =
+            if let Some(ref spot) = conn.spot {
+                let raw = spot.symbols.clone().unwrap_or_default();
+                let converted: Vec<String> = raw.iter().map(|s| to_okx_inst_id(s)).collect();
+                (
+                    converted,
+                    spot.redun_conn_count.unwrap_or(1),
+                    spot.bbo_shm_name.clone(),
+                    spot.trade_shm_name.clone(),
+                    spot.depth5_shm_name.clone(),
+                    spot.proxy.clone(),
+                )
+            } else {
+                (vec![], 1, None, None, None, None)
+            };
 
         // Swap config (OKX uses "swap" section instead of "futures")
-        let (swap_symbols, swap_conn_count, swap_bbo, swap_trade, swap_depth5) = if let Some(ref swap) = conn.swap {
-            let raw = swap.symbols.clone().unwrap_or_default();
-            let converted: Vec<String> = raw.iter().map(|s| to_okx_swap_inst_id(s)).collect();
-            (
-                converted,
-                swap.redun_conn_count.unwrap_or(1),
-                swap.bbo_shm_name.clone(),
-                swap.trade_shm_name.clone(),
-                swap.depth5_shm_name.clone(),
-            )
-        } else {
-            (vec![], 1, None, None, None)
-        };
+        let (swap_symbols, swap_conn_count, swap_bbo, swap_trade, swap_depth5, swap_proxy) =
+            if let Some(ref swap) = conn.swap {
+                let raw = swap.symbols.clone().unwrap_or_default();
+                let converted: Vec<String> = raw.iter().map(|s| to_okx_swap_inst_id(s)).collect();
+                (
+                    converted,
+                    swap.redun_conn_count.unwrap_or(1),
+                    swap.bbo_shm_name.clone(),
+                    swap.trade_shm_name.clone(),
+                    swap.depth5_shm_name.clone(),
+                    swap.proxy.clone(),
+                )
+            } else {
+                (vec![], 1, None, None, None, None)
+            };
 
         Ok(Self {
             md_size,
@@ -89,6 +118,24 @@ impl OkxConfig {
             swap_bbo_shm_name: swap_bbo,
             swap_trade_shm_name: swap_trade,
             swap_depth5_shm_name: swap_depth5,
+            spot_depth_n_shm_name: conn.spot.as_ref().and_then(|c| c.depth_n_shm_name.clone()),
+            swap_depth_n_shm_name: conn.swap.as_ref().and_then(|c| c.depth_n_shm_name.clone()),
+            spot_depth_n_levels: conn.spot.as_ref().and_then(|c| c.depth_n_levels()),
+            swap_depth_n_levels: conn.swap.as_ref().and_then(|c| c.depth_n_levels()),
+            spot_depth_channel: conn
+                .spot
+                .as_ref()
+                .and_then(|c| c.depth_channel.clone())
+                .unwrap_or_else(|| "books5".into()),
+            swap_depth_channel: conn
+                .swap
+                .as_ref()
+                .and_then(|c| c.depth_channel.clone())
+                .unwrap_or_else(|| "books5".into()),
+            spot_proxy,
+            swap_proxy,
+            spot_local_binds: conn.spot.as_ref().and_then(|c| c.local_binds.clone()).unwrap_or_default(),
+            swap_local_binds: conn.swap.as_ref().and_then(|c| c.local_binds.clone()).unwrap_or_default(),
             ping_interval_sec,
         })
     }
@@ -114,8 +161,12 @@ pub fn to_okx_inst_id(symbol: &str) -> String {
     symbol.to_string()
 }
 
-/// Convert a standard symbol to OKX swap instId (`BTC-USDT-SWAP`).
+/// Convert a standard symbol to OKX swap instId (`BTC-USDT-SWAP`). A swap
+/// instId is returned unchanged.
 pub fn to_okx_swap_inst_id(symbol: &str) -> String {
+    if symbol.ends_with("-SWAP") {
+        return symbol.to_string();
+    }
     format!("{}-SWAP", to_okx_inst_id(symbol))
 }
 
@@ -134,5 +185,6 @@ mod tests {
     fn symbol_conversion_swap() {
         assert_eq!(to_okx_swap_inst_id("BTCUSDT"), "BTC-USDT-SWAP");
         assert_eq!(to_okx_swap_inst_id("ETHUSDT"), "ETH-USDT-SWAP");
+        assert_eq!(to_okx_swap_inst_id("ETH-USDT-SWAP"), "ETH-USDT-SWAP"); // already a swap instId
     }
 }
@@ -0,0 +1,95 @@
+//! OKX instrument listing for symbol discovery.
+//!
+//! Instruments come from `public/instruments`, 24h volumes from
+//! `market/tickers`. Futures are the `SWAP` contracts, linear and inverse.
+
+use anyhow::{Result, anyhow};
+use async_trait::async_trait;
+use k4_core::{instrument::okx::parse_instruments, types::ProductType};
+use serde_json::Value;
+
+use crate::discovery::{InstrumentSource, Listing, set_volumes};
+
+/// Lists spot and swap instruments.
+pub struct OkxInstruments {
+    http: reqwest::Client,
+    base_url: String,
+}
+
+impl OkxInstruments {
+    /// Instruments from `base_url` (normally
+    /// [`REST_URL`](super::REST_URL)).
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self { http: reqwest::Client::new(), base_url: base_url.into() }
+    }
+
+    /// The `data` array of a response.
+    async fn get(&self, path: &str) -> Result<Vec<Value>> {
+        let url = format!("{}{path}", self.base_url);
+        let resp: Value = self.http.get(&url).send().await?.error_for_status()?.json().await?;
+        if resp.get("code").and_then(Value::as_str) != Some("0") {
+            return Err(anyhow!("{path} error: {resp}"));
+        }
+        Ok(resp.get("data").and_then(Value::as_array).cloned().unwrap_or_default())
+    }
+}
+
+/// 24h quote volume: `volCcy24h` is in quote currency for spot but in base
+/// currency for swaps.
+fn parse_volume(v: &Value, swap: bool) -> Option<(String, f64)> {
+    let num = |key| v.get(key)?.as_str()?.parse::<f64>().ok();
+    let volume = if swap { num("volCcy24h")? * num("last")? } else { num("volCcy24h")? };
+    Some((v.get("instId")?.as_str()?.to_string(), volume))
+}
+
+#[async_trait]
+impl InstrumentSource for OkxInstruments {
+    async fn instruments(&self, product_type: ProductType, with_volume: bool) -> Result<Vec<Listing>> {
+        let (inst_type, swap) = match product_type {
+            ProductType::Spot => ("SPOT", false),
+            _ => ("SWAP", true),
+        };
+        let data = self.get(&format!("/api/v5/public/instruments?instType={inst_type}")).await?;
+        let mut listings: Vec<Listing> = parse_instruments(&data).into_iter().map(Listing::from).collect();
+        if with_volume {
+            let tickers = self.get(&format!("/api/v5/market/tickers?instType={inst_type}")).await?;
+            set_volumes(&mut listings, tickers.iter().filter_map(|v| parse_volume(v, swap)).collect());
+        }
+        Ok(listings)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backfill::stand_in::serve;
+
+    #[tokio::test]
+    async fn lists_swaps_with_volume() {
+        let (base, _) = serve(|path| {
+            let data = if path.starts_with("/api/v5/public/instruments") {
+                serde_json::json!([
+                    {"instType": "SWAP", "instId": "BTC-USDT-SWAP", "uly": "BTC-USDT", "ctType": "linear",
+                     "ctVal": "0.01", "settleCcy": "USDT", "tickSz": "0.1", "lotSz": "0.01", "minSz": "0.01",
+                     "state": "live", "expTime": ""},
+                ])
+            } else {
+                serde_json::json!([{"instId": "BTC-USDT-SWAP", "volCcy24h": "10", "last": "40000"}])
+            };
+            serde_json::json!({"code": "0", "msg": "", "data": data}).to_string()
+        })
+        .await;
+
+        let listings = OkxInstruments::new(base).instruments(ProductType::Futures, true).await.unwrap();
+
+        assert_eq!(listings[0].instrument.symbol, "BTC-USDT-SWAP");
+        assert_eq!(listings[0].instrument.canonical_id(), "OKX:BTC-USDT:PERP");
+        assert_eq!((listings[0].instrument.contract_multiplier, listings[0].volume_24h), (0.01, Some(400000.0)));
+    }
+
+    #[tokio::test]
+    async fn error_code_is_an_error() {
+        let (base, _) = serve(|_| r#"{"code":"50011","msg":"Too Many Requests","data":[]}"#.into()).await;
+        assert!(OkxInstruments::new(base).instruments(ProductType::Spot, false).await.is_err());
+    }
+}
@@ -6,10 +6,22 @@
 //! - `bbo-tbt` → [`Bookticker`]
 //! - `trades` → [`Trade`]
 //! - `books5` → [`Depth5`]
+//! - `books`, `books-l2-tbt`, `books50-l2-tbt` → [`Depth5`] / [`DepthN`] from a [`ChecksumBooks`]
+//!   local book
 
 use k4_core::{time_util, *};
+use simd_json::prelude::*;
 
-use crate::json_util::{fill_depth5_levels, parse_str_f64, parse_str_i32, parse_str_u64};
+use super::config::{to_okx_inst_id, to_okx_swap_inst_id};
+use crate::{
+    checksum_book::{BookMsg, ChecksumBooks, with_raw_levels},
+    json_util::{Json, fill_depth5_levels, parse_str_f64, parse_str_i32, parse_str_u64, with_json},
+    subscription::SubscriptionMsgs,
+};
+
+/// Depth channels: the `books5` snapshots, then the full-book channels kept
+/// as checksummed local books.
+pub const DEPTH_CHANNELS: [&str; 4] = ["books5", "books", "books-l2-tbt", "books50-l2-tbt"];
 
 /// Parse an OKX JSON WebSocket message into a [`MarketDataMsg`].
 ///
@@ -20,61 +32,108 @@ pub fn parse_message(data: &mut [u8]) -> Option<MarketDataMsg> {
         return None;
     }
 
-    let v: serde_json::Value = simd_json::serde::from_slice(data).ok()?;
+    with_json(data, |v| {
+        let arg = v.get("arg")?;
+        let channel = arg.get("channel")?.into_string()?;
+        let inst_id = arg.get("instId")?.into_string()?;
 
-    let arg = v.get("arg")?;
-    let channel = arg.get("channel")?.as_str()?;
-    let inst_id = arg.get("instId")?.as_str()?;
+        match channel {
+            "bbo-tbt" => parse_book_ticker(v, inst_id),
+            "trades" => parse_trade(v, inst_id),
+            "books5" => parse_depth5(v, inst_id),
+            _ => None,
+        }
+    })?
+}
 
-    match channel {
-        "bbo-tbt" => parse_book_ticker(&v, inst_id),
-        "trades" => parse_trade(&v, inst_id),
-        "books5" => parse_depth5(&v, inst_id),
-        _ => None,
+/// [`parse_message`] onto `out`, with the full-book channels applied to
+/// `books`.
+pub fn parse_message_with_books(data: &mut [u8], books: &ChecksumBooks, out: &mut Vec<MarketDataMsg>) {
+    if data == b"pong" {
+        return;
     }
+
+    with_json(data, |v| {
+        let Some(arg) = v.get("arg") else { return };
+        let Some(channel) = arg.get("channel").and_then(|c| c.into_string()) else { return };
+        let Some(inst_id) = arg.get("instId").and_then(|i| i.into_string()) else { return };
+
+        match channel {
+            "bbo-tbt" => out.extend(parse_book_ticker(v, inst_id)),
+            "trades" => out.extend(parse_trade(v, inst_id)),
+            "books5" => out.extend(parse_depth5(v, inst_id)),
+            "books" | "books-l2-tbt" | "books50-l2-tbt" => {
+                on_book(v, inst_id, books, out);
+            }
+            _ => {}
+        }
+    });
 }
 
 /// Build subscription message for OKX spot symbols.
 ///
 /// Subscribes to `bbo-tbt`, `trades`, and `books5` for each symbol.
 pub fn build_spot_subscribe(symbols: &[String]) -> String {
-    let args: Vec<serde_json::Value> = symbols
-        .iter()
-        .flat_map(|s| {
-            vec![
-                serde_json::json!({"channel": "bbo-tbt", "instId": s}),
-                serde_json::json!({"channel": "trades", "instId": s}),
-                serde_json::json!({"channel": "books5", "instId": s}),
-            ]
-        })
-        .collect();
+    request("3000", "subscribe", symbols)
+}
 
-    serde_json::json!({
-        "id": "3000",
-        "op": "subscribe",
-        "args": args
-    })
-    .to_string()
+/// Build unsubscription message for OKX spot symbols.
+pub fn build_spot_unsubscribe(symbols: &[String]) -> String {
+    request("3000", "unsubscribe", symbols)
 }
 
 /// Build subscription message for OKX swap symbols.
 ///
 /// Same channels as spot but with swap instIds (e.g. `BTC-USDT-SWAP`).
 pub fn build_swap_subscribe(symbols: &[String]) -> String {
+    request("3001", "subscribe", symbols)
+}
+
+/// Build unsubscription message for OKX swap symbols.
+pub fn build_swap_unsubscribe(symbols: &[String]) -> String {
+    request("3001", "unsubscribe", symbols)
+}
+
+/// Subscription messages for spot (`swap = false`) or swap symbols with
+/// `depth_channel` in place of `books5`, or `None` if it is not one of
+/// [`DEPTH_CHANNELS`].
+pub fn subscription(swap: bool, depth_channel: &str) -> Option<SubscriptionMsgs> {
+    let channel = *DEPTH_CHANNELS.iter().find(|c| **c == depth_channel)?;
+    let id = if swap { "3001" } else { "3000" };
+    Some(SubscriptionMsgs::new(
+        move |s| request_with_depth(id, "subscribe", s, channel),
+        move |s| request_with_depth(id, "unsubscribe", s, channel),
+        if swap { to_okx_swap_inst_id } else { to_okx_inst_id },
+    ))
+}
+
+/// Unsubscribe then resubscribe `inst_id`'s `channel`, for a fresh book
+/// snapshot.
+pub fn build_book_resubscribe(channel: &str, inst_id: &str) -> Vec<String> {
+    ["unsubscribe", "subscribe"]
+        .map(|op| serde_json::json!({"op": op, "args": [{"channel": channel, "instId": inst_id}]}).to_string())
+        .into()
+}
+
+fn request(id: &str, op: &str, symbols: &[String]) -> String {
+    request_with_depth(id, op, symbols, "books5")
+}
+
+fn request_with_depth(id: &str, op: &str, symbols: &[String], depth_channel: &str) -> String {
     let args: Vec<serde_json::Value> = symbols
         .iter()
         .flat_map(|s| {
             vec![
                 serde_json::json!({"channel": "bbo-tbt", "instId": s}),
                 serde_json::json!({"channel": "trades", "instId": s}),
-                serde_json::json!({"channel": "books5", "instId": s}),
+                serde_json::json!({"channel": depth_channel, "instId": s}),
             ]
         })
         .collect();
 
     serde_json::json!({
-        "id": "3001",
-        "op": "subscribe",
+        "id": id,
+        "op": op,
         "args": args
     })
     .to_string()
@@ -84,16 +143,16 @@ pub fn build_swap_subscribe(symbols: &[String]) -> String {
 // Individual parsers
 // ---------------------------------------------------------------------------
 
-fn parse_book_ticker(v: &serde_json::Value, inst_id: &str) -> Option<MarketDataMsg> {
+fn parse_book_ticker(v: Json<'_, '_>, inst_id: &str) -> Option<MarketDataMsg> {
     let local_time = time_util::now_us();
-    let data = v.get("data")?.as_array()?.first()?;
+    let data = v.get("data")?.get_idx(0)?;
 
     let product_type = product_type_from_inst_id(inst_id);
 
     let asks = data.get("asks")?.as_array()?;
     let bids = data.get("bids")?.as_array()?;
-    let ask0 = asks.first()?.as_array()?;
-    let bid0 = bids.first()?.as_array()?;
+    let ask0 = asks.get(0)?.as_array()?;
+    let bid0 = bids.get(0)?.as_array()?;
 
     let ts_ms = parse_str_u64(data.get("ts"))?;
     let seq_id = parse_str_u64(data.get("seqId"))?;
@@ -104,26 +163,27 @@ fn parse_book_ticker(v: &serde_json::Value, inst_id: &str) -> Option<MarketDataM
         event_timestamp_us: ts_ms * 1000,
         trade_timestamp_us: ts_ms * 1000,
         update_id: seq_id,
-        ask_price: parse_str_f64(ask0.first())?,
+        ask_price: parse_str_f64(ask0.get(0))?,
         ask_vol: parse_str_f64(ask0.get(1))?,
-        bid_price: parse_str_f64(bid0.first())?,
+        bid_price: parse_str_f64(bid0.get(0))?,
         bid_vol: parse_str_f64(bid0.get(1))?,
         // OKX provides order count at index 3 of each level.
         ask_order_count: parse_str_i32(ask0.get(3)).unwrap_or(0),
         bid_order_count: parse_str_i32(bid0.get(3)).unwrap_or(0),
         local_time_us: local_time,
+        kernel_rx_us: 0,
     };
 
     Some(MarketDataMsg::Bbo(bbo))
 }
 
-fn parse_trade(v: &serde_json::Value, inst_id: &str) -> Option<MarketDataMsg> {
+fn parse_trade(v: Json<'_, '_>, inst_id: &str) -> Option<MarketDataMsg> {
     let local_time = time_util::now_us();
-    let data = v.get("data")?.as_array()?.first()?;
+    let data = v.get("data")?.get_idx(0)?;
 
     let product_type = product_type_from_inst_id(inst_id);
     let ts_ms = parse_str_u64(data.get("ts"))?;
-    let side = data.get("side")?.as_str()?;
+    let side = data.get("side")?.into_string()?;
 
     let trade = Trade {
         symbol: symbol_to_bytes(inst_id),
@@ -134,15 +194,18 @@ fn parse_trade(v: &serde_json::Value, inst_id: &str) -> Option<MarketDataMsg> {
         price: parse_str_f64(data.get("px"))?,
         vol: parse_str_f64(data.get("sz"))?,
         is_buyer_maker: side == "sell",
+        backfilled: false,
+        cross_seq: 0,
         local_time_us: local_time,
+        kernel_rx_us: 0,
     };
 
     Some(MarketDataMsg::Trade(trade))
 }
 
-fn parse_depth5(v: &serde_json::Value, inst_id: &str) -> Option<MarketDataMsg> {
+fn parse_depth5(v: Json<'_, '_>, inst_id: &str) -> Option<MarketDataMsg> {
     let local_time = time_util::now_us();
-    let data = v.get("data")?.as_array()?.first()?;
+    let data = v.get("data")?.get_idx(0)?;
 
     let product_type = product_type_from_inst_id(inst_id);
     let ts_ms = parse_str_u64(data.get("ts"))?;
@@ -167,6 +230,7 @@ fn parse_depth5(v: &serde_json::Value, inst_id: &str) -> Option<MarketDataMsg> {
         bid_order_counts: [0; 5],
         ask_order_counts: [0; 5],
         local_time_us: local_time,
+        kernel_rx_us: 0,
     };
 
     fill_depth5_levels(&mut depth, bids, asks);
@@ -174,6 +238,30 @@ fn parse_depth5(v: &serde_json::Value, inst_id: &str) -> Option<MarketDataMsg> {
     Some(MarketDataMsg::Depth5(depth))
 }
 
+/// Apply a full-book channel message to `books`; `prevSeqId` is -1 on
+/// snapshots.
+fn on_book(v: Json<'_, '_>, inst_id: &str, books: &ChecksumBooks, out: &mut Vec<MarketDataMsg>) -> Option<()> {
+    let data = v.get("data")?.get_idx(0)?;
+    let ts_ms = parse_str_u64(data.get("ts"))?;
+    let snapshot = v.get("action")?.into_string()? == "snapshot";
+    let seq = data.get("seqId")?.as_u64()?;
+    let checksum = data.get("checksum")?.as_i64()? as i32;
+    with_raw_levels(data.get("bids"), data.get("asks"), |bids, asks| {
+        let msg = BookMsg {
+            snapshot,
+            seq,
+            prev_seq: data.get("prevSeqId").and_then(|p| p.as_u64()),
+            checksum,
+            event_timestamp_us: ts_ms * 1000,
+            trade_timestamp_us: ts_ms * 1000,
+            bids,
+            asks,
+        };
+        books.on_message(inst_id, msg, out);
+    });
+    Some(())
+}
+
 // ---------------------------------------------------------------------------
 // Helpers
 // ---------------------------------------------------------------------------
@@ -181,13 +269,14 @@ fn parse_depth5(v: &serde_json::Value, inst_id: &str) -> Option<MarketDataMsg> {
 /// Determine product type from OKX instId.
 ///
 /// Symbols ending in `-SWAP` are swap/futures, otherwise spot.
-fn product_type_from_inst_id(inst_id: &str) -> ProductType {
+pub(crate) fn product_type_from_inst_id(inst_id: &str) -> ProductType {
     if inst_id.ends_with("-SWAP") { ProductType::Futures } else { ProductType::Spot }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::order_book::TickSizes;
 
     #[test]
     fn parse_bbo_tbt() {
@@ -241,6 +330,55 @@ mod tests {
         }
     }
 
+    #[test]
+    fn books_channel_feeds_checksummed_book() {
+        let resubscribe = Box::new(|s: &str| build_book_resubscribe("books", s));
+        let (books, mut requests) =
+            ChecksumBooks::new("okx_spot", ProductType::Spot, 400, None, TickSizes::default(), resubscribe);
+        let mut out = Vec::new();
+        let mut json = br#"{
+            "arg": {"channel": "books", "instId": "BTC-USDT"},
+            "action": "snapshot",
+            "data": [{
+                "asks": [["3366.8", "9", "10", "3"], ["3368", "8", "3", "4"]],
+                "bids": [["3366.1", "7", "0", "3"], ["3366", "6", "3", "4"]],
+                "ts": "1597026383085",
+                "checksum": -1881014294,
+                "prevSeqId": -1,
+                "seqId": 123456
+            }]
+        }"#
+        .to_vec();
+        parse_message_with_books(&mut json, &books, &mut out);
+        let [MarketDataMsg::Depth5(d)] = &out[..] else { panic!("{out:?}") };
+        assert_eq!((d.bid_prices[0], d.ask_prices[0], d.ask_order_counts[1], d.update_id), (3366.1, 3366.8, 4, 123456));
+
+        let mut json = br#"{
+            "arg": {"channel": "books", "instId": "BTC-USDT"},
+            "action": "update",
+            "data": [{"asks": [], "bids": [["3366", "0", "0", "0"]], "ts": "1597026383185", "checksum": 1, "prevSeqId": 123456, "seqId": 123457}]
+        }"#
+        .to_vec();
+        parse_message_with_books(&mut json, &books, &mut out);
+        assert_eq!(out.len(), 1);
+        assert_eq!(books.checksum_failures(), 1);
+        assert_eq!(
+            requests.try_recv().unwrap(),
+            r#"{"args":[{"channel":"books","instId":"BTC-USDT"}],"op":"unsubscribe"}"#
+        );
+        assert!(requests.try_recv().unwrap().contains(r#""op":"subscribe""#));
+    }
+
+    #[test]
+    fn subscription_uses_depth_channel() {
+        let msgs = subscription(true, "books-l2-tbt").unwrap();
+        let sub = (msgs.subscribe)(&["BTC-USDT-SWAP".into()]);
+        assert!(sub.contains(r#""channel":"books-l2-tbt""#) && sub.contains(r#""id":"3001""#));
+        assert!(!sub.contains("books5"));
+        assert_eq!((msgs.venue_symbol)("BTCUSDT"), "BTC-USDT-SWAP");
+        assert!(subscription(false, "books400").is_none());
+    }
+
     #[test]
     fn pong_returns_none() {
         assert!(parse_message(&mut b"pong".to_vec()).is_none());
@@ -3,70 +3,164 @@
 //! Produces up to 2 [`StreamDef`]s (same URL, different subscriptions):
 //! - Spot — bbo-tbt, trades, books5
 //! - Swap — bbo-tbt, trades, books5
+//!
+//! `depth_channel` swaps books5 for a full-book channel (`books`,
+//! `books-l2-tbt`, `books50-l2-tbt`), kept as a checksum-verified local book
+//! (see [`checksum_book`](crate::checksum_book)) that also feeds DepthN.
 
+pub mod backfill;
 pub mod config;
+pub mod discovery;
 pub mod json_parser;
 
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
+
+use anyhow::{Result, anyhow};
+use k4_core::{
+    config::ConnectionConfig,
+    instrument::InstrumentRegistry,
+    types::{Exchange, ProductType},
+    ws::{PingPayload, PongMatcher},
+};
+use tokio::sync::mpsc;
+use tracing::warn;
 
-use anyhow::Result;
-use k4_core::{config::ConnectionConfig, ws::PingPayload};
+use self::{backfill::OkxTradeFetcher, config::OkxConfig};
+use crate::{
+    backfill::TradeFetcher,
+    checksum_book::ChecksumBooks,
+    order_book::TickSizes,
+    pipeline::{PingConfig, ShmNames, StreamDef, TextParser},
+    seq_gap::GapTolerance,
+};
 
-use self::config::OkxConfig;
-use crate::pipeline::{PingConfig, ShmNames, StreamDef};
+/// Public REST API.
+pub const REST_URL: &str = "https://www.okx.com";
 
 const OKX_WS_URL: &str = "wss://ws.okx.com:8443/ws/v5/public";
 
-/// Build OKX stream definitions from the connection config.
-pub fn build(conn_config: &ConnectionConfig) -> Result<Vec<StreamDef>> {
+/// Build OKX stream definitions from the connection config, with tick
+/// sizes for local books from `instruments`.
+pub fn build(conn_config: &ConnectionConfig, instruments: &InstrumentRegistry) -> Result<Vec<StreamDef>> {
     let cfg = OkxConfig::from_connection(conn_config)?;
-    let ping =
-        PingConfig { interval: Duration::from_secs(cfg.ping_interval_sec), payload: PingPayload::Text("ping".into()) };
+    for (product, depth_channel, levels) in [
+        ("spot", &cfg.spot_depth_channel, cfg.spot_depth_n_levels),
+        ("swap", &cfg.swap_depth_channel, cfg.swap_depth_n_levels),
+    ] {
+        if depth_channel == "books5" && levels.is_some() {
+            warn!("[okx] {product}: DepthN is not available from books5; depth_n_shm_name / depth_levels ignored");
+        }
+    }
+    let ping = PingConfig {
+        interval: Duration::from_secs(cfg.ping_interval_sec),
+        payload: PingPayload::Text("ping".into()),
+        pong: Some(PongMatcher::Exact("pong".into())),
+        pong_timeout: conn_config.pong_timeout(),
+    };
+    // Trade ids are not gap-checked by default; `seq_gap_tolerance` can
+    // enable it.
+    let backfill: Option<Arc<dyn TradeFetcher>> =
+        conn_config.backfill_enabled().then(|| Arc::new(OkxTradeFetcher::new(REST_URL)) as _);
     let mut streams = Vec::new();
 
     if !cfg.spot_symbols.is_empty() {
+        let subscription = json_parser::subscription(false, &cfg.spot_depth_channel)
+            .ok_or_else(|| anyhow!("[okx] unknown spot depth_channel {:?}", cfg.spot_depth_channel))?;
+        let ticks = TickSizes::from_registry(instruments, Exchange::Okx, ProductType::Spot);
+        let (parser, conn_requests) =
+            parser("okx_spot", ProductType::Spot, &cfg.spot_depth_channel, cfg.spot_depth_n_levels, ticks.clone());
         streams.push(StreamDef {
             label: "okx_spot".into(),
             ws_url: OKX_WS_URL.into(),
-            subscribe_msg: json_parser::build_spot_subscribe(&cfg.spot_symbols),
+            subscribe_msg: (subscription.subscribe)(&cfg.spot_symbols),
+            subscription: Some(subscription),
             ping: Some(ping.clone()),
             extra_headers: Default::default(),
+            proxy: cfg.spot_proxy.clone(),
+            local_binds: cfg.spot_local_binds.clone(),
+            conn_count: cfg.spot_conn_count,
             shm: ShmNames {
                 bbo: cfg.spot_bbo_shm_name.clone(),
                 trade: cfg.spot_trade_shm_name.clone(),
                 depth5: cfg.spot_depth5_shm_name.clone(),
+                depth_n: cfg.spot_depth_n_shm_name.clone().filter(|_| conn_requests.is_some()),
                 ..Default::default()
             },
             symbols: cfg.spot_symbols.clone(),
+            product_type: ProductType::Spot,
             md_size: cfg.md_size,
-            text_parser: Some(Box::new(|data| json_parser::parse_message(data).into_iter().collect())),
+            text_parser: Some(parser),
             binary_parser: None,
             custom_trade_dedup: None,
             dedup_cpu_core: None,
+            busy_poll: None,
+            gap_tolerance: GapTolerance::default(),
+            gap_hook: None,
+            trade_backfill: backfill.clone(),
+            background: Vec::new(),
+            ticks: conn_requests.is_some().then_some(ticks),
+            conn_requests,
         });
     }
 
     if !cfg.swap_symbols.is_empty() {
+        let subscription = json_parser::subscription(true, &cfg.swap_depth_channel)
+            .ok_or_else(|| anyhow!("[okx] unknown swap depth_channel {:?}", cfg.swap_depth_channel))?;
+        let ticks = TickSizes::from_registry(instruments, Exchange::Okx, ProductType::Futures);
+        let (parser, conn_requests) =
+            parser("okx_swap", ProductType::Futures, &cfg.swap_depth_channel, cfg.swap_depth_n_levels, ticks.clone());
         streams.push(StreamDef {
             label: "okx_swap".into(),
             ws_url: OKX_WS_URL.into(),
-            subscribe_msg: json_parser::build_swap_subscribe(&cfg.swap_symbols),
+            subscribe_msg: (subscription.subscribe)(&cfg.swap_symbols),
+            subscription: Some(subscription),
             ping: Some(ping.clone()),
             extra_headers: Default::default(),
+            proxy: cfg.swap_proxy.clone(),
+            local_binds: cfg.swap_local_binds.clone(),
+            conn_count: cfg.swap_conn_count,
             shm: ShmNames {
                 bbo: cfg.swap_bbo_shm_name.clone(),
                 trade: cfg.swap_trade_shm_name.clone(),
                 depth5: cfg.swap_depth5_shm_name.clone(),
+                depth_n: cfg.swap_depth_n_shm_name.clone().filter(|_| conn_requests.is_some()),
                 ..Default::default()
             },
             symbols: cfg.swap_symbols.clone(),
+            product_type: ProductType::Futures,
             md_size: cfg.md_size,
-            text_parser: Some(Box::new(|data| json_parser::parse_message(data).into_iter().collect())),
+            text_parser: Some(parser),
             binary_parser: None,
             custom_trade_dedup: None,
             dedup_cpu_core: None,
+            busy_poll: None,
+            gap_tolerance: GapTolerance::default(),
+            gap_hook: None,
+            trade_backfill: backfill.clone(),
+            background: Vec::new(),
+            ticks: conn_requests.is_some().then_some(ticks),
+            conn_requests,
         });
     }
 
     Ok(streams)
 }
+
+/// Parser for a stream subscribed to `depth_channel`, and for full-book
+/// channels the receiver of its book resubscriptions.
+pub(crate) fn parser(
+    label: &'static str,
+    product_type: ProductType,
+    depth_channel: &str,
+    depth_n_levels: Option<usize>,
+    ticks: TickSizes,
+) -> (TextParser, Option<mpsc::UnboundedReceiver<String>>) {
+    if depth_channel == "books5" {
+        return (Box::new(|data, out| out.extend(json_parser::parse_message(data))), None);
+    }
+    let max_levels = if depth_channel == "books50-l2-tbt" { 50 } else { 400 };
+    let channel = depth_channel.to_string();
+    let resubscribe = Box::new(move |inst_id: &str| json_parser::build_book_resubscribe(&channel, inst_id));
+    let (books, requests) = ChecksumBooks::new(label, product_type, max_levels, depth_n_levels, ticks, resubscribe);
+    (Box::new(move |data, out| json_parser::parse_message_with_books(data, &books, out)), Some(requests))
+}
@@ -0,0 +1,487 @@
+//! Incremental order books keyed on integer price ticks.
+//!
+//! Incremental depth feeds (Bybit `orderbook.{50,200,1000}`, Binance diff
+//! depth, OKX and Bitget full books) send an initial snapshot followed by
+//! delta messages that add, update, or remove levels. Prices are converted
+//! to whole ticks of the instrument's tick size (see [`TickSizes`]), so a
+//! level is found by exact integer key rather than by float comparison, and
+//! each side is a `BTreeMap` from ticks to level: O(log N) per level update,
+//! best level first when iterated.
+//!
+//! [`BookSide`] is the building block, generic over what a level holds.
+//! [`OrderBook`] pairs two sides of `[price, volume]` levels and can extract
+//! the top 5 levels as a [`Depth5`]-compatible tuple, or more into a
+//! [`DepthN`].
+//!
+//! [`Depth5`]: k4_core::types::Depth5
+
+use std::{
+    collections::BTreeMap,
+    sync::{Arc, Mutex, RwLock},
+};
+
+use ahash::{AHashMap, AHashSet};
+use k4_core::{
+    instrument::InstrumentRegistry,
+    types::{DepthN, Exchange, MAX_DEPTH_LEVELS, ProductType},
+};
+use tracing::warn;
+
+/// An instrument's price increment, converting prices to integer ticks.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TickSize(f64);
+
+impl TickSize {
+    /// Grid for instruments whose tick size is not known: fine enough that
+    /// distinct quoted prices never share a tick, coarse enough that prices
+    /// up to 10^9 fit in an `i64`.
+    pub const FALLBACK: Self = Self(1e-9);
+
+    /// `None` unless `tick_size` is positive and finite.
+    pub fn new(tick_size: f64) -> Option<Self> {
+        (tick_size.is_finite() && tick_size > 0.0).then_some(Self(tick_size))
+    }
+
+    /// The increment itself.
+    pub fn get(self) -> f64 {
+        self.0
+    }
+
+    /// `price` in whole ticks, rounded to the nearest, so that float noise
+    /// (`0.1 + 0.2` against `0.3`) lands on the same level.
+    #[inline]
+    pub fn ticks(self, price: f64) -> i64 {
+        (price / self.0).round() as i64
+    }
+}
+
+impl Default for TickSize {
+    fn default() -> Self {
+        Self::FALLBACK
+    }
+}
+
+/// Tick sizes of one stream's instruments, by venue symbol.
+///
+/// Clones share the sizes, so the pipeline can add those of symbols
+/// subscribed at runtime (see [`update`](Self::update)) to the copy a
+/// parser's books hold. Sizes are only looked up when a book is created.
+#[derive(Debug, Clone, Default)]
+pub struct TickSizes {
+    /// Venue and product the sizes are taken from (`None` = none known).
+    market: Option<(Exchange, ProductType)>,
+    ticks: Arc<RwLock<AHashMap<String, TickSize>>>,
+    /// Symbols already warned about falling back to [`TickSize::FALLBACK`].
+    warned: Arc<Mutex<AHashSet<String>>>,
+}
+
+impl TickSizes {
+    /// The tick sizes of `exchange`'s `product_type` instruments in
+    /// `instruments`.
+    pub fn from_registry(instruments: &InstrumentRegistry, exchange: Exchange, product_type: ProductType) -> Self {
+        let sizes = Self { market: Some((exchange, product_type)), ..Self::default() };
+        sizes.update(instruments);
+        sizes
+    }
+
+    /// Venue and product the sizes are taken from.
+    pub fn market(&self) -> Option<(Exchange, ProductType)> {
+        self.market
+    }
+
+    /// Whether `symbol`'s tick size is known.
+    pub fn contains(&self, symbol: &str) -> bool {
+        self.ticks.read().unwrap().contains_key(symbol)
+    }
+
+    /// Take the tick sizes of this market's instruments in `instruments`,
+    /// including any listed since the sizes were created.
+    pub fn update(&self, instruments: &InstrumentRegistry) {
+        let Some((exchange, product_type)) = self.market else { return };
+        let mut ticks = self.ticks.write().unwrap();
+        ticks.extend(
+            instruments
+                .iter()
+                .filter(|(_, i)| i.exchange == exchange && i.product_type == product_type)
+                .filter_map(|(_, i)| Some((i.symbol.clone(), TickSize::new(i.tick_size)?))),
+        );
+    }
+
+    /// `symbol`'s tick size, or [`TickSize::FALLBACK`] (with a warning, once
+    /// per symbol) if it is not known.
+    pub fn get(&self, symbol: &str) -> TickSize {
+        if let Some(&tick) = self.ticks.read().unwrap().get(symbol) {
+            return tick;
+        }
+        if self.warned.lock().unwrap().insert(symbol.to_string()) {
+            warn!("{symbol}: tick size unknown, keeping its book on a {:e} price grid", TickSize::FALLBACK.get());
+        }
+        TickSize::FALLBACK
+    }
+}
+
+/// One side of a book: levels keyed by price ticks, iterated best first.
+#[derive(Debug, Clone)]
+pub struct BookSide<L> {
+    /// Bids are keyed by negated ticks, so that ascending keys run from the
+    /// best level to the worst on both sides.
+    levels: BTreeMap<i64, L>,
+    sign: i64,
+}
+
+impl<L> BookSide<L> {
+    /// An empty bid side (best = highest price).
+    pub fn bids() -> Self {
+        Self { levels: BTreeMap::new(), sign: -1 }
+    }
+
+    /// An empty ask side (best = lowest price).
+    pub fn asks() -> Self {
+        Self { levels: BTreeMap::new(), sign: 1 }
+    }
+
+    /// The level at `ticks`.
+    pub fn get_mut(&mut self, ticks: i64) -> Option<&mut L> {
+        self.levels.get_mut(&(ticks * self.sign))
+    }
+
+    /// Insert the level at `ticks`, replacing any already there.
+    pub fn insert(&mut self, ticks: i64, level: L) {
+        self.levels.insert(ticks * self.sign, level);
+    }
+
+    /// Remove the level at `ticks`, if any.
+    pub fn remove(&mut self, ticks: i64) -> Option<L> {
+        self.levels.remove(&(ticks * self.sign))
+    }
+
+    /// Drop the worst levels beyond the best `max_levels`.
+    pub fn truncate(&mut self, max_levels: usize) {
+        while self.levels.len() > max_levels {
+            self.levels.pop_last();
+        }
+    }
+
+    /// The best level.
+    pub fn best(&self) -> Option<&L> {
+        self.levels.values().next()
+    }
+
+    /// Levels, best first.
+    pub fn iter(&self) -> impl Iterator<Item = &L> {
+        self.levels.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    pub fn clear(&mut self) {
+        self.levels.clear();
+    }
+}
+
+/// Incremental order book of `[price, volume]` levels, keeping up to
+/// `max_levels` per side.
+///
+/// Levels keep the price as received, so published prices are exactly the
+/// exchange's, whatever the tick grid.
+#[derive(Debug, Clone)]
+pub struct OrderBook {
+    tick: TickSize,
+    max_levels: usize,
+    bids: BookSide<[f64; 2]>,
+    asks: BookSide<[f64; 2]>,
+}
+
+impl OrderBook {
+    /// An empty book on `tick`'s grid. `max_levels` is the depth the feed
+    /// maintains (e.g. 200 for Bybit `orderbook.200`, the REST snapshot
+    /// limit for Binance diff depth); levels beyond it are dropped.
+    pub fn new(tick: TickSize, max_levels: usize) -> Self {
+        Self { tick, max_levels, bids: BookSide::bids(), asks: BookSide::asks() }
+    }
+
+    /// Replace the entire book with a snapshot of `[price, volume]` levels,
+    /// in any order.
+    pub fn set_snapshot(&mut self, bids: &[[f64; 2]], asks: &[[f64; 2]]) {
+        self.bids.clear();
+        self.asks.clear();
+        self.update(bids, asks);
+    }
+
+    /// Apply an incremental delta to the book.
+    ///
+    /// For each `[price, volume]` pair, a zero volume **removes** the level
+    /// at that price; otherwise the level is **inserted** or its volume
+    /// **updated**. A side that grows beyond `max_levels` drops its worst
+    /// level (highest ask / lowest bid).
+    pub fn update(&mut self, bids: &[[f64; 2]], asks: &[[f64; 2]]) {
+        for &level in bids {
+            apply(&mut self.bids, self.tick, self.max_levels, level);
+        }
+        for &level in asks {
+            apply(&mut self.asks, self.tick, self.max_levels, level);
+        }
+    }
+
+    /// Bid levels, highest price first.
+    pub fn bids(&self) -> &BookSide<[f64; 2]> {
+        &self.bids
+    }
+
+    /// Ask levels, lowest price first.
+    pub fn asks(&self) -> &BookSide<[f64; 2]> {
+        &self.asks
+    }
+
+    /// Extract the top 5 levels from each side.
+    ///
+    /// Returns `(bid_prices, bid_vols, ask_prices, ask_vols, bid_level, ask_level)`.
+    pub fn get_depth5(&self) -> ([f64; 5], [f64; 5], [f64; 5], [f64; 5], u32, u32) {
+        let mut bid_prices = [0.0f64; 5];
+        let mut bid_vols = [0.0f64; 5];
+        let mut ask_prices = [0.0f64; 5];
+        let mut ask_vols = [0.0f64; 5];
+
+        let bid_level = fill_side(&self.bids, &mut bid_prices, &mut bid_vols);
+        let ask_level = fill_side(&self.asks, &mut ask_prices, &mut ask_vols);
+
+        (bid_prices, bid_vols, ask_prices, ask_vols, bid_level, ask_level)
+    }
+
+    /// Copy the top `levels` levels of each side into `depth`, setting
+    /// `bid_level` / `ask_level` to the number copied.
+    pub fn fill_depth_n(&self, depth: &mut DepthN, levels: usize) {
+        let n = levels.min(MAX_DEPTH_LEVELS);
+        depth.bid_level = fill_side(&self.bids, &mut depth.bid_prices[..n], &mut depth.bid_vols[..n]);
+        depth.ask_level = fill_side(&self.asks, &mut depth.ask_prices[..n], &mut depth.ask_vols[..n]);
+    }
+
+    /// Returns `true` if the book has no levels on either side.
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Helpers
+// ---------------------------------------------------------------------------
+
+/// Insert, update or (zero volume) remove one level of `side`.
+#[inline]
+fn apply(side: &mut BookSide<[f64; 2]>, tick: TickSize, max_levels: usize, [price, vol]: [f64; 2]) {
+    let ticks = tick.ticks(price);
+    if vol > 0.0 {
+        side.insert(ticks, [price, vol]);
+        side.truncate(max_levels);
+    } else {
+        side.remove(ticks);
+    }
+}
+
+/// Copy the best levels of `side` into `prices` / `vols`, returning how many.
+fn fill_side(side: &BookSide<[f64; 2]>, prices: &mut [f64], vols: &mut [f64]) -> u32 {
+    let mut n = 0;
+    for (i, &[price, vol]) in side.iter().take(prices.len()).enumerate() {
+        prices[i] = price;
+        vols[i] = vol;
+        n += 1;
+    }
+    n
+}
+
+#[cfg(test)]
+mod tests {
+    use k4_core::instrument::{Instrument, InstrumentKind};
+    use proptest::prelude::*;
+
+    use super::*;
+
+    #[test]
+    fn snapshot_and_depth5() {
+        let mut book = OrderBook::new(TickSize::FALLBACK, 50);
+        book.set_snapshot(
+            &[[98.0, 3.0], [100.0, 1.0], [99.0, 2.0], [97.0, 4.0], [96.0, 5.0], [95.0, 6.0]],
+            &[[101.0, 1.0], [102.0, 2.0], [103.0, 3.0], [104.0, 4.0], [105.0, 5.0], [106.0, 6.0]],
+        );
+
+        let (bp, bv, ap, av, bl, al) = book.get_depth5();
+        assert_eq!((bl, al), (5, 5));
+        assert_eq!(bp, [100.0, 99.0, 98.0, 97.0, 96.0]);
+        assert_eq!((ap[0], bv[0], av[0]), (101.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn incremental_update() {
+        let mut book = OrderBook::new(TickSize::FALLBACK, 50);
+        book.set_snapshot(&[[100.0, 1.0], [99.0, 2.0]], &[[101.0, 1.0], [102.0, 2.0]]);
+
+        // Update: change volume at 100.0, add new level at 100.5
+        book.update(&[[100.0, 5.0], [100.5, 3.0]], &[]);
+
+        let (bp, bv, _, _, bl, _) = book.get_depth5();
+        assert_eq!(bl, 3);
+        // Best bid should be 100.5 (newly inserted), then 100.0 (updated volume)
+        assert_eq!((bp[0], bv[0]), (100.5, 3.0));
+        assert_eq!((bp[1], bv[1]), (100.0, 5.0));
+    }
+
+    #[test]
+    fn remove_level() {
+        let mut book = OrderBook::new(TickSize::FALLBACK, 50);
+        book.set_snapshot(&[[100.0, 1.0], [99.0, 2.0]], &[[101.0, 1.0]]);
+
+        // Remove bid at 100.0 (vol=0)
+        book.update(&[[100.0, 0.0]], &[]);
+
+        let (bp, bv, _, _, bl, _) = book.get_depth5();
+        assert_eq!(bl, 1);
+        assert_eq!((bp[0], bv[0]), (99.0, 2.0));
+    }
+
+    #[test]
+    fn prices_on_the_same_tick_share_a_level() {
+        let mut book = OrderBook::new(TickSize::new(0.1).unwrap(), 50);
+        book.set_snapshot(&[[0.3, 1.0]], &[]);
+        book.update(&[[0.1 + 0.2, 4.0]], &[]);
+        assert_eq!(book.bids().len(), 1);
+        assert_eq!(book.bids().best(), Some(&[0.1 + 0.2, 4.0]));
+        book.update(&[[0.30000001, 0.0]], &[]);
+        assert!(book.is_empty());
+    }
+
+    #[test]
+    fn keeps_the_best_max_levels() {
+        let mut book = OrderBook::new(TickSize::new(0.5).unwrap(), 2);
+        book.set_snapshot(&[[10.0, 1.0], [11.0, 1.0], [12.0, 1.0]], &[[13.0, 1.0], [15.0, 1.0], [14.0, 1.0]]);
+        book.update(&[[9.5, 1.0]], &[[12.5, 1.0]]);
+        assert_eq!(book.bids().iter().map(|l| l[0]).collect::<Vec<_>>(), [12.0, 11.0]);
+        assert_eq!(book.asks().iter().map(|l| l[0]).collect::<Vec<_>>(), [12.5, 13.0]);
+    }
+
+    #[test]
+    fn fill_depth_n_beyond_five_levels() {
+        let mut book = OrderBook::new(TickSize::new(1.0).unwrap(), 50);
+        let bids: Vec<[f64; 2]> = (0..8).map(|i| [100.0 - i as f64, 1.0]).collect();
+        book.set_snapshot(&bids, &[[101.0, 2.0]]);
+
+        let mut depth = DepthN::default();
+        book.fill_depth_n(&mut depth, 7);
+
+        assert_eq!((depth.bid_level, depth.ask_level), (7, 1));
+        assert_eq!(depth.bid_prices[6], 94.0);
+        assert_eq!(depth.bid_prices[7], 0.0);
+        assert_eq!((depth.ask_prices[0], depth.ask_vols[0]), (101.0, 2.0));
+    }
+
+    #[test]
+    fn tick_sizes_come_from_the_registry() {
+        let mut instruments = InstrumentRegistry::new();
+        instruments.insert(Instrument {
+            exchange: Exchange::Bybit,
+            product_type: ProductType::Futures,
+            kind: InstrumentKind::Perpetual,
+            symbol: "BTCUSDT".into(),
+            base: "BTC".into(),
+            quote: "USDT".into(),
+            settle: "USDT".into(),
+            tick_size: 0.1,
+            lot_size: 0.001,
+            min_qty: 0.001,
+            contract_multiplier: 1.0,
+            expiry_ms: None,
+            trading: true,
+        });
+        let ticks = TickSizes::from_registry(&instruments, Exchange::Bybit, ProductType::Futures);
+        assert_eq!(ticks.get("BTCUSDT"), TickSize::new(0.1).unwrap());
+        assert_eq!(ticks.get("ETHUSDT"), TickSize::FALLBACK);
+        assert_eq!(ticks.get("ETHUSDT"), TickSize::FALLBACK);
+        assert_eq!(*ticks.warned.lock().unwrap(), AHashSet::from_iter(["ETHUSDT".to_string()]), "warned once");
+        let spot = TickSizes::from_registry(&instruments, Exchange::Bybit, ProductType::Spot);
+        assert_eq!(spot.get("BTCUSDT"), TickSize::FALLBACK);
+
+        // Instruments listed later reach every clone.
+        let shared = ticks.clone();
+        let btc = instruments.iter().next().unwrap().1.clone();
+        instruments.insert(Instrument { symbol: "ETHUSDT".into(), tick_size: 0.01, ..btc });
+        ticks.update(&instruments);
+        assert!(shared.contains("ETHUSDT"));
+        assert_eq!(shared.get("ETHUSDT"), TickSize::new(0.01).unwrap());
+        assert!(TickSize::new(0.0).is_none());
+    }
+
+    /// Reference model: each side a `BTreeMap` from ticks to volume.
+    #[derive(Default)]
+    struct Model {
+        bids: BTreeMap<i64, f64>,
+        asks: BTreeMap<i64, f64>,
+    }
+
+    impl Model {
+        fn apply(&mut self, max_levels: usize, bid: bool, ticks: i64, vol: f64) {
+            let side = if bid { &mut self.bids } else { &mut self.asks };
+            if vol > 0.0 {
+                side.insert(ticks, vol);
+            } else {
+                side.remove(&ticks);
+            }
+            while side.len() > max_levels {
+                // The worst level: lowest bid, highest ask.
+                if bid {
+                    side.pop_first()
+                } else {
+                    side.pop_last()
+                };
+            }
+        }
+    }
+
+    /// `(is_bid, ticks, volume)`, volume 0 in a quarter of the levels.
+    fn level() -> impl Strategy<Value = (bool, i64, f64)> {
+        (any::<bool>(), 1_000i64..1_100, prop_oneof![1 => Just(0.0), 3 => 0.001f64..100.0])
+    }
+
+    proptest! {
+        #[test]
+        fn matches_btreemap_model(
+            tick in prop::sample::select(vec![1e-8, 0.01, 0.1, 0.5, 25.0]),
+            max_levels in 1usize..40,
+            batches in prop::collection::vec(
+                (prop::bool::weighted(0.15), prop::collection::vec(level(), 0..30)),
+                1..40,
+            ),
+        ) {
+            let tick_size = TickSize::new(tick).unwrap();
+            let mut book = OrderBook::new(tick_size, max_levels);
+            let mut model = Model::default();
+            for (snapshot, levels) in batches {
+                // Prices carry the float noise of `ticks * tick`.
+                let side = |bid: bool| -> Vec<[f64; 2]> {
+                    levels.iter().filter(|l| l.0 == bid).map(|&(_, t, vol)| [t as f64 * tick, vol]).collect()
+                };
+                if snapshot {
+                    book.set_snapshot(&side(true), &side(false));
+                    model = Model::default();
+                } else {
+                    book.update(&side(true), &side(false));
+                }
+                for &(bid, ticks, vol) in &levels {
+                    model.apply(max_levels, bid, ticks, vol);
+                }
+
+                let ticks_of = |side: &BookSide<[f64; 2]>| -> Vec<(i64, f64)> {
+                    side.iter().map(|&[price, vol]| (tick_size.ticks(price), vol)).collect()
+                };
+                let bids: Vec<(i64, f64)> = model.bids.iter().rev().map(|(&t, &v)| (t, v)).collect();
+                let asks: Vec<(i64, f64)> = model.asks.iter().map(|(&t, &v)| (t, v)).collect();
+                prop_assert_eq!(ticks_of(book.bids()), bids);
+                prop_assert_eq!(ticks_of(book.asks()), asks);
+            }
+        }
+    }
+}
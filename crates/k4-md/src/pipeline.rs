@@ -11,18 +11,46 @@
 //! ```text
 //! StreamDef ──► GenericMd.init_shm()  ──► ShmMdStore per stream
 //!          ──► GenericMd.start()      ──► [channel + dedup task + WS task] per stream
-//!          ──► GenericMd.stop()       ──► abort all tasks
+//!                                         or [busy-poll thread] per busy-polled stream
+//!          ──► GenericMd.subscribe()  ──► claim SHM slots + (un)subscribe on live connections
+//!          ──► GenericMd.stop()       ──► abort all tasks, stop busy-poll threads
 //! ```
 
-use std::{collections::HashMap, sync::Arc, time::Duration};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    pin::Pin,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
 
-use anyhow::Result;
+use anyhow::{Result, bail};
 use async_trait::async_trait;
-use k4_core::{shm::ShmMdStore, types::*, udp::UdpSender, ws::PingPayload};
-use tracing::info;
+use k4_core::{
+    config::{BusyPollConfig, UdpSenderConfig},
+    instrument::InstrumentRegistry,
+    shm::ShmMdStore,
+    types::*,
+    udp::{UdpSender, UdpSenderOptions},
+    ws::{
+        BinaryCompression, DEFAULT_MAX_MESSAGE_SIZE, EVENT_CAPACITY, LocalBind, PingPayload, PongMatcher, ProxyConfig,
+        ReconnectPolicy, WsConnStats, WsEvent, WsStats,
+    },
+};
+use tokio::sync::{broadcast, mpsc};
+use tracing::{info, warn};
 
 use crate::{
-    dedup_worker::{self, ProductShmStores, TradeDeduper},
+    backfill::{self, TradeFetcher},
+    busy_poll::{self, StreamParser},
+    dedup_worker::{self, Deduper, ProductShmStores, TradeDeduper},
+    discovery::InstrumentSource,
+    order_book::TickSizes,
+    seq_gap::{GapCount, GapHook, GapMonitor, GapStats, GapTolerance},
+    subscription::{ConnRequest, Resubscribe, SubscriptionMsgs, SymbolChange},
     ws_helper,
 };
 
@@ -30,14 +58,20 @@ use crate::{
 // StreamDef — describes one WS-to-SHM pipeline
 // ---------------------------------------------------------------------------
 
-/// A text message parser: `raw_bytes -> Vec<MarketDataMsg>`.
+/// A text message parser: `(raw_bytes, out)`, pushing zero or more
+/// [`MarketDataMsg`] onto `out`.
 ///
 /// Accepts `&mut [u8]` so that `simd-json` can perform in-place SIMD parsing.
-/// The caller is responsible for providing a mutable copy of the raw text.
-pub type TextParser = Box<dyn Fn(&mut [u8]) -> Vec<MarketDataMsg> + Send + Sync>;
+/// The caller provides a mutable copy of the raw text and the output `Vec`;
+/// both are reused across messages so the hot path does not allocate.
+pub type TextParser = Box<dyn Fn(&mut [u8], &mut Vec<MarketDataMsg>) + Send + Sync>;
+
+/// A binary message parser: `(raw_bytes, out)`, pushing onto `out`.
+pub type BinaryParser = Box<dyn Fn(&[u8], &mut Vec<MarketDataMsg>) + Send + Sync>;
 
-/// A binary message parser: `raw_bytes -> Vec<MarketDataMsg>`.
-pub type BinaryParser = Box<dyn Fn(&[u8]) -> Vec<MarketDataMsg> + Send + Sync>;
+/// A task a stream's parser relies on (e.g. fetching order book snapshots),
+/// spawned when the stream starts and aborted when it stops.
+pub type BackgroundTask = Pin<Box<dyn Future<Output = ()> + Send>>;
 
 /// SHM store names for one stream. `None` means "don't create this store".
 #[derive(Debug, Clone, Default)]
@@ -46,6 +80,7 @@ pub struct ShmNames {
     pub agg: Option<String>,
     pub trade: Option<String>,
     pub depth5: Option<String>,
+    pub depth_n: Option<String>,
 }
 
 /// Ping / keep-alive configuration for a WebSocket connection.
@@ -53,6 +88,10 @@ pub struct ShmNames {
 pub struct PingConfig {
     pub interval: Duration,
     pub payload: PingPayload,
+    /// Recognises the exchange's reply to `payload` (for RTT and liveness).
+    pub pong: Option<PongMatcher>,
+    /// Reconnect when a ping goes unanswered this long.
+    pub pong_timeout: Option<Duration>,
 }
 
 /// Everything needed to set up one WS-to-SHM pipeline.
@@ -66,14 +105,28 @@ pub struct StreamDef {
     pub ws_url: String,
     /// Subscription message sent immediately after WS connect.
     pub subscribe_msg: String,
+    /// Request builders for adding and removing symbols at runtime (`None` =
+    /// symbols are fixed).
+    pub subscription: Option<SubscriptionMsgs>,
     /// Ping configuration (exchange-specific format and interval).
     pub ping: Option<PingConfig>,
     /// Extra HTTP headers for the WS handshake (e.g. API key).
     pub extra_headers: HashMap<String, String>,
+    /// Egress proxy for the WS connection (`None` = direct).
+    pub proxy: Option<ProxyConfig>,
+    /// Local source IPs / interfaces for the WS connections. Each redundant
+    /// connection starts on the next one, round-robin; the rest remain its
+    /// failover paths.
+    pub local_binds: Vec<LocalBind>,
+    /// Redundant WS connections carrying the same subscription (at least 1).
+    pub conn_count: u32,
     /// SHM store names — controls which data types are persisted.
     pub shm: ShmNames,
     /// Symbols this stream covers (used for SHM store creation).
     pub symbols: Vec<String>,
+    /// Product the symbols belong to; selects the stream for runtime
+    /// (un)subscription.
+    pub product_type: ProductType,
     /// Ring buffer size per symbol in SHM.
     pub md_size: u32,
     /// Text (JSON) message parser. Most exchanges use this.
@@ -84,6 +137,24 @@ pub struct StreamDef {
     pub custom_trade_dedup: Option<TradeDeduper>,
     /// CPU core to pin the dedup thread to.
     pub dedup_cpu_core: Option<i32>,
+    /// Run this stream on a dedicated busy-polling thread (see
+    /// [`busy_poll`](crate::busy_poll)) instead of the tokio runtime.
+    pub busy_poll: Option<BusyPollConfig>,
+    /// Per-type ID steps beyond which a jump counts as a sequence gap.
+    pub gap_tolerance: GapTolerance,
+    /// Called for every detected sequence gap (e.g. to start a backfill).
+    pub gap_hook: Option<GapHook>,
+    /// Fetches trades missed in a trade/aggTrade gap over REST (see
+    /// [`backfill`](crate::backfill)).
+    pub trade_backfill: Option<Arc<dyn TradeFetcher>>,
+    /// Tasks spawned on the tokio runtime when the stream starts.
+    pub background: Vec<BackgroundTask>,
+    /// Requests the parser wants sent on the live connections (e.g.
+    /// resubscribing a channel whose book failed its checksum).
+    pub conn_requests: Option<mpsc::UnboundedReceiver<String>>,
+    /// Tick sizes the parser's local books are keyed on, if it keeps any;
+    /// extended with the instruments of symbols subscribed at runtime.
+    pub ticks: Option<TickSizes>,
 }
 
 // ---------------------------------------------------------------------------
@@ -98,8 +169,55 @@ pub struct GenericMd {
     name: String,
     streams: Vec<StreamDef>,
     stores: Vec<Option<ProductShmStores>>,
+    udp_config: Option<(UdpSenderConfig, Option<Exchange>)>,
     udp: Option<Arc<UdpSender>>,
+    reconnect: ReconnectPolicy,
+    permessage_deflate: bool,
+    binary_compression: Option<BinaryCompression>,
+    max_message_size: usize,
+    redundancy: ws_helper::Redundancy,
+    events: broadcast::Sender<WsEvent>,
+    /// Stats handle of each started connection, by stream label and slot.
+    ws_stats: Vec<(String, WsStats)>,
+    ws_stats_interval: Option<Duration>,
+    backfill_max_trades: u64,
     tasks: Vec<tokio::task::JoinHandle<()>>,
+    /// Sequence-gap counters of each started stream, by label.
+    gap_stats: Vec<(String, GapStats)>,
+    /// Busy-poll threads and their stop flags; they are not tokio tasks, so
+    /// aborting cannot stop them.
+    busy_polls: Vec<(Arc<AtomicBool>, std::thread::JoinHandle<()>)>,
+    /// Free SHM slots per store for symbols added at runtime.
+    spare_symbol_slots: usize,
+    /// Started streams that accept runtime symbol changes.
+    controls: Vec<StreamControl>,
+    /// Contract specs of the module's exchange, extended with those of
+    /// symbols subscribed at runtime.
+    instruments: InstrumentRegistry,
+    /// Lists the exchange's instruments when a symbol subscribed at runtime
+    /// is not in `instruments`.
+    instrument_source: Option<Arc<dyn InstrumentSource>>,
+}
+
+/// Handles for changing the symbols of one started stream.
+struct StreamControl {
+    label: String,
+    product_type: ProductType,
+    msgs: SubscriptionMsgs,
+    /// Currently subscribed symbols, in the stream's own form.
+    symbols: Vec<String>,
+    /// Most symbols the SHM stores have slots for.
+    capacity: usize,
+    dedup: crossbeam_channel::Sender<SymbolChange>,
+    ws: mpsc::UnboundedSender<ConnRequest>,
+    ticks: Option<TickSizes>,
+}
+
+impl StreamControl {
+    /// The request to send on (re)connect for the current symbols.
+    fn on_connect(&self) -> Option<String> {
+        (!self.symbols.is_empty()).then(|| (self.msgs.subscribe)(&self.symbols))
+    }
 }
 
 impl GenericMd {
@@ -109,7 +227,179 @@ impl GenericMd {
     /// `binance::build()`, `okx::build()`, etc.
     pub fn new(name: String, streams: Vec<StreamDef>) -> Self {
         let n = streams.len();
-        Self { name, streams, stores: (0..n).map(|_| None).collect(), udp: None, tasks: Vec::new() }
+        Self {
+            name,
+            streams,
+            stores: (0..n).map(|_| None).collect(),
+            udp_config: None,
+            udp: None,
+            reconnect: ReconnectPolicy::default(),
+            permessage_deflate: false,
+            binary_compression: None,
+            max_message_size: DEFAULT_MAX_MESSAGE_SIZE,
+            redundancy: ws_helper::Redundancy::default(),
+            events: broadcast::channel(EVENT_CAPACITY).0,
+            ws_stats: Vec::new(),
+            ws_stats_interval: None,
+            backfill_max_trades: backfill::DEFAULT_MAX_TRADES_PER_GAP,
+            tasks: Vec::new(),
+            gap_stats: Vec::new(),
+            busy_polls: Vec::new(),
+            spare_symbol_slots: 0,
+            controls: Vec::new(),
+            instruments: InstrumentRegistry::new(),
+            instrument_source: None,
+        }
+    }
+
+    /// Reserve this many free slots in every SHM store for symbols
+    /// subscribed at runtime.
+    pub fn with_spare_symbol_slots(mut self, spare: Option<usize>) -> Self {
+        self.spare_symbol_slots = spare.unwrap_or(0);
+        self
+    }
+
+    /// Resolve the tick sizes of symbols subscribed at runtime as at
+    /// startup: from `instruments`, refreshed from the exchange's instrument
+    /// list via `source` when a symbol is missing from it.
+    pub fn with_instruments(
+        mut self,
+        instruments: InstrumentRegistry,
+        source: Option<Arc<dyn InstrumentSource>>,
+    ) -> Self {
+        self.instruments = instruments;
+        self.instrument_source = source;
+        self
+    }
+
+    /// Most trades backfilled per sequence gap.
+    pub fn with_backfill_max_trades(mut self, max_trades: Option<u64>) -> Self {
+        self.backfill_max_trades = max_trades.unwrap_or(backfill::DEFAULT_MAX_TRADES_PER_GAP);
+        self
+    }
+
+    /// Override the exchange's sequence-gap tolerances for every stream of
+    /// this module; a step of `0` disables the check for that type.
+    pub fn with_seq_gap_tolerance(mut self, overrides: Option<HashMap<MessageType, u64>>) -> Self {
+        if let Some(overrides) = overrides {
+            for stream in &mut self.streams {
+                stream.gap_tolerance = stream.gap_tolerance.with_overrides(&overrides);
+            }
+        }
+        self
+    }
+
+    /// Busy-poll the streams named in `streams` (by label). Labels that
+    /// match no stream are logged and ignored; a stream that connects
+    /// through a proxy or local binds, or with permessage-deflate (set by
+    /// [`with_permessage_deflate`](Self::with_permessage_deflate) first),
+    /// cannot be polled and is an error.
+    pub fn with_busy_poll(mut self, streams: Option<HashMap<String, BusyPollConfig>>) -> Result<Self> {
+        for (label, cfg) in streams.unwrap_or_default() {
+            match self.streams.iter_mut().find(|s| s.label == label) {
+                Some(_) if self.permessage_deflate => {
+                    bail!(
+                        "[{}] busy_poll: stream {label:?} would use permessage-deflate, which polling does not support",
+                        self.name
+                    )
+                }
+                Some(stream) if stream.proxy.is_some() || !stream.local_binds.is_empty() => {
+                    bail!(
+                        "[{}] busy_poll: stream {label:?} uses a proxy or local binds, which polling does not support",
+                        self.name
+                    )
+                }
+                Some(stream) => stream.busy_poll = Some(cfg),
+                None => warn!("[{}] busy_poll: no stream labelled {label:?}", self.name),
+            }
+        }
+        Ok(self)
+    }
+
+    /// Log every stream's connection stats and sequence gaps at this interval.
+    pub fn with_ws_stats_interval(mut self, interval: Option<Duration>) -> Self {
+        self.ws_stats_interval = interval;
+        self
+    }
+
+    /// Offer permessage-deflate on every WebSocket stream of this module.
+    pub fn with_permessage_deflate(mut self, enabled: bool) -> Self {
+        self.permessage_deflate = enabled;
+        self
+    }
+
+    /// Inflate binary frames compressed this way on every WebSocket stream
+    /// of this module.
+    pub fn with_binary_compression(mut self, compression: Option<BinaryCompression>) -> Self {
+        self.binary_compression = compression;
+        self
+    }
+
+    /// Largest frame or (inflated) message accepted on every WebSocket
+    /// stream of this module.
+    pub fn with_max_message_size(mut self, max: Option<usize>) -> Self {
+        self.max_message_size = max.unwrap_or(DEFAULT_MAX_MESSAGE_SIZE);
+        self
+    }
+
+    /// Evaluate every stream's redundant connections at `interval`,
+    /// replacing an unhealthy one and, with `reset_slowest`, the slowest.
+    pub fn with_heartbeat(mut self, interval: Option<Duration>, reset_slowest: bool) -> Self {
+        self.redundancy.hb_interval = interval;
+        self.redundancy.reset_on_hb = reset_slowest;
+        self
+    }
+
+    /// Pin each stream's redundant connections to distinct IPs of its host,
+    /// re-resolving the host at most every `refresh` (`None` = no pinning).
+    pub fn with_dns_refresh(mut self, refresh: Option<Duration>) -> Self {
+        self.redundancy.dns_refresh = refresh;
+        self
+    }
+
+    /// Count a connection silent this long as unhealthy while another of
+    /// its stream is healthy (`None` = only disconnects count).
+    pub fn with_stale_after(mut self, stale_after: Option<Duration>) -> Self {
+        self.redundancy.stale_after = stale_after;
+        self
+    }
+
+    /// Reconnect policy for every WebSocket stream of this module.
+    pub fn with_reconnect(mut self, policy: ReconnectPolicy) -> Self {
+        self.reconnect = policy;
+        self
+    }
+
+    /// Forward deduplicated data over UDP if `config` is present and enabled.
+    ///
+    /// `exchange` is stamped into every packet so receivers can route by it.
+    /// The sender is created in [`start`](crate::MdModule::start).
+    pub fn with_udp_sender(mut self, config: Option<UdpSenderConfig>, exchange: Option<Exchange>) -> Self {
+        self.udp_config = config.filter(|c| c.is_enabled()).map(|c| (c, exchange));
+        self
+    }
+
+    /// Give the local books of the streams gaining `changes` the tick sizes
+    /// of their new symbols, listing the exchange's instruments again first
+    /// if the registry lacks any, as startup discovery does. Symbols that
+    /// stay unknown fall back to a fine price grid, as at startup.
+    async fn resolve_tick_sizes(&mut self, product_type: ProductType, changes: &[(usize, Vec<String>)]) {
+        let unlisted = changes.iter().any(|(i, added)| {
+            self.controls[*i].ticks.as_ref().and_then(TickSizes::market).is_some_and(|(exchange, product_type)| {
+                added.iter().any(|sym| self.instruments.by_venue_symbol(exchange, product_type, sym).is_none())
+            })
+        });
+        if unlisted && let Some(source) = &self.instrument_source {
+            match source.instruments(product_type, false).await {
+                Ok(listings) => self.instruments.extend(listings.into_iter().map(|l| l.instrument)),
+                Err(e) => warn!("[{}] listing instruments for runtime {product_type:?} symbols: {e:#}", self.name),
+            }
+        }
+        for (i, _) in changes {
+            if let Some(ticks) = &self.controls[*i].ticks {
+                ticks.update(&self.instruments);
+            }
+        }
     }
 }
 
@@ -119,6 +409,21 @@ impl crate::MdModule for GenericMd {
         &self.name
     }
 
+    fn subscribe_events(&self) -> Option<broadcast::Receiver<WsEvent>> {
+        Some(self.events.subscribe())
+    }
+
+    fn ws_stats(&self) -> Vec<(String, WsConnStats)> {
+        self.ws_stats.iter().map(|(label, stats)| (label.clone(), stats.snapshot())).collect()
+    }
+
+    fn gap_stats(&self) -> Vec<(String, GapCount)> {
+        self.gap_stats
+            .iter()
+            .flat_map(|(label, stats)| stats.snapshot().into_iter().map(move |count| (label.clone(), count)))
+            .collect()
+    }
+
     async fn init_shm(&mut self) -> Result<()> {
         for (i, stream) in self.streams.iter().enumerate() {
             if stream.symbols.is_empty() {
@@ -127,12 +432,26 @@ impl crate::MdModule for GenericMd {
             let syms = &stream.symbols;
             let md_size = stream.md_size;
             let shm = &stream.shm;
+            let spare = if stream.subscription.is_some() { self.spare_symbol_slots } else { 0 };
 
             let stores = ProductShmStores {
-                bbo: shm.bbo.as_ref().map(|n| ShmMdStore::create(n, syms, md_size)).transpose()?,
-                agg: shm.agg.as_ref().map(|n| ShmMdStore::create(n, syms, md_size)).transpose()?,
-                trade: shm.trade.as_ref().map(|n| ShmMdStore::create(n, syms, md_size)).transpose()?,
-                depth5: shm.depth5.as_ref().map(|n| ShmMdStore::create(n, syms, md_size)).transpose()?,
+                bbo: shm.bbo.as_ref().map(|n| ShmMdStore::create_with_spare(n, syms, spare, md_size)).transpose()?,
+                agg: shm.agg.as_ref().map(|n| ShmMdStore::create_with_spare(n, syms, spare, md_size)).transpose()?,
+                trade: shm
+                    .trade
+                    .as_ref()
+                    .map(|n| ShmMdStore::create_with_spare(n, syms, spare, md_size))
+                    .transpose()?,
+                depth5: shm
+                    .depth5
+                    .as_ref()
+                    .map(|n| ShmMdStore::create_with_spare(n, syms, spare, md_size))
+                    .transpose()?,
+                depth_n: shm
+                    .depth_n
+                    .as_ref()
+                    .map(|n| ShmMdStore::create_with_spare(n, syms, spare, md_size))
+                    .transpose()?,
             };
             self.stores[i] = Some(stores);
         }
@@ -142,6 +461,43 @@ impl crate::MdModule for GenericMd {
     }
 
     async fn start(&mut self) -> Result<()> {
+        if let Some((cfg, exchange)) = &self.udp_config {
+            let dest = format!("{}:{}", cfg.ip, cfg.port).parse()?;
+            let mut options = UdpSenderOptions {
+                source_id: cfg.source_id.unwrap_or(0),
+                exchange: *exchange,
+                heartbeat_interval: cfg.heartbeat_interval(),
+                replay_addr: cfg.replay_port.map(|port| SocketAddr::from(([0, 0, 0, 0], port))),
+                socket: cfg.socket.clone().unwrap_or_default(),
+                ..Default::default()
+            };
+            if let Some(n) = cfg.retransmit_buffer_size {
+                options.retransmit_capacity = n;
+            }
+            if let Some(n) = cfg.queue_capacity {
+                options.queue_capacity = n;
+            }
+            if let Some(p) = cfg.drop_policy {
+                options.drop_policy = p;
+            }
+            let sender = Arc::new(UdpSender::with_options(dest, options).await?);
+            info!("[{}] UDP sender → {dest}", self.name);
+
+            if let Some(interval) = cfg.stats_interval() {
+                let sender = sender.clone();
+                let name = self.name.clone();
+                self.tasks.push(tokio::spawn(async move {
+                    let mut ticker = tokio::time::interval(interval);
+                    ticker.tick().await;
+                    loop {
+                        ticker.tick().await;
+                        info!("[{name}] UDP sender stats: {}", sender.stats());
+                    }
+                }));
+            }
+            self.udp = Some(sender);
+        }
+
         // Take ownership of streams and stores for the move closures.
         // We swap each stream out of the vec one at a time.
         let n = self.streams.len();
@@ -153,23 +509,127 @@ impl crate::MdModule for GenericMd {
             };
 
             let stream = &mut self.streams[i];
+            self.tasks.extend(stream.background.drain(..).map(tokio::spawn));
+
+            // Dedup channel; busy-polled streams only receive backfill on it.
+            let (tx, rx) = crossbeam_channel::bounded::<MarketDataMsg>(8192);
+
+            let mut deduper = Deduper::new(stores, self.udp.clone(), stream.custom_trade_dedup.take());
+            if stream.gap_tolerance.is_enabled() {
+                let mut hook = stream.gap_hook.take();
+                let tolerance = stream.gap_tolerance;
+                let stats = GapStats::default();
+                if let Some(fetcher) = stream.trade_backfill.clone()
+                    && (tolerance.trade.is_some() || tolerance.agg.is_some())
+                {
+                    let (backfill_hook, task) = backfill::spawn_backfill(
+                        stream.label.clone(),
+                        fetcher,
+                        tx.clone(),
+                        stats.clone(),
+                        self.backfill_max_trades,
+                    );
+                    self.tasks.push(task);
+                    hook = Some(match hook {
+                        Some(mut user_hook) => {
+                            let mut backfill_hook = backfill_hook;
+                            Box::new(move |gap| {
+                                user_hook(gap);
+                                backfill_hook(gap);
+                            })
+                        }
+                        None => backfill_hook,
+                    });
+                }
+                self.gap_stats.push((stream.label.clone(), stats.clone()));
+                let monitor = GapMonitor::new(stream.label.as_str(), stats, hook);
+                deduper = deduper.with_gap_check(tolerance, monitor);
+            }
+            let (ws_tx, ws_rx) = mpsc::unbounded_channel();
+            let control = (stream.subscription.is_some() || stream.conn_requests.is_some()).then_some(ws_rx);
+            if let Some(mut requests) = stream.conn_requests.take() {
+                let ws_tx = ws_tx.clone();
+                self.tasks.push(tokio::spawn(async move {
+                    while let Some(msg) = requests.recv().await {
+                        if ws_tx.send(ConnRequest::Send(msg)).is_err() {
+                            break;
+                        }
+                    }
+                }));
+            }
+            if let Some(msgs) = stream.subscription.clone() {
+                let (dedup_tx, dedup_rx) = crossbeam_channel::unbounded();
+                deduper = deduper.with_symbol_changes(dedup_rx);
+                self.controls.push(StreamControl {
+                    label: stream.label.clone(),
+                    product_type: stream.product_type,
+                    msgs,
+                    symbols: stream.symbols.clone(),
+                    capacity: stream.symbols.len() + self.spare_symbol_slots,
+                    dedup: dedup_tx,
+                    ws: ws_tx,
+                    ticks: stream.ticks.clone(),
+                });
+            }
+            if let Some(cfg) = stream.busy_poll.clone() {
+                let parser = match (stream.binary_parser.take(), stream.text_parser.take()) {
+                    (Some(parser), _) => StreamParser::Binary(parser),
+                    (None, Some(parser)) => StreamParser::Text(parser),
+                    (None, None) => continue,
+                };
+                let stats: Vec<WsStats> = (0..cfg.effective_connections()).map(|_| WsStats::default()).collect();
+                for (id, stats) in stats.iter().enumerate() {
+                    self.ws_stats.push((format!("{}[{id}]", stream.label), stats.clone()));
+                }
+                let stop = Arc::new(AtomicBool::new(false));
+                let params = busy_poll::BusyPollParams {
+                    url: stream.ws_url.clone(),
+                    subscribe_msg: stream.subscribe_msg.clone(),
+                    extra_headers: stream.extra_headers.clone(),
+                    reconnect: self.reconnect.clone(),
+                    binary_compression: self.binary_compression,
+                    max_message_size: self.max_message_size,
+                    ping: stream.ping.clone(),
+                    events: Some(self.events.clone()),
+                    stats,
+                    parser,
+                    deduper,
+                    inject: rx,
+                    control,
+                    cpu_core: cfg.cpu_core,
+                    stop: stop.clone(),
+                    label: stream.label.clone(),
+                };
+                let thread = std::thread::Builder::new()
+                    .name(format!("busy-poll-{}", stream.label))
+                    .spawn(move || busy_poll::run_busy_poll_stream(params))?;
+                self.busy_polls.push((stop, thread));
+                continue;
+            }
+
             let label = stream.label.clone();
             let url = stream.ws_url.clone();
             let sub_msg = stream.subscribe_msg.clone();
             let headers = stream.extra_headers.clone();
+            let proxy = stream.proxy.clone();
+            let local_binds = stream.local_binds.clone();
+            let reconnect = self.reconnect.clone();
+            let permessage_deflate = self.permessage_deflate;
+            let binary_compression = self.binary_compression;
+            let max_message_size = self.max_message_size;
+            let events = Some(self.events.clone());
+            let stats: Vec<WsStats> = (0..stream.conn_count.max(1)).map(|_| WsStats::default()).collect();
+            for (id, stats) in stats.iter().enumerate() {
+                self.ws_stats.push((format!("{label}[{id}]"), stats.clone()));
+            }
+            let redundancy = self.redundancy.clone();
             let ping = stream.ping.clone();
             let cpu_core = stream.dedup_cpu_core;
 
-            // Create dedup channel
-            let (tx, rx) = crossbeam_channel::bounded::<MarketDataMsg>(8192);
-
             // Spawn dedup task
-            let udp = self.udp.clone();
             let dedup_label = label.clone();
-            let custom_td = stream.custom_trade_dedup.take();
-
             self.tasks.push(tokio::task::spawn_blocking(move || {
-                dedup_worker::run_dedup_loop(&dedup_label, rx, stores, udp, custom_td, cpu_core);
+                dedup_worker::run_dedup_loop(&dedup_label, rx, deduper, cpu_core);
             }));
 
             // Spawn WS task
@@ -180,8 +640,18 @@ impl crate::MdModule for GenericMd {
                         url,
                         subscribe_msg: sub_msg,
                         extra_headers: headers,
+                        proxy,
+                        local_binds,
+                        reconnect,
+                        permessage_deflate,
+                        binary_compression,
+                        max_message_size,
+                        events,
+                        stats,
+                        redundancy,
                         tx,
                         parser: binary_parser,
+                        control,
                         label: ws_label,
                     })
                     .await;
@@ -193,9 +663,19 @@ impl crate::MdModule for GenericMd {
                         url,
                         subscribe_msg: sub_msg,
                         extra_headers: headers,
+                        proxy,
+                        local_binds,
+                        reconnect,
+                        permessage_deflate,
+                        binary_compression,
+                        max_message_size,
+                        events,
+                        stats,
+                        redundancy,
                         ping,
                         tx,
                         parser: text_parser,
+                        control,
                         label: ws_label,
                     })
                     .await;
@@ -203,15 +683,493 @@ impl crate::MdModule for GenericMd {
             }
         }
 
+        if let Some(interval) = self.ws_stats_interval {
+            let ws_stats = self.ws_stats.clone();
+            let gap_stats = self.gap_stats.clone();
+            let name = self.name.clone();
+            self.tasks.push(tokio::spawn(async move {
+                let mut ticker = tokio::time::interval(interval);
+                ticker.tick().await;
+                loop {
+                    ticker.tick().await;
+                    for (label, stats) in &ws_stats {
+                        info!("[{name}] {label} WS stats: {}", stats.snapshot());
+                    }
+                    for (label, stats) in &gap_stats {
+                        for count in stats.snapshot() {
+                            info!("[{name}] {label} sequence gaps: {count}");
+                        }
+                    }
+                }
+            }));
+        }
+
         info!("[{}] started {} tasks", self.name, self.tasks.len());
         Ok(())
     }
 
+    async fn subscribe(&mut self, product_type: ProductType, symbols: &[String]) -> Result<()> {
+        if !self.controls.iter().any(|c| c.product_type == product_type) {
+            bail!("[{}] no running {product_type:?} stream accepts runtime subscriptions", self.name);
+        }
+        // Check every stream's SHM slots before changing any of them.
+        let mut changes: Vec<(usize, Vec<String>)> = Vec::new();
+        for (i, control) in self.controls.iter().enumerate().filter(|(_, c)| c.product_type == product_type) {
+            let mut added: Vec<String> = Vec::new();
+            for sym in symbols.iter().map(|s| (control.msgs.venue_symbol)(s)) {
+                if !control.symbols.contains(&sym) && !added.contains(&sym) {
+                    added.push(sym);
+                }
+            }
+            if added.is_empty() {
+                continue;
+            }
+            if control.symbols.len() + added.len() > control.capacity {
+                bail!(
+                    "[{}] {}: cannot add {} symbols, {} of {} SHM slots in use (see spare_symbol_slots)",
+                    self.name,
+                    control.label,
+                    added.len(),
+                    control.symbols.len(),
+                    control.capacity
+                );
+            }
+            changes.push((i, added));
+        }
+        self.resolve_tick_sizes(product_type, &changes).await;
+
+        for (i, added) in changes {
+            let control = &mut self.controls[i];
+            // Slots first, so the first messages of the new symbols land.
+            control.dedup.send(SymbolChange::Add(added.clone()))?;
+            control.symbols.extend(added.iter().cloned());
+            let change = Resubscribe { on_connect: control.on_connect(), now: (control.msgs.subscribe)(&added) };
+            control.ws.send(ConnRequest::Resubscribe(change))?;
+            info!("[{}] {}: subscribed {added:?}", self.name, control.label);
+        }
+        Ok(())
+    }
+
+    async fn unsubscribe(&mut self, product_type: ProductType, symbols: &[String]) -> Result<()> {
+        let mut matched = false;
+        for control in self.controls.iter_mut().filter(|c| c.product_type == product_type) {
+            matched = true;
+            let removing: Vec<String> = symbols.iter().map(|s| (control.msgs.venue_symbol)(s)).collect();
+            let (removed, kept): (Vec<String>, Vec<String>) =
+                control.symbols.drain(..).partition(|sym| removing.contains(sym));
+            control.symbols = kept;
+            if removed.is_empty() {
+                continue;
+            }
+            let change = Resubscribe { on_connect: control.on_connect(), now: (control.msgs.unsubscribe)(&removed) };
+            control.ws.send(ConnRequest::Resubscribe(change))?;
+            control.dedup.send(SymbolChange::Remove(removed.clone()))?;
+            info!("[{}] {}: unsubscribed {removed:?}", self.name, control.label);
+        }
+        if !matched {
+            bail!("[{}] no running {product_type:?} stream accepts runtime subscriptions", self.name);
+        }
+        Ok(())
+    }
+
     async fn stop(&mut self) -> Result<()> {
+        self.controls.clear();
+        let threads: Vec<_> = self
+            .busy_polls
+            .drain(..)
+            .map(|(stop, thread)| {
+                stop.store(true, Ordering::Relaxed);
+                thread
+            })
+            .collect();
         for task in self.tasks.drain(..) {
             task.abort();
         }
+        // The loops close their connections before they return.
+        tokio::task::spawn_blocking(move || {
+            for thread in threads {
+                let _ = thread.join();
+            }
+        })
+        .await?;
         info!("[{}] stopped", self.name);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use futures_util::StreamExt;
+    use k4_core::instrument::{Instrument, InstrumentKind};
+    use tokio::net::TcpListener;
+
+    use super::*;
+    use crate::{MdModule, discovery::Listing, order_book::TickSize};
+
+    fn stream(port: u16) -> StreamDef {
+        let subscription = SubscriptionMsgs::new(
+            |symbols| format!("sub:{}", symbols.join(",")),
+            |symbols| format!("unsub:{}", symbols.join(",")),
+            str::to_uppercase,
+        );
+        StreamDef {
+            label: "test_stream".into(),
+            ws_url: format!("ws://127.0.0.1:{port}/ws"),
+            subscribe_msg: (subscription.subscribe)(&["BTCUSDT".into()]),
+            subscription: Some(subscription),
+            ping: None,
+            extra_headers: Default::default(),
+            proxy: None,
+            local_binds: Vec::new(),
+            conn_count: 1,
+            shm: ShmNames { trade: Some("test_pipeline_runtime_sub".into()), ..Default::default() },
+            symbols: vec!["BTCUSDT".into()],
+            product_type: ProductType::Spot,
+            md_size: 16,
+            text_parser: Some(Box::new(|_, _| {})),
+            binary_parser: None,
+            custom_trade_dedup: None,
+            dedup_cpu_core: None,
+            busy_poll: None,
+            gap_tolerance: GapTolerance::default(),
+            gap_hook: None,
+            trade_backfill: None,
+            background: Vec::new(),
+            conn_requests: None,
+            ticks: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn runtime_subscription_changes_reach_live_and_later_connections() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (seen_tx, mut seen) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            // Record text; end the session after an unsubscribe.
+            while let Ok((tcp, _)) = listener.accept().await {
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                while let Some(Ok(msg)) = ws.next().await {
+                    let text = msg.to_text().unwrap_or_default().to_string();
+                    let unsub = text.starts_with("unsub:");
+                    seen_tx.send(text).unwrap();
+                    if unsub {
+                        break;
+                    }
+                }
+            }
+        });
+        let mut next = async || tokio::time::timeout(Duration::from_secs(5), seen.recv()).await.unwrap().unwrap();
+
+        let mut md = GenericMd::new("test".into(), vec![stream(port)])
+            .with_spare_symbol_slots(Some(1))
+            .with_reconnect(ReconnectPolicy { initial_backoff_ms: 10, ..Default::default() });
+        md.init_shm().await.unwrap();
+        md.start().await.unwrap();
+        assert_eq!(next().await, "sub:BTCUSDT");
+
+        md.subscribe(ProductType::Spot, &["ethusdt".into(), "BTCUSDT".into()]).await.unwrap();
+        assert_eq!(next().await, "sub:ETHUSDT");
+        // The one spare slot is taken; no stream serves futures.
+        assert!(md.subscribe(ProductType::Spot, &["SOLUSDT".into()]).await.is_err());
+        assert!(md.subscribe(ProductType::Futures, &["BTCUSDT".into()]).await.is_err());
+
+        md.unsubscribe(ProductType::Spot, &["BTCUSDT".into(), "XRPUSDT".into()]).await.unwrap();
+        assert_eq!(next().await, "unsub:BTCUSDT");
+        // The reconnect subscribes to the current symbols only.
+        assert_eq!(next().await, "sub:ETHUSDT");
+        // The freed slot can be claimed again.
+        md.subscribe(ProductType::Spot, &["SOLUSDT".into()]).await.unwrap();
+        assert_eq!(next().await, "sub:SOLUSDT");
+        md.stop().await.unwrap();
+    }
+
+    /// A server recording the text frames it receives, on its own port.
+    async fn recorder() -> (u16, mpsc::UnboundedReceiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (seen_tx, seen) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                while let Some(Ok(msg)) = ws.next().await {
+                    let _ = seen_tx.send(msg.to_text().unwrap_or_default().to_string());
+                }
+            }
+        });
+        (port, seen)
+    }
+
+    async fn next_text(seen: &mut mpsc::UnboundedReceiver<String>) -> String {
+        tokio::time::timeout(Duration::from_secs(5), seen.recv()).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn subscribe_checks_every_stream_before_changing_any() {
+        let (port_a, mut seen_a) = recorder().await;
+        let (port_b, mut seen_b) = recorder().await;
+        let mut b = stream(port_b);
+        b.label = "b".into();
+        b.shm.trade = Some("test_pipeline_check_first_b".into());
+        b.symbols = vec!["BTCUSDT".into(), "ETHUSDT".into()];
+        b.subscribe_msg = "sub:BTCUSDT,ETHUSDT".into();
+        let mut a = stream(port_a);
+        a.label = "a".into();
+        a.shm.trade = Some("test_pipeline_check_first_a".into());
+        let mut md = GenericMd::new("test".into(), vec![b, a]).with_spare_symbol_slots(Some(1));
+        md.init_shm().await.unwrap();
+        md.start().await.unwrap();
+        assert_eq!(next_text(&mut seen_b).await, "sub:BTCUSDT,ETHUSDT");
+        assert_eq!(next_text(&mut seen_a).await, "sub:BTCUSDT");
+
+        // `b` has a slot for SOLUSDT, `a` none for both; neither changes.
+        assert!(md.subscribe(ProductType::Spot, &["ETHUSDT".into(), "SOLUSDT".into()]).await.is_err());
+        md.unsubscribe(ProductType::Spot, &["BTCUSDT".into()]).await.unwrap();
+        assert_eq!(next_text(&mut seen_b).await, "unsub:BTCUSDT");
+        assert_eq!(next_text(&mut seen_a).await, "unsub:BTCUSDT");
+        md.stop().await.unwrap();
+    }
+
+    /// Lists one spot instrument, counting the requests.
+    struct OneListing(std::sync::atomic::AtomicUsize);
+
+    #[async_trait]
+    impl InstrumentSource for OneListing {
+        async fn instruments(&self, product_type: ProductType, _with_volume: bool) -> Result<Vec<Listing>> {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            Ok(vec![Listing::from(Instrument {
+                exchange: Exchange::Okx,
+                product_type,
+                kind: InstrumentKind::Spot,
+                symbol: "ETHUSDT".into(),
+                base: "ETH".into(),
+                quote: "USDT".into(),
+                settle: "USDT".into(),
+                tick_size: 0.5,
+                lot_size: 0.001,
+                min_qty: 0.001,
+                contract_multiplier: 1.0,
+                expiry_ms: None,
+                trading: true,
+            })])
+        }
+    }
+
+    #[tokio::test]
+    async fn runtime_symbols_take_tick_sizes_from_the_instrument_list() {
+        let (port, mut seen) = recorder().await;
+        let ticks = TickSizes::from_registry(&InstrumentRegistry::new(), Exchange::Okx, ProductType::Spot);
+        let mut def = stream(port);
+        def.shm.trade = Some("test_pipeline_runtime_ticks".into());
+        def.ticks = Some(ticks.clone());
+        let source = Arc::new(OneListing(Default::default()));
+        let mut md = GenericMd::new("test".into(), vec![def])
+            .with_spare_symbol_slots(Some(2))
+            .with_instruments(InstrumentRegistry::new(), Some(source.clone()));
+        md.init_shm().await.unwrap();
+        md.start().await.unwrap();
+        assert_eq!(next_text(&mut seen).await, "sub:BTCUSDT");
+
+        md.subscribe(ProductType::Spot, &["ETHUSDT".into()]).await.unwrap();
+        assert_eq!(next_text(&mut seen).await, "sub:ETHUSDT");
+        assert_eq!(ticks.get("ETHUSDT"), TickSize::new(0.5).unwrap());
+        assert_eq!(source.0.load(Ordering::Relaxed), 1);
+
+        // Unlisted symbols are still subscribed, on the fallback grid.
+        md.subscribe(ProductType::Spot, &["NEWUSDT".into()]).await.unwrap();
+        assert_eq!(next_text(&mut seen).await, "sub:NEWUSDT");
+        assert!(!ticks.contains("NEWUSDT"));
+        assert_eq!(source.0.load(Ordering::Relaxed), 2);
+        md.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn parser_requests_reach_live_connection_without_changing_subscription() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (seen_tx, mut seen) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            // Record text; end the session after a request.
+            while let Ok((tcp, _)) = listener.accept().await {
+                let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                while let Some(Ok(msg)) = ws.next().await {
+                    let text = msg.to_text().unwrap_or_default().to_string();
+                    let request = text.starts_with("resub:");
+                    seen_tx.send(text).unwrap();
+                    if request {
+                        break;
+                    }
+                }
+            }
+        });
+        let mut next = async || tokio::time::timeout(Duration::from_secs(5), seen.recv()).await.unwrap().unwrap();
+
+        let (requests, rx) = mpsc::unbounded_channel();
+        let mut def = stream(port);
+        def.shm.trade = Some("test_pipeline_conn_requests".into());
+        def.conn_requests = Some(rx);
+        let mut md = GenericMd::new("test".into(), vec![def])
+            .with_reconnect(ReconnectPolicy { initial_backoff_ms: 10, ..Default::default() });
+        md.init_shm().await.unwrap();
+        md.start().await.unwrap();
+        assert_eq!(next().await, "sub:BTCUSDT");
+
+        requests.send("resub:BTCUSDT".into()).unwrap();
+        assert_eq!(next().await, "resub:BTCUSDT");
+        assert_eq!(next().await, "sub:BTCUSDT");
+        md.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn redundant_connections_start_on_different_local_binds() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (seen_tx, mut seen) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            // Record text with the address it came from.
+            while let Ok((tcp, peer)) = listener.accept().await {
+                let seen_tx = seen_tx.clone();
+                tokio::spawn(async move {
+                    let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                    while let Some(Ok(msg)) = ws.next().await {
+                        let _ = seen_tx.send((peer.ip().to_string(), msg.to_text().unwrap_or_default().to_string()));
+                    }
+                });
+            }
+        });
+        let mut next_two = async || {
+            let mut got = Vec::new();
+            for _ in 0..2 {
+                got.push(tokio::time::timeout(Duration::from_secs(5), seen.recv()).await.unwrap().unwrap());
+            }
+            got.sort();
+            got
+        };
+
+        let binds = vec!["127.0.0.1".parse().unwrap(), "127.0.0.2".parse().unwrap()];
+        let mut def = StreamDef { local_binds: binds, conn_count: 2, ..stream(port) };
+        def.shm.trade = Some("test_pipeline_local_binds".into());
+        let mut md = GenericMd::new("test".into(), vec![def]).with_spare_symbol_slots(Some(1));
+        md.init_shm().await.unwrap();
+        md.start().await.unwrap();
+        let sub = |ip: &str, text: &str| (ip.to_string(), text.to_string());
+        assert_eq!(next_two().await, vec![sub("127.0.0.1", "sub:BTCUSDT"), sub("127.0.0.2", "sub:BTCUSDT")]);
+
+        // Runtime changes reach every connection.
+        md.subscribe(ProductType::Spot, &["ETHUSDT".into()]).await.unwrap();
+        assert_eq!(next_two().await, vec![sub("127.0.0.1", "sub:ETHUSDT"), sub("127.0.0.2", "sub:ETHUSDT")]);
+        let labels: Vec<String> = md.ws_stats().into_iter().map(|(label, _)| label).collect();
+        assert_eq!(labels, ["test_stream[0]", "test_stream[1]"]);
+        md.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn heartbeat_replaces_a_silent_connection() {
+        use futures_util::SinkExt;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (seen_tx, mut seen) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            // Record each connection's subscription; only the first one
+            // gets data, the others stay silent.
+            for n in 0.. {
+                let Ok((tcp, _)) = listener.accept().await else { break };
+                let seen_tx = seen_tx.clone();
+                tokio::spawn(async move {
+                    let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                    let Some(Ok(sub)) = ws.next().await else { return };
+                    let _ = seen_tx.send((n, sub.to_text().unwrap_or_default().to_string()));
+                    while n == 0 && ws.send("tick".into()).await.is_ok() {
+                        tokio::time::sleep(Duration::from_millis(20)).await;
+                    }
+                    while ws.next().await.is_some() {}
+                });
+            }
+        });
+        let mut next = async || tokio::time::timeout(Duration::from_secs(5), seen.recv()).await.unwrap().unwrap();
+
+        let mut def = StreamDef { conn_count: 2, ..stream(port) };
+        def.shm.trade = Some("test_pipeline_heartbeat".into());
+        let mut md = GenericMd::new("test".into(), vec![def])
+            .with_heartbeat(Some(Duration::from_millis(100)), false)
+            .with_dns_refresh(Some(Duration::from_secs(60)))
+            .with_stale_after(Some(Duration::from_millis(300)));
+        md.init_shm().await.unwrap();
+        md.start().await.unwrap();
+        let mut first = vec![next().await, next().await];
+        first.sort();
+        assert_eq!(first, [(0, "sub:BTCUSDT".to_string()), (1, "sub:BTCUSDT".to_string())]);
+
+        // The silent connection is replaced; the one with data is kept.
+        assert_eq!(next().await, (2, "sub:BTCUSDT".to_string()));
+        md.stop().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn heartbeat_resets_the_connection_with_the_slowest_pong() {
+        use futures_util::SinkExt;
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (seen_tx, mut seen) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            // Answer pings, the first accepted connection slowly; record
+            // subscriptions and closes.
+            for n in 0.. {
+                let Ok((tcp, _)) = listener.accept().await else { break };
+                let seen_tx = seen_tx.clone();
+                tokio::spawn(async move {
+                    let mut ws = tokio_tungstenite::accept_async(tcp).await.unwrap();
+                    while let Some(Ok(msg)) = ws.next().await {
+                        let tokio_tungstenite::tungstenite::Message::Text(text) = msg else { continue };
+                        if text != "ping" {
+                            let _ = seen_tx.send((n, text.to_string()));
+                            continue;
+                        }
+                        if n == 0 {
+                            tokio::time::sleep(Duration::from_millis(30)).await;
+                        }
+                        let _ = ws.send("pong".into()).await;
+                    }
+                    let _ = seen_tx.send((n, "closed".to_string()));
+                });
+            }
+        });
+        let mut next = async || tokio::time::timeout(Duration::from_secs(5), seen.recv()).await.unwrap().unwrap();
+
+        let mut def = StreamDef { conn_count: 2, ..stream(port) };
+        def.shm.trade = Some("test_pipeline_rtt_ranking".into());
+        def.ping = Some(PingConfig {
+            interval: Duration::from_millis(100),
+            payload: PingPayload::Text("ping".into()),
+            pong: Some(PongMatcher::Exact("pong".into())),
+            pong_timeout: None,
+        });
+        let mut md = GenericMd::new("test".into(), vec![def]).with_heartbeat(Some(Duration::from_millis(500)), true);
+        md.init_shm().await.unwrap();
+        md.start().await.unwrap();
+        let mut first = vec![next().await, next().await];
+        first.sort();
+        assert_eq!(first, [(0, "sub:BTCUSDT".to_string()), (1, "sub:BTCUSDT".to_string())]);
+
+        // The close and the replacement's subscription race.
+        let mut reset = vec![next().await, next().await];
+        reset.sort();
+        assert_eq!(reset, [(0, "closed".to_string()), (2, "sub:BTCUSDT".to_string())]);
+        md.stop().await.unwrap();
+    }
+
+    #[test]
+    fn busy_poll_rejects_streams_it_cannot_connect() {
+        let busy_poll = || Some(HashMap::from([("test_stream".to_string(), BusyPollConfig::default())]));
+        let md = GenericMd::new("test".into(), vec![stream(1)]).with_busy_poll(busy_poll()).unwrap();
+        assert!(md.streams[0].busy_poll.is_some());
+
+        let bound = StreamDef { local_binds: vec!["127.0.0.1".parse().unwrap()], ..stream(1) };
+        assert!(GenericMd::new("test".into(), vec![bound]).with_busy_poll(busy_poll()).is_err());
+
+        let deflated = GenericMd::new("test".into(), vec![stream(1)]).with_permessage_deflate(true);
+        assert!(deflated.with_busy_poll(busy_poll()).is_err());
+    }
+}
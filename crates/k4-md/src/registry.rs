@@ -1,9 +1,11 @@
 //! Module registry — factory for creating MD modules from config.
 
+use std::sync::Arc;
+
 use anyhow::{Result, anyhow};
-use k4_core::config::ConnectionConfig;
+use k4_core::{config::ConnectionConfig, instrument::InstrumentRegistry};
 
-use crate::{MdModule, pipeline::GenericMd, udp::UdpMd};
+use crate::{MdModule, discovery, pipeline::GenericMd, udp::UdpMd};
 
 /// Create an `MdModule` based on the `exchange` field in the config.
 ///
@@ -11,7 +13,15 @@ use crate::{MdModule, pipeline::GenericMd, udp::UdpMd};
 /// module's `build()` function produces `StreamDef`s that are fed into
 /// [`GenericMd`]. The UDP module is handled separately since it doesn't use
 /// WebSocket.
-pub fn create_md_module(config: &ConnectionConfig) -> Result<Box<dyn MdModule>> {
+///
+/// Symbol selectors must already be resolved (see
+/// [`discovery::resolve_symbols`](crate::discovery::resolve_symbols)), which
+/// also returns the `instruments` whose tick sizes key local order books;
+/// books of instruments missing from it use
+/// [`TickSize::FALLBACK`](crate::order_book::TickSize::FALLBACK). Symbols
+/// subscribed at runtime are resolved against it too, after listing the
+/// exchange's instruments again if it lacks them.
+pub fn create_md_module(config: &ConnectionConfig, instruments: &InstrumentRegistry) -> Result<Box<dyn MdModule>> {
     let exchange = config.exchange.to_lowercase();
 
     if exchange == "udp" {
@@ -19,12 +29,28 @@ pub fn create_md_module(config: &ConnectionConfig) -> Result<Box<dyn MdModule>>
     }
 
     let streams = match exchange.as_str() {
-        "binance" => crate::binance::build(config)?,
-        "okx" => crate::okx::build(config)?,
-        "bitget" => crate::bitget::build(config)?,
-        "bybit" => crate::bybit::build(config)?,
+        "binance" => crate::binance::build(config, instruments)?,
+        "okx" => crate::okx::build(config, instruments)?,
+        "bitget" => crate::bitget::build(config, instruments)?,
+        "bybit" => crate::bybit::build(config, instruments)?,
         other => return Err(anyhow!("Unknown exchange: {other}")),
     };
 
-    Ok(Box::new(GenericMd::new(config.module_name(), streams)))
+    Ok(Box::new(
+        GenericMd::new(config.module_name(), streams)
+            .with_udp_sender(config.udp_sender.clone(), exchange.parse().ok())
+            .with_reconnect(config.reconnect.clone().unwrap_or_default())
+            .with_heartbeat(config.hb_interval(), config.redun_reset_on_hb.unwrap_or(false))
+            .with_dns_refresh(config.dns_refresh())
+            .with_stale_after(config.stale_after())
+            .with_permessage_deflate(config.permessage_deflate.unwrap_or(false))
+            .with_binary_compression(config.binary_compression)
+            .with_max_message_size(config.max_message_bytes)
+            .with_ws_stats_interval(config.ws_stats_interval())
+            .with_seq_gap_tolerance(config.seq_gap_tolerance.clone())
+            .with_backfill_max_trades(config.backfill.as_ref().and_then(|b| b.max_trades_per_gap))
+            .with_busy_poll(config.busy_poll.clone())?
+            .with_spare_symbol_slots(config.spare_symbol_slots)
+            .with_instruments(instruments.clone(), discovery::instrument_source(&exchange).ok().map(Arc::from)),
+    ))
 }
@@ -0,0 +1,275 @@
+//! Sequence-gap detection for the dedup worker.
+//!
+//! Where an exchange's IDs are contiguous (Binance trade and aggTrade ids),
+//! a jump past the last seen ID means every redundant connection missed the
+//! data in between. [`Deduper`](crate::dedup_worker::Deduper)
+//! checks each accepted ID against a per-type [`GapTolerance`] and reports
+//! jumps to a [`GapMonitor`], which counts them in [`GapStats`], logs them
+//! (rate-limited per symbol and type) and passes them to an optional
+//! [`GapHook`], e.g. to trigger a REST backfill.
+//!
+//! Bitget is out of scope: its trade ids are not contiguous, `books1` and
+//! `books5` are throttled snapshots whose `seq` skips, and `books` carries no
+//! previous `seq` to chain on, so its streams keep [`GapTolerance::default`]
+//! and missed book updates are caught by the [checksum](crate::checksum_book)
+//! alone.
+
+use std::{
+    collections::HashMap,
+    fmt,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use k4_core::types::MessageType;
+use tracing::warn;
+
+/// Minimum time between gap log lines for one symbol and message type.
+const GAP_LOG_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Largest ID step per message type that is not a gap (`1` = strictly
+/// contiguous, `None` = not checked).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct GapTolerance {
+    pub bbo: Option<u64>,
+    pub agg: Option<u64>,
+    pub trade: Option<u64>,
+    pub depth5: Option<u64>,
+    pub depth_n: Option<u64>,
+}
+
+impl GapTolerance {
+    /// Apply per-type overrides from config; `0` disables the check.
+    pub fn with_overrides(mut self, overrides: &HashMap<MessageType, u64>) -> Self {
+        for (&msg_type, &step) in overrides {
+            let slot = match msg_type {
+                MessageType::BookTicker => &mut self.bbo,
+                MessageType::AggTrade => &mut self.agg,
+                MessageType::Trade => &mut self.trade,
+                MessageType::Depth5 => &mut self.depth5,
+                MessageType::DepthN => &mut self.depth_n,
+                _ => continue,
+            };
+            *slot = (step > 0).then_some(step);
+        }
+        self
+    }
+
+    /// Whether any type is checked.
+    pub fn is_enabled(&self) -> bool {
+        self.bbo.is_some()
+            || self.agg.is_some()
+            || self.trade.is_some()
+            || self.depth5.is_some()
+            || self.depth_n.is_some()
+    }
+}
+
+/// A detected jump in one symbol's ID sequence: IDs `from..to` were missed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SeqGap {
+    pub msg_type: MessageType,
+    pub symbol: String,
+    pub from: u64,
+    pub to: u64,
+}
+
+impl SeqGap {
+    /// Number of missed IDs.
+    pub fn missing(&self) -> u64 {
+        self.to - self.from
+    }
+}
+
+/// Called on the dedup thread for every detected gap. Must not block.
+pub type GapHook = Box<dyn FnMut(&SeqGap) + Send>;
+
+/// Gap counters of one symbol and message type.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GapCount {
+    pub msg_type: MessageType,
+    pub symbol: String,
+    /// Gaps detected.
+    pub gaps: u64,
+    /// IDs missed across all gaps.
+    pub missing: u64,
+    /// Missed IDs that backfill found had already scrolled out of the
+    /// exchange's REST window.
+    pub unrecoverable: u64,
+}
+
+impl fmt::Display for GapCount {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} {}: {} gaps, {} missing", self.msg_type, self.symbol, self.gaps, self.missing)?;
+        if self.unrecoverable > 0 {
+            write!(f, ", {} unrecoverable", self.unrecoverable)?;
+        }
+        Ok(())
+    }
+}
+
+/// Gap counters of one stream, shared between its dedup worker and readers.
+#[derive(Debug, Clone, Default)]
+pub struct GapStats(Arc<Mutex<HashMap<GapKey, GapCounters>>>);
+
+/// Message type and symbol.
+type GapKey = (MessageType, String);
+
+/// Gaps, missing ids and unrecoverable ids.
+type GapCounters = (u64, u64, u64);
+
+impl GapStats {
+    fn record(&self, gap: &SeqGap) {
+        let mut counts = self.0.lock().unwrap();
+        let (gaps, missing, _) = counts.entry((gap.msg_type, gap.symbol.clone())).or_default();
+        *gaps += 1;
+        *missing += gap.missing();
+    }
+
+    /// Count `ids` of `gap` as lost for good.
+    pub(crate) fn record_unrecoverable(&self, gap: &SeqGap, ids: u64) {
+        let mut counts = self.0.lock().unwrap();
+        counts.entry((gap.msg_type, gap.symbol.clone())).or_default().2 += ids;
+    }
+
+    /// Current counters, sorted by symbol then message type.
+    pub fn snapshot(&self) -> Vec<GapCount> {
+        let mut out: Vec<_> = self
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|((msg_type, symbol), &(gaps, missing, unrecoverable))| GapCount {
+                msg_type: *msg_type,
+                symbol: symbol.clone(),
+                gaps,
+                missing,
+                unrecoverable,
+            })
+            .collect();
+        out.sort_by(|a, b| (&a.symbol, a.msg_type as i8).cmp(&(&b.symbol, b.msg_type as i8)));
+        out
+    }
+}
+
+/// Counts, logs and forwards the gaps of one stream.
+pub struct GapMonitor {
+    label: String,
+    stats: GapStats,
+    hook: Option<GapHook>,
+    /// Last log time and gaps not logged since, per symbol and type.
+    logged: HashMap<GapKey, (Instant, u64)>,
+}
+
+impl GapMonitor {
+    pub fn new(label: impl Into<String>, stats: GapStats, hook: Option<GapHook>) -> Self {
+        Self { label: label.into(), stats, hook, logged: HashMap::new() }
+    }
+
+    /// Record a gap of `msg_type` IDs `from..to` for `symbol`.
+    pub fn on_gap(&mut self, msg_type: MessageType, symbol: &str, from: u64, to: u64) {
+        let gap = SeqGap { msg_type, symbol: symbol.to_string(), from, to };
+        self.stats.record(&gap);
+        self.log(&gap);
+        if let Some(hook) = &mut self.hook {
+            hook(&gap);
+        }
+    }
+
+    fn log(&mut self, gap: &SeqGap) {
+        let now = Instant::now();
+        let key = (gap.msg_type, gap.symbol.clone());
+        match self.logged.get_mut(&key) {
+            Some((at, suppressed)) if now.duration_since(*at) < GAP_LOG_INTERVAL => *suppressed += 1,
+            Some((at, suppressed)) => {
+                warn!(
+                    "[{}] {:?} {} sequence gap: missed {}..{} ({} missing, {} more gaps since last report)",
+                    self.label,
+                    gap.msg_type,
+                    gap.symbol,
+                    gap.from,
+                    gap.to,
+                    gap.missing(),
+                    suppressed
+                );
+                *at = now;
+                *suppressed = 0;
+            }
+            None => {
+                warn!(
+                    "[{}] {:?} {} sequence gap: missed {}..{} ({} missing)",
+                    self.label,
+                    gap.msg_type,
+                    gap.symbol,
+                    gap.from,
+                    gap.to,
+                    gap.missing()
+                );
+                self.logged.insert(key, (now, 0));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_per_symbol_and_type_and_calls_hook() {
+        let stats = GapStats::default();
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let hook_seen = seen.clone();
+        let mut monitor = GapMonitor::new(
+            "test",
+            stats.clone(),
+            Some(Box::new(move |g: &SeqGap| hook_seen.lock().unwrap().push(g.clone()))),
+        );
+
+        monitor.on_gap(MessageType::Trade, "BTCUSDT", 10, 12);
+        monitor.on_gap(MessageType::Trade, "BTCUSDT", 20, 25);
+        monitor.on_gap(MessageType::AggTrade, "BTCUSDT", 5, 6);
+        monitor.on_gap(MessageType::Trade, "ETHUSDT", 1, 4);
+
+        assert_eq!(
+            stats.snapshot(),
+            [
+                GapCount {
+                    msg_type: MessageType::Trade,
+                    symbol: "BTCUSDT".into(),
+                    gaps: 2,
+                    missing: 7,
+                    unrecoverable: 0
+                },
+                GapCount {
+                    msg_type: MessageType::AggTrade,
+                    symbol: "BTCUSDT".into(),
+                    gaps: 1,
+                    missing: 1,
+                    unrecoverable: 0
+                },
+                GapCount {
+                    msg_type: MessageType::Trade,
+                    symbol: "ETHUSDT".into(),
+                    gaps: 1,
+                    missing: 3,
+                    unrecoverable: 0
+                },
+            ]
+        );
+        let seen = seen.lock().unwrap();
+        assert_eq!(seen.len(), 4);
+        assert_eq!(seen[1], SeqGap { msg_type: MessageType::Trade, symbol: "BTCUSDT".into(), from: 20, to: 25 });
+        // The second BTCUSDT trade gap fell inside the log interval.
+        assert_eq!(monitor.logged[&(MessageType::Trade, "BTCUSDT".into())].1, 1);
+    }
+
+    #[test]
+    fn overrides_enable_and_disable_types() {
+        let defaults = GapTolerance { trade: Some(1), ..Default::default() };
+        let t = defaults.with_overrides(&HashMap::from([(MessageType::Trade, 0), (MessageType::BookTicker, 5)]));
+        assert_eq!(t, GapTolerance { bbo: Some(5), ..Default::default() });
+        assert!(t.is_enabled());
+        assert!(!GapTolerance::default().is_enabled());
+    }
+}
@@ -0,0 +1,84 @@
+//! Runtime symbol subscription changes.
+//!
+//! Streams whose [`StreamDef::subscription`](crate::pipeline::StreamDef) is
+//! set can gain and lose symbols while running, via
+//! [`MdModule::subscribe`](crate::MdModule::subscribe) and
+//! [`MdModule::unsubscribe`](crate::MdModule::unsubscribe). A change travels
+//! two ways:
+//!
+//! - a [`SymbolChange`] to the stream's [`Deduper`](crate::dedup_worker::Deduper), which claims or
+//!   frees the symbol's spare SHM slots;
+//! - a [`Resubscribe`] to the stream's connection task(s), which send the (un)subscribe request on
+//!   the live connections and use the full new subscription on every later reconnect.
+//!
+//! The connection tasks also take one-off requests from the stream's parser
+//! ([`StreamDef::conn_requests`](crate::pipeline::StreamDef)), such as
+//! resubscribing a channel whose book failed its checksum.
+//!
+//! On subscribe the SHM slots are claimed before the request goes out, so the
+//! first messages of a new symbol are not dropped; on unsubscribe the slots
+//! are freed after it. A subscribe that does not fit the spare slots of every
+//! stream it affects changes none of them. New symbols' tick sizes are
+//! resolved through the instrument registry, as at startup (see
+//! [`GenericMd::with_instruments`](crate::pipeline::GenericMd::with_instruments)).
+
+use std::{fmt, sync::Arc};
+
+/// Builds a request for the given symbols.
+pub type RequestFn = Arc<dyn Fn(&[String]) -> String + Send + Sync>;
+
+/// Builds a stream's subscription requests.
+#[derive(Clone)]
+pub struct SubscriptionMsgs {
+    /// Request subscribing to the given symbols.
+    pub subscribe: RequestFn,
+    /// Request unsubscribing from the given symbols.
+    pub unsubscribe: RequestFn,
+    /// Convert a configured symbol (e.g. `"BTCUSDT"`) to the stream's own
+    /// form (e.g. OKX `"BTC-USDT-SWAP"`).
+    pub venue_symbol: fn(&str) -> String,
+}
+
+impl SubscriptionMsgs {
+    /// Wrap the request builders of a stream.
+    pub fn new(
+        subscribe: impl Fn(&[String]) -> String + Send + Sync + 'static,
+        unsubscribe: impl Fn(&[String]) -> String + Send + Sync + 'static,
+        venue_symbol: fn(&str) -> String,
+    ) -> Self {
+        Self { subscribe: Arc::new(subscribe), unsubscribe: Arc::new(unsubscribe), venue_symbol }
+    }
+}
+
+impl fmt::Debug for SubscriptionMsgs {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SubscriptionMsgs").finish_non_exhaustive()
+    }
+}
+
+/// A change to a running stream's WebSocket subscription.
+#[derive(Debug, Clone)]
+pub struct Resubscribe {
+    /// Sent on every later (re)connect in place of the previous subscription
+    /// (`None` = no symbols left).
+    pub on_connect: Option<String>,
+    /// Sent now on the live connections.
+    pub now: String,
+}
+
+/// A request for a running stream's connection task(s).
+#[derive(Debug, Clone)]
+pub enum ConnRequest {
+    /// Change the subscription.
+    Resubscribe(Resubscribe),
+    /// Send a request on the live connections, leaving the subscription
+    /// used on reconnect as it is.
+    Send(String),
+}
+
+/// A change to the symbols a dedup worker writes to SHM.
+#[derive(Debug, Clone, PartialEq)]
+pub enum SymbolChange {
+    Add(Vec<String>),
+    Remove(Vec<String>),
+}
@@ -1,12 +1,17 @@
 //! UDP market data module configuration.
 //!
 //! Extracts UDP receiver settings from the [`ConnectionConfig`] `udp_receiver`
-//! section, including listen address and per-product SHM names.
+//! section: listen address, liveness/gap settings, and the list of routes
+//! mapping received data to SHM stores.
 
-use std::net::SocketAddr;
+use std::{net::SocketAddr, time::Duration};
 
-use anyhow::{Result, anyhow};
-use k4_core::config::ConnectionConfig;
+use anyhow::{Result, anyhow, bail};
+use k4_core::{
+    config::{ConnectionConfig, UdpReceiverConfig, UdpRouteConfig},
+    types::{MessageType, ProductType},
+    udp::UdpSocketOptions,
+};
 
 /// Parsed UDP receiver configuration.
 #[derive(Debug, Clone)]
@@ -17,57 +22,116 @@ pub struct UdpMdConfig {
     /// Shared memory buffer size per instrument.
     pub md_size: u32,
 
-    // -- Spot --
-    /// Spot symbols to allocate SHM slots for.
-    pub spot_symbols: Vec<String>,
-
-    /// SHM name for spot BookTicker data.
-    pub spot_bbo_shm_name: Option<String>,
-    /// SHM name for spot AggTrade data.
-    pub spot_agg_shm_name: Option<String>,
-    /// SHM name for spot Trade data.
-    pub spot_trade_shm_name: Option<String>,
-    /// SHM name for spot Depth5 data.
-    pub spot_depth5_shm_name: Option<String>,
-
-    // -- Futures (UBase) --
-    /// UBase symbols to allocate SHM slots for.
-    pub ubase_symbols: Vec<String>,
-
-    /// SHM name for UBase BookTicker data.
-    pub ubase_bbo_shm_name: Option<String>,
-    /// SHM name for UBase AggTrade data.
-    pub ubase_agg_shm_name: Option<String>,
-    /// SHM name for UBase Trade data.
-    pub ubase_trade_shm_name: Option<String>,
-    /// SHM name for UBase Depth5 data.
-    pub ubase_depth5_shm_name: Option<String>,
+    /// A sender is declared down after this long without a heartbeat.
+    pub heartbeat_timeout: Duration,
+
+    /// Give-up timeout for filling sequence gaps (`None` = no gap filling).
+    pub gap_fill_timeout: Option<Duration>,
+
+    /// Record kernel receive timestamps for latency measurement.
+    pub kernel_timestamps: bool,
+
+    /// Socket tuning for the listening socket.
+    pub socket: UdpSocketOptions,
+
+    /// Routes in evaluation order: explicit `routes` first, then the legacy
+    /// spot/ubase fields translated into routes.
+    pub routes: Vec<UdpRouteConfig>,
 }
 
 impl UdpMdConfig {
     /// Build a [`UdpMdConfig`] from a [`ConnectionConfig`].
     ///
-    /// Returns an error if the `udp_receiver` section is missing.
+    /// Returns an error if the `udp_receiver` section is missing or a route
+    /// names a non-market-data message type.
     pub fn from_connection(conn: &ConnectionConfig) -> Result<Self> {
         let udp = conn.udp_receiver.as_ref().ok_or_else(|| anyhow!("missing udp_receiver config for UDP module"))?;
 
         let listen_addr: SocketAddr = format!("{}:{}", udp.ip, udp.port).parse()?;
 
+        let mut routes = udp.routes.clone().unwrap_or_default();
+        routes.extend(legacy_routes(udp));
+        for r in &routes {
+            if !matches!(
+                r.msg_type,
+                MessageType::BookTicker
+                    | MessageType::Trade
+                    | MessageType::AggTrade
+                    | MessageType::Depth5
+                    | MessageType::DepthN
+            ) {
+                bail!("udp route {}: unsupported msg_type {:?}", r.shm_name, r.msg_type);
+            }
+        }
+
         Ok(Self {
             listen_addr,
             md_size: conn.effective_md_size(),
-
-            spot_symbols: udp.spot_symbols.clone().unwrap_or_default(),
-            spot_bbo_shm_name: udp.spot_bbo_shm_name.clone(),
-            spot_agg_shm_name: udp.spot_agg_shm_name.clone(),
-            spot_trade_shm_name: udp.spot_trade_shm_name.clone(),
-            spot_depth5_shm_name: udp.spot_depth5_shm_name.clone(),
-
-            ubase_symbols: udp.ubase_symbols.clone().unwrap_or_default(),
-            ubase_bbo_shm_name: udp.ubase_bbo_shm_name.clone(),
-            ubase_agg_shm_name: udp.ubase_agg_shm_name.clone(),
-            ubase_trade_shm_name: udp.ubase_trade_shm_name.clone(),
-            ubase_depth5_shm_name: udp.ubase_depth5_shm_name.clone(),
+            heartbeat_timeout: Duration::from_millis(udp.heartbeat_timeout_ms.unwrap_or(3000)),
+            gap_fill_timeout: udp.gap_fill_timeout_ms.filter(|&ms| ms > 0).map(Duration::from_millis),
+            kernel_timestamps: udp.kernel_timestamps.unwrap_or(true),
+            socket: udp.socket.clone().unwrap_or_default(),
+            routes,
         })
     }
 }
+
+/// Every product type except spot (the legacy "ubase" bucket).
+const NON_SPOT: [ProductType; 7] = [
+    ProductType::Futures,
+    ProductType::UMargin,
+    ProductType::CoinMargin,
+    ProductType::Options,
+    ProductType::UsdtFutures,
+    ProductType::UsdcFutures,
+    ProductType::BtcMargin,
+];
+
+/// Translate the `spot_*` / `ubase_*` fields into routes: spot stores take
+/// `ProductType::Spot`, ubase stores take every other product type.
+fn legacy_routes(udp: &UdpReceiverConfig) -> Vec<UdpRouteConfig> {
+    let spot_symbols = udp.spot_symbols.clone().unwrap_or_default();
+    let ubase_symbols = udp.ubase_symbols.clone().unwrap_or_default();
+
+    let spot = [
+        (MessageType::BookTicker, &udp.spot_bbo_shm_name),
+        (MessageType::AggTrade, &udp.spot_agg_shm_name),
+        (MessageType::Trade, &udp.spot_trade_shm_name),
+        (MessageType::Depth5, &udp.spot_depth5_shm_name),
+    ];
+    let ubase = [
+        (MessageType::BookTicker, &udp.ubase_bbo_shm_name),
+        (MessageType::AggTrade, &udp.ubase_agg_shm_name),
+        (MessageType::Trade, &udp.ubase_trade_shm_name),
+        (MessageType::Depth5, &udp.ubase_depth5_shm_name),
+    ];
+
+    let mut routes = Vec::new();
+    if !spot_symbols.is_empty() {
+        for (msg_type, name) in spot {
+            if let Some(name) = name {
+                routes.push(UdpRouteConfig {
+                    exchange: None,
+                    product_types: Some(vec![ProductType::Spot]),
+                    msg_type,
+                    shm_name: name.clone(),
+                    symbols: spot_symbols.clone(),
+                });
+            }
+        }
+    }
+    if !ubase_symbols.is_empty() {
+        for (msg_type, name) in ubase {
+            if let Some(name) = name {
+                routes.push(UdpRouteConfig {
+                    exchange: None,
+                    product_types: Some(NON_SPOT.to_vec()),
+                    msg_type,
+                    shm_name: name.clone(),
+                    symbols: ubase_symbols.clone(),
+                });
+            }
+        }
+    }
+    routes
+}
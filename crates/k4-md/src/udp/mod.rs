@@ -10,47 +10,87 @@
 //! BinanceMd ──► UdpSender ─── UDP ──► UdpMd ──► SHM (spot + futures)
 //! ```
 //!
-//! Incoming packets are routed by [`ProductType`] to the appropriate SHM store:
-//! - `ProductType::Spot` → spot SHM stores
-//! - All other variants → futures (UBase) SHM stores
+//! Incoming messages are written to SHM according to an ordered list of
+//! routes (see [`k4_core::config::UdpRouteConfig`]): each route matches on the
+//! sender's exchange (from the packet header), the product type, and the
+//! message type, and selects one SHM store with its own symbol list. The
+//! legacy `spot_*` / `ubase_*` fields are translated into routes — spot data
+//! to the spot stores, every other product type to the ubase stores.
+//!
+//! Senders emit periodic heartbeats. When a sender goes silent for longer than
+//! `heartbeat_timeout_ms`, the SHM stores of the routes it feeds — those of
+//! the message types it had been sending that match its exchange — are
+//! flagged stale (see [`ShmMdStore::set_stale`]) until its heartbeats resume.
+//!
+//! With `gap_fill_timeout_ms` set, lost packets are fetched from the sender's
+//! TCP replay service (`udp_sender.replay_port`) before later packets are
+//! written, so each SHM ring sees messages in sender order. Gaps that cannot
+//! be filled within the timeout are skipped and logged.
+//!
+//! On Linux each packet carries its kernel receive time (`SO_TIMESTAMPNS`),
+//! which is stored with every record as `kernel_rx_us`, next to the sender's
+//! `local_time_us`, so consumers can measure latency themselves. Two latency
+//! histograms are logged every [`LATENCY_SAMPLES`] messages:
+//! *transit* (sender's `local_time_us` → kernel receive; requires synced
+//! clocks across hosts) and *stack* (kernel receive → receive loop).
 //!
 //! Configuration is read from the `udp_receiver` section of the connection JSON.
 
 pub mod config;
+mod route;
 
-use std::sync::Arc;
+use std::{cell::RefCell, sync::Arc};
 
 use anyhow::Result;
 use async_trait::async_trait;
 use k4_core::{
     config::ConnectionConfig,
-    shm::ShmMdStore,
-    udp::{UdpCallbackHandler, UdpReceiver},
+    latency::LatencyCollector,
+    udp::{FeedEvent, FeedState, RecvMeta, UdpCallbackHandler, UdpReceiver, UdpReceiverOptions},
     *,
 };
-use tracing::{error, info};
+use tracing::{debug, error, info, warn};
 
-use self::config::UdpMdConfig;
+use self::{config::UdpMdConfig, route::Router};
+
+/// Latency histograms are logged and reset after this many samples.
+const LATENCY_SAMPLES: u64 = 100_000;
+
+/// Receive-side latency histograms, fed from kernel receive timestamps.
+#[derive(Default)]
+struct RecvLatency {
+    /// Sender `local_time_us` → kernel receive.
+    transit: LatencyCollector,
+    /// Kernel receive → receive loop.
+    stack: LatencyCollector,
+}
+
+impl RecvLatency {
+    fn record(&mut self, meta: &RecvMeta, msg: &MarketDataMsg) {
+        let Some(kernel_us) = meta.kernel_rx_us else { return };
+        self.transit.record(kernel_us.saturating_sub(msg.local_time_us()));
+        self.stack.record(meta.recv_us.saturating_sub(kernel_us));
+        if self.stack.count() >= LATENCY_SAMPLES {
+            if let (Some(transit), Some(stack)) = (self.transit.stats(), self.stack.stats()) {
+                info!("[udp] latency transit: {transit}");
+                info!("[udp] latency stack: {stack}");
+            }
+            self.transit.reset();
+            self.stack.reset();
+        }
+    }
+}
 
 /// UDP market data module — receives pre-deduped data and writes to SHM.
 ///
-/// Listens on a single UDP socket and demultiplexes incoming messages by
-/// [`ProductType`] into separate spot and futures SHM stores.
+/// Listens on a single UDP socket and demultiplexes incoming messages into
+/// SHM stores via the configured routes.
 pub struct UdpMd {
     /// Parsed configuration.
     config: UdpMdConfig,
 
-    // -- Spot SHM stores --
-    spot_bbo_shm: Option<Arc<ShmMdStore<Bookticker>>>,
-    spot_agg_shm: Option<Arc<ShmMdStore<AggTrade>>>,
-    spot_trade_shm: Option<Arc<ShmMdStore<Trade>>>,
-    spot_depth5_shm: Option<Arc<ShmMdStore<Depth5>>>,
-
-    // -- Futures SHM stores --
-    ubase_bbo_shm: Option<Arc<ShmMdStore<Bookticker>>>,
-    ubase_agg_shm: Option<Arc<ShmMdStore<AggTrade>>>,
-    ubase_trade_shm: Option<Arc<ShmMdStore<Trade>>>,
-    ubase_depth5_shm: Option<Arc<ShmMdStore<Depth5>>>,
+    /// Routes and their SHM stores (created in `init_shm`).
+    router: Option<Arc<Router>>,
 
     /// Background receiver task handle.
     task: Option<tokio::task::JoinHandle<()>>,
@@ -62,18 +102,7 @@ impl UdpMd {
     /// No connections are opened until [`MdModule::start`] is called.
     pub fn new(conn_config: &ConnectionConfig) -> Result<Self> {
         let config = UdpMdConfig::from_connection(conn_config)?;
-        Ok(Self {
-            config,
-            spot_bbo_shm: None,
-            spot_agg_shm: None,
-            spot_trade_shm: None,
-            spot_depth5_shm: None,
-            ubase_bbo_shm: None,
-            ubase_agg_shm: None,
-            ubase_trade_shm: None,
-            ubase_depth5_shm: None,
-            task: None,
-        })
+        Ok(Self { config, router: None, task: None })
     }
 }
 
@@ -84,108 +113,59 @@ impl crate::MdModule for UdpMd {
     }
 
     async fn init_shm(&mut self) -> Result<()> {
-        let md_size = self.config.md_size;
-
-        // -- Spot SHM --
-        if !self.config.spot_symbols.is_empty() {
-            let syms = &self.config.spot_symbols;
-            if let Some(ref name) = self.config.spot_bbo_shm_name {
-                self.spot_bbo_shm = Some(Arc::new(ShmMdStore::create(name, syms, md_size)?));
-            }
-            if let Some(ref name) = self.config.spot_agg_shm_name {
-                self.spot_agg_shm = Some(Arc::new(ShmMdStore::create(name, syms, md_size)?));
-            }
-            if let Some(ref name) = self.config.spot_trade_shm_name {
-                self.spot_trade_shm = Some(Arc::new(ShmMdStore::create(name, syms, md_size)?));
-            }
-            if let Some(ref name) = self.config.spot_depth5_shm_name {
-                self.spot_depth5_shm = Some(Arc::new(ShmMdStore::create(name, syms, md_size)?));
-            }
-        }
-
-        // -- Futures (UBase) SHM --
-        if !self.config.ubase_symbols.is_empty() {
-            let syms = &self.config.ubase_symbols;
-            if let Some(ref name) = self.config.ubase_bbo_shm_name {
-                self.ubase_bbo_shm = Some(Arc::new(ShmMdStore::create(name, syms, md_size)?));
-            }
-            if let Some(ref name) = self.config.ubase_agg_shm_name {
-                self.ubase_agg_shm = Some(Arc::new(ShmMdStore::create(name, syms, md_size)?));
-            }
-            if let Some(ref name) = self.config.ubase_trade_shm_name {
-                self.ubase_trade_shm = Some(Arc::new(ShmMdStore::create(name, syms, md_size)?));
-            }
-            if let Some(ref name) = self.config.ubase_depth5_shm_name {
-                self.ubase_depth5_shm = Some(Arc::new(ShmMdStore::create(name, syms, md_size)?));
-            }
-        }
-
-        info!(
-            "[udp] SHM initialized — spot symbols: {}, ubase symbols: {}",
-            self.config.spot_symbols.len(),
-            self.config.ubase_symbols.len(),
-        );
+        let router = Router::create(&self.config.routes, self.config.md_size)?;
+        info!("[udp] SHM initialized — {} routes, {} stores", self.config.routes.len(), router.store_count());
+        self.router = Some(Arc::new(router));
         Ok(())
     }
 
     async fn start(&mut self) -> Result<()> {
-        let receiver = UdpReceiver::bind(self.config.listen_addr).await?;
-
-        // Clone Arc references for the move closures.
-        let spot_bbo = self.spot_bbo_shm.clone();
-        let ubase_bbo = self.ubase_bbo_shm.clone();
+        let router = self.router.clone().ok_or_else(|| anyhow::anyhow!("init_shm must run before start"))?;
 
-        let spot_trade = self.spot_trade_shm.clone();
-        let ubase_trade = self.ubase_trade_shm.clone();
-
-        let spot_agg = self.spot_agg_shm.clone();
-        let ubase_agg = self.ubase_agg_shm.clone();
-
-        let spot_depth5 = self.spot_depth5_shm.clone();
-        let ubase_depth5 = self.ubase_depth5_shm.clone();
+        let options = UdpReceiverOptions {
+            heartbeat_timeout: self.config.heartbeat_timeout,
+            gap_fill_timeout: self.config.gap_fill_timeout,
+            kernel_timestamps: self.config.kernel_timestamps,
+            socket: self.config.socket.clone(),
+        };
+        let receiver = UdpReceiver::bind_with_options(self.config.listen_addr, options).await?;
 
+        let stale = router.clone();
+        let latency = RefCell::new(RecvLatency::default());
         let handler = UdpCallbackHandler {
-            on_bbo: Some(Box::new(move |bbo: Bookticker| {
-                let sym = symbol_from_bytes(&bbo.symbol);
-                let store = match bbo.product_type {
-                    ProductType::Spot => &spot_bbo,
-                    _ => &ubase_bbo,
-                };
-                if let Some(s) = store {
-                    s.write(sym, &bbo);
+            on_bbo: None,
+            on_trade: None,
+            on_agg_trade: None,
+            on_depth5: None,
+            on_depth_n: None,
+
+            on_market_data: Some(Box::new(move |meta: RecvMeta, mut msg: MarketDataMsg| {
+                latency.borrow_mut().record(&meta, &msg);
+                if let Some(kernel_us) = meta.kernel_rx_us {
+                    msg.set_kernel_rx_us(kernel_us);
                 }
-            })),
-
-            on_trade: Some(Box::new(move |trade: Trade| {
-                let sym = symbol_from_bytes(&trade.symbol);
-                let store = match trade.product_type {
-                    ProductType::Spot => &spot_trade,
-                    _ => &ubase_trade,
-                };
-                if let Some(s) = store {
-                    s.write(sym, &trade);
+                if !router.write(meta.exchange, &msg) {
+                    debug!(
+                        "[udp] no route for {:?} {:?} {} from {:?}",
+                        msg.msg_type(),
+                        msg.product_type(),
+                        symbol_from_bytes(msg.symbol()),
+                        meta.exchange
+                    );
                 }
             })),
 
-            on_agg_trade: Some(Box::new(move |agg: AggTrade| {
-                let sym = symbol_from_bytes(&agg.symbol);
-                let store = match agg.product_type {
-                    ProductType::Spot => &spot_agg,
-                    _ => &ubase_agg,
-                };
-                if let Some(s) = store {
-                    s.write(sym, &agg);
-                }
-            })),
+            on_heartbeat: None,
 
-            on_depth5: Some(Box::new(move |depth: Depth5| {
-                let sym = symbol_from_bytes(&depth.symbol);
-                let store = match depth.product_type {
-                    ProductType::Spot => &spot_depth5,
-                    _ => &ubase_depth5,
-                };
-                if let Some(s) = store {
-                    s.write(sym, &depth);
+            on_feed_state: Some(Box::new(move |ev: FeedEvent| {
+                let stale_now = ev.state == FeedState::Down;
+                if stale_now {
+                    warn!("[udp] source {} ({}) down — marking stores stale", ev.source_id, ev.peer);
+                } else {
+                    info!("[udp] source {} ({}) up", ev.source_id, ev.peer);
+                }
+                for msg_type in ev.heartbeat.active_channels() {
+                    stale.set_stale(ev.source_id, ev.exchange, msg_type, stale_now);
                 }
             })),
         };
@@ -0,0 +1,276 @@
+//! Routing of received UDP market data to SHM stores.
+//!
+//! Each [`Route`] pairs an exchange / product-type filter with one typed SHM
+//! store. Routes are evaluated in configuration order and a message is
+//! written to the first route that matches and holds its symbol.
+
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Result, bail};
+use k4_core::{config::UdpRouteConfig, shm::ShmMdStore, *};
+
+/// A typed SHM store targeted by a route.
+#[derive(Clone)]
+enum RouteStore {
+    Bbo(Arc<ShmMdStore<Bookticker>>),
+    Trade(Arc<ShmMdStore<Trade>>),
+    AggTrade(Arc<ShmMdStore<AggTrade>>),
+    Depth5(Arc<ShmMdStore<Depth5>>),
+    DepthN(Arc<ShmMdStore<DepthN>>),
+}
+
+impl RouteStore {
+    fn create(msg_type: MessageType, name: &str, symbols: &[String], md_size: u32) -> Result<Self> {
+        Ok(match msg_type {
+            MessageType::BookTicker => Self::Bbo(Arc::new(ShmMdStore::create(name, symbols, md_size)?)),
+            MessageType::Trade => Self::Trade(Arc::new(ShmMdStore::create(name, symbols, md_size)?)),
+            MessageType::AggTrade => Self::AggTrade(Arc::new(ShmMdStore::create(name, symbols, md_size)?)),
+            MessageType::Depth5 => Self::Depth5(Arc::new(ShmMdStore::create(name, symbols, md_size)?)),
+            MessageType::DepthN => Self::DepthN(Arc::new(ShmMdStore::create(name, symbols, md_size)?)),
+            other => bail!("udp route {name}: unsupported msg_type {other:?}"),
+        })
+    }
+
+    /// Write `msg` if it has the store's type and its symbol has a slot.
+    fn write(&self, symbol: &str, msg: &MarketDataMsg) -> bool {
+        match (self, msg) {
+            (Self::Bbo(s), MarketDataMsg::Bbo(d)) => s.write(symbol, d),
+            (Self::Trade(s), MarketDataMsg::Trade(d)) => s.write(symbol, d),
+            (Self::AggTrade(s), MarketDataMsg::AggTrade(d)) => s.write(symbol, d),
+            (Self::Depth5(s), MarketDataMsg::Depth5(d)) => s.write(symbol, d),
+            (Self::DepthN(s), MarketDataMsg::DepthN(d)) => s.write(symbol, &**d),
+            _ => false,
+        }
+    }
+
+    fn set_stale(&self, stale: bool) {
+        match self {
+            Self::Bbo(s) => s.set_stale(stale),
+            Self::Trade(s) => s.set_stale(stale),
+            Self::AggTrade(s) => s.set_stale(stale),
+            Self::Depth5(s) => s.set_stale(stale),
+            Self::DepthN(s) => s.set_stale(stale),
+        }
+    }
+}
+
+/// One configured route.
+struct Route {
+    exchange: Option<Exchange>,
+    product_types: Option<Vec<ProductType>>,
+    msg_type: MessageType,
+    store: RouteStore,
+    /// Index of `store` in [`Router::stores`].
+    store_index: usize,
+}
+
+impl Route {
+    fn matches(&self, exchange: Option<Exchange>, msg: &MarketDataMsg) -> bool {
+        self.msg_type == msg.msg_type()
+            && self.exchange.is_none_or(|e| exchange == Some(e))
+            && self.product_types.as_ref().is_none_or(|p| p.contains(&msg.product_type()))
+    }
+
+    /// Whether a source sending `msg_type` for `exchange` may feed this route.
+    fn fed_by(&self, exchange: Option<Exchange>, msg_type: MessageType) -> bool {
+        self.msg_type == msg_type && self.exchange.is_none_or(|e| exchange == Some(e))
+    }
+}
+
+/// Ordered set of routes with their SHM stores.
+pub struct Router {
+    routes: Vec<Route>,
+    /// Distinct stores (routes may share one).
+    stores: Vec<RouteStore>,
+    /// Per store, the down sources that feed it; a store is stale while any is.
+    down_sources: Mutex<Vec<HashSet<u32>>>,
+}
+
+impl Router {
+    /// Create the SHM stores for `routes`. Routes naming the same SHM segment
+    /// share one store whose symbol list is the union of theirs.
+    pub fn create(routes: &[UdpRouteConfig], md_size: u32) -> Result<Self> {
+        let mut merged: Vec<(&str, MessageType, Vec<String>)> = Vec::new();
+        for r in routes {
+            match merged.iter_mut().find(|(name, _, _)| *name == r.shm_name) {
+                Some((_, msg_type, symbols)) => {
+                    if *msg_type != r.msg_type {
+                        bail!("udp routes disagree on msg_type for shm {}", r.shm_name);
+                    }
+                    for s in &r.symbols {
+                        if !symbols.contains(s) {
+                            symbols.push(s.clone());
+                        }
+                    }
+                }
+                None => merged.push((&r.shm_name, r.msg_type, r.symbols.clone())),
+            }
+        }
+
+        let mut by_name = HashMap::new();
+        let mut stores = Vec::new();
+        for (name, msg_type, symbols) in merged {
+            let store = RouteStore::create(msg_type, name, &symbols, md_size)?;
+            by_name.insert(name, (store.clone(), stores.len()));
+            stores.push(store);
+        }
+
+        let routes = routes
+            .iter()
+            .map(|r| {
+                let (store, store_index) = by_name[r.shm_name.as_str()].clone();
+                Route {
+                    exchange: r.exchange,
+                    product_types: r.product_types.clone(),
+                    msg_type: r.msg_type,
+                    store,
+                    store_index,
+                }
+            })
+            .collect();
+
+        let down_sources = Mutex::new(vec![HashSet::new(); stores.len()]);
+        Ok(Self { routes, stores, down_sources })
+    }
+
+    /// Number of distinct SHM stores.
+    pub fn store_count(&self) -> usize {
+        self.stores.len()
+    }
+
+    /// Write `msg` to the first matching route. Returns `false` if no route
+    /// took it.
+    pub fn write(&self, exchange: Option<Exchange>, msg: &MarketDataMsg) -> bool {
+        let symbol = symbol_from_bytes(msg.symbol());
+        self.routes.iter().any(|r| r.matches(exchange, msg) && r.store.write(symbol, msg))
+    }
+
+    /// Record whether source `source_id`, sending `msg_type` for `exchange`,
+    /// is down. The stores of the routes it feeds are flagged stale while any
+    /// of their sources is down.
+    pub fn set_stale(&self, source_id: u32, exchange: Option<Exchange>, msg_type: MessageType, down: bool) {
+        let mut down_sources = self.down_sources.lock().unwrap();
+        for route in self.routes.iter().filter(|r| r.fed_by(exchange, msg_type)) {
+            let sources = &mut down_sources[route.store_index];
+            if down {
+                sources.insert(source_id);
+            } else {
+                sources.remove(&source_id);
+            }
+            route.store.set_stale(!sources.is_empty());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn route(
+        exchange: Option<Exchange>,
+        product_types: Option<Vec<ProductType>>,
+        shm_name: &str,
+        symbols: &[&str],
+    ) -> UdpRouteConfig {
+        UdpRouteConfig {
+            exchange,
+            product_types,
+            msg_type: MessageType::BookTicker,
+            shm_name: shm_name.into(),
+            symbols: symbols.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    fn bbo(symbol: &str, product_type: ProductType, update_id: u64) -> MarketDataMsg {
+        MarketDataMsg::Bbo(Bookticker {
+            symbol: symbol_to_bytes(symbol),
+            product_type,
+            update_id,
+            ..Default::default()
+        })
+    }
+
+    #[test]
+    fn routes_by_exchange_product_and_symbol() {
+        let router = Router::create(
+            &[
+                route(Some(Exchange::Okx), Some(vec![ProductType::CoinMargin]), "test_route_okx_cm", &["BTC-USD-SWAP"]),
+                route(None, Some(vec![ProductType::UsdcFutures]), "test_route_usdc", &["BTCPERP"]),
+                route(None, None, "test_route_any", &["BTCUSDT"]),
+            ],
+            8,
+        )
+        .unwrap();
+
+        assert!(router.write(Some(Exchange::Okx), &bbo("BTC-USD-SWAP", ProductType::CoinMargin, 1)));
+        // Wrong exchange for the OKX route, and no other route holds the symbol.
+        assert!(!router.write(Some(Exchange::Bybit), &bbo("BTC-USD-SWAP", ProductType::CoinMargin, 2)));
+        assert!(router.write(Some(Exchange::Bybit), &bbo("BTCPERP", ProductType::UsdcFutures, 3)));
+        assert!(router.write(None, &bbo("BTCUSDT", ProductType::Spot, 4)));
+        // Wrong message type.
+        let trade = MarketDataMsg::Trade(Trade { symbol: symbol_to_bytes("BTCUSDT"), ..Default::default() });
+        assert!(!router.write(None, &trade));
+
+        let RouteStore::Bbo(store) = &router.routes[0].store else { unreachable!() };
+        assert_eq!(store.read_latest("BTC-USD-SWAP").unwrap().update_id, 1);
+        let RouteStore::Bbo(store) = &router.routes[1].store else { unreachable!() };
+        assert_eq!(store.read_latest("BTCPERP").unwrap().update_id, 3);
+    }
+
+    #[test]
+    fn shared_shm_name_merges_symbols() {
+        let name = "test_route_shared";
+        let router = Router::create(
+            &[
+                route(Some(Exchange::Binance), None, name, &["BTCUSDT"]),
+                route(Some(Exchange::Bybit), None, name, &["ETHUSDT"]),
+            ],
+            8,
+        )
+        .unwrap();
+        assert_eq!(router.store_count(), 1);
+        assert!(router.write(Some(Exchange::Binance), &bbo("BTCUSDT", ProductType::Spot, 1)));
+        assert!(router.write(Some(Exchange::Bybit), &bbo("ETHUSDT", ProductType::Spot, 2)));
+
+        let mut conflicting = route(None, None, name, &["BTCUSDT"]);
+        conflicting.msg_type = MessageType::Trade;
+        let err = Router::create(&[route(None, None, name, &["BTCUSDT"]), conflicting], 8);
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn staleness_follows_the_sources_feeding_each_route() {
+        let router = Router::create(
+            &[
+                route(Some(Exchange::Binance), None, "test_route_stale_binance", &["BTCUSDT"]),
+                route(Some(Exchange::Bybit), None, "test_route_stale_bybit", &["BTCUSDT"]),
+                route(None, None, "test_route_stale_any", &["ETHUSDT"]),
+            ],
+            8,
+        )
+        .unwrap();
+        let stale = |i: usize, symbol: &str| {
+            let RouteStore::Bbo(store) = &router.routes[i].store else { unreachable!() };
+            store.is_stale(symbol)
+        };
+        let bbo = MessageType::BookTicker;
+
+        // Source 1 (Binance) goes down; source 2 (Bybit) comes up and stays up.
+        router.set_stale(1, Some(Exchange::Binance), bbo, true);
+        router.set_stale(2, Some(Exchange::Bybit), bbo, false);
+        assert!(stale(0, "BTCUSDT"));
+        assert!(!stale(1, "BTCUSDT"));
+        // The catch-all route is fed by both and stays stale while one is down.
+        assert!(stale(2, "ETHUSDT"));
+        // Other message types are untouched.
+        router.set_stale(2, Some(Exchange::Bybit), MessageType::Trade, true);
+        assert!(!stale(1, "BTCUSDT"));
+
+        router.set_stale(1, Some(Exchange::Binance), bbo, false);
+        assert!(!stale(0, "BTCUSDT"));
+        assert!(!stale(2, "ETHUSDT"));
+    }
+}
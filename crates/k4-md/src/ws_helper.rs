@@ -1,24 +1,71 @@
 //! Shared WebSocket connection helpers for market data modules.
+//!
+//! A stream runs one connection per [`WsStats`] handle it is given, through
+//! a [`RedundantWsClient`]: every connection carries the same subscription,
+//! and with several `local_binds` each connection starts on its own path.
+//! Every heartbeat the client replaces an unhealthy connection (and, if
+//! configured, the slowest), re-resolving the exchange host first when
+//! connections are pinned to its IPs (see [`Redundancy`]).
+//!
+//! The message callbacks use per-thread scratch buffers: each frame is copied
+//! into a reused `Vec<u8>` (simd-json parses in place) and parsers push into
+//! a reused output `Vec`, so steady-state processing does not allocate per
+//! message. A callback runs to completion without yielding, so connections
+//! on the same thread never hold the scratch at the same time, and no lock is
+//! shared between them.
 
-use std::{collections::HashMap, sync::Arc};
+use std::{cell::RefCell, collections::HashMap, sync::Arc, time::Duration};
 
 use crossbeam_channel::Sender;
 use k4_core::{
     types::MarketDataMsg,
-    ws::client::{OnBinaryCallback, OnMessageCallback, WsConnConfig, WsConnection},
+    ws::{
+        BinaryCompression, LocalBind, ProxyConfig, ReconnectPolicy, RedundantWsClient, WsEvent, WsEventSink, WsStats,
+        client::{OnBinaryCallback, OnMessageCallback, WsConnConfig},
+        redundant::RedundantConfig,
+    },
 };
-use tracing::warn;
+use tokio::sync::{broadcast, mpsc};
+use tracing::{info, warn};
 
-use crate::pipeline::PingConfig;
+use crate::{pipeline::PingConfig, subscription::ConnRequest};
+
+/// How a stream's redundant connections are evaluated and replaced (see
+/// [`RedundantConfig`]).
+#[derive(Debug, Clone, Default)]
+pub struct Redundancy {
+    /// Evaluate the connections at this interval (`None` = never).
+    pub hb_interval: Option<Duration>,
+    /// Replace the slowest connection on every evaluation.
+    pub reset_on_hb: bool,
+    /// Pin connections to distinct IPs of the host, re-resolving it at most
+    /// this often (`None` = no pinning).
+    pub dns_refresh: Option<Duration>,
+    /// Replace a connection silent this long while another is healthy.
+    pub stale_after: Option<Duration>,
+}
 
 /// Parameters for a text-mode WebSocket MD stream.
 pub struct TextStreamParams<F> {
     pub url: String,
     pub subscribe_msg: String,
     pub extra_headers: HashMap<String, String>,
+    pub proxy: Option<ProxyConfig>,
+    pub local_binds: Vec<LocalBind>,
+    pub reconnect: ReconnectPolicy,
+    pub permessage_deflate: bool,
+    pub binary_compression: Option<BinaryCompression>,
+    pub max_message_size: usize,
+    /// Lifecycle events are published here, tagged with `label`.
+    pub events: Option<broadcast::Sender<WsEvent>>,
+    /// One handle per redundant connection, which records its stats.
+    pub stats: Vec<WsStats>,
+    pub redundancy: Redundancy,
     pub ping: Option<PingConfig>,
     pub tx: Sender<MarketDataMsg>,
     pub parser: F,
+    /// Subscription changes and other requests to apply while running.
+    pub control: Option<mpsc::UnboundedReceiver<ConnRequest>>,
     pub label: String,
 }
 
@@ -26,32 +73,49 @@ pub struct TextStreamParams<F> {
 /// them to the dedup channel. Blocks until cancelled.
 pub async fn run_ws_text_stream<F>(params: TextStreamParams<F>)
 where
-    F: Fn(&mut [u8]) -> Vec<MarketDataMsg> + Send + Sync + 'static,
+    F: Fn(&mut [u8], &mut Vec<MarketDataMsg>) + Send + Sync + 'static,
 {
-    let TextStreamParams { url, subscribe_msg, extra_headers, ping, tx, parser, label } = params;
+    let TextStreamParams {
+        url,
+        subscribe_msg,
+        extra_headers,
+        proxy,
+        local_binds,
+        reconnect,
+        permessage_deflate,
+        binary_compression,
+        max_message_size,
+        events,
+        stats,
+        redundancy,
+        ping,
+        tx,
+        parser,
+        control,
+        label,
+    } = params;
 
-    let on_msg: OnMessageCallback = Arc::new(move |_conn_id, text| {
-        let mut buf = text.as_bytes().to_vec();
-        for msg in parser(&mut buf) {
-            if tx.try_send(msg).is_err() {
-                warn!("[{label}] dedup channel full");
-            }
-        }
-    });
+    let events = events.map(|tx| WsEventSink::new(label.as_str(), tx));
+    let on_msg = text_callback(parser, tx, label.clone());
 
     let config = WsConnConfig {
-        url,
         subscribe_msg: Some(subscribe_msg),
         extra_headers,
         ping_interval: ping.as_ref().map(|p| p.interval),
-        ping_payload: ping.map(|p| p.payload),
-        id: 0,
+        ping_payload: ping.as_ref().map(|p| p.payload.clone()),
+        pong: ping.as_ref().and_then(|p| p.pong.clone()),
+        pong_timeout: ping.and_then(|p| p.pong_timeout),
+        proxy,
+        local_binds,
+        reconnect,
+        permessage_deflate,
+        binary_compression,
+        max_message_size,
+        events,
+        ..WsConnConfig::new(url)
     };
 
-    let mut conn = WsConnection::new(config);
-    conn.start(on_msg, None);
-    std::future::pending::<()>().await;
-    conn.stop().await;
+    run_redundant(config, stats, redundancy, on_msg, None, control, &label).await;
 }
 
 /// Parameters for a binary-mode WebSocket MD stream.
@@ -59,8 +123,21 @@ pub struct BinaryStreamParams<F> {
     pub url: String,
     pub subscribe_msg: String,
     pub extra_headers: HashMap<String, String>,
+    pub proxy: Option<ProxyConfig>,
+    pub local_binds: Vec<LocalBind>,
+    pub reconnect: ReconnectPolicy,
+    pub permessage_deflate: bool,
+    pub binary_compression: Option<BinaryCompression>,
+    pub max_message_size: usize,
+    /// Lifecycle events are published here, tagged with `label`.
+    pub events: Option<broadcast::Sender<WsEvent>>,
+    /// One handle per redundant connection, which records its stats.
+    pub stats: Vec<WsStats>,
+    pub redundancy: Redundancy,
     pub tx: Sender<MarketDataMsg>,
     pub parser: F,
+    /// Subscription changes and other requests to apply while running.
+    pub control: Option<mpsc::UnboundedReceiver<ConnRequest>>,
     pub label: String,
 }
 
@@ -68,33 +145,373 @@ pub struct BinaryStreamParams<F> {
 /// until cancelled.
 pub async fn run_ws_binary_stream<F>(params: BinaryStreamParams<F>)
 where
-    F: Fn(&[u8]) -> Vec<MarketDataMsg> + Send + Sync + 'static,
+    F: Fn(&[u8], &mut Vec<MarketDataMsg>) + Send + Sync + 'static,
 {
-    let BinaryStreamParams { url, subscribe_msg, extra_headers, tx, parser, label } = params;
-
-    let tx_clone = tx.clone();
-    let label_clone = label.clone();
-    let on_binary: OnBinaryCallback = Arc::new(move |_conn_id, data| {
-        for msg in parser(data) {
-            if tx_clone.try_send(msg).is_err() {
-                warn!("[{label_clone}] SBE dedup channel full");
-            }
-        }
-    });
+    let BinaryStreamParams {
+        url,
+        subscribe_msg,
+        extra_headers,
+        proxy,
+        local_binds,
+        reconnect,
+        permessage_deflate,
+        binary_compression,
+        max_message_size,
+        events,
+        stats,
+        redundancy,
+        tx,
+        parser,
+        control,
+        label,
+    } = params;
+
+    let events = events.map(|tx| WsEventSink::new(label.as_str(), tx));
+    let on_binary = binary_callback(parser, tx, label.clone());
 
     let on_text: OnMessageCallback = Arc::new(|_conn_id, _text| {});
 
     let config = WsConnConfig {
-        url,
         subscribe_msg: Some(subscribe_msg),
         extra_headers,
-        ping_interval: None,
-        ping_payload: None,
-        id: 0,
+        proxy,
+        local_binds,
+        reconnect,
+        permessage_deflate,
+        binary_compression,
+        max_message_size,
+        events,
+        ..WsConnConfig::new(url)
     };
 
-    let mut conn = WsConnection::new(config);
-    conn.start(on_text, Some(on_binary));
-    std::future::pending::<()>().await;
-    conn.stop().await;
+    run_redundant(config, stats, redundancy, on_text, Some(on_binary), control, &label).await;
+}
+
+/// Run one connection per `stats` handle from `base_config`, applying
+/// subscription changes and requests to all of them as they arrive and
+/// evaluating them every heartbeat. Never returns; the stream runs until
+/// cancelled.
+async fn run_redundant(
+    base_config: WsConnConfig,
+    stats: Vec<WsStats>,
+    redundancy: Redundancy,
+    on_text: OnMessageCallback,
+    on_binary: Option<OnBinaryCallback>,
+    mut control: Option<mpsc::UnboundedReceiver<ConnRequest>>,
+    label: &str,
+) {
+    let config = RedundantConfig {
+        base_config,
+        conn_count: stats.len().max(1) as u32,
+        hb_interval: redundancy.hb_interval,
+        reset_on_hb: redundancy.reset_on_hb,
+        dns_refresh: redundancy.dns_refresh,
+        stale_after: redundancy.stale_after,
+    };
+    let mut client = RedundantWsClient::with_stats(config, stats);
+    client.start(on_text.clone(), on_binary.clone()).await;
+    let mut heartbeat = redundancy.hb_interval.map(|interval| {
+        let mut ticker = tokio::time::interval_at(tokio::time::Instant::now() + interval, interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+        ticker
+    });
+    loop {
+        tokio::select! {
+            request = recv_or_pending(&mut control) => {
+                let msg = match request {
+                    Some(ConnRequest::Resubscribe(change)) => {
+                        client.set_subscribe_msg(change.on_connect);
+                        change.now
+                    }
+                    Some(ConnRequest::Send(msg)) => msg,
+                    None => {
+                        control = None;
+                        continue;
+                    }
+                };
+                if let Err(e) = client.send(&msg).await {
+                    warn!("[{label}] cannot send subscription change: {e}");
+                }
+            }
+            _ = tick_or_pending(&mut heartbeat) => {
+                if let Some(slot) = client.evaluate_and_reset(on_text.clone(), on_binary.clone()).await {
+                    info!("[{label}] replaced connection in slot {slot}");
+                }
+            }
+        }
+    }
+}
+
+async fn recv_or_pending(control: &mut Option<mpsc::UnboundedReceiver<ConnRequest>>) -> Option<ConnRequest> {
+    match control {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn tick_or_pending(interval: &mut Option<tokio::time::Interval>) {
+    match interval {
+        Some(i) => {
+            i.tick().await;
+        }
+        None => std::future::pending().await,
+    }
+}
+
+/// Buffers reused across the messages handled on one thread.
+#[derive(Default)]
+struct Scratch {
+    /// Mutable copy of the current frame for in-place parsing.
+    buf: Vec<u8>,
+    /// Parser output, drained into the dedup channel.
+    out: Vec<MarketDataMsg>,
+}
+
+thread_local! {
+    static SCRATCH: RefCell<Scratch> = RefCell::default();
+}
+
+/// Text callback: copy into the scratch buffer, parse, forward.
+fn text_callback<F>(parser: F, tx: Sender<MarketDataMsg>, label: String) -> OnMessageCallback
+where
+    F: Fn(&mut [u8], &mut Vec<MarketDataMsg>) + Send + Sync + 'static,
+{
+    Arc::new(move |_conn_id, text| {
+        SCRATCH.with_borrow_mut(|Scratch { buf, out }| {
+            buf.clear();
+            buf.extend_from_slice(text.as_bytes());
+            parser(buf, out);
+            for msg in out.drain(..) {
+                if tx.try_send(msg).is_err() {
+                    warn!("[{label}] dedup channel full");
+                }
+            }
+        })
+    })
+}
+
+/// Binary callback: parse straight from the frame, forward.
+fn binary_callback<F>(parser: F, tx: Sender<MarketDataMsg>, label: String) -> OnBinaryCallback
+where
+    F: Fn(&[u8], &mut Vec<MarketDataMsg>) + Send + Sync + 'static,
+{
+    Arc::new(move |_conn_id, data| {
+        SCRATCH.with_borrow_mut(|Scratch { out, .. }| {
+            parser(data, out);
+            for msg in out.drain(..) {
+                if tx.try_send(msg).is_err() {
+                    warn!("[{label}] SBE dedup channel full");
+                }
+            }
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use k4_core::types::{DepthNBox, ProductType};
+
+    use super::*;
+    use crate::{alloc_counter::allocations, binance, bybit, order_book::TickSizes};
+
+    #[test]
+    fn text_path_does_not_allocate_per_message() {
+        let (tx, rx) = crossbeam_channel::bounded(16);
+        let (parser, _requests) =
+            bybit::parser("test", ProductType::Futures, "orderbook.50", None, TickSizes::default());
+        let on_msg = text_callback(parser, tx, "test".into());
+        let frames = [
+            include_str!("../benches/samples/bybit_orderbook1.json"),
+            include_str!("../benches/samples/bybit_public_trade.json"),
+        ];
+
+        // The first frames size the scratch buffers.
+        for frame in frames {
+            on_msg(0, frame);
+            while rx.try_recv().is_ok() {}
+        }
+
+        let n = allocations(|| {
+            for _ in 0..100 {
+                // Redundant connections interleave on the thread's scratch.
+                for (conn_id, frame) in frames.iter().enumerate() {
+                    on_msg(conn_id, frame);
+                    assert!(rx.try_recv().is_ok(), "{frame}");
+                    while rx.try_recv().is_ok() {}
+                }
+            }
+        });
+        assert_eq!(n, 0);
+    }
+
+    /// A Binance SBE `BestBidAskStreamEvent` frame.
+    fn sbe_best_bid_ask(update_id: i64) -> Vec<u8> {
+        let mut frame = Vec::new();
+        for header in [0u16, 10001, 1, 1] {
+            frame.extend_from_slice(&header.to_le_bytes()); // blockLength, templateId, schemaId, version
+        }
+        for v in [1_700_000_000_000_000, update_id] {
+            frame.extend_from_slice(&v.to_le_bytes()); // eventTime, bookUpdateId
+        }
+        frame.extend_from_slice(&[(-2i8) as u8, (-4i8) as u8]); // price and qty exponents
+        for v in [3_000_050i64, 15_000, 3_000_100, 20_000] {
+            frame.extend_from_slice(&v.to_le_bytes());
+        }
+        frame.push(7);
+        frame.extend_from_slice(b"BTCUSDT");
+        frame
+    }
+
+    #[test]
+    fn binary_path_does_not_allocate_per_message() {
+        let (tx, rx) = crossbeam_channel::bounded(16);
+        let on_binary = binary_callback(binance::sbe_parser::parse_sbe_message, tx, "test".into());
+        let frames: Vec<_> = (0..300).map(sbe_best_bid_ask).collect();
+
+        on_binary(0, &frames[0]);
+        assert!(rx.try_recv().is_ok());
+
+        let n = allocations(|| {
+            for (i, frame) in frames.iter().enumerate() {
+                on_binary(i % 2, frame);
+                assert!(rx.try_recv().is_ok());
+            }
+        });
+        assert_eq!(n, 0);
+    }
+
+    #[test]
+    fn exchange_text_parsers_do_not_allocate_per_message() {
+        use crate::{bitget, okx};
+
+        fn check<F>(parser: F, frames: &[&str])
+        where
+            F: Fn(&mut [u8], &mut Vec<MarketDataMsg>) + Send + Sync + 'static,
+        {
+            let (tx, rx) = crossbeam_channel::bounded(16);
+            let on_msg = text_callback(parser, tx, "test".into());
+            let run = || {
+                for frame in frames {
+                    on_msg(0, frame);
+                    assert!(rx.try_recv().is_ok(), "{frame}");
+                    while rx.try_recv().is_ok() {}
+                }
+            };
+            run();
+            // Stock the shared DepthN pool, so boxes taken by tests on other
+            // threads do not leave it empty.
+            drop((0..64).map(|_| DepthNBox::default()).collect::<Vec<_>>());
+            assert_eq!(allocations(|| (0..50).for_each(|_| run())), 0);
+        }
+
+        check(
+            |data: &mut [u8], out: &mut Vec<_>| out.extend(binance::json_parser::parse_message(data)),
+            &[
+                include_str!("../benches/samples/binance_book_ticker.json"),
+                include_str!("../benches/samples/binance_depth5.json"),
+            ],
+        );
+        // Depth5 plus a pooled DepthN per update.
+        check(
+            |data: &mut [u8], out: &mut Vec<_>| binance::json_parser::parse_message_with_depth_n(data, 20, out),
+            &[include_str!("../benches/samples/binance_depth5.json")],
+        );
+        check(
+            |data: &mut [u8], out: &mut Vec<_>| out.extend(okx::json_parser::parse_message(data)),
+            &[include_str!("../benches/samples/okx_bbo_tbt.json"), include_str!("../benches/samples/okx_books5.json")],
+        );
+        check(
+            bitget::json_parser::parse_message,
+            &[
+                include_str!("../benches/samples/bitget_trade.json"),
+                include_str!("../benches/samples/bitget_books5.json"),
+            ],
+        );
+    }
+
+    /// Run `frames` through the text path: the first ten warm up the buffers
+    /// and books, each of the rest must publish without allocating.
+    fn check_book_stream<F>(parser: F, frames: &[String])
+    where
+        F: Fn(&mut [u8], &mut Vec<MarketDataMsg>) + Send + Sync + 'static,
+    {
+        let (tx, rx) = crossbeam_channel::bounded(16);
+        let on_msg = text_callback(parser, tx, "test".into());
+        let (warm_up, measured) = frames.split_at(10);
+        for frame in warm_up {
+            on_msg(0, frame);
+            while rx.try_recv().is_ok() {}
+        }
+        drop((0..64).map(|_| DepthNBox::default()).collect::<Vec<_>>());
+        let n = allocations(|| {
+            for frame in measured {
+                on_msg(0, frame);
+                assert!(matches!(rx.try_recv(), Ok(MarketDataMsg::Depth5(_))), "{frame}");
+                assert!(matches!(rx.try_recv(), Ok(MarketDataMsg::DepthN(_))), "{frame}");
+            }
+        });
+        assert_eq!(n, 0);
+    }
+
+    /// Bid sizes alternating between updates.
+    fn bid_size(i: u64) -> &'static str {
+        if i.is_multiple_of(2) { "7" } else { "8" }
+    }
+
+    #[test]
+    fn local_book_parsers_do_not_allocate_per_message() {
+        use crate::{
+            binance::diff_depth::{self, DepthSnapshot, DiffDepthBooks},
+            okx,
+        };
+
+        // OKX `books`: checksummed snapshot, then updates chained on `prevSeqId`.
+        let (parser, _requests) = okx::parser("test", ProductType::Spot, "books", Some(20), TickSizes::default());
+        let frames: Vec<String> = (0..100u64)
+            .map(|i| {
+                let size = bid_size(i);
+                let checksum = crc32fast::hash(format!("3366.1:{size}:3366.8:9").as_bytes()) as i32;
+                let (action, prev) = if i == 0 { ("snapshot", -1) } else { ("update", 10 + i as i64 - 1) };
+                format!(
+                    r#"{{"arg":{{"channel":"books","instId":"BTC-USDT"}},"action":"{action}","data":[{{"asks":[["3366.8","9","0","1"]],"bids":[["3366.1","{size}","0","2"]],"ts":"1","checksum":{checksum},"prevSeqId":{prev},"seqId":{}}}]}}"#,
+                    10 + i
+                )
+            })
+            .collect();
+        check_book_stream(parser, &frames);
+
+        // Bybit `orderbook.50`: snapshot, then contiguous deltas.
+        let (parser, _requests) =
+            bybit::parser("test", ProductType::Futures, "orderbook.50", Some(20), TickSizes::default());
+        let frames: Vec<String> = (0..100u64)
+            .map(|i| {
+                let kind = if i == 0 { "snapshot" } else { "delta" };
+                format!(
+                    r#"{{"topic":"orderbook.50.BTCUSDT","type":"{kind}","ts":1,"cts":1,"data":{{"s":"BTCUSDT","b":[["100","{}"]],"a":[["101","1"]],"u":{},"seq":{}}}}}"#,
+                    bid_size(i),
+                    100 + i,
+                    1000 + i
+                )
+            })
+            .collect();
+        check_book_stream(parser, &frames);
+
+        // Binance diff depth, synced to a snapshot before the measured events.
+        let (books, _requests) = DiffDepthBooks::new(ProductType::Spot, Some(20), TickSizes::default());
+        let frames: Vec<String> = (0..100u64)
+            .map(|i| {
+                format!(
+                    r#"{{"e":"depthUpdate","E":1,"s":"BTCUSDT","U":{0},"u":{0},"b":[["100","{1}"]],"a":[]}}"#,
+                    101 + i,
+                    bid_size(i)
+                )
+            })
+            .collect();
+        diff_depth::parser(books.clone())(&mut frames[0].clone().into_bytes(), &mut Vec::new());
+        books.on_snapshot(
+            "BTCUSDT",
+            DepthSnapshot { last_update_id: 100, bids: vec![[100.0, 1.0]], asks: vec![[101.0, 1.0]] },
+        );
+        check_book_stream(diff_depth::parser(books), &frames[1..]);
+    }
 }
@@ -15,7 +15,9 @@ use std::path::PathBuf;
 
 use anyhow::Result;
 use clap::Parser;
-use tracing::{error, info};
+use k4_core::ws::{DisconnectReason, WsEvent, WsEventKind};
+use tokio::sync::broadcast::{self, error::RecvError};
+use tracing::{debug, error, info, warn};
 
 /// Crypto Gateway Market Data & Trading Runner.
 #[derive(Parser)]
@@ -43,14 +45,32 @@ async fn main() -> Result<()> {
     info!("k4-runner starting — config={}, log_level={}", cli.config.display(), cli.log_level,);
 
     // 2. Load configuration
-    let config = k4_core::config::load_config(&cli.config)?;
+    let mut config = k4_core::config::load_config(&cli.config)?;
     info!("config loaded — {} connection(s)", config.connections.len(),);
 
     // 3. Create and start MD modules from the connections array
     let mut md_modules: Vec<Box<dyn k4_md::MdModule>> = Vec::new();
+    // Published instrument specs; the regions stay mapped while held.
+    let mut instrument_stores = Vec::new();
 
-    for (idx, conn_config) in config.connections.iter().enumerate() {
-        match k4_md::registry::create_md_module(conn_config) {
+    for (idx, conn_config) in config.connections.iter_mut().enumerate() {
+        let instruments = match k4_md::discovery::resolve_symbols(conn_config).await {
+            Ok(instruments) => instruments,
+            Err(e) => {
+                error!("connection[{idx}]: failed to resolve symbols for '{}': {e:#}", conn_config.exchange);
+                continue;
+            }
+        };
+        if let Some(shm_name) = &conn_config.instrument_shm_name {
+            match instruments.publish(shm_name) {
+                Ok(store) => {
+                    info!("connection[{idx}]: published {} instruments to '{shm_name}'", instruments.len());
+                    instrument_stores.push(store);
+                }
+                Err(e) => error!("connection[{idx}]: failed to publish instruments to '{shm_name}': {e:#}"),
+            }
+        }
+        match k4_md::registry::create_md_module(conn_config, &instruments) {
             Ok(module) => {
                 info!("connection[{idx}]: created MD module '{}' (exchange={})", module.name(), conn_config.exchange,);
                 md_modules.push(module);
@@ -66,7 +86,12 @@ async fn main() -> Result<()> {
         module.init_shm().await?;
     }
 
-    // Start all modules
+    // Watch connection lifecycle events, then start all modules
+    for module in &md_modules {
+        if let Some(events) = module.subscribe_events() {
+            tokio::spawn(watch_events(module.name().to_string(), events));
+        }
+    }
     for module in &mut md_modules {
         module.start().await?;
         info!("module '{}' started", module.name());
@@ -89,3 +114,26 @@ async fn main() -> Result<()> {
     info!("all modules stopped — goodbye");
     Ok(())
 }
+
+/// Log the WebSocket lifecycle events of one module.
+///
+/// Connects are routine; disconnects are warnings and a connection giving up
+/// for good is an error, since its stream stays dark until restart.
+async fn watch_events(module: String, mut events: broadcast::Receiver<WsEvent>) {
+    loop {
+        match events.recv().await {
+            Ok(event) => match &event.kind {
+                WsEventKind::Connecting { .. }
+                | WsEventKind::Connected
+                | WsEventKind::Disconnected { reason: DisconnectReason::Shutdown } => {
+                    debug!("module '{module}': {event}")
+                }
+                WsEventKind::Disconnected { .. } => warn!("module '{module}': {event}"),
+                WsEventKind::GivingUp { retry_in: Some(_), .. } => warn!("module '{module}': {event}"),
+                WsEventKind::GivingUp { retry_in: None, .. } => error!("module '{module}': {event}"),
+            },
+            Err(RecvError::Lagged(n)) => warn!("module '{module}': {n} connection events dropped"),
+            Err(RecvError::Closed) => return,
+        }
+    }
+}
@@ -63,6 +63,13 @@ pub struct BinanceTdConfig {
     #[serde(default = "default_spot_ws_api_url")]
     pub spot_ws_api_url: String,
 
+    // -- Reference data --
+    /// SHM region the Binance MD module publishes its instruments to (its
+    /// `instrument_shm_name`). Read on login; without it no contract specs
+    /// are available.
+    #[serde(default)]
+    pub instrument_shm_name: Option<String>,
+
     // -- Timing --
     /// `recvWindow` for signed requests (milliseconds, 0 = Binance default).
     #[serde(default = "default_recv_window")]
@@ -89,6 +96,7 @@ impl Default for BinanceTdConfig {
             ubase_ws_url: default_ubase_ws_url(),
             cbase_ws_url: default_cbase_ws_url(),
             spot_ws_api_url: default_spot_ws_api_url(),
+            instrument_shm_name: None,
             recv_window: default_recv_window(),
             listen_key_refresh_secs: default_listen_key_interval(),
         }
@@ -26,20 +26,18 @@ pub mod auth;
 pub mod config;
 pub mod futures;
 pub mod spot;
-pub mod symbol_mapper;
 
 use std::{sync::Arc, time::Duration};
 
 use anyhow::{Result, anyhow};
 use async_trait::async_trait;
-use k4_core::{enums::AccountType, trading::*};
+use k4_core::{enums::AccountType, instrument::InstrumentRegistry, trading::*};
 use tracing::{error, info, warn};
 
 use self::{
     config::BinanceTdConfig,
     futures::{FuturesClient, FuturesVariant},
     spot::SpotClient,
-    symbol_mapper::SymbolMapper,
 };
 use crate::event::{TdEvent, TdEventSender};
 
@@ -59,8 +57,8 @@ pub struct BinanceTd {
     cbase: Option<Arc<FuturesClient>>,
     /// Channel for emitting events to the strategy layer.
     event_tx: TdEventSender,
-    /// Bidirectional symbol mapper.
-    symbol_mapper: SymbolMapper,
+    /// Contract specs published by the MD module, read on login.
+    instruments: InstrumentRegistry,
     /// Background task handles (listen key refresh, user data WS, etc.).
     tasks: Vec<tokio::task::JoinHandle<()>>,
 }
@@ -78,12 +76,19 @@ impl BinanceTd {
             ubase: None,
             cbase: None,
             event_tx: tx,
-            symbol_mapper: SymbolMapper::new(),
+            instruments: InstrumentRegistry::new(),
             tasks: Vec::new(),
         };
         (td, rx)
     }
 
+    /// Contract specs (tick size, lot size, ...) by canonical id or venue
+    /// symbol, as the MD module published them to
+    /// [`instrument_shm_name`](BinanceTdConfig::instrument_shm_name).
+    pub fn instruments(&self) -> &InstrumentRegistry {
+        &self.instruments
+    }
+
     /// Start a background task that refreshes a listen key at a fixed interval.
     fn spawn_listen_key_refresh(
         &mut self,
@@ -241,12 +246,16 @@ impl crate::TdModule for BinanceTd {
             }
         }
 
-        // Load symbol mappings from exchange info (best-effort)
-        if let Some(ref spot) = self.spot {
-            match spot.get_exchange_info().await {
-                Ok(info) => self.symbol_mapper.load_from_exchange_info(&info),
-                Err(e) => warn!("[binance-td] failed to load spot exchange info: {e}"),
-            }
+        // Contract specs from the MD module's registry (best-effort)
+        match &self.config.instrument_shm_name {
+            Some(name) => match InstrumentRegistry::open(name) {
+                Ok(instruments) => {
+                    info!("[binance-td] {} instruments from {name}", instruments.len());
+                    self.instruments = instruments;
+                }
+                Err(e) => warn!("[binance-td] failed to read instruments from {name}: {e:#}"),
+            },
+            None => warn!("[binance-td] no instrument_shm_name, contract specs unavailable"),
         }
 
         info!(